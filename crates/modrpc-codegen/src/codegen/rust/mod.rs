@@ -25,6 +25,10 @@ fn import_qualified(
     }
 }
 
+fn role_list_tokens(roles: &[String]) -> rust::Tokens {
+    quote! { &[$(for role in roles join (, ) => $(quoted(role)))] }
+}
+
 pub fn rust_interface(
     db: &Database,
     interface: &Interface,
@@ -40,6 +44,7 @@ pub fn rust_interface(
     let mut events_field_tokens = rust::Tokens::new();
     let mut events_constr_tokens = rust::Tokens::new();
     for events_list in &interface.events {
+        let from_roles_tokens = role_list_tokens(&events_list.from_roles);
         for event in &events_list.events {
             let event_type_tokens = mproto_codegen::codegen::rust::rust_type_tokens(mproto_cx, &event.ty);
             events_field_tokens = quote!{
@@ -50,7 +55,7 @@ pub fn rust_interface(
             let event_name_tokens = quote! { $("\"")$(&event.name)$("\"") };
             events_constr_tokens = quote! {
                 $events_constr_tokens
-                $(&event.name): ib.event($event_name_tokens),
+                $(&event.name): ib.event_from($event_name_tokens, $(&from_roles_tokens)),
             };
         }
     }
@@ -58,9 +63,11 @@ pub fn rust_interface(
     let mut objects_field_tokens = rust::Tokens::new();
     let mut objects_constr_tokens = rust::Tokens::new();
     for object in &interface.objects {
-        if db.lookup_interface(&object.construct).is_none() {
+        let Some(object_interface) = db.lookup_interface(&object.construct) else {
             panic!("failed to lookup interface '{}'", object.construct.name);
         };
+        let object_roles_tokens = role_list_tokens(&object_interface.roles);
+        let role_args_tokens = role_list_tokens(&object.role_args);
 
         let object_type_args = &mproto_codegen::codegen::rust::rust_type_arg_list(
             mproto_cx, &object.type_args, None,
@@ -77,7 +84,11 @@ pub fn rust_interface(
 
         objects_constr_tokens = quote! {
             $objects_constr_tokens
-            $(&object.name): $(&object.construct.name)Interface::new(ib),
+            $(&object.name): ib.object(
+                $(&object_roles_tokens),
+                $(&role_args_tokens),
+                |ib| $(&object.construct.name)Interface::new(ib),
+            ),
         };
     }
 
@@ -0,0 +1,182 @@
+use clap::{App, Arg};
+use modrpc::{CaptureDirection, CaptureRecord, PacketBundle, TransmitPacket};
+use mproto::BaseLen;
+
+use crate::{topics::TopicMap, value::Decoder};
+
+mod topics;
+mod value;
+
+// Longest payload printed in full when it can't be decoded.
+const MAX_HEX_LEN: usize = 64;
+
+struct Schema {
+    db: modrpc_codegen::Database,
+    topics: TopicMap,
+}
+
+fn main() {
+    let matches =
+        App::new("modrpc-dump")
+            .version("0.1")
+            .author("modrpc authors")
+            .about("Print the bundles recorded in a modrpc packet capture.")
+            .arg(Arg::with_name("CAPTURE")
+                .help("Path to a capture written by modrpc::PacketCapture")
+                .required(true)
+                .index(1)
+            )
+            .args_from_usage("-s, --schema [schema] 'Path to the modrpc schema of the captured planes.'")
+            .args_from_usage("-i, --interface [interface] 'Interface of the captured planes. Defaults to the schema's only interface.'")
+            .args_from_usage("-t, --transport [transport] 'Only print bundles of this transport id.'")
+            .get_matches();
+
+    let capture_path = matches.value_of("CAPTURE").unwrap();
+    let transport_filter = matches.value_of("transport").map(|transport| {
+        transport.parse::<u32>().unwrap_or_else(|_| {
+            println!("ERROR: Invalid transport id '{transport}'");
+            std::process::exit(1);
+        })
+    });
+
+    let schema = matches.value_of("schema").map(|schema_path| {
+        load_schema(schema_path, matches.value_of("interface")).unwrap_or_else(|e| {
+            println!("ERROR: Failed to load '{schema_path}': {e}");
+            std::process::exit(1);
+        })
+    });
+
+    let mut reader = match modrpc::CaptureReader::open(capture_path) {
+        Ok(reader) => reader,
+        Err(e) => {
+            println!("ERROR: Failed to open '{capture_path}': {e}");
+            std::process::exit(1);
+        }
+    };
+
+    loop {
+        let record = match reader.next_record() {
+            Ok(Some(record)) => record,
+            Ok(None) => break,
+            Err(e) => {
+                println!("ERROR: Failed to read '{capture_path}': {e}");
+                std::process::exit(1);
+            }
+        };
+        if transport_filter.is_some_and(|transport_id| transport_id != record.transport_id) {
+            continue;
+        }
+
+        let transport_name = reader.transport_name(record.transport_id).unwrap_or("?");
+        print_record(&record, transport_name, schema.as_ref());
+    }
+}
+
+fn load_schema(schema_path: &str, interface_name: Option<&str>) -> Result<Schema, String> {
+    let schema = modrpc_codegen::parse::parse_file(schema_path)?;
+    let interface_name = match interface_name {
+        Some(interface_name) => interface_name.to_string(),
+        None => match &schema.interfaces[..] {
+            [interface] => interface.name.clone(),
+            _ => return Err("the schema has several interfaces - pick one with --interface".into()),
+        },
+    };
+    let db = topics::load_schema(&schema)?;
+    let topics = TopicMap::new(&db, &interface_name)?;
+
+    Ok(Schema { db, topics })
+}
+
+fn print_record(record: &CaptureRecord, transport_name: &str, schema: Option<&Schema>) {
+    let direction = match record.direction {
+        CaptureDirection::Inbound => "<-",
+        CaptureDirection::Outbound => "->",
+    };
+    print!(
+        "{}.{:09} {transport_name}#{} {direction} ",
+        record.timestamp.as_secs(),
+        record.timestamp.subsec_nanos(),
+        record.transport_id,
+    );
+
+    let bundle = &record.bundle[..];
+    if bundle.len() < PacketBundle::BASE_LEN {
+        println!("truncated bundle {}", hex(bundle));
+        return;
+    }
+    let Ok(header) = mproto::decode_value::<PacketBundle>(bundle) else {
+        println!("invalid bundle {}", hex(bundle));
+        return;
+    };
+    if modrpc::is_control_bundle(bundle) {
+        println!("{}", describe_control_bundle(&header, bundle));
+        return;
+    }
+    println!("channel {} ({} bytes)", header.channel_id, header.length);
+
+    let bundle_end = std::cmp::min(PacketBundle::BASE_LEN + header.length as usize, bundle.len());
+    let mut cursor = PacketBundle::BASE_LEN;
+    while cursor < bundle_end {
+        let packet_header = (cursor + TransmitPacket::BASE_LEN <= bundle_end)
+            .then(|| mproto::decode_value::<TransmitPacket>(&bundle[cursor..]).ok())
+            .flatten();
+        let Some(packet_header) = packet_header else {
+            println!("    truncated packet {}", hex(&bundle[cursor..bundle_end]));
+            return;
+        };
+        let payload_start = cursor + TransmitPacket::BASE_LEN;
+        let payload_end = payload_start + packet_header.payload_length as usize;
+        if payload_end > bundle_end {
+            println!("    truncated packet {}", hex(&bundle[cursor..bundle_end]));
+            return;
+        }
+        print_packet(&packet_header, &bundle[payload_start..payload_end], schema);
+        cursor = payload_end;
+    }
+}
+
+fn print_packet(header: &TransmitPacket, payload: &[u8], schema: Option<&Schema>) {
+    print!(
+        "    plane {} topic {} from {}",
+        header.plane_id, header.topic, header.source.endpoint,
+    );
+    if header.infra_id != 0 {
+        print!(" infra {}", header.infra_id);
+    }
+
+    let topic = schema.and_then(|schema| Some((schema, schema.topics.get(header.topic)?)));
+    match topic {
+        Some((schema, topic)) => {
+            match Decoder::new(schema.db.mproto_db()).decode(&topic.ty, payload) {
+                Ok(value) => println!(" {}: {value}", topic.path),
+                Err(e) => println!(" {}: {e}: {}", topic.path, hex(payload)),
+            }
+        }
+        None => println!(": {}", hex(payload)),
+    }
+}
+
+fn describe_control_bundle(header: &PacketBundle, bundle: &[u8]) -> String {
+    if modrpc::is_keepalive_bundle(bundle) {
+        "keepalive".into()
+    } else if modrpc::is_goodbye_bundle(bundle) {
+        "goodbye".into()
+    } else if let Some(algorithms) = modrpc::decode_compression_hello(bundle) {
+        format!("compression hello {algorithms:#x}")
+    } else if let Some(subscriptions) = modrpc::decode_topic_subscriptions(bundle) {
+        format!("topic subscriptions {subscriptions:?}")
+    } else {
+        format!("control channel {:#x} {}", header.channel_id, hex(&bundle[PacketBundle::BASE_LEN..]))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex: String = bytes.iter()
+        .take(MAX_HEX_LEN)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if bytes.len() > MAX_HEX_LEN {
+        hex += &format!("... ({} bytes)", bytes.len());
+    }
+    hex
+}
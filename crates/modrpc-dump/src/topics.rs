@@ -0,0 +1,202 @@
+use modrpc_codegen::{
+    Database,
+    ast::{Interface, QualifiedIdentifier, Schema},
+};
+use mproto_codegen::ast::Type;
+
+use crate::value::{TypeBindings, resolve_type};
+
+/// Load `schema` and everything it imports.
+pub fn load_schema(schema: &Schema) -> Result<Database, String> {
+    let local_mproto_module = mproto_codegen::Module::from_type_defs(schema.type_defs.clone());
+    let mut db = Database::new(mproto_codegen::Database::new(local_mproto_module));
+    modrpc_codegen::codegen::load_imports_recursive(&mut db, schema)
+        .map_err(|_| "failed to load imports".to_string())?;
+    for interface in &schema.interfaces {
+        let _ = db.local().add_interface(interface.clone());
+    }
+
+    Ok(db)
+}
+
+/// An event of a plane's interface.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopicInfo {
+    /// The event's path relative to the plane's root object, e.g. `requests.request`.
+    pub path: String,
+    /// The event's payload type, resolved with `resolve_type`.
+    pub ty: Type,
+}
+
+/// Maps the topics of a plane to the events of its interface, numbered the way
+/// `modrpc::InterfaceBuilder` numbers them.
+pub struct TopicMap {
+    topics: Vec<TopicInfo>,
+}
+
+impl TopicMap {
+    pub fn new(db: &Database, interface_name: &str) -> Result<Self, String> {
+        let interface = db
+            .lookup_interface(&QualifiedIdentifier::local(interface_name))
+            .ok_or_else(|| format!("no interface named '{interface_name}'"))?;
+        let mut topics = Vec::new();
+        add_interface_topics(db, interface, None, &TypeBindings::new(), "", &mut topics)?;
+
+        Ok(Self { topics })
+    }
+
+    pub fn get(&self, topic: u32) -> Option<&TopicInfo> {
+        self.topics.get(topic as usize)
+    }
+}
+
+fn add_interface_topics(
+    db: &Database,
+    interface: &Interface,
+    module: Option<&str>,
+    bindings: &TypeBindings,
+    path_prefix: &str,
+    topics: &mut Vec<TopicInfo>,
+) -> Result<(), String> {
+    // Events come first, then each object's topics in turn - the order generated
+    // `InterfaceSchema::new` impls declare them in.
+    for events_list in &interface.events {
+        for event in &events_list.events {
+            topics.push(TopicInfo {
+                path: format!("{path_prefix}{}", event.name),
+                ty: resolve_type(&event.ty, module, bindings),
+            });
+        }
+    }
+
+    for object in &interface.objects {
+        let object_module = object.construct.module.as_deref().or(module);
+        let object_interface = db
+            .lookup_interface(&QualifiedIdentifier {
+                name: object.construct.name.clone(),
+                module: object_module.map(String::from),
+            })
+            .ok_or_else(|| format!("no interface named '{}'", object.construct.name))?;
+        let object_bindings = object_interface.type_params.iter()
+            .cloned()
+            .zip(object.type_args.iter().map(|arg| resolve_type(arg, module, bindings)))
+            .collect();
+        add_interface_topics(
+            db,
+            object_interface,
+            object_module,
+            &object_bindings,
+            &format!("{path_prefix}{}.", object.name),
+            topics,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use mproto_codegen::ast::PrimitiveType;
+
+    use crate::value::{Decoder, Value};
+    use super::*;
+
+    fn load_test_schema() -> Database {
+        let std_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../proto/std.modrpc");
+        let schema = modrpc_codegen::parse::parse_schema(format!(r#"
+            import std "{std_path}"
+
+            interface Echo<T> @(Client, Server) {{
+                events @(Client) -> @(Server) {{
+                    ping: Ping<T>,
+                }}
+            }}
+
+            interface App @(Player, Host) {{
+                events @(Host) -> @(Player) {{
+                    greeting: string,
+                }}
+
+                objects {{
+                    echo: Echo<u16> @(Player, Host),
+                    requests: std.Request<string, u32> @(Player, Host),
+                }}
+            }}
+
+            struct Ping<T> {{ seq: T, tag: option<Tag> }}
+
+            enum Tag {{ Plain, Labeled {{ label: string }} }}
+        "#).trim()).unwrap();
+
+        load_schema(&schema).unwrap()
+    }
+
+    #[test]
+    fn test_topic_map() {
+        let db = load_test_schema();
+        let topics = TopicMap::new(&db, "App").unwrap();
+
+        let paths: Vec<_> = (0..5).map(|topic| topics.get(topic).map(|t| t.path.as_str())).collect();
+        assert_eq!(
+            paths,
+            [
+                Some("greeting"),
+                Some("echo.ping"),
+                Some("requests.request"),
+                Some("requests.response"),
+                None,
+            ],
+        );
+        assert_eq!(topics.get(0).unwrap().ty, Type::Primitive(PrimitiveType::String));
+        assert_eq!(
+            topics.get(1).unwrap().ty,
+            Type::Defined {
+                ident: mproto_codegen::ast::QualifiedIdentifier::local("Ping"),
+                args: vec![Type::Primitive(PrimitiveType::U16)],
+            },
+        );
+        assert!(TopicMap::new(&db, "Missing").is_err());
+    }
+
+    #[test]
+    fn test_decode_payloads() {
+        let db = load_test_schema();
+        let topics = TopicMap::new(&db, "App").unwrap();
+        let decoder = Decoder::new(db.mproto_db());
+
+        let request = std_modrpc::Request {
+            request_id: 7,
+            worker: 1,
+            trace_context: Some(std_modrpc::TraceContext { trace_id: 3, span_id: 4, flags: 1 }),
+            payload: "hello".to_string(),
+        };
+        let mut payload = vec![0u8; mproto::encoded_len(&request)];
+        mproto::encode_value(&request, &mut payload[..]);
+        let value = decoder.decode(&topics.get(2).unwrap().ty, &payload).unwrap();
+        assert_eq!(
+            value.to_string(),
+            "Request { request_id: 7, worker: 1, trace_context: Some(TraceContext { trace_id: 3, \
+             span_id: 4, flags: 1 }), payload: \"hello\" }",
+        );
+
+        // Truncated payloads and out of range offsets are reported, not panicked on.
+        assert!(decoder.decode(&topics.get(2).unwrap().ty, &payload[..10]).is_err());
+        let mut bad_offset = payload.clone();
+        let string_offset = bad_offset.len() - "hello".len() - 4;
+        bad_offset[string_offset..string_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(decoder.decode(&topics.get(2).unwrap().ty, &bad_offset).is_err());
+
+        // Ping<u16> { seq: 5, tag: Some(Tag::Labeled { label: "x" }) }
+        let ping_ty = &topics.get(1).unwrap().ty;
+        let mut ping = vec![5, 0, 1, 1];
+        ping.extend_from_slice(&1u32.to_le_bytes());
+        ping.extend_from_slice(&12u32.to_le_bytes());
+        ping.push(b'x');
+        let value = decoder.decode(ping_ty, &ping).unwrap();
+        assert_eq!(value.to_string(), "Ping { seq: 5, tag: Some(Tag::Labeled { label: \"x\" }) }");
+
+        // Ping<u16> { seq: 6, tag: None }
+        let value = decoder.decode(ping_ty, &[6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(matches!(value, Value::Struct { ref fields, .. } if fields[1].1 == Value::Option(None)));
+    }
+}
@@ -0,0 +1,396 @@
+use std::{collections::HashMap, fmt};
+
+use mproto_codegen::ast::{EnumVariant, NamedField, PrimitiveType, QualifiedIdentifier, Type, TypeBody};
+
+// Deeper nesting than any sane schema - stops payloads whose boxes point back at themselves.
+const MAX_DEPTH: usize = 64;
+
+pub type TypeBindings = HashMap<String, Type>;
+
+/// Qualify `ty`'s references to types local to `module` and replace its type parameters with
+/// their `bindings`, so that it can be looked up from outside `module`.
+pub fn resolve_type(ty: &Type, module: Option<&str>, bindings: &TypeBindings) -> Type {
+    let resolve = |ty: &Type| Box::new(resolve_type(ty, module, bindings));
+    match ty {
+        Type::Primitive(PrimitiveType::Box(inner_ty)) => {
+            Type::Primitive(PrimitiveType::Box(resolve(inner_ty)))
+        }
+        Type::Primitive(PrimitiveType::List(item_ty)) => {
+            Type::Primitive(PrimitiveType::List(resolve(item_ty)))
+        }
+        Type::Primitive(PrimitiveType::Option(item_ty)) => {
+            Type::Primitive(PrimitiveType::Option(resolve(item_ty)))
+        }
+        Type::Primitive(PrimitiveType::Result(ok_ty, err_ty)) => {
+            Type::Primitive(PrimitiveType::Result(resolve(ok_ty), resolve(err_ty)))
+        }
+        Type::Primitive(primitive) => Type::Primitive(primitive.clone()),
+        Type::Defined { ident, args } => {
+            if ident.module.is_none()
+                && args.is_empty()
+                && let Some(binding) = bindings.get(&ident.name)
+            {
+                return binding.clone();
+            }
+            Type::Defined {
+                ident: QualifiedIdentifier {
+                    name: ident.name.clone(),
+                    module: ident.module.clone().or_else(|| module.map(String::from)),
+                },
+                args: args.iter().map(|arg| resolve_type(arg, module, bindings)).collect(),
+            }
+        }
+    }
+}
+
+/// A decoded mproto value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Void,
+    Bool(bool),
+    UInt(u128),
+    Int(i128),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+    Option(Option<Box<Value>>),
+    Result(Result<Box<Value>, Box<Value>>),
+    Struct { name: String, fields: Vec<(String, Value)> },
+    Enum { name: String, variant: String, fields: Vec<(String, Value)> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    Truncated,
+    Invalid(&'static str),
+    UnknownType(String),
+    TooDeep,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "payload is truncated"),
+            DecodeError::Invalid(reason) => write!(f, "invalid payload: {reason}"),
+            DecodeError::UnknownType(name) => write!(f, "unknown type '{name}'"),
+            DecodeError::TooDeep => write!(f, "payload is nested too deeply"),
+        }
+    }
+}
+
+/// Decode payloads of types defined in `db`. Types must have been resolved with `resolve_type`.
+pub struct Decoder<'a> {
+    db: &'a mproto_codegen::Database,
+}
+
+struct Cursor<'b> {
+    buffer: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> Cursor<'b> {
+    fn base(&mut self, len: usize) -> Result<&'b [u8], DecodeError> {
+        let base = self.buffer
+            .get(self.offset..self.offset + len)
+            .ok_or(DecodeError::Truncated)?;
+        self.offset += len;
+        Ok(base)
+    }
+
+    fn u32(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.base(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn advance(&mut self, len: usize) -> Result<(), DecodeError> {
+        self.base(len).map(|_| ())
+    }
+
+    // Follow an offset into the scratch area.
+    fn scratch(&mut self) -> Result<Cursor<'b>, DecodeError> {
+        let offset = self.u32()?;
+        if offset > self.buffer.len() {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(Cursor { buffer: self.buffer, offset })
+    }
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(db: &'a mproto_codegen::Database) -> Self {
+        Self { db }
+    }
+
+    pub fn decode(&self, ty: &Type, payload: &[u8]) -> Result<Value, DecodeError> {
+        self.decode_at(ty, &mut Cursor { buffer: payload, offset: 0 }, 0)
+    }
+
+    fn decode_at(&self, ty: &Type, cursor: &mut Cursor, depth: usize) -> Result<Value, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        let depth = depth + 1;
+
+        macro_rules! le {
+            ($t:ty) => {
+                <$t>::from_le_bytes(cursor.base(size_of::<$t>())?.try_into().unwrap())
+            };
+        }
+
+        let value = match ty {
+            Type::Primitive(primitive) => match primitive {
+                PrimitiveType::Void => Value::Void,
+                PrimitiveType::U8 => Value::UInt(le!(u8).into()),
+                PrimitiveType::U16 => Value::UInt(le!(u16).into()),
+                PrimitiveType::U32 => Value::UInt(le!(u32).into()),
+                PrimitiveType::U64 => Value::UInt(le!(u64).into()),
+                PrimitiveType::U128 => Value::UInt(le!(u128)),
+                PrimitiveType::I8 => Value::Int(le!(i8).into()),
+                PrimitiveType::I16 => Value::Int(le!(i16).into()),
+                PrimitiveType::I32 => Value::Int(le!(i32).into()),
+                PrimitiveType::I64 => Value::Int(le!(i64).into()),
+                PrimitiveType::I128 => Value::Int(le!(i128)),
+                PrimitiveType::F32 => Value::Float(le!(f32).into()),
+                PrimitiveType::F64 => Value::Float(le!(f64)),
+                PrimitiveType::Bool => match le!(u8) {
+                    0 => Value::Bool(false),
+                    1 => Value::Bool(true),
+                    _ => return Err(DecodeError::Invalid("bool out of range")),
+                },
+                PrimitiveType::String => {
+                    let len = cursor.u32()?;
+                    let scratch = cursor.scratch()?.base(len)?;
+                    let string = std::str::from_utf8(scratch)
+                        .map_err(|_| DecodeError::Invalid("string is not UTF-8"))?;
+                    Value::String(string.into())
+                }
+                PrimitiveType::Box(inner_ty) => {
+                    self.decode_at(inner_ty, &mut cursor.scratch()?, depth)?
+                }
+                PrimitiveType::List(item_ty) => {
+                    let len = cursor.u32()?;
+                    let mut items_cursor = cursor.scratch()?;
+                    // Zero-sized items take no room, so bound their count by the payload instead.
+                    if len.saturating_mul(self.base_len(item_ty, depth)?.max(1)) > cursor.buffer.len() {
+                        return Err(DecodeError::Truncated);
+                    }
+                    let items = (0..len)
+                        .map(|_| self.decode_at(item_ty, &mut items_cursor, depth))
+                        .collect::<Result<_, _>>()?;
+                    Value::List(items)
+                }
+                PrimitiveType::Option(item_ty) => match le!(u8) {
+                    0 => {
+                        cursor.advance(self.base_len(item_ty, depth)?)?;
+                        Value::Option(None)
+                    }
+                    1 => Value::Option(Some(Box::new(self.decode_at(item_ty, cursor, depth)?))),
+                    _ => return Err(DecodeError::Invalid("option tag out of range")),
+                },
+                PrimitiveType::Result(ok_ty, err_ty) => {
+                    let ok_len = self.base_len(ok_ty, depth)?;
+                    let err_len = self.base_len(err_ty, depth)?;
+                    match le!(u8) {
+                        0 => {
+                            let ok = self.decode_at(ok_ty, cursor, depth)?;
+                            cursor.advance(ok_len.max(err_len) - ok_len)?;
+                            Value::Result(Ok(Box::new(ok)))
+                        }
+                        1 => {
+                            let err = self.decode_at(err_ty, cursor, depth)?;
+                            cursor.advance(ok_len.max(err_len) - err_len)?;
+                            Value::Result(Err(Box::new(err)))
+                        }
+                        _ => return Err(DecodeError::Invalid("result tag out of range")),
+                    }
+                }
+            },
+            Type::Defined { ident, args } => {
+                let (type_def, bindings) = self.lookup(ident, args)?;
+                let module = ident.module.as_deref();
+                match &type_def.body {
+                    TypeBody::Struct(s) => Value::Struct {
+                        name: type_def.name.clone(),
+                        fields: self.decode_fields(&s.fields, module, &bindings, cursor, depth)?,
+                    },
+                    TypeBody::Enum(e) => {
+                        let tag = le!(u8) as usize;
+                        let (variant_name, variant) = e.variants
+                            .get(tag)
+                            .ok_or(DecodeError::Invalid("enum tag out of range"))?;
+                        let base_len = self.base_len(ty, depth)?;
+                        let fields = match variant {
+                            EnumVariant::Empty => Vec::new(),
+                            EnumVariant::NamedFields { fields } => {
+                                self.decode_fields(fields, module, &bindings, cursor, depth)?
+                            }
+                        };
+                        let variant_len = self.variant_base_len(variant, module, &bindings, depth)?;
+                        cursor.advance(base_len - 1 - variant_len)?;
+                        Value::Enum {
+                            name: type_def.name.clone(),
+                            variant: variant_name.clone(),
+                            fields,
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn decode_fields(
+        &self,
+        fields: &[NamedField],
+        module: Option<&str>,
+        bindings: &TypeBindings,
+        cursor: &mut Cursor,
+        depth: usize,
+    ) -> Result<Vec<(String, Value)>, DecodeError> {
+        fields.iter()
+            .map(|field| {
+                let ty = resolve_type(&field.ty, module, bindings);
+                Ok((field.name.clone(), self.decode_at(&ty, cursor, depth)?))
+            })
+            .collect()
+    }
+
+    fn lookup(
+        &self,
+        ident: &QualifiedIdentifier,
+        args: &[Type],
+    ) -> Result<(&'a mproto_codegen::ast::TypeDef, TypeBindings), DecodeError> {
+        let type_def = self.db.lookup_type_def(ident).ok_or_else(|| {
+            DecodeError::UnknownType(match &ident.module {
+                Some(module) => format!("{module}.{}", ident.name),
+                None => ident.name.clone(),
+            })
+        })?;
+        if type_def.params.len() != args.len() {
+            return Err(DecodeError::UnknownType(type_def.name.clone()));
+        }
+        let bindings = type_def.params.iter().cloned().zip(args.iter().cloned()).collect();
+
+        Ok((type_def, bindings))
+    }
+
+    fn base_len(&self, ty: &Type, depth: usize) -> Result<usize, DecodeError> {
+        if depth > MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        let depth = depth + 1;
+
+        let base_len = match ty {
+            Type::Primitive(primitive) => match primitive {
+                PrimitiveType::Void => 0,
+                PrimitiveType::U8 | PrimitiveType::I8 | PrimitiveType::Bool => 1,
+                PrimitiveType::U16 | PrimitiveType::I16 => 2,
+                PrimitiveType::U32 | PrimitiveType::I32 | PrimitiveType::F32 => 4,
+                PrimitiveType::U64 | PrimitiveType::I64 | PrimitiveType::F64 => 8,
+                PrimitiveType::U128 | PrimitiveType::I128 => 16,
+                PrimitiveType::String | PrimitiveType::List(_) => 8,
+                PrimitiveType::Box(_) => 4,
+                PrimitiveType::Option(item_ty) => 1 + self.base_len(item_ty, depth)?,
+                PrimitiveType::Result(ok_ty, err_ty) => {
+                    1 + self.base_len(ok_ty, depth)?.max(self.base_len(err_ty, depth)?)
+                }
+            },
+            Type::Defined { ident, args } => {
+                let (type_def, bindings) = self.lookup(ident, args)?;
+                let module = ident.module.as_deref();
+                match &type_def.body {
+                    TypeBody::Struct(s) => self.fields_base_len(&s.fields, module, &bindings, depth)?,
+                    TypeBody::Enum(e) => {
+                        let mut max_variant_len = 0;
+                        for (_, variant) in &e.variants {
+                            max_variant_len = max_variant_len
+                                .max(self.variant_base_len(variant, module, &bindings, depth)?);
+                        }
+                        // 1 extra byte for the enum tag
+                        1 + max_variant_len
+                    }
+                }
+            }
+        };
+
+        Ok(base_len)
+    }
+
+    fn variant_base_len(
+        &self,
+        variant: &EnumVariant,
+        module: Option<&str>,
+        bindings: &TypeBindings,
+        depth: usize,
+    ) -> Result<usize, DecodeError> {
+        match variant {
+            EnumVariant::Empty => Ok(0),
+            EnumVariant::NamedFields { fields } => {
+                self.fields_base_len(fields, module, bindings, depth)
+            }
+        }
+    }
+
+    fn fields_base_len(
+        &self,
+        fields: &[NamedField],
+        module: Option<&str>,
+        bindings: &TypeBindings,
+        depth: usize,
+    ) -> Result<usize, DecodeError> {
+        let mut base_len = 0;
+        for field in fields {
+            base_len += self.base_len(&resolve_type(&field.ty, module, bindings), depth)?;
+        }
+        Ok(base_len)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn fields(f: &mut fmt::Formatter<'_>, fields: &[(String, Value)]) -> fmt::Result {
+            if fields.is_empty() {
+                return Ok(());
+            }
+            write!(f, " {{ ")?;
+            for (i, (name, value)) in fields.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{name}: {value}")?;
+            }
+            write!(f, " }}")
+        }
+
+        match self {
+            Value::Void => write!(f, "()"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::UInt(n) => write!(f, "{n}"),
+            Value::Int(n) => write!(f, "{n}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::String(s) => write!(f, "{s:?}"),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Option(None) => write!(f, "None"),
+            Value::Option(Some(value)) => write!(f, "Some({value})"),
+            Value::Result(Ok(value)) => write!(f, "Ok({value})"),
+            Value::Result(Err(value)) => write!(f, "Err({value})"),
+            Value::Struct { name, fields: struct_fields } => {
+                write!(f, "{name}")?;
+                fields(f, struct_fields)
+            }
+            Value::Enum { name, variant, fields: variant_fields } => {
+                write!(f, "{name}::{variant}")?;
+                fields(f, variant_fields)
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use core::time::Duration;
+use std::{cell::RefCell, rc::Rc};
+
+use modrpc_executor::{ModrpcExecutor, SimExecutor};
+use std_modrpc::{
+    RequestClient,
+    RequestClientBuilder,
+    RequestClientConfig,
+    RequestClientRole,
+    RequestInitState,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+};
+
+fn memory_transport(link: modrpc::MemoryLink) -> modrpc::MemoryTransport {
+    modrpc::MemoryTransport {
+        in_buffer_pool: modrpc::HeapBufferPool::new(256, 16, 16),
+        out_buffer_pool: modrpc::HeapBufferPool::new(256, 16, 16),
+        worker_id: modrpc::WorkerId::local(),
+        link,
+    }
+}
+
+async fn start_server(
+    rt: &modrpc::RuntimeHandle,
+    transport: modrpc::TransportHandle,
+    handler: impl AsyncFnMut(modrpc::EndpointAddr, u32) -> u32 + 'static,
+) {
+    let _ =
+        rt.start_role::<RequestServerRole<u32, u32>>(modrpc::RoleConfig {
+            plane_id: 0,
+            endpoint_addr: modrpc::EndpointAddr { endpoint: 1 },
+            transport,
+            topic_channels: modrpc::TopicChannels::SingleChannel { channel_id: 0 },
+            config: RequestServerConfig { },
+            init: RequestInitState { },
+        })
+        .local_async(|cx| {
+            RequestServerBuilder::new("request_server", cx.hooks.clone(), cx.stubs, cx.config, *cx.init)
+                .build(cx.setup, handler);
+        }).await;
+}
+
+async fn start_client(
+    rt: &modrpc::RuntimeHandle,
+    link: modrpc::MemoryLink,
+) -> RequestClient<u32, u32> {
+    let transport = rt.add_transport(memory_transport(link)).await;
+    let mut request_client = None;
+    let _ =
+        rt.start_role::<RequestClientRole<u32, u32>>(modrpc::RoleConfig {
+            plane_id: 0,
+            endpoint_addr: modrpc::EndpointAddr { endpoint: 2 },
+            transport,
+            topic_channels: modrpc::TopicChannels::SingleChannel { channel_id: 0 },
+            config: RequestClientConfig { },
+            init: RequestInitState { },
+        })
+        .local_async(|cx| {
+            let builder = RequestClientBuilder::new("request_client", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+            request_client = Some(builder.create_handle(cx.setup));
+            builder.build(cx.setup);
+        }).await;
+    request_client.unwrap()
+}
+
+#[test]
+fn test_capture_replay() {
+    // Removed when dropped, whether or not the test passes.
+    let capture_dir = tempfile::tempdir().unwrap();
+    let capture_path = capture_dir.path().join("capture.pcapng");
+    let capture = modrpc::PacketCapture::create(&capture_path).unwrap();
+
+    let mut ex = SimExecutor::with_seed(7);
+    let (client_rt, _client_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (server_rt, _server_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .with_capture(capture.clone())
+        .start::<SimExecutor>();
+    let (replay_rt, _replay_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let network = modrpc::MemoryNetwork::new(modrpc::MemoryNetworkConfig::default());
+    let (client_link, server_link) = network.connect("client", "server");
+
+    ex.run_until(async move {
+        let transport = server_rt.add_transport(memory_transport(server_link)).await;
+        start_server(&server_rt, transport, async |_source, request: u32| request * 2).await;
+        let request_client = start_client(&client_rt, client_link).await;
+        for request in 1..=3u32 {
+            assert_eq!(request_client.call(request).await, request * 2);
+        }
+        capture.flush().unwrap();
+
+        let records = modrpc::CaptureReader::open(&capture_path)
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert!(records.iter().all(|record| record.transport_id == 0));
+        assert!(records.iter().any(|record| record.direction == modrpc::CaptureDirection::Outbound));
+
+        // Replaying the requests the server received runs them through a new server.
+        let replayed = Rc::new(RefCell::new(Vec::new()));
+        let transport = replay_rt.add_transport(modrpc::LocalTransport {
+            buffer_size: 256,
+            buffer_pool_batches: 16,
+            buffer_pool_batch_size: 16,
+        })
+        .await;
+        start_server(&replay_rt, transport.clone(), {
+            let replayed = replayed.clone();
+            async move |_source, request: u32| {
+                replayed.borrow_mut().push(request);
+                request * 2
+            }
+        }).await;
+        let inbound = records.into_iter()
+            .filter(|record| record.direction == modrpc::CaptureDirection::Inbound);
+        modrpc::replay_capture(replay_rt.local_worker_context().unwrap(), &transport, inbound)
+            .await
+            .unwrap();
+        SimExecutor::sleep(Duration::from_millis(1)).await;
+        assert_eq!(*replayed.borrow(), [1, 2, 3]);
+    });
+}
@@ -6,9 +6,15 @@ use core::{
     time::Duration,
     task::{Context, Poll},
 };
-#[cfg(feature = "std")]
+#[cfg(any(feature = "futures-executor", feature = "tokio"))]
 use std::rc::Rc;
 
+#[cfg(feature = "sim")]
+mod sim;
+
+#[cfg(feature = "sim")]
+pub use sim::{SimExecutor, SimInterval, SimSleep, SimSleeper};
+
 pub trait ModrpcExecutor {
     type Sleep: Future<Output = ()> + 'static;
     type Interval: Interval + 'static;
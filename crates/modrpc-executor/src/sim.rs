@@ -0,0 +1,493 @@
+use crate::{Interval, ModrpcExecutor, Sleeper};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    rc::{Rc, Weak},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::Wake,
+    thread::Thread,
+};
+
+const DEFAULT_SEED: u64 = 0x6d6f_6472_7063_7369;
+const MAIN_TASK_ID: u64 = 0;
+
+std::thread_local! {
+    static CURRENT_SIM: RefCell<Weak<SimState>> = const { RefCell::new(Weak::new()) };
+}
+
+/// A single-threaded executor for deterministic tests. Time is virtual: when no task is ready to
+/// run, the clock jumps straight to the earliest pending timer. The next task to poll is picked
+/// from the ready tasks by an RNG, so a given seed always produces the same interleaving.
+///
+/// The timers created by `ModrpcExecutor::sleep`, `interval` and `new_sleeper` belong to the most
+/// recently created `SimExecutor` on the calling thread.
+pub struct SimExecutor {
+    state: Rc<SimState>,
+}
+
+type TimerKey = (Duration, u64);
+
+struct SimState {
+    seed: u64,
+    rng: Cell<u64>,
+    now: Cell<Duration>,
+    next_timer_seq: Cell<u64>,
+    timers: RefCell<BTreeMap<TimerKey, Waker>>,
+    next_task_id: Cell<u64>,
+    tasks: RefCell<HashMap<u64, Task>>,
+    ready: Arc<ReadyQueue>,
+}
+
+struct Task {
+    future: Option<Pin<Box<dyn Future<Output = ()>>>>,
+    waker: Arc<TaskWaker>,
+}
+
+struct ReadyQueue {
+    task_ids: Mutex<Vec<u64>>,
+    thread: Thread,
+}
+
+struct TaskWaker {
+    task_id: u64,
+    scheduled: AtomicBool,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.ready
+                .task_ids
+                .lock()
+                .expect("modrpc SimExecutor ready queue lock poisoned")
+                .push(self.task_id);
+            self.ready.thread.unpark();
+        }
+    }
+}
+
+impl SimExecutor {
+    pub fn with_seed(seed: u64) -> Self {
+        let state = Rc::new(SimState {
+            seed,
+            rng: Cell::new(seed),
+            now: Cell::new(Duration::ZERO),
+            next_timer_seq: Cell::new(0),
+            timers: RefCell::new(BTreeMap::new()),
+            next_task_id: Cell::new(MAIN_TASK_ID + 1),
+            tasks: RefCell::new(HashMap::new()),
+            ready: Arc::new(ReadyQueue {
+                task_ids: Mutex::new(Vec::new()),
+                thread: std::thread::current(),
+            }),
+        });
+        CURRENT_SIM.with(|current| *current.borrow_mut() = Rc::downgrade(&state));
+
+        Self { state }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.state.seed
+    }
+
+    /// Virtual time elapsed since the calling thread's current simulation was created.
+    pub fn now() -> Duration {
+        current_sim().now.get()
+    }
+
+    /// The number of spawned tasks that haven't completed yet.
+    pub fn task_count(&self) -> usize {
+        self.state.tasks.borrow().len()
+    }
+}
+
+impl Drop for SimExecutor {
+    fn drop(&mut self) {
+        // Tasks commonly hold spawners that point back at the executor - drop them outside of the
+        // borrow so their destructors can still reach the timers.
+        let tasks = core::mem::take(&mut *self.state.tasks.borrow_mut());
+        drop(tasks);
+        self.state.timers.borrow_mut().clear();
+
+        CURRENT_SIM.with(|current| {
+            let mut current = current.borrow_mut();
+            if core::ptr::eq(current.as_ptr(), Rc::as_ptr(&self.state)) {
+                *current = Weak::new();
+            }
+        });
+    }
+}
+
+impl SimState {
+    fn next_random(&self) -> u64 {
+        // SplitMix64
+        let mut z = self.rng.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.rng.set(z);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn new_waker(&self, task_id: u64) -> Arc<TaskWaker> {
+        Arc::new(TaskWaker {
+            task_id,
+            scheduled: AtomicBool::new(false),
+            ready: self.ready.clone(),
+        })
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()>>>) {
+        let task_id = self.next_task_id.get();
+        self.next_task_id.set(task_id + 1);
+
+        let waker = self.new_waker(task_id);
+        waker.wake_by_ref();
+        self.tasks.borrow_mut().insert(task_id, Task { future: Some(future), waker });
+    }
+
+    /// Remove a random task from the ready queue.
+    fn pop_ready(&self) -> Option<u64> {
+        let mut task_ids = self.ready
+            .task_ids
+            .lock()
+            .expect("modrpc SimExecutor ready queue lock poisoned");
+        if task_ids.is_empty() {
+            return None;
+        }
+        let index = (self.next_random() % task_ids.len() as u64) as usize;
+        Some(task_ids.swap_remove(index))
+    }
+
+    fn poll_task(&self, task_id: u64) {
+        let Some((mut future, waker)) = self.tasks
+            .borrow_mut()
+            .get_mut(&task_id)
+            .and_then(|task| Some((task.future.take()?, task.waker.clone())))
+        else {
+            return;
+        };
+
+        waker.scheduled.store(false, Ordering::Release);
+        let waker = Waker::from(waker);
+        let mut cx = Context::from_waker(&waker);
+        if future.as_mut().poll(&mut cx).is_ready() {
+            self.tasks.borrow_mut().remove(&task_id);
+        } else if let Some(task) = self.tasks.borrow_mut().get_mut(&task_id) {
+            task.future = Some(future);
+        }
+    }
+
+    /// Advance the clock to the earliest timer and wake every timer that is due. Returns false if
+    /// there are no timers.
+    fn advance_clock(&self) -> bool {
+        let due = {
+            let mut timers = self.timers.borrow_mut();
+            let Some(&(deadline, _)) = timers.keys().next() else {
+                return false;
+            };
+            self.now.set(self.now.get().max(deadline));
+
+            let later = timers.split_off(&(self.now.get(), u64::MAX));
+            core::mem::replace(&mut *timers, later)
+        };
+        for (_, waker) in due {
+            waker.wake();
+        }
+
+        true
+    }
+
+    fn add_timer(&self, deadline: Duration, waker: Waker) -> TimerKey {
+        let seq = self.next_timer_seq.get();
+        self.next_timer_seq.set(seq + 1);
+        self.timers.borrow_mut().insert((deadline, seq), waker);
+        (deadline, seq)
+    }
+}
+
+fn current_sim() -> Rc<SimState> {
+    CURRENT_SIM
+        .with(|current| current.borrow().upgrade())
+        .expect("modrpc SimExecutor timer used on a thread without a simulation")
+}
+
+impl ModrpcExecutor for SimExecutor {
+    type Sleep = SimSleep;
+    type Interval = SimInterval;
+    type Sleeper = SimSleeper;
+
+    fn new() -> Self {
+        Self::with_seed(DEFAULT_SEED)
+    }
+
+    fn spawner(&mut self) -> ispawn::LocalSpawner {
+        ispawn::LocalSpawner::new(SimSpawner(self.state.clone()))
+    }
+
+    fn run_until<R>(&mut self, future: impl Future<Output = R>) -> R {
+        let mut future = core::pin::pin!(future);
+        let main_waker = self.state.new_waker(MAIN_TASK_ID);
+        main_waker.wake_by_ref();
+
+        loop {
+            let Some(task_id) = self.state.pop_ready() else {
+                if !self.state.advance_clock() {
+                    // Nothing can make progress until some other thread wakes one of our tasks.
+                    std::thread::park();
+                }
+                continue;
+            };
+
+            if task_id == MAIN_TASK_ID {
+                main_waker.scheduled.store(false, Ordering::Release);
+                let waker = Waker::from(main_waker.clone());
+                if let Poll::Ready(result) = future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                    return result;
+                }
+            } else {
+                self.state.poll_task(task_id);
+            }
+        }
+    }
+
+    fn sleep(duration: Duration) -> Self::Sleep {
+        let sim = current_sim();
+        let deadline = sim.now.get() + duration;
+        SimSleep::until(&sim, deadline)
+    }
+
+    fn interval(period: Duration) -> Self::Interval {
+        let sim = current_sim();
+        SimInterval {
+            sim: Rc::downgrade(&sim),
+            next_tick: sim.now.get(),
+            period,
+        }
+    }
+
+    fn new_sleeper() -> Self::Sleeper {
+        SimSleeper { current_sleep: None }
+    }
+}
+
+struct SimSpawner(Rc<SimState>);
+
+impl ispawn::IntoLocalSpawner for SimSpawner {
+    unsafe fn into_handle(self) -> *const () {
+        Rc::into_raw(self.0) as *const ()
+    }
+
+    unsafe fn spawn_dyn(
+        _: *const (),
+        builder: ispawn::SpawnCompleterBuilder,
+        future_layout: std::alloc::Layout,
+    ) -> ispawn::SpawnCompleter {
+        let future_ptr = unsafe { std::alloc::alloc(future_layout) } as *mut ();
+        builder.build(future_ptr, future_ptr)
+    }
+
+    unsafe fn finish_spawn(
+        handle: *const (),
+        task_ptr_as_dyn_future: *mut dyn Future<Output = ()>,
+    ) -> ispawn::Result<()> {
+        let future: Box<dyn Future<Output = ()>> =
+            unsafe { Box::from_raw(task_ptr_as_dyn_future) };
+        let sim = unsafe { &*(handle as *const SimState) };
+        sim.spawn(Box::into_pin(future));
+        Ok(())
+    }
+
+    unsafe fn on_clone(handle: *const ()) {
+        unsafe { Rc::increment_strong_count(handle as *const SimState) };
+    }
+
+    unsafe fn on_drop(handle: *const ()) {
+        unsafe { drop(Rc::from_raw(handle as *const SimState)) };
+    }
+}
+
+/// Completes once the simulation's clock reaches its deadline.
+pub struct SimSleep {
+    sim: Weak<SimState>,
+    deadline: Duration,
+    timer: Option<TimerKey>,
+}
+
+impl SimSleep {
+    fn until(sim: &Rc<SimState>, deadline: Duration) -> Self {
+        Self { sim: Rc::downgrade(sim), deadline, timer: None }
+    }
+}
+
+impl Future for SimSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // A finished simulation never wakes its sleepers.
+        let Some(sim) = self.sim.upgrade() else {
+            return Poll::Pending;
+        };
+
+        if sim.now.get() >= self.deadline {
+            if let Some(timer) = self.timer.take() {
+                sim.timers.borrow_mut().remove(&timer);
+            }
+            return Poll::Ready(());
+        }
+
+        match self.timer {
+            Some(timer) => {
+                sim.timers.borrow_mut().insert(timer, cx.waker().clone());
+            }
+            None => {
+                self.timer = Some(sim.add_timer(self.deadline, cx.waker().clone()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for SimSleep {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer
+            && let Some(sim) = self.sim.upgrade()
+            && let Ok(mut timers) = sim.timers.try_borrow_mut()
+        {
+            timers.remove(&timer);
+        }
+    }
+}
+
+/// Ticks immediately, then once every period of virtual time.
+pub struct SimInterval {
+    sim: Weak<SimState>,
+    next_tick: Duration,
+    period: Duration,
+}
+
+impl Interval for SimInterval {
+    async fn tick(&mut self) {
+        let Some(sim) = self.sim.upgrade() else {
+            return core::future::pending().await;
+        };
+        let deadline = self.next_tick;
+        self.next_tick = deadline.max(sim.now.get()) + self.period;
+
+        SimSleep::until(&sim, deadline).await;
+    }
+}
+
+pub struct SimSleeper {
+    current_sleep: Option<SimSleep>,
+}
+
+impl Sleeper for SimSleeper {
+    fn snooze(self: Pin<&mut Self>, duration: Duration) -> bool {
+        let current_sleep = &mut self.get_mut().current_sleep;
+        if current_sleep.is_none() {
+            *current_sleep = Some(SimExecutor::sleep(duration));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn poll_sleep(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if let Some(sleep) = &mut this.current_sleep {
+            match Pin::new(sleep).poll(cx) {
+                Poll::Ready(()) => {
+                    this.current_sleep = None;
+                    Poll::Ready(())
+                }
+                Poll::Pending => Poll::Pending,
+            }
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn run_interleaving(seed: u64) -> (Vec<u32>, Duration) {
+        let mut ex = SimExecutor::with_seed(seed);
+        let spawner = ex.spawner();
+        let order = Rc::new(RefCell::new(Vec::new()));
+
+        let (done_tx, mut done_rx) = localq::mpsc::channel(1);
+        for task in 0..8 {
+            let order = order.clone();
+            let done_tx = done_tx.clone();
+            spawner
+                .spawn(async move {
+                    for _ in 0..4 {
+                        order.borrow_mut().push(task);
+                        core::future::ready(()).await;
+                        SimExecutor::sleep(Duration::from_millis(10)).await;
+                    }
+                    done_tx.send(()).await.unwrap();
+                })
+                .unwrap();
+        }
+
+        let elapsed = ex.run_until(async move {
+            for _ in 0..8 {
+                done_rx.recv().await.unwrap();
+            }
+            SimExecutor::now()
+        });
+        assert_eq!(ex.task_count(), 0);
+
+        (order.take(), elapsed)
+    }
+
+    #[test]
+    fn test_virtual_time() {
+        let mut ex = SimExecutor::new();
+        let elapsed = ex.run_until(async {
+            SimExecutor::sleep(Duration::from_secs(3600)).await;
+
+            let mut interval = SimExecutor::interval(Duration::from_secs(1));
+            for _ in 0..5 {
+                interval.tick().await;
+            }
+
+            let mut sleeper = Box::pin(SimExecutor::new_sleeper());
+            assert!(sleeper.as_mut().snooze(Duration::from_millis(500)));
+            assert!(!sleeper.as_mut().snooze(Duration::from_millis(500)));
+            core::future::poll_fn(|cx| sleeper.as_mut().poll_sleep(cx)).await;
+
+            SimExecutor::now()
+        });
+
+        assert_eq!(elapsed, Duration::from_millis(3_604_500));
+    }
+
+    #[test]
+    fn test_seeded_interleaving() {
+        let (order, elapsed) = run_interleaving(1);
+        assert_eq!(order.len(), 32);
+        assert_eq!(elapsed, Duration::from_millis(40));
+
+        assert_eq!(run_interleaving(1).0, order);
+        assert!((2..10).any(|seed| run_interleaving(seed).0 != order));
+    }
+}
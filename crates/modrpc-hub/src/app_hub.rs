@@ -1,30 +1,85 @@
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
+    collections::HashMap,
     net::SocketAddr,
     rc::Rc,
 };
 
-use modrpc::{EndpointAddr, PlaneHandshake};
+use futures_util::future::LocalBoxFuture;
+
+use modrpc::{
+    CompressionConfig, EndpointAddr, InterfaceBuilder, InterfaceSchema, KeepaliveConfig,
+    PlaneHandshake,
+};
 
 use crate::{
-    Broadcaster, BroadcasterHandle, ChannelId, LocalHubTransport,
+    Broadcaster, BroadcasterHandle, ChannelId, ConnectionLimits, ConnectionStats,
+    HubShutdownConfig, IngressPolicy, LocalHubTransport, SpokeEgressConfig, SpokeHandle,
+    TransportIndex,
 };
+use crate::limits::ConnectionTracker;
+use crate::shutdown::TaskTracker;
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+use crate::shutdown::wait_notified;
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+use crate::limits::ConnectionPermit;
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+use crate::SpokeConfig;
 
 #[cfg(feature = "tcp-transport")]
-use crate::spawn_tcp_spoke;
+use std::net::IpAddr;
+#[cfg(feature = "tcp-transport")]
+use crate::{spawn_tcp_spoke, Federation, FederationConfig};
 #[cfg(feature = "websocket-transport")]
 use crate::spawn_websocket_spoke;
 
-pub trait AppHubDelegate {
+/// How long to wait before accepting again after a listener fails to accept, e.g. because the
+/// process ran out of file descriptors.
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+const ACCEPT_ERROR_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+
+
+pub trait AppHubDelegate: Sized {
     type Init<'a>: mproto::Encode;
+    /// Credentials each client sends before it's assigned a plane when `CLIENT_HELLO` is set -
+    /// see `modrpc::tcp_client_hello`. Hubs that don't ask for a hello can use `()`.
+    type Hello<'a>: mproto::Decode<'a>;
+    /// Who an authenticated client is.
+    type Identity: 'static;
+
+    /// Whether clients must send a hello before they're assigned a plane. Clients that connect
+    /// with a plain `modrpc::tcp_connect` don't send one, so they can only join hubs that leave
+    /// this unset.
+    const CLIENT_HELLO: bool = false;
+
+    /// Accept a client, or reject it with a reason that's sent back to it. `hello` holds the
+    /// client's credentials if `CLIENT_HELLO` is set and is `None` otherwise.
+    #[allow(async_fn_in_trait)]
+    async fn authenticate(
+        &self,
+        endpoint_addr: modrpc::EndpointAddr,
+        hello: Option<Self::Hello<'_>>,
+    ) -> Result<Self::Identity, String>;
 
+    /// Choose the plane an authenticated client joins and send it the plane's init by calling
+    /// `handshake_fn(plane_id, init)`. The client is disconnected if this returns without calling
+    /// `handshake_fn`. Planes can be started on demand with `AppHub::start_plane`.
     #[allow(async_fn_in_trait)]
     async fn client_handshake(
         &self,
+        hub: &AppHub<Self>,
         endpoint_addr: modrpc::EndpointAddr,
-        handshake_fn: impl for<'a> AsyncFnOnce(Self::Init<'a>) -> std::io::Result<()>,
+        identity: &Self::Identity,
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, Self::Init<'a>) -> std::io::Result<()>,
     ) -> std::io::Result<()>;
 
+    /// Roles of a plane's interface that a client may act as. The hub drops bundles carrying
+    /// events none of these roles may send. `None` lets the client send any event. Only takes
+    /// effect on planes whose interface the hub knows - see `AppHub::declare_plane_interface`.
+    fn client_roles(&self, _plane_id: u32, _identity: &Self::Identity) -> Option<Vec<&'static str>> {
+        None
+    }
+
     #[allow(async_fn_in_trait)]
     async fn client_disconnected(&self, endpoint_addr: modrpc::EndpointAddr);
 }
@@ -32,10 +87,24 @@ pub trait AppHubDelegate {
 pub struct AppHubBuilder {
     buffer_pool: modrpc::HeapBufferPool,
     rt: modrpc::RuntimeHandle,
+    hub_id: u32,
     max_packet_size: usize,
-    broadcaster_worker: modrpc::WorkerId,
+    broadcaster_workers: Vec<modrpc::WorkerId>,
+    keepalive: Option<KeepaliveConfig>,
+    compression: Option<CompressionConfig>,
+    spoke_egress: SpokeEgressConfig,
+    connection_limits: ConnectionLimits,
+    plane_channels: HashMap<u32, modrpc::TopicChannels>,
     #[cfg(feature = "tcp-transport")]
     tcp_bind_addr: Option<SocketAddr>,
+    #[cfg(feature = "tcp-transport")]
+    federation_bind_addr: Option<SocketAddr>,
+    #[cfg(feature = "tcp-transport")]
+    federation_peers: Option<Vec<IpAddr>>,
+    #[cfg(feature = "tcp-transport")]
+    upstream_addr: Option<SocketAddr>,
+    #[cfg(feature = "tcp-transport")]
+    federated_plane_ids: Vec<u32>,
     #[cfg(feature = "websocket-transport")]
     websocket_bind_addr: Option<SocketAddr>,
 }
@@ -49,23 +118,96 @@ impl AppHubBuilder {
         Self {
             buffer_pool,
             rt,
+            hub_id: 0,
             max_packet_size,
-            broadcaster_worker: modrpc::WorkerId::local(),
+            broadcaster_workers: vec![modrpc::WorkerId::local()],
+            keepalive: None,
+            compression: None,
+            spoke_egress: SpokeEgressConfig::default(),
+            connection_limits: ConnectionLimits::default(),
+            plane_channels: HashMap::new(),
             #[cfg(feature = "tcp-transport")]
             tcp_bind_addr: None,
+            #[cfg(feature = "tcp-transport")]
+            federation_bind_addr: None,
+            #[cfg(feature = "tcp-transport")]
+            federation_peers: None,
+            #[cfg(feature = "tcp-transport")]
+            upstream_addr: None,
+            #[cfg(feature = "tcp-transport")]
+            federated_plane_ids: Vec::new(),
             #[cfg(feature = "websocket-transport")]
             websocket_bind_addr: None,
         }
     }
 
+    /// Identifies this hub among federated hubs. Endpoint addresses assigned by the hub are
+    /// prefixed with it so they stay unique across the federation.
+    pub fn hub_id(mut self, hub_id: u32) -> Self {
+        self.hub_id = hub_id;
+        self
+    }
+
     pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
         assert!(max_packet_size <= self.buffer_pool.buffer_size());
         self.max_packet_size = max_packet_size;
         self
     }
 
+    /// Run the broadcaster on a dedicated worker instead of the worker that builds the hub.
     pub fn broadcaster_worker(mut self, worker_id: modrpc::WorkerId) -> Self {
-        self.broadcaster_worker = worker_id;
+        self.broadcaster_workers = vec![worker_id];
+        self
+    }
+
+    /// Shard the broadcaster by channel ID across every worker in a group.
+    pub fn broadcaster_worker_group(mut self, worker_group: modrpc::WorkerGroup) -> Self {
+        self.broadcaster_workers = worker_group.worker_ids().collect();
+        self
+    }
+
+    /// Send keepalives to every spoke and disconnect spokes that go silent for between one and two
+    /// idle timeouts. Clients must send keepalives at an interval shorter than the idle timeout -
+    /// see `modrpc::TransportOptions::keepalive`.
+    pub fn keepalive(mut self, keepalive: KeepaliveConfig) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Compress bundles sent to spokes that negotiate compression. Requires the `compression`
+    /// feature - without it the hub advertises no algorithms and clients never receive compressed
+    /// bundles.
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Size and overflow policy of the queue of outgoing bundles buffered for each spoke.
+    pub fn spoke_egress(mut self, spoke_egress: SpokeEgressConfig) -> Self {
+        self.spoke_egress = spoke_egress;
+        self
+    }
+
+    /// Limit how many clients may connect, how long they may take to handshake, and how fast
+    /// they may send.
+    pub fn connection_limits(mut self, connection_limits: ConnectionLimits) -> Self {
+        self.connection_limits = connection_limits;
+        self
+    }
+
+    /// Relay a plane over the channels of `topic_channels` instead of the single channel with the
+    /// same ID as the plane, so e.g. bulk transfers don't hold up requests. Clients of the plane and
+    /// roles started on it with `AppHub::start_plane` send on these channels - clients must be
+    /// connected with the same `modrpc::TransportOptions::topic_channels`. Channel IDs must be
+    /// distinct across planes, and no higher than `modrpc::MAX_CHANNEL_ID`.
+    pub fn plane_channels(mut self, plane_id: u32, topic_channels: modrpc::TopicChannels) -> Self {
+        for channel_id in topic_channels.channel_ids() {
+            assert!(
+                channel_id <= modrpc::MAX_CHANNEL_ID,
+                "channel {channel_id:#x} of plane {plane_id:08x} is above modrpc::MAX_CHANNEL_ID",
+            );
+        }
+        self.plane_channels.insert(plane_id, topic_channels);
         self
     }
 
@@ -75,278 +217,872 @@ impl AppHubBuilder {
         self
     }
 
+    /// Accept federation links from downstream hubs. Linked hubs may send anything on the
+    /// federated planes, so unless `allow_federation_peers` is used, `bind_addr` should only be
+    /// reachable from a private network.
+    #[cfg(feature = "tcp-transport")]
+    pub fn with_federation(mut self, bind_addr: SocketAddr) -> Self {
+        self.federation_bind_addr = Some(bind_addr);
+        self
+    }
+
+    /// Only accept federation links from these addresses.
+    #[cfg(feature = "tcp-transport")]
+    pub fn allow_federation_peers(mut self, peers: impl IntoIterator<Item = IpAddr>) -> Self {
+        self.federation_peers = Some(peers.into_iter().collect());
+        self
+    }
+
+    /// Link to another hub's federation listener as one of its spokes, exchanging bundles with
+    /// every hub reachable through it. Each hub has at most one upstream, and every hub in the
+    /// federation needs a distinct `hub_id`.
+    #[cfg(feature = "tcp-transport")]
+    pub fn connect_upstream(mut self, upstream_addr: SocketAddr) -> Self {
+        self.upstream_addr = Some(upstream_addr);
+        self
+    }
+
+    /// Exchange a plane's bundles with federated hubs.
+    #[cfg(feature = "tcp-transport")]
+    pub fn federate_plane(mut self, plane_id: u32) -> Self {
+        self.federated_plane_ids.push(plane_id);
+        self
+    }
+
     #[cfg(feature = "websocket-transport")]
     pub fn with_websocket(mut self, bind_addr: SocketAddr) -> Self {
         self.websocket_bind_addr = Some(bind_addr);
         self
     }
 
-    pub async fn build<Role, Delegate>(
-        self,
-        delegate: Delegate,
-        config: Role::Config,
-        init: Role::Init,
-    ) -> modrpc::StartRoleHandle<Role>
+    pub async fn build<Delegate>(self, delegate: Delegate) -> AppHub<Delegate>
     where
-        Role: modrpc::InterfaceRole,
         Delegate: AppHubDelegate + 'static,
-        for<'a> Delegate::Init<'a>: mproto::Compatible<Role::Init>,
     {
-        // TODO support running the broadcaster on a separate thread
-        /*let broadcaster_handle = self.rt.get_worker(self.broadcaster_worker).run_once(|worker_cx| {
-            let mut broadcaster = Broadcaster::new(64);
-            let broadcaster_handle = broadcaster.handle();
-            worker_cx.spawn(async move {
-                broadcaster.run().await;
-            });
-            broadcaster_handle
-        })
-        .await;*/
+        assert!(
+            self.rt.local_worker_context().is_some(),
+            "modrpc_hub::AppHubBuilder::build must run on a modrpc worker",
+        );
 
-        let worker_cx = self.rt.local_worker_context()
-            .expect("modrpc_hub::AppHubBuilder::build must run on a modrpc worker");
+        let broadcaster_handle =
+            Broadcaster::spawn_sharded(&self.rt, self.broadcaster_workers.iter().copied(), 64).await;
 
-        let mut broadcaster = Broadcaster::new(64);
-        let broadcaster_handle = broadcaster.handle();
-        worker_cx.spawn(async move {
-            broadcaster.run().await;
-        });
+        // Bind every listener up front so their addresses are known once the hub is built.
+        #[cfg(feature = "tcp-transport")]
+        let tcp_listener = match self.tcp_bind_addr {
+            Some(bind_addr) => Some(bind_listener(bind_addr).await),
+            None => None,
+        };
+        #[cfg(feature = "websocket-transport")]
+        let websocket_listener = match self.websocket_bind_addr {
+            Some(bind_addr) => Some(bind_listener(bind_addr).await),
+            None => None,
+        };
+        #[cfg(feature = "tcp-transport")]
+        let federation_listener = match self.federation_bind_addr {
+            Some(bind_addr) => Some(bind_listener(bind_addr).await),
+            None => None,
+        };
 
-        let transport = self.rt.add_transport(LocalHubTransport::Static {
-            buffer_pool: self.buffer_pool.clone(),
-            broadcaster_handle: broadcaster_handle.clone(),
-            channel_ids: vec![0x42424242],
-        })
-        .await;
+        #[cfg(feature = "tcp-transport")]
+        let federation = (federation_listener.is_some() || self.upstream_addr.is_some()).then(|| {
+            Federation::new(
+                FederationConfig {
+                    hub_id: self.hub_id,
+                    channel_ids: self.federated_plane_ids.iter()
+                        .flat_map(|&plane_id| plane_channels(&self.plane_channels, plane_id).channel_ids())
+                        .collect(),
+                    buffer_pool: self.buffer_pool.clone(),
+                    max_packet_size: self.max_packet_size,
+                    keepalive: self.keepalive,
+                    compression: self.compression,
+                    spoke_egress: self.spoke_egress,
+                    allowed_peers: self.federation_peers.clone(),
+                },
+                broadcaster_handle.clone(),
+            )
+        });
 
+        let hub_endpoint_id = (self.hub_id as u64) << 32;
+        let hub = AppHub {
+            inner: Rc::new(AppHubInner {
+                rt: self.rt.clone(),
+                buffer_pool: self.buffer_pool.clone(),
+                broadcaster_handle: broadcaster_handle.clone(),
+                hub_endpoint_addr: EndpointAddr { endpoint: hub_endpoint_id },
+                next_endpoint_id: Cell::new(hub_endpoint_id + 1),
+                connections: ConnectionTracker::new(self.connection_limits, self.rt.metrics()),
+                shutdown_signal: bab::SignalTree::new(),
+                tasks: TaskTracker::new(),
+                spokes: RefCell::new(HashMap::new()),
+                plane_interfaces: RefCell::new(HashMap::new()),
+                plane_transports: RefCell::new(Vec::new()),
+                max_packet_size: self.max_packet_size,
+                keepalive: self.keepalive,
+                compression: self.compression,
+                spoke_egress: self.spoke_egress,
+                plane_channels: self.plane_channels,
+                #[cfg(feature = "tcp-transport")]
+                tcp_addr: tcp_listener.as_ref().and_then(|listener| listener.local_addr().ok()),
+                #[cfg(feature = "websocket-transport")]
+                websocket_addr: websocket_listener.as_ref()
+                    .and_then(|listener| listener.local_addr().ok()),
+                #[cfg(feature = "tcp-transport")]
+                federation_addr: federation_listener.as_ref()
+                    .and_then(|listener| listener.local_addr().ok()),
+                #[cfg(feature = "tcp-transport")]
+                federation: federation.clone(),
+            }),
+            identities: Rc::new(RefCell::new(HashMap::new())),
+            memberships: Rc::new(RefCell::new(HashMap::new())),
+        };
         let delegate = Rc::new(delegate);
-        let next_endpoint_id = Rc::new(Cell::new(1));
 
         #[cfg(feature = "tcp-transport")]
-        if let Some(tcp_bind_addr) = self.tcp_bind_addr {
-            spawn_hub_tcp(
-                self.buffer_pool.clone(),
-                self.rt.clone(),
-                broadcaster_handle.clone(),
-                self.max_packet_size,
-                next_endpoint_id.clone(),
-                tcp_bind_addr,
-                delegate.clone(),
-            );
+        if let Some(federation) = &federation {
+            let worker_cx = self.rt.local_worker_context()
+                .expect("modrpc_hub::AppHubBuilder::build must run on a modrpc worker");
+            if let Some(upstream_addr) = self.upstream_addr {
+                federation.spawn_upstream(worker_cx, upstream_addr);
+            }
+            if let Some(federation_listener) = federation_listener {
+                federation.spawn_listener(worker_cx, federation_listener);
+            }
+        }
+
+        #[cfg(feature = "tcp-transport")]
+        if let Some(tcp_listener) = tcp_listener {
+            spawn_hub_tcp(hub.clone(), tcp_listener, delegate.clone());
         }
         #[cfg(feature = "websocket-transport")]
-        if let Some(websocket_bind_addr) = self.websocket_bind_addr {
-            spawn_hub_websocket(
-                self.buffer_pool.clone(),
-                self.rt.clone(),
-                broadcaster_handle.clone(),
-                self.max_packet_size,
-                next_endpoint_id.clone(),
-                websocket_bind_addr,
-                delegate.clone(),
-            );
+        if let Some(websocket_listener) = websocket_listener {
+            spawn_hub_websocket(hub.clone(), websocket_listener, delegate.clone());
         }
 
-        self.rt.start_role(modrpc::RoleConfig {
-            plane_id: 0x42424242,
-            endpoint_addr: modrpc::EndpointAddr { endpoint: 0 },
+        hub
+    }
+}
+
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+async fn bind_listener(bind_addr: SocketAddr) -> tokio::net::TcpListener {
+    tokio::net::TcpListener::bind(bind_addr).await
+        .unwrap_or_else(|e| panic!("modrpc_hub failed to listen on {bind_addr}: {e}"))
+}
+
+fn plane_channels(
+    plane_channels: &HashMap<u32, modrpc::TopicChannels>,
+    plane_id: u32,
+) -> modrpc::TopicChannels {
+    plane_channels.get(&plane_id).cloned()
+        .unwrap_or(modrpc::TopicChannels::SingleChannel { channel_id: plane_id })
+}
+
+/// A running hub. Each plane the hub hosts is a separate interface instance whose clients are
+/// broadcast to over a channel with the same ID as the plane, or the channels set with
+/// `AppHubBuilder::plane_channels`.
+pub struct AppHub<Delegate: AppHubDelegate> {
+    inner: Rc<AppHubInner>,
+    identities: Rc<RefCell<HashMap<EndpointAddr, Rc<Delegate::Identity>>>>,
+    memberships: Rc<RefCell<HashMap<u32, Rc<HostedMembershipDyn<Delegate>>>>>,
+}
+
+type HostedMembershipDyn<Delegate> =
+    dyn HostedMembership<<Delegate as AppHubDelegate>::Identity>;
+
+trait HostedMembership<Identity> {
+    fn join<'a>(&'a self, endpoint_addr: EndpointAddr, identity: &Identity) -> LocalBoxFuture<'a, ()>;
+    fn leave(&self, endpoint_addr: EndpointAddr) -> LocalBoxFuture<'_, ()>;
+}
+
+struct MembershipHostWithMetadata<M, MetadataFn> {
+    host: std_modrpc::MembershipHost<M>,
+    metadata_fn: MetadataFn,
+}
+
+impl<Identity, M, MetadataFn> HostedMembership<Identity> for MembershipHostWithMetadata<M, MetadataFn>
+where
+    M: mproto::Owned + Clone,
+    MetadataFn: Fn(&Identity) -> M,
+{
+    fn join<'a>(&'a self, endpoint_addr: EndpointAddr, identity: &Identity) -> LocalBoxFuture<'a, ()> {
+        let metadata = (self.metadata_fn)(identity);
+        Box::pin(self.host.join(endpoint_addr.endpoint, metadata))
+    }
+
+    fn leave(&self, endpoint_addr: EndpointAddr) -> LocalBoxFuture<'_, ()> {
+        Box::pin(self.host.leave(endpoint_addr.endpoint))
+    }
+}
+
+struct AppHubInner {
+    rt: modrpc::RuntimeHandle,
+    buffer_pool: modrpc::HeapBufferPool,
+    broadcaster_handle: BroadcasterHandle,
+    hub_endpoint_addr: EndpointAddr,
+    next_endpoint_id: Cell<u64>,
+    connections: Rc<ConnectionTracker>,
+    shutdown_signal: bab::SignalTree,
+    tasks: Rc<TaskTracker>,
+    spokes: RefCell<HashMap<TransportIndex, SpokeHandle>>,
+    plane_interfaces: RefCell<HashMap<u32, Rc<InterfaceBuilder>>>,
+    // Transports of the planes started with `start_plane` - their roles stop when these do.
+    plane_transports: RefCell<Vec<modrpc::TransportHandle>>,
+    max_packet_size: usize,
+    keepalive: Option<KeepaliveConfig>,
+    compression: Option<CompressionConfig>,
+    spoke_egress: SpokeEgressConfig,
+    plane_channels: HashMap<u32, modrpc::TopicChannels>,
+    #[cfg(feature = "tcp-transport")]
+    tcp_addr: Option<SocketAddr>,
+    #[cfg(feature = "websocket-transport")]
+    websocket_addr: Option<SocketAddr>,
+    #[cfg(feature = "tcp-transport")]
+    federation_addr: Option<SocketAddr>,
+    #[cfg(feature = "tcp-transport")]
+    federation: Option<Rc<Federation>>,
+}
+
+impl<Delegate: AppHubDelegate> Clone for AppHub<Delegate> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            identities: self.identities.clone(),
+            memberships: self.memberships.clone(),
+        }
+    }
+}
+
+impl<Delegate: AppHubDelegate> AppHub<Delegate> {
+    pub fn broadcaster_handle(&self) -> &BroadcasterHandle {
+        &self.inner.broadcaster_handle
+    }
+
+    pub fn connection_stats(&self) -> ConnectionStats {
+        self.inner.connections.stats()
+    }
+
+    /// Stop accepting clients, stop the planes started with `start_plane`, close federation
+    /// links, and close the connected clients, first giving them until `config.drain_timeout` to
+    /// receive the bundles already queued for them. Resolves once every listener, link and client
+    /// task has exited.
+    pub async fn shutdown(&self, config: HubShutdownConfig) {
+        let inner = &self.inner;
+        let worker_cx = inner.rt.local_worker_context()
+            .expect("modrpc_hub::AppHub::shutdown must run on a modrpc worker");
+
+        inner.shutdown_signal.notify();
+        for transport in inner.plane_transports.borrow_mut().drain(..) {
+            transport.shutdown_signal.notify();
+        }
+        #[cfg(feature = "tcp-transport")]
+        if let Some(federation) = &inner.federation {
+            federation.shutdown().await;
+        }
+
+        let spokes: Vec<SpokeHandle> = inner.spokes.borrow().values().cloned().collect();
+        let drain = async {
+            // Say goodbye to every client at once so that one whose queue is full doesn't hold
+            // up the rest.
+            futures_util::future::join_all(spokes.iter().map(async |spoke| {
+                if config.goodbye {
+                    let _ = spoke.send_goodbye().await;
+                }
+                spoke.drain();
+            }))
+            .await;
+            futures_util::future::join_all(spokes.iter().map(SpokeHandle::exited)).await;
+        };
+        futures_lite::future::or(drain, worker_cx.sleep(config.drain_timeout)).await;
+
+        for spoke in &spokes {
+            spoke.close();
+        }
+        inner.tasks.idle().await;
+    }
+
+    /// The identity a connected client authenticated as.
+    pub fn client_identity(&self, endpoint_addr: EndpointAddr) -> Option<Rc<Delegate::Identity>> {
+        self.identities.borrow().get(&endpoint_addr).cloned()
+    }
+
+    /// Let the hub enforce `AppHubDelegate::client_roles` on a plane it relays without hosting.
+    /// Planes started with `start_plane` are declared automatically.
+    pub fn declare_plane_interface<Interface: InterfaceSchema>(&self, plane_id: u32) {
+        let mut ib = InterfaceBuilder::new();
+        Interface::new(&mut ib);
+        self.inner.plane_interfaces.borrow_mut().insert(plane_id, Rc::new(ib));
+    }
+
+    /// Announce clients joining and leaving a plane through a `std.Membership` object the hub
+    /// hosts on it. `metadata_fn` describes each member to observers. Clients should be sent
+    /// `host.init_state()` as part of their init.
+    pub fn host_membership<M>(
+        &self,
+        plane_id: u32,
+        host: std_modrpc::MembershipHost<M>,
+        metadata_fn: impl Fn(&Delegate::Identity) -> M + 'static,
+    )
+    where
+        M: mproto::Owned + Clone,
+    {
+        self.memberships.borrow_mut().insert(
+            plane_id,
+            Rc::new(MembershipHostWithMetadata { host, metadata_fn }),
+        );
+    }
+
+    /// The channels a plane is relayed over.
+    pub fn plane_channels(&self, plane_id: u32) -> modrpc::TopicChannels {
+        plane_channels(&self.inner.plane_channels, plane_id)
+    }
+
+    /// The address the hub accepts tcp clients on - see `AppHubBuilder::with_tcp`.
+    #[cfg(feature = "tcp-transport")]
+    pub fn tcp_addr(&self) -> Option<SocketAddr> {
+        self.inner.tcp_addr
+    }
+
+    /// The address the hub accepts websocket clients on - see `AppHubBuilder::with_websocket`.
+    #[cfg(feature = "websocket-transport")]
+    pub fn websocket_addr(&self) -> Option<SocketAddr> {
+        self.inner.websocket_addr
+    }
+
+    /// The address the hub accepts links from downstream hubs on - see
+    /// `AppHubBuilder::with_federation`.
+    #[cfg(feature = "tcp-transport")]
+    pub fn federation_addr(&self) -> Option<SocketAddr> {
+        self.inner.federation_addr
+    }
+
+    /// The hub's links to federated hubs, if it has any.
+    #[cfg(feature = "tcp-transport")]
+    pub fn federation(&self) -> Option<&Rc<Federation>> {
+        self.inner.federation.as_ref()
+    }
+
+    /// Start the hub's role on a plane, with its own local transport. The plane's channels are
+    /// advertised to federated hubs.
+    pub async fn start_plane<Role>(
+        &self,
+        plane_id: u32,
+        config: Role::Config,
+        init: Role::Init,
+    ) -> modrpc::StartRoleHandle<Role>
+    where
+        Role: modrpc::InterfaceRole,
+        for<'a> Delegate::Init<'a>: mproto::Compatible<Role::Init>,
+    {
+        self.declare_plane_interface::<Role::Interface>(plane_id);
+
+        let inner = &self.inner;
+        let topic_channels = self.plane_channels(plane_id);
+        #[cfg(feature = "tcp-transport")]
+        if let Some(federation) = &inner.federation {
+            federation.advertise_channels(topic_channels.channel_ids()).await;
+        }
+        let transport = inner.rt.add_transport(LocalHubTransport::Static {
+            buffer_pool: inner.buffer_pool.clone(),
+            broadcaster_handle: inner.broadcaster_handle.clone(),
+            channel_ids: topic_channels.channel_ids(),
+        })
+        .await;
+        inner.plane_transports.borrow_mut().push(transport.clone());
+
+        inner.rt.start_role(modrpc::RoleConfig {
+            plane_id,
+            endpoint_addr: inner.hub_endpoint_addr,
             transport,
-            topic_channels: modrpc::TopicChannels::SingleChannel { channel_id: 0x42424242 },
+            topic_channels,
             config,
             init,
         })
     }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    fn alloc_endpoint_addr(&self) -> EndpointAddr {
+        let endpoint = self.inner.next_endpoint_id.get();
+        self.inner.next_endpoint_id.set(endpoint + 1);
+        EndpointAddr { endpoint }
+    }
+
+    /// Run a client's handshake, giving up if it times out or the hub shuts down.
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    async fn run_handshake<T>(
+        &self,
+        worker_cx: &modrpc::WorkerContext,
+        handshake: impl Future<Output = std::io::Result<T>>,
+    ) -> std::io::Result<T> {
+        let handshake = futures_lite::future::or(handshake, async {
+            wait_notified(&self.inner.shutdown_signal).await;
+            Err(std::io::ErrorKind::ConnectionAborted.into())
+        });
+        let Some(handshake_timeout) = self.inner.connections.limits().handshake_timeout else {
+            return handshake.await;
+        };
+        futures_lite::future::or(handshake, async {
+            worker_cx.sleep(handshake_timeout).await;
+            self.inner.connections.handshake_timed_out();
+            Err(std::io::ErrorKind::TimedOut.into())
+        })
+        .await
+    }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    fn client_ingress_policy(
+        &self,
+        delegate: &Delegate,
+        plane_id: u32,
+        endpoint_addr: EndpointAddr,
+        identity: &Delegate::Identity,
+    ) -> IngressPolicy {
+        let allowed_topics = delegate.client_roles(plane_id, identity).and_then(|roles| {
+            let plane_interfaces = self.inner.plane_interfaces.borrow();
+            let Some(ib) = plane_interfaces.get(&plane_id) else {
+                log::warn!("Can't restrict client roles on undeclared plane {plane_id:08x}");
+                return None;
+            };
+            Some(IngressPolicy::topics_for_roles(ib, &roles))
+        });
+
+        IngressPolicy {
+            plane_id,
+            channel_ids: self.plane_channels(plane_id).channel_ids(),
+            source: endpoint_addr,
+            allowed_topics,
+            rate_limit: self.inner.connections.limits().rate_limit,
+        }
+    }
+
+    /// Planes are relayed over the channel with the same ID unless told otherwise, so clients
+    /// can't be put on planes with IDs above `modrpc::MAX_CHANNEL_ID`.
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    fn check_plane_channels(&self, plane_id: u32) -> std::io::Result<()> {
+        let channel_ids = self.plane_channels(plane_id).channel_ids();
+        if channel_ids.iter().any(|&channel_id| channel_id > modrpc::MAX_CHANNEL_ID) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("plane {plane_id:08x} is relayed over a channel above modrpc::MAX_CHANNEL_ID"),
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    fn client_spoke_config(
+        &self,
+        delegate: &Delegate,
+        plane_id: u32,
+        endpoint_addr: EndpointAddr,
+        identity: &Delegate::Identity,
+    ) -> SpokeConfig {
+        SpokeConfig {
+            keepalive: self.inner.keepalive,
+            compression: self.inner.compression,
+            compression_negotiation: modrpc::CompressionNegotiation::Offer,
+            egress: self.inner.spoke_egress,
+            ingress_policy: Some(self.client_ingress_policy(delegate, plane_id, endpoint_addr, identity)),
+        }
+    }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    async fn client_joined(
+        &self,
+        plane_id: u32,
+        endpoint_addr: EndpointAddr,
+        identity: Delegate::Identity,
+    ) {
+        let membership = self.memberships.borrow().get(&plane_id).cloned();
+        if let Some(membership) = membership {
+            membership.join(endpoint_addr, &identity).await;
+        }
+        self.identities.borrow_mut().insert(endpoint_addr, Rc::new(identity));
+    }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    async fn client_left(&self, plane_id: u32, endpoint_addr: EndpointAddr) {
+        self.identities.borrow_mut().remove(&endpoint_addr);
+        let membership = self.memberships.borrow().get(&plane_id).cloned();
+        if let Some(membership) = membership {
+            membership.leave(endpoint_addr).await;
+        }
+    }
+
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    async fn add_client_spoke(&self, plane_id: u32, spoke: &SpokeHandle) {
+        let channel_ids = self.plane_channels(plane_id).channel_ids().into_iter()
+            .map(|channel_id| (ChannelId { channel_id }, ChannelId { channel_id }))
+            .collect();
+        self.inner.broadcaster_handle.add_next_hop_to_channels(spoke.transport(), channel_ids)
+            .await;
+
+        self.inner.spokes.borrow_mut().insert(spoke.transport(), spoke.clone());
+        // Spokes added after shutdown started were missed by it.
+        if self.inner.shutdown_signal.is_notified() {
+            spoke.close();
+        }
+    }
+
+    /// Wait for a client's spoke to shut down, then for its tasks to exit.
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    async fn serve_client_spoke(
+        &self,
+        delegate: &Delegate,
+        plane_id: u32,
+        endpoint_addr: EndpointAddr,
+        identity: Delegate::Identity,
+        spoke: SpokeHandle,
+    ) {
+        self.add_client_spoke(plane_id, &spoke).await;
+        self.client_joined(plane_id, endpoint_addr, identity).await;
+
+        wait_notified(spoke.shutdown_signal()).await;
+        if spoke.was_rate_limited() {
+            self.inner.connections.rate_limited_disconnect();
+        }
+        self.client_left(plane_id, endpoint_addr).await;
+        delegate.client_disconnected(endpoint_addr).await;
+
+        spoke.exited().await;
+        self.inner.spokes.borrow_mut().remove(&spoke.transport());
+    }
 }
 
 #[cfg(feature = "tcp-transport")]
-fn spawn_hub_tcp(
-    buffer_pool: modrpc::HeapBufferPool,
-    rt: modrpc::RuntimeHandle,
-    broadcaster_handle: BroadcasterHandle,
-    max_packet_size: usize,
-    next_endpoint_id: Rc<Cell<u64>>,
-    bind_addr: SocketAddr,
-    delegate: Rc<impl AppHubDelegate + 'static>,
+fn spawn_hub_tcp<Delegate: AppHubDelegate + 'static>(
+    hub: AppHub<Delegate>,
+    listener: tokio::net::TcpListener,
+    delegate: Rc<Delegate>,
 ) {
+    let rt = hub.inner.rt.clone();
     let worker_spawner = rt.local_worker_context()
         .expect("modrpc_hub::spawn_hub_tcp must run on a modrpc worker")
         .spawner();
-    let raw_spawner = worker_spawner.raw_spawner().clone();
-    worker_spawner.spawn(async move {
+    let tasks = hub.inner.tasks.clone();
+    worker_spawner.spawn(tasks.track(async move {
         let worker_cx = rt.local_worker_context()
             .expect("modrpc_hub::spawn_hub_tcp must run on a modrpc worker");
-
-        let listener = tokio::net::TcpListener::bind(bind_addr).await
-            .expect("tcp listener");
+        let bind_addr = hub.inner.tcp_addr.expect("tcp listener address");
 
         log::info!("Serving modrpc_hub on tcp://{bind_addr}");
 
-        loop {
-            let (mut stream, client_addr) = match listener.accept().await {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!("Failed to accept client: {}", e);
+        let accept_loop = async {
+            loop {
+                let (stream, client_addr) = match listener.accept().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::error!("Failed to accept client: {}", e);
+                        worker_cx.sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                let Some(permit) = hub.inner.connections.try_admit(client_addr.ip()) else {
+                    log::warn!(
+                        "Rejecting modrpc_hub tcp client {client_addr}: connection limit reached"
+                    );
                     continue;
-                }
-            };
+                };
 
-            log::info!("Accepted modrpc_hub tcp client {client_addr}");
+                log::info!("Accepted modrpc_hub tcp client {client_addr}");
 
-            if let Err(e) = stream.set_nodelay(true) {
-                log::warn!("Failed to set_nodelay(true) for tcp client {client_addr}: {e}");
+                worker_cx.spawn(hub.inner.tasks.track(serve_tcp_client(
+                    hub.clone(),
+                    delegate.clone(),
+                    stream,
+                    client_addr,
+                    permit,
+                )));
             }
+        };
+        futures_lite::future::or(accept_loop, wait_notified(&hub.inner.shutdown_signal)).await;
+
+        log::info!("Stopped serving modrpc_hub on tcp://{bind_addr}");
+    }));
+}
+
+#[cfg(feature = "tcp-transport")]
+async fn serve_tcp_client<Delegate: AppHubDelegate + 'static>(
+    hub: AppHub<Delegate>,
+    delegate: Rc<Delegate>,
+    mut stream: tokio::net::TcpStream,
+    client_addr: SocketAddr,
+    _permit: ConnectionPermit,
+) {
+    let worker_cx = hub.inner.rt.local_worker_context()
+        .expect("modrpc_hub tcp clients must be served on a modrpc worker");
+
+    if let Err(e) = stream.set_nodelay(true) {
+        log::warn!("Failed to set_nodelay(true) for tcp client {client_addr}: {e}");
+    }
 
-            let endpoint_addr = EndpointAddr { endpoint: next_endpoint_id.get() };
-            if let Err(e) =
-                delegate.client_handshake(
+    let endpoint_addr = hub.alloc_endpoint_addr();
+    let handshake = hub.run_handshake(worker_cx, async {
+        let identity = tcp_authenticate(&mut stream, endpoint_addr, &*delegate).await
+            .inspect_err(|e| log::info!("Failed to authenticate client {client_addr}: {e}"))?;
+
+        let joined_plane_id = Cell::new(None);
+        delegate.client_handshake(
+            &hub,
+            endpoint_addr,
+            &identity,
+            async |plane_id, init_payload| {
+                hub.check_plane_channels(plane_id)?;
+                joined_plane_id.set(Some(plane_id));
+                tcp_handshake(
+                    &mut stream,
+                    plane_id,
                     endpoint_addr,
-                    async |init_payload| {
-                        let plane_id = 0x42424242;
-                        tcp_handshake(
-                            &mut stream,
-                            plane_id,
-                            endpoint_addr,
-                            init_payload,
-                        )
-                        .await
-                    }
+                    init_payload,
                 )
                 .await
-            {
-                log::error!("Failed to handshake with client {client_addr}: {e}");
-                continue;
             }
-            next_endpoint_id.set(next_endpoint_id.get() + 1);
+        )
+        .await
+        .inspect_err(|e| log::error!("Failed to handshake with client {client_addr}: {e}"))?;
 
-            let (broadcaster_nexthop, tcp_shutdown) = spawn_tcp_spoke(
-                &worker_cx,
-                broadcaster_handle.clone(),
-                buffer_pool.clone(),
-                stream,
-                max_packet_size,
-            )
-            .await;
+        Ok((identity, joined_plane_id.get()))
+    });
+    let (identity, plane_id) = match handshake.await {
+        Ok((identity, Some(plane_id))) => (identity, plane_id),
+        Ok((_, None)) => {
+            log::info!("Client {client_addr} wasn't assigned a plane");
+            return;
+        }
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::TimedOut {
+                log::warn!("Disconnecting client {client_addr}: handshake timed out");
+            }
+            return;
+        }
+    };
 
-            broadcaster_handle.add_next_hop_to_channels(
-                broadcaster_nexthop,
-                vec![
-                    (
-                        ChannelId { channel_id: 0x42424242 },
-                        ChannelId { channel_id: 0x42424242 },
-                    ),
-                ],
-            )
-            .await;
+    let spoke = spawn_tcp_spoke(
+        worker_cx,
+        hub.inner.broadcaster_handle.clone(),
+        hub.inner.buffer_pool.clone(),
+        stream,
+        hub.inner.max_packet_size,
+        hub.client_spoke_config(&delegate, plane_id, endpoint_addr, &identity),
+    )
+    .await;
 
-            raw_spawner.spawn({
-                let delegate = delegate.clone();
-                async move {
-                    tcp_shutdown.wait().await;
-                    delegate.client_disconnected(endpoint_addr).await;
-                }
-            })
-            .expect("modrpc-hub tcp spawn client disconnect handler");
-        }
-    });
+    hub.serve_client_spoke(&delegate, plane_id, endpoint_addr, identity, spoke).await;
 }
 
 #[cfg(feature = "websocket-transport")]
-fn spawn_hub_websocket(
-    buffer_pool: modrpc::HeapBufferPool,
-    rt: modrpc::RuntimeHandle,
-    broadcaster_handle: BroadcasterHandle,
-    max_packet_size: usize,
-    next_endpoint_id: Rc<Cell<u64>>,
-    bind_addr: SocketAddr,
-    delegate: Rc<impl AppHubDelegate + 'static>,
+fn spawn_hub_websocket<Delegate: AppHubDelegate + 'static>(
+    hub: AppHub<Delegate>,
+    listener: tokio::net::TcpListener,
+    delegate: Rc<Delegate>,
 ) {
+    let rt = hub.inner.rt.clone();
     let worker_spawner = rt.local_worker_context()
         .expect("modrpc_hub::spawn_hub_websocket must run on a modrpc worker")
         .spawner();
-    let raw_spawner = worker_spawner.raw_spawner().clone();
-    worker_spawner.spawn(async move {
+    let tasks = hub.inner.tasks.clone();
+    worker_spawner.spawn(tasks.track(async move {
         let worker_cx = rt.local_worker_context()
             .expect("modrpc_hub::spawn_hub_websocket must run on a modrpc worker");
-
-        let listener = tokio::net::TcpListener::bind(bind_addr).await
-            .expect("tcp listener");
+        let bind_addr = hub.inner.websocket_addr.expect("websocket listener address");
 
         log::info!("Serving modrpc_hub on ws://{bind_addr}");
 
-        loop {
-            let (tcp_stream, client_addr) = match listener.accept().await {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!("Failed to accept client: {}", e);
+        let accept_loop = async {
+            loop {
+                let (tcp_stream, client_addr) = match listener.accept().await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::error!("Failed to accept client: {}", e);
+                        worker_cx.sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                let Some(permit) = hub.inner.connections.try_admit(client_addr.ip()) else {
+                    log::warn!(
+                        "Rejecting modrpc_hub websocket client {client_addr}: connection limit reached"
+                    );
                     continue;
-                }
-            };
+                };
 
-            log::info!("Accepted modrpc_hub websocket client {client_addr}");
+                log::info!("Accepted modrpc_hub websocket client {client_addr}");
 
-            if let Err(e) = tcp_stream.set_nodelay(true) {
-                log::warn!("Failed to set_nodelay(true) for websocket client {client_addr}: {e}");
+                worker_cx.spawn(hub.inner.tasks.track(serve_websocket_client(
+                    hub.clone(),
+                    delegate.clone(),
+                    tcp_stream,
+                    client_addr,
+                    permit,
+                )));
             }
+        };
+        futures_lite::future::or(accept_loop, wait_notified(&hub.inner.shutdown_signal)).await;
 
-            let Ok(mut websocket) = tokio_tungstenite::accept_async(tcp_stream).await else {
-                log::info!("Failed to accept websocket client");
-                continue;
-            };
+        log::info!("Stopped serving modrpc_hub on ws://{bind_addr}");
+    }));
+}
 
-            let endpoint_addr = EndpointAddr { endpoint: next_endpoint_id.get() };
-            if let Err(e) =
-                delegate.client_handshake(
+#[cfg(feature = "websocket-transport")]
+async fn serve_websocket_client<Delegate: AppHubDelegate + 'static>(
+    hub: AppHub<Delegate>,
+    delegate: Rc<Delegate>,
+    tcp_stream: tokio::net::TcpStream,
+    client_addr: SocketAddr,
+    _permit: ConnectionPermit,
+) {
+    let worker_cx = hub.inner.rt.local_worker_context()
+        .expect("modrpc_hub websocket clients must be served on a modrpc worker");
+
+    if let Err(e) = tcp_stream.set_nodelay(true) {
+        log::warn!("Failed to set_nodelay(true) for websocket client {client_addr}: {e}");
+    }
+
+    let endpoint_addr = hub.alloc_endpoint_addr();
+    let handshake = hub.run_handshake(worker_cx, async {
+        let mut websocket = tokio_tungstenite::accept_async(tcp_stream).await
+            .map_err(std::io::Error::other)
+            .inspect_err(|_| log::info!("Failed to accept websocket client"))?;
+
+        let identity = websocket_authenticate(&mut websocket, endpoint_addr, &*delegate).await
+            .inspect_err(|e| log::info!("Failed to authenticate client {client_addr}: {e}"))?;
+
+        let joined_plane_id = Cell::new(None);
+        delegate.client_handshake(
+            &hub,
+            endpoint_addr,
+            &identity,
+            async |plane_id, init_payload| {
+                hub.check_plane_channels(plane_id)?;
+                joined_plane_id.set(Some(plane_id));
+                websocket_handshake(
+                    &mut websocket,
+                    plane_id,
                     endpoint_addr,
-                    async |init_payload| {
-                        let plane_id = 0x42424242;
-                        websocket_handshake(
-                            &mut websocket,
-                            plane_id,
-                            endpoint_addr,
-                            init_payload,
-                        )
-                        .await
-                    }
+                    init_payload,
                 )
                 .await
-            {
-                log::error!("Failed to handshake with client {client_addr}: {e}");
-                continue;
             }
-            next_endpoint_id.set(next_endpoint_id.get() + 1);
+        )
+        .await
+        .inspect_err(|e| log::error!("Failed to handshake with client {client_addr}: {e}"))?;
 
-            log::info!("Handshake with websocket client {client_addr} success");
+        Ok((websocket, identity, joined_plane_id.get()))
+    });
+    let (websocket, identity, plane_id) = match handshake.await {
+        Ok((websocket, identity, Some(plane_id))) => (websocket, identity, plane_id),
+        Ok((_, _, None)) => {
+            log::info!("Client {client_addr} wasn't assigned a plane");
+            return;
+        }
+        Err(e) => {
+            if e.kind() == std::io::ErrorKind::TimedOut {
+                log::warn!("Disconnecting client {client_addr}: handshake timed out");
+            }
+            return;
+        }
+    };
 
-            let (broadcaster_nexthop, websocket_shutdown) = spawn_websocket_spoke(
-                &worker_cx,
-                broadcaster_handle.clone(),
-                buffer_pool.clone(),
-                websocket,
-                max_packet_size,
-            )
-            .await;
+    log::info!("Handshake with websocket client {client_addr} success");
 
-            broadcaster_handle.add_next_hop_to_channels(
-                broadcaster_nexthop,
-                vec![
-                    (
-                        ChannelId { channel_id: 0x42424242 },
-                        ChannelId { channel_id: 0x42424242 },
-                    ),
-                ],
-            )
-            .await;
+    let spoke = spawn_websocket_spoke(
+        worker_cx,
+        hub.inner.broadcaster_handle.clone(),
+        hub.inner.buffer_pool.clone(),
+        websocket,
+        hub.inner.max_packet_size,
+        hub.client_spoke_config(&delegate, plane_id, endpoint_addr, &identity),
+    )
+    .await;
 
-            raw_spawner.spawn({
-                let delegate = delegate.clone();
-                async move {
-                    websocket_shutdown.wait().await;
-                    delegate.client_disconnected(endpoint_addr).await;
-                }
-            })
-            .expect("modrpc-hub websocket spawn client disconnect handler");
+    hub.serve_client_spoke(&delegate, plane_id, endpoint_addr, identity, spoke).await;
+}
+
+#[cfg(feature = "tcp-transport")]
+async fn tcp_authenticate<Delegate: AppHubDelegate>(
+    stream: &mut tokio::net::TcpStream,
+    endpoint_addr: EndpointAddr,
+    delegate: &Delegate,
+) -> std::io::Result<Delegate::Identity> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    if !Delegate::CLIENT_HELLO {
+        return delegate.authenticate(endpoint_addr, None).await
+            .map_err(|reason| std::io::Error::new(std::io::ErrorKind::PermissionDenied, reason));
+    }
+
+    let mut hello_len_bytes = [0u8; 2];
+    stream.read_exact(&mut hello_len_bytes).await?;
+    let mut hello_bytes = vec![0u8; u16::from_le_bytes(hello_len_bytes) as usize];
+    stream.read_exact(&mut hello_bytes).await?;
+
+    let (reply, result) = authenticate(endpoint_addr, &hello_bytes, delegate).await;
+
+    let reply_len = mproto::encoded_len(&reply);
+    let mut reply_buf = vec![0u8; 2 + reply_len];
+    reply_buf[..2].copy_from_slice(&(reply_len as u16).to_le_bytes());
+    mproto::encode_value(&reply, &mut reply_buf[2..]);
+    stream.write_all(&reply_buf).await?;
+
+    result
+}
+
+#[cfg(feature = "websocket-transport")]
+async fn websocket_authenticate<Delegate: AppHubDelegate>(
+    websocket: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>,
+    endpoint_addr: EndpointAddr,
+    delegate: &Delegate,
+) -> std::io::Result<Delegate::Identity> {
+    use tokio_tungstenite::tungstenite::protocol::Message;
+    use futures_util::{sink::SinkExt, stream::StreamExt};
+
+    if !Delegate::CLIENT_HELLO {
+        return delegate.authenticate(endpoint_addr, None).await
+            .map_err(|reason| std::io::Error::new(std::io::ErrorKind::PermissionDenied, reason));
+    }
+
+    let hello_bytes = loop {
+        let message = websocket.next().await
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?
+            .map_err(std::io::Error::other)?;
+        match message {
+            Message::Binary(hello_bytes) => break hello_bytes,
+            Message::Ping(_) | Message::Pong(_) => {}
+            _ => return Err(std::io::ErrorKind::InvalidData.into()),
         }
-    });
+    };
+
+    let (reply, result) = authenticate(endpoint_addr, &hello_bytes, delegate).await;
+
+    websocket.send(Message::Binary(mproto::encode_value_vec(&reply).into())).await
+        .map_err(std::io::Error::other)?;
+
+    result
+}
+
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+async fn authenticate<Delegate: AppHubDelegate>(
+    endpoint_addr: EndpointAddr,
+    hello_bytes: &[u8],
+    delegate: &Delegate,
+) -> (modrpc::ClientHelloReply, std::io::Result<Delegate::Identity>) {
+    let hello = match mproto::decode_value(hello_bytes) {
+        Ok(hello) => hello,
+        Err(e) => {
+            return (
+                Err("malformed client hello".into()),
+                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+            );
+        }
+    };
+
+    match delegate.authenticate(endpoint_addr, Some(hello)).await {
+        Ok(identity) => (Ok(()), Ok(identity)),
+        Err(reason) => {
+            let e = std::io::Error::new(std::io::ErrorKind::PermissionDenied, reason.clone());
+            (Err(reason), Err(e))
+        }
+    }
 }
 
 #[cfg(feature = "tcp-transport")]
@@ -363,7 +1099,7 @@ async fn tcp_handshake(
     let mut payload_buf = vec![0u8; 2 + payload_len];
     payload_buf[..2].copy_from_slice(&(payload_len as u16).to_le_bytes());
     mproto::encode_value(payload, &mut payload_buf[2..]);
-    stream.write_all(payload_buf[..].into()).await?;
+    stream.write_all(&payload_buf).await?;
 
     Ok(())
 }
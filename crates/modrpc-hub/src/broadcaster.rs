@@ -1,18 +1,27 @@
-use std::collections::HashMap;
-
-#[cfg(feature = "websocket-transport")]
-use tokio_tungstenite::tungstenite::{
-    Error as WsError,
-    protocol::Message as WsMessage,
+use std::collections::{HashMap, HashSet};
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
 };
-#[cfg(feature = "websocket-transport")]
-use futures_util::sink::{Sink, SinkExt};
-#[cfg(feature = "gloo-websocket")]
-use futures_util::sink::{Sink, SinkExt};
 
 use modrpc::{
+    compressed_channel_id,
+    BundleCompressor,
+    CompressionConfig,
+    COMPRESSED_CHANNEL_BIT,
+    EndpointAddr,
     Packet,
-    PacketBundle, ShatterPacketBundle,
+    ShatterPacketBundle,
+    TopicSubscription,
+    TransmitPacket,
+};
+use mproto::BaseLen;
+
+use crate::spoke_egress::{
+    EgressBundle,
+    EgressPayload,
+    SpokeEgressQueue,
+    SpokeEgressStats,
 };
 
 pub struct InPacket {
@@ -21,30 +30,26 @@ pub struct InPacket {
     pub packet: Packet,
 }
 
-#[cfg(feature = "websocket-transport")]
-pub type WsSinkBox = Box<dyn Sink<WsMessage, Error = WsError> + Send + std::marker::Unpin>;
-#[cfg(feature = "gloo-websocket")]
-pub type GlooWsSinkBox = Box<dyn Sink<gloo_net::websocket::Message, Error = gloo_net::websocket::WebSocketError> + std::marker::Unpin>;
+struct SendInPacket {
+    transport: TransportIndex,
+    channel_id: u32,
+    packet: bab::SendPacket,
+}
+
+/// The broadcaster shard a request or packet was sent to has shut down.
+#[derive(Debug)]
+pub struct BroadcasterShutdown;
 
 enum BroadcasterRequest {
-    #[cfg(feature = "tcp-transport")]
-    AddTcp {
-        stream: tokio::net::tcp::OwnedWriteHalf,
-        response_tx: oneshot::Sender<TransportIndex>,
-    },
-    #[cfg(feature = "websocket-transport")]
-    AddWs {
-        ws_tx: WsSinkBox,
-        response_tx: oneshot::Sender<TransportIndex>,
-    },
-    #[cfg(feature = "gloo-websocket")]
-    AddGlooWs {
-        ws_tx: GlooWsSinkBox,
-        response_tx: oneshot::Sender<TransportIndex>,
+    AddSpoke {
+        transport: TransportIndex,
+        egress: SpokeEgressQueue,
+        response_tx: oneshot::Sender<()>,
     },
     AddLocal {
-        tx: localq::mpsc::Sender<Packet>,
-        response_tx: oneshot::Sender<TransportIndex>,
+        transport: TransportIndex,
+        tx: burstq::Sender<bab::SendPacket>,
+        response_tx: oneshot::Sender<()>,
     },
     Remove {
         transport: TransportIndex,
@@ -55,28 +60,87 @@ enum BroadcasterRequest {
         channel_ids: Vec<(ChannelId, ChannelId)>, // [(local channel ID, remote channel ID)]
         response_tx: oneshot::Sender<()>,
     },
+    SendKeepalive {
+        transport: TransportIndex,
+    },
+    EnableCompression {
+        transport: TransportIndex,
+        config: CompressionConfig,
+    },
+    SetTopicSubscriptions {
+        transport: TransportIndex,
+        subscriber: TopicSubscriber,
+        response_tx: oneshot::Sender<()>,
+    },
+    GetEgressStats {
+        response_tx: oneshot::Sender<HashMap<TransportIndex, SpokeEgressStats>>,
+    },
+}
+
+// A TCP or WebSocket client. Bundles are handed off to the spoke's writer task through its egress
+// queue so that a slow client can't hold up delivery to everyone else.
+struct SpokeTransport {
+    egress: SpokeEgressQueue,
+    // Only the packets this spoke subscribed to are sent to it. Spokes that never subscribed get
+    // every bundle on their channels.
+    subscriber: Option<TopicSubscriber>,
 }
 
-#[cfg(feature = "tcp-transport")]
-struct TcpTransport {
-    stream: tokio::net::tcp::OwnedWriteHalf,
+struct TopicSubscriber {
+    endpoint: Option<EndpointAddr>,
+    topics: HashMap<u32, TopicSubscription>,
 }
 
-#[cfg(feature = "websocket-transport")]
-struct WsTransport {
-    ws_tx: WsSinkBox,
+enum FilteredBundle {
+    All,
+    Some(Vec<u8>),
+    None,
 }
 
-#[cfg(feature = "gloo-websocket")]
-struct GlooWsTransport {
-    ws_tx: GlooWsSinkBox,
+impl TopicSubscriber {
+    fn filter_bundle(&self, bundle: &[u8]) -> FilteredBundle {
+        let mut filtered = Vec::new();
+        let mut is_filtered = false;
+
+        let mut cursor = 0;
+        while cursor + TransmitPacket::BASE_LEN <= bundle.len() {
+            let Ok(header) = mproto::decode_value::<TransmitPacket>(&bundle[cursor..]) else {
+                break;
+            };
+            let packet_end =
+                (cursor + TransmitPacket::BASE_LEN + header.payload_length as usize).min(bundle.len());
+            let packet = &bundle[cursor..packet_end];
+
+            // Key exchanges for encrypted planes are relayed to every endpoint on the plane.
+            let wanted = header.infra_id == modrpc::KEY_EXCHANGE_INFRA_ID
+                || self.topics.get(&header.topic).is_some_and(|subscription| {
+                    self.endpoint.is_none_or(|endpoint| subscription.matches(packet, endpoint))
+                });
+            if wanted {
+                filtered.extend_from_slice(packet);
+            } else {
+                is_filtered = true;
+            }
+            cursor = packet_end;
+        }
+
+        if !is_filtered {
+            FilteredBundle::All
+        } else if filtered.is_empty() {
+            FilteredBundle::None
+        } else {
+            FilteredBundle::Some(filtered)
+        }
+    }
 }
 
 struct LocalTransport {
-    tx: localq::mpsc::Sender<Packet>,
+    tx: burstq::Sender<bab::SendPacket>,
 }
 
-type TransportKey = slotmap::DefaultKey;
+// Transport keys are allocated by the BroadcasterHandle so that a transport has the same index on
+// every shard.
+type TransportKey = u64;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 enum TransportType {
@@ -106,83 +170,105 @@ struct NextHop {
     transport: TransportIndex,
 }
 
-const BUNDLE_HEADER_LEN: usize = <PacketBundle as mproto::BaseLen>::BASE_LEN;
+const IN_PACKET_BURST_LEN: usize = 32;
+const REQUEST_BURST_LEN: usize = 8;
 
 pub struct Broadcaster {
-    in_packet_receiver: localq::mpsc::Receiver<InPacket>,
-    in_packet_sender: localq::mpsc::Sender<InPacket>,
+    in_packet_rx: burstq::Receiver<SendInPacket>,
+    request_rx: burstq::Receiver<BroadcasterRequest>,
+    shard: BroadcasterShard,
 
     // Map local channel_id to list of transports to broadcast packet bundles to
     next_hops: HashMap<ChannelId, Vec<NextHop>>,
     transport_local_channel_ids: HashMap<TransportIndex, Vec<ChannelId>>,
 
-    #[cfg(feature = "tcp-transport")]
-    tcp_transports: slotmap::SlotMap<TransportKey, TcpTransport>,
-    #[cfg(feature = "websocket-transport")]
-    ws_transports: slotmap::SlotMap<TransportKey, WsTransport>,
-    #[cfg(feature = "gloo-websocket")]
-    gloo_ws_transports: slotmap::SlotMap<TransportKey, GlooWsTransport>,
-    local_transports: slotmap::SlotMap<TransportKey, LocalTransport>,
+    spoke_transports: HashMap<TransportIndex, SpokeTransport>,
+    local_transports: HashMap<TransportIndex, LocalTransport>,
 
-    request_tx: localq::mpsc::Sender<BroadcasterRequest>,
-    request_rx: localq::mpsc::Receiver<BroadcasterRequest>,
+    // Bundles are compressed at most once no matter how many spokes they're sent to.
+    compressor: Option<BundleCompressor>,
+    compressed_transports: HashSet<TransportIndex>,
 }
 
 impl Broadcaster {
     pub fn new(packet_queue_capacity: usize) -> Self {
-        let (in_packet_sender, in_packet_receiver) = localq::mpsc::channel(packet_queue_capacity);
-        let (request_tx, request_rx) = localq::mpsc::channel(16);
+        let (in_packet_tx, in_packet_rx) = burstq::mpmc(packet_queue_capacity);
+        let (request_tx, request_rx) = burstq::mpmc(16);
 
         Self {
-            in_packet_receiver,
-            in_packet_sender,
+            in_packet_rx,
+            request_rx,
+            shard: BroadcasterShard {
+                in_packet_tx,
+                request_tx,
+            },
 
             next_hops: HashMap::new(),
             transport_local_channel_ids: HashMap::new(),
 
-            local_transports: slotmap::SlotMap::new(),
-            #[cfg(feature = "tcp-transport")]
-            tcp_transports: slotmap::SlotMap::new(),
-            #[cfg(feature = "websocket-transport")]
-            ws_transports: slotmap::SlotMap::new(),
-            #[cfg(feature = "gloo-websocket")]
-            gloo_ws_transports: slotmap::SlotMap::new(),
+            spoke_transports: HashMap::new(),
+            local_transports: HashMap::new(),
 
-            request_tx,
-            request_rx,
+            compressor: None,
+            compressed_transports: HashSet::new(),
         }
     }
 
-    pub fn handle(&self) -> BroadcasterHandle {
-        BroadcasterHandle {
-            in_packet_sender: self.in_packet_sender.clone(),
-            request: self.request_tx.clone(),
+    /// Run a broadcaster shard on each of the given workers. Channels are split between the shards
+    /// by channel ID, so bundles on any one channel are still broadcast in order.
+    pub async fn spawn_sharded(
+        rt: &modrpc::RuntimeHandle,
+        workers: impl IntoIterator<Item = modrpc::WorkerId>,
+        packet_queue_capacity: usize,
+    ) -> BroadcasterHandle {
+        let mut shards = Vec::new();
+        for worker_id in workers {
+            let shard = rt.get_worker(worker_id).run_once(move |worker_cx| {
+                let mut broadcaster = Broadcaster::new(packet_queue_capacity);
+                let shard = broadcaster.shard.clone();
+                worker_cx.spawn(async move {
+                    broadcaster.run().await;
+                });
+                shard
+            })
+            .await;
+            shards.push(shard);
         }
+        assert!(!shards.is_empty(), "modrpc_hub::Broadcaster::spawn_sharded needs at least one worker");
+
+        BroadcasterHandle::new(shards)
     }
 
-    pub fn add_local_transport(&mut self, tx: localq::mpsc::Sender<Packet>) -> TransportIndex {
-        let key = self.local_transports.insert(LocalTransport { tx });
-        log::debug!("Added Local transport {:?}", key);
-        TransportIndex {
-            transport_type: TransportType::Local,
-            transport: key,
-        }
+    /// Get a handle to this broadcaster as the only shard.
+    pub fn handle(&self) -> BroadcasterHandle {
+        BroadcasterHandle::new(vec![self.shard.clone()])
     }
 
     pub async fn run(&mut self) {
-        use futures_util::FutureExt;
+        let mut in_packets = Vec::with_capacity(IN_PACKET_BURST_LEN);
+        let mut requests = Vec::with_capacity(REQUEST_BURST_LEN);
 
         loop {
-            futures_util::select! {
-                in_packet = self.in_packet_receiver.recv().fuse() => {
-                    let Ok(in_packet) = in_packet else { break; };
-                    self.handle_in_packet(in_packet).await;
-                }
-                request = self.request_rx.recv().fuse() => {
-                    let Ok(request) = request else { break; };
-                    self.handle_request(request).await;
-                }
-            };
+            let result = futures_lite::future::or(
+                self.in_packet_rx.recv(IN_PACKET_BURST_LEN, |r| in_packets.extend(r.into_iter())),
+                self.request_rx.recv(REQUEST_BURST_LEN, |r| requests.extend(r.into_iter())),
+            )
+            .await;
+            if result.is_err() {
+                break;
+            }
+
+            for in_packet in in_packets.drain(..) {
+                self.handle_in_packet(InPacket {
+                    transport: in_packet.transport,
+                    channel_id: in_packet.channel_id,
+                    packet: in_packet.packet.receive(),
+                })
+                .await;
+            }
+            for request in requests.drain(..) {
+                self.handle_request(request).await;
+            }
         }
     }
 
@@ -199,15 +285,25 @@ impl Broadcaster {
         );
 
         if let Some(next_hops) = self.next_hops.get(&local_channel_id) {
+            let wants_compressed = next_hops.iter().any(|next_hop| {
+                next_hop.transport != in_packet.transport
+                    && self.compressed_transports.contains(&next_hop.transport)
+                    && compressed_channel_id(next_hop.remote_channel_id.channel_id).is_some()
+            });
+            let compressed_payload = if wants_compressed {
+                self.compressor.as_mut()
+                    .and_then(|c| c.compress(&in_packet.packet))
+                    .map(Arc::<[u8]>::from)
+            } else {
+                None
+            };
+
             if let Err(_) = Self::broadcast(
                 in_packet,
                 next_hops,
-                #[cfg(feature = "tcp-transport")]
-                &mut self.tcp_transports,
-                #[cfg(feature = "websocket-transport")]
-                &mut self.ws_transports,
-                #[cfg(feature = "gloo-websocket")]
-                &mut self.gloo_ws_transports,
+                compressed_payload,
+                &self.compressed_transports,
+                &mut self.spoke_transports,
                 &mut self.local_transports,
             ).await {
                 // TODO can this even fail?
@@ -223,21 +319,16 @@ impl Broadcaster {
     async fn remove_transport(&mut self, transport: TransportIndex) {
         log::info!("removing transport {:?}", transport);
 
+        self.compressed_transports.remove(&transport);
+
         match transport.transport_type {
-            #[cfg(feature = "tcp-transport")]
-            TransportType::Tcp => {
-                self.tcp_transports.remove(transport.transport);
-            }
-            #[cfg(feature = "websocket-transport")]
-            TransportType::WebSocket => {
-                self.ws_transports.remove(transport.transport);
-            }
-            #[cfg(feature = "gloo-websocket")]
-            TransportType::GlooWebSocket => {
-                self.gloo_ws_transports.remove(transport.transport);
-            }
             TransportType::Local => {
-                self.local_transports.remove(transport.transport);
+                self.local_transports.remove(&transport);
+            }
+            _ => {
+                if let Some(spoke) = self.spoke_transports.remove(&transport) {
+                    spoke.egress.close();
+                }
             }
         }
 
@@ -265,42 +356,15 @@ impl Broadcaster {
 
     async fn handle_request(&mut self, request: BroadcasterRequest) {
         match request {
-            #[cfg(feature = "tcp-transport")]
-            BroadcasterRequest::AddTcp { stream, response_tx } => {
-                let key = self.tcp_transports.insert(TcpTransport {
-                    stream,
-                });
-                log::debug!("Added TCP transport {:?}", key);
-                let _ = response_tx.send(TransportIndex {
-                    transport_type: TransportType::Tcp,
-                    transport: key,
-                });
-            }
-            #[cfg(feature = "websocket-transport")]
-            BroadcasterRequest::AddWs { ws_tx, response_tx } => {
-                let key = self.ws_transports.insert(WsTransport { ws_tx });
-                log::debug!("Added WebSocket transport {:?}", key);
-                let _ = response_tx.send(TransportIndex {
-                    transport_type: TransportType::WebSocket,
-                    transport: key,
-                });
-            }
-            #[cfg(feature = "gloo-websocket")]
-            BroadcasterRequest::AddGlooWs { ws_tx, response_tx } => {
-                let key = self.gloo_ws_transports.insert(GlooWsTransport { ws_tx });
-                log::debug!("Added Gloo WebSocket transport {:?}", key);
-                let _ = response_tx.send(TransportIndex {
-                    transport_type: TransportType::GlooWebSocket,
-                    transport: key,
-                });
+            BroadcasterRequest::AddSpoke { transport, egress, response_tx } => {
+                self.spoke_transports.insert(transport, SpokeTransport { egress, subscriber: None });
+                log::debug!("Added transport {:?}", transport);
+                let _ = response_tx.send(());
             }
-            BroadcasterRequest::AddLocal { tx, response_tx } => {
-                let key = self.local_transports.insert(LocalTransport { tx });
-                log::debug!("Added Local transport {:?}", key);
-                let _ = response_tx.send(TransportIndex {
-                    transport_type: TransportType::Local,
-                    transport: key,
-                });
+            BroadcasterRequest::AddLocal { transport, tx, response_tx } => {
+                self.local_transports.insert(transport, LocalTransport { tx });
+                log::debug!("Added Local transport {:?}", transport);
+                let _ = response_tx.send(());
             }
             BroadcasterRequest::Remove { transport, response_tx } => {
                 self.remove_transport(transport).await;
@@ -332,19 +396,64 @@ impl Broadcaster {
                 // Don't care if requester hung up
                 let _ = response_tx.send(());
             }
+            BroadcasterRequest::SendKeepalive { transport } => {
+                self.send_keepalive(transport).await;
+            }
+            BroadcasterRequest::EnableCompression { transport, config } => {
+                log::debug!("Enabling compression for transport {:?}", transport);
+                self.compressor.get_or_insert_with(|| BundleCompressor::new(config));
+                self.compressed_transports.insert(transport);
+            }
+            BroadcasterRequest::SetTopicSubscriptions { transport, subscriber, response_tx } => {
+                log::debug!(
+                    "Setting topic subscriptions transport={:?} topics={:?}",
+                    transport,
+                    subscriber.topics.keys(),
+                );
+                if let Some(spoke) = self.spoke_transports.get_mut(&transport) {
+                    spoke.subscriber = Some(subscriber);
+                }
+                let _ = response_tx.send(());
+            }
+            BroadcasterRequest::GetEgressStats { response_tx } => {
+                let _ = response_tx.send(self.egress_stats());
+            }
+        }
+    }
+
+    fn egress_stats(&self) -> HashMap<TransportIndex, SpokeEgressStats> {
+        self.spoke_transports.iter()
+            .map(|(&transport, spoke)| (transport, spoke.egress.stats()))
+            .collect()
+    }
+
+    async fn send_keepalive(&mut self, transport: TransportIndex) {
+        log::trace!("Sending keepalive to transport={:?}", transport);
+
+        // Local transports can't go silent.
+        if transport.transport_type == TransportType::Local {
+            return;
+        }
+
+        if let Some(spoke) = self.spoke_transports.get(&transport) {
+            let keepalive = EgressBundle {
+                channel_id: modrpc::KEEPALIVE_CHANNEL_ID,
+                payload: EgressPayload::Empty,
+            };
+            if let Err(e) = spoke.egress.push(keepalive).await {
+                log::debug!("TransportHub spoke egress closed: {:?} {:?}", transport, e);
+                self.spoke_transports.remove(&transport);
+            }
         }
     }
 
     async fn broadcast(
         in_packet: InPacket,
         next_hops: &[NextHop],
-        #[cfg(feature = "tcp-transport")]
-        tcp_transports: &mut slotmap::SlotMap<TransportKey, TcpTransport>,
-        #[cfg(feature = "websocket-transport")]
-        ws_transports: &mut slotmap::SlotMap<TransportKey, WsTransport>,
-        #[cfg(feature = "gloo-websocket")]
-        gloo_ws_transports: &mut slotmap::SlotMap<TransportKey, GlooWsTransport>,
-        local_transports: &mut slotmap::SlotMap<TransportKey, LocalTransport>,
+        compressed_payload: Option<Arc<[u8]>>,
+        compressed_transports: &HashSet<TransportIndex>,
+        spoke_transports: &mut HashMap<TransportIndex, SpokeTransport>,
+        local_transports: &mut HashMap<TransportIndex, LocalTransport>,
     ) -> std::io::Result<()> {
         for next_hop in next_hops {
             let transport_index = next_hop.transport;
@@ -361,204 +470,178 @@ impl Broadcaster {
                 in_packet.packet.len(),
             );
 
-            match transport_index.transport_type {
-                #[cfg(feature = "tcp-transport")]
-                TransportType::Tcp => {
-                    let bundle_payload = &in_packet.packet[..];
-
-                    // Fill bundle header
-                    let mut bundle_header_buf = [0u8; BUNDLE_HEADER_LEN];
-                    mproto::encode_value(
-                        PacketBundle {
-                            channel_id: next_hop.remote_channel_id.channel_id,
-                            length: bundle_payload.len() as u16,
-                        },
-                        &mut bundle_header_buf,
-                    );
-
-                    if let Some(tcp_transport) = tcp_transports.get_mut(transport_index.transport) {
-                        if let Err(_) =
-                            Self::write_tcp_bundle(
-                                &mut tcp_transport.stream,
-                                &bundle_header_buf,
-                                bundle_payload,
-                            ).await
-                        {
-                            log::debug!("TransportHub tcp transport closed: {:?}", transport_index);
-                            // Remove transport
-                            tcp_transports.remove(transport_index.transport);
-                        }
+            if transport_index.transport_type == TransportType::Local {
+                let Some(local_transport) = local_transports.get(&transport_index) else {
+                    continue;
+                };
+
+                for packet in ShatterPacketBundle::new(&in_packet.packet) {
+                    if let Err(_) = send_one(&local_transport.tx, packet.send()).await {
+                        local_transports.remove(&transport_index);
+                        break;
                     }
                 }
-                #[cfg(feature = "websocket-transport")]
-                TransportType::WebSocket => {
-                    let bundle_payload = &in_packet.packet[..];
-                    let mut message = vec![0u8; BUNDLE_HEADER_LEN + bundle_payload.len()];
-
-                    // Fill bundle header
-                    mproto::encode_value(
-                        PacketBundle {
-                            channel_id: next_hop.remote_channel_id.channel_id,
-                            length: bundle_payload.len() as u16,
-                        },
-                        &mut message[..BUNDLE_HEADER_LEN],
-                    );
-
-                    message[BUNDLE_HEADER_LEN..].copy_from_slice(bundle_payload);
-
-                    if let Some(ws_transport) = ws_transports.get_mut(transport_index.transport) {
-                        if let Err(_) = ws_transport.ws_tx.send(WsMessage::Binary(message.into())).await {
-                            log::debug!("WebSocket transport closed: {:?}", transport_index);
-                            // Remove transport
-                            ws_transports.remove(transport_index.transport);
-                        }
-                    }
+                continue;
+            }
+
+            let Some(spoke) = spoke_transports.get(&transport_index) else {
+                continue;
+            };
+
+            let filtered = match &spoke.subscriber {
+                Some(subscriber) => subscriber.filter_bundle(&in_packet.packet),
+                None => FilteredBundle::All,
+            };
+            let channel_id = next_hop.remote_channel_id.channel_id;
+            let (channel_id, payload) = match (filtered, &compressed_payload) {
+                (FilteredBundle::None, _) => continue,
+                // Filtered bundles are rare enough to not be worth compressing separately.
+                (FilteredBundle::Some(filtered), _) => {
+                    (channel_id, EgressPayload::Shared(filtered.into()))
                 }
-                #[cfg(feature = "gloo-websocket")]
-                TransportType::GlooWebSocket => {
-                    let bundle_payload = &in_packet.packet[..];
-                    let mut message = vec![0u8; BUNDLE_HEADER_LEN + bundle_payload.len()];
-
-                    // Fill bundle header
-                    mproto::encode_value(
-                        PacketBundle {
-                            channel_id: next_hop.remote_channel_id.channel_id,
-                            length: bundle_payload.len() as u16,
-                        },
-                        &mut message[..BUNDLE_HEADER_LEN],
-                    );
-
-                    message[BUNDLE_HEADER_LEN..].copy_from_slice(bundle_payload);
-
-                    if let Some(ws_transport) = gloo_ws_transports.get_mut(transport_index.transport) {
-                        if let Err(_) =
-                            ws_transport.ws_tx.send(
-                                gloo_net::websocket::Message::Bytes(message)
-                            )
-                            .await
-                        {
-                            log::debug!("Gloo WebSocket transport closed: {:?}", transport_index);
-                            // Remove transport
-                            gloo_ws_transports.remove(transport_index.transport);
-                        }
-                    }
+                (FilteredBundle::All, Some(compressed))
+                    if compressed_transports.contains(&transport_index)
+                        && compressed_channel_id(channel_id).is_some() =>
+                {
+                    (channel_id | COMPRESSED_CHANNEL_BIT, EgressPayload::Shared(compressed.clone()))
                 }
-                TransportType::Local => {
-                    let Some(local_transport) =
-                        local_transports.get(transport_index.transport)
-                    else {
-                        continue;
-                    };
-
-                    for packet in ShatterPacketBundle::new(&in_packet.packet) {
-                        if let Err(_) = local_transport.tx.send(packet).await {
-                            local_transports.remove(transport_index.transport);
-                            break;
-                        }
-                    }
+                (FilteredBundle::All, _) => {
+                    (channel_id, EgressPayload::Packet(in_packet.packet.clone().send()))
                 }
+            };
+            let bundle = EgressBundle { channel_id, payload };
+            if let Err(e) = spoke.egress.push(bundle).await {
+                log::debug!("TransportHub spoke egress closed: {:?} {:?}", transport_index, e);
+                // Remove transport
+                spoke_transports.remove(&transport_index);
             }
         }
 
         Ok(())
     }
+}
 
-    #[cfg(feature = "tcp-transport")]
-    async fn write_tcp_bundle(
-        stream: &mut tokio::net::tcp::OwnedWriteHalf,
-        header: &[u8],
-        payload: &[u8],
-    ) -> std::io::Result<()> {
-        use tokio::io::AsyncWriteExt;
-
-        // TODO vectored write
-        stream.write_all(header).await?;
-        stream.write_all(payload).await?;
+async fn send_one<T>(tx: &burstq::Sender<T>, value: T) -> Result<(), BroadcasterShutdown> {
+    tx.send(1, move |mut w| unsafe {
+        w.write_at(0, value);
+    })
+    .await
+    .map(|_| ())
+    .map_err(|_| BroadcasterShutdown)
+}
 
-        Ok(())
-    }
+#[derive(Clone)]
+struct BroadcasterShard {
+    in_packet_tx: burstq::Sender<SendInPacket>,
+    request_tx: burstq::Sender<BroadcasterRequest>,
 }
 
 #[derive(Clone)]
 pub struct BroadcasterHandle {
-    in_packet_sender: localq::mpsc::Sender<InPacket>,
-    request: localq::mpsc::Sender<BroadcasterRequest>,
+    shards: Arc<[BroadcasterShard]>,
+    next_transport_key: Arc<AtomicU64>,
 }
 
 impl BroadcasterHandle {
-    pub fn in_packet_sender(&self) -> &localq::mpsc::Sender<InPacket> {
-        &self.in_packet_sender
+    fn new(shards: Vec<BroadcasterShard>) -> Self {
+        Self {
+            shards: shards.into(),
+            next_transport_key: Arc::new(AtomicU64::new(0)),
+        }
     }
 
-    #[cfg(feature = "tcp-transport")]
-    pub async fn add_tcp(
-        &self,
-        stream: tokio::net::tcp::OwnedWriteHalf,
-    ) -> TransportIndex {
-        let (response_tx, response_rx) = oneshot::channel();
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, channel_id: u32) -> &BroadcasterShard {
+        &self.shards[channel_id as usize % self.shards.len()]
+    }
+
+    fn new_transport(&self, transport_type: TransportType) -> TransportIndex {
+        TransportIndex {
+            transport_type,
+            transport: self.next_transport_key.fetch_add(1, Ordering::Relaxed),
+        }
+    }
 
-        self.request.send(BroadcasterRequest::AddTcp {
-            stream,
-            response_tx,
+    /// Hand a bundle received from a transport to the shard that broadcasts its channel.
+    pub async fn send_in_packet(&self, in_packet: InPacket) -> Result<(), BroadcasterShutdown> {
+        let shard = self.shard(in_packet.channel_id);
+        send_one(&shard.in_packet_tx, SendInPacket {
+            transport: in_packet.transport,
+            channel_id: in_packet.channel_id,
+            packet: in_packet.packet.send(),
         })
         .await
-        .unwrap();
-        let transport_index = response_rx.await.unwrap();
+    }
 
-        transport_index
+    #[cfg(feature = "tcp-transport")]
+    pub async fn add_tcp(
+        &self,
+        egress: SpokeEgressQueue,
+    ) -> TransportIndex {
+        self.add_spoke(TransportType::Tcp, egress).await
     }
 
     #[cfg(feature = "websocket-transport")]
     pub async fn add_ws(
         &self,
-        ws_tx: WsSinkBox,
+        egress: SpokeEgressQueue,
     ) -> TransportIndex {
-        let (response_tx, response_rx) = oneshot::channel();
-
-        self.request.send(BroadcasterRequest::AddWs {
-            ws_tx,
-            response_tx,
-        })
-        .await
-        .unwrap();
-        let transport_index = response_rx.await.unwrap();
-
-        transport_index
+        self.add_spoke(TransportType::WebSocket, egress).await
     }
 
     #[cfg(feature = "gloo-websocket")]
     pub async fn add_gloo_ws(
         &self,
-        ws_tx: GlooWsSinkBox,
+        egress: SpokeEgressQueue,
     ) -> TransportIndex {
-        let (response_tx, response_rx) = oneshot::channel();
+        self.add_spoke(TransportType::GlooWebSocket, egress).await
+    }
 
-        self.request.send(BroadcasterRequest::AddGlooWs {
-            ws_tx,
-            response_tx,
-        })
-        .await
-        .unwrap();
-        let transport_index = response_rx.await.unwrap();
+    async fn add_spoke(
+        &self,
+        transport_type: TransportType,
+        egress: SpokeEgressQueue,
+    ) -> TransportIndex {
+        let transport = self.new_transport(transport_type);
+
+        // Every shard may need to broadcast to the spoke.
+        for shard in self.shards.iter() {
+            let (response_tx, response_rx) = oneshot::channel();
+            send_one(&shard.request_tx, BroadcasterRequest::AddSpoke {
+                transport,
+                egress: egress.clone(),
+                response_tx,
+            })
+            .await
+            .unwrap();
+            response_rx.await.unwrap();
+        }
 
-        transport_index
+        transport
     }
 
     pub async fn add_local(
         &self,
-        tx: localq::mpsc::Sender<Packet>,
+        tx: burstq::Sender<bab::SendPacket>,
     ) -> TransportIndex {
-        let (response_tx, response_rx) = oneshot::channel();
-
-        self.request.send(BroadcasterRequest::AddLocal {
-            tx,
-            response_tx,
-        })
-        .await
-        .unwrap();
-        let transport_index = response_rx.await.unwrap();
+        let transport = self.new_transport(TransportType::Local);
+
+        for shard in self.shards.iter() {
+            let (response_tx, response_rx) = oneshot::channel();
+            send_one(&shard.request_tx, BroadcasterRequest::AddLocal {
+                transport,
+                tx: tx.clone(),
+                response_tx,
+            })
+            .await
+            .unwrap();
+            response_rx.await.unwrap();
+        }
 
-        transport_index
+        transport
     }
 
     pub async fn add_next_hop_to_channels(
@@ -566,31 +649,256 @@ impl BroadcasterHandle {
         next_hop_transport: TransportIndex,
         channel_ids: Vec<(ChannelId, ChannelId)>,
     ) {
-        let (response_tx, response_rx) = oneshot::channel();
+        let mut shard_channel_ids: Vec<Vec<(ChannelId, ChannelId)>> =
+            vec![Vec::new(); self.shards.len()];
+        for (local_channel_id, remote_channel_id) in channel_ids {
+            shard_channel_ids[local_channel_id.channel_id as usize % self.shards.len()]
+                .push((local_channel_id, remote_channel_id));
+        }
 
-        self.request.send(BroadcasterRequest::AddNextHopToChannels {
-            next_hop_transport,
-            channel_ids,
-            response_tx,
-        })
-        .await
-        .unwrap();
-        let _ = response_rx.await.unwrap();
+        for (shard, channel_ids) in self.shards.iter().zip(shard_channel_ids) {
+            if channel_ids.is_empty() {
+                continue;
+            }
+
+            let (response_tx, response_rx) = oneshot::channel();
+            send_one(&shard.request_tx, BroadcasterRequest::AddNextHopToChannels {
+                next_hop_transport,
+                channel_ids,
+                response_tx,
+            })
+            .await
+            .unwrap();
+            let _ = response_rx.await.unwrap();
+        }
     }
 
-    pub async fn remove_transport(
+    /// Compress bundles sent to a transport from now on. The spoke must only do this once the
+    /// client has said it can decompress them.
+    pub async fn enable_compression(
         &self,
         transport: TransportIndex,
+        config: CompressionConfig,
     ) {
+        for shard in self.shards.iter() {
+            // Don't care if the broadcaster has already shut down
+            let _ = send_one(
+                &shard.request_tx,
+                BroadcasterRequest::EnableCompression { transport, config },
+            )
+            .await;
+        }
+    }
+
+    /// Only send a spoke the packets on the given topics from now on. Subscriptions addressed to
+    /// an endpoint are only honored if the spoke's `endpoint` is known.
+    pub async fn set_topic_subscriptions(
+        &self,
+        transport: TransportIndex,
+        endpoint: Option<EndpointAddr>,
+        subscriptions: Vec<TopicSubscription>,
+    ) {
+        let topics: HashMap<u32, TopicSubscription> = subscriptions.into_iter()
+            .map(|subscription| (subscription.topic, subscription))
+            .collect();
+
+        for shard in self.shards.iter() {
+            let (response_tx, response_rx) = oneshot::channel();
+            let subscriber = TopicSubscriber { endpoint, topics: topics.clone() };
+            // Don't care if the broadcaster has already shut down
+            if send_one(
+                &shard.request_tx,
+                BroadcasterRequest::SetTopicSubscriptions { transport, subscriber, response_tx },
+            )
+            .await
+            .is_ok()
+            {
+                let _ = response_rx.await;
+            }
+        }
+    }
+
+    /// Send a keepalive bundle to a single transport, bypassing channel routing.
+    pub async fn send_keepalive(
+        &self,
+        transport: TransportIndex,
+    ) {
+        // Every shard knows about every spoke, so it doesn't matter which one sends it.
+        // Don't care if the broadcaster has already shut down
+        let _ = send_one(&self.shards[0].request_tx, BroadcasterRequest::SendKeepalive { transport })
+            .await;
+    }
+
+    /// Egress queue stats for every TCP and WebSocket spoke.
+    pub async fn egress_stats(&self) -> HashMap<TransportIndex, SpokeEgressStats> {
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.request.send(BroadcasterRequest::Remove {
-            transport,
-            response_tx,
-        })
-        .await
-        .unwrap();
-        let _ = response_rx.await.unwrap();
+        send_one(&self.shards[0].request_tx, BroadcasterRequest::GetEgressStats { response_tx })
+            .await
+            .unwrap();
+        response_rx.await.unwrap()
+    }
+
+    pub async fn remove_transport(
+        &self,
+        transport: TransportIndex,
+    ) {
+        for shard in self.shards.iter() {
+            let (response_tx, response_rx) = oneshot::channel();
+
+            send_one(&shard.request_tx, BroadcasterRequest::Remove {
+                transport,
+                response_tx,
+            })
+            .await
+            .unwrap();
+            let _ = response_rx.await.unwrap();
+        }
     }
 }
 
+#[cfg(all(test, feature = "tcp-transport"))]
+mod test {
+    use modrpc_executor::ModrpcExecutor;
+
+    use super::*;
+    use crate::SpokeEgressConfig;
+
+    fn new_packet(buffer_pool: &bab::HeapBufferPool, payload: &[u8]) -> Packet {
+        let buffer = buffer_pool.try_acquire().unwrap();
+        unsafe {
+            buffer.initialize_rc(1, 0, 0);
+            core::ptr::copy_nonoverlapping(payload.as_ptr(), buffer.data(), payload.len());
+            Packet::new(buffer, 0, payload.len())
+        }
+    }
+
+    #[test]
+    fn test_sharded_broadcast() {
+        let mut ex = modrpc_executor::FuturesExecutor::new();
+        let mut rt = modrpc::RuntimeBuilder::new_with_local(ex.spawner());
+        let broadcaster_group = rt.new_worker_group(2);
+        let (rt, rt_shutdown) = rt.start::<modrpc_executor::FuturesExecutor>();
+        let buffer_pool = bab::HeapBufferPool::new(64, 4, 4);
+
+        ex.run_until(async {
+            let broadcaster =
+                Broadcaster::spawn_sharded(&rt, broadcaster_group.worker_ids(), 64).await;
+            assert_eq!(broadcaster.shard_count(), 2);
+
+            let source_egress = SpokeEgressQueue::new(SpokeEgressConfig::default());
+            let source = broadcaster.add_tcp(source_egress.clone()).await;
+            let sink_egress = SpokeEgressQueue::new(SpokeEgressConfig::default());
+            let sink = broadcaster.add_tcp(sink_egress.clone()).await;
+
+            // Channels 1 and 2 are broadcast by different shards.
+            let channel_ids: Vec<_> = [1, 2].into_iter()
+                .map(|channel_id| (ChannelId { channel_id }, ChannelId { channel_id: channel_id + 10 }))
+                .collect();
+            broadcaster.add_next_hop_to_channels(source, channel_ids.clone()).await;
+            broadcaster.add_next_hop_to_channels(sink, channel_ids).await;
+
+            for (channel_id, payload) in [(1, b"one"), (2, b"two")] {
+                broadcaster.send_in_packet(InPacket {
+                    transport: source,
+                    channel_id,
+                    packet: new_packet(&buffer_pool, payload),
+                })
+                .await
+                .unwrap();
+            }
+
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                let bundle = sink_egress.pop().await.unwrap();
+                received.push((bundle.channel_id, bundle.payload.to_vec()));
+            }
+            received.sort();
+            assert_eq!(received, vec![(11, b"one".to_vec()), (12, b"two".to_vec())]);
+
+            // Bundles aren't sent back to where they came from.
+            assert_eq!(source_egress.stats().max_queue_depth, 0);
+
+            broadcaster.remove_transport(sink).await;
+            assert!(sink_egress.is_closed());
+        });
+
+        drop(buffer_pool);
+        ex.run_until(rt_shutdown.shutdown());
+    }
+
+    fn transmit_packet(topic: u32, payload: &[u8]) -> Vec<u8> {
+        let mut packet = mproto::encode_value_vec(TransmitPacket {
+            payload_length: payload.len() as u16,
+            infra_id: 0,
+            plane_id: 1,
+            topic,
+            source: EndpointAddr { endpoint: 1 },
+        });
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_topic_subscriptions() {
+        let mut ex = modrpc_executor::FuturesExecutor::new();
+        let (rt, rt_shutdown) =
+            modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+            .start::<modrpc_executor::FuturesExecutor>();
+        let buffer_pool = bab::HeapBufferPool::new(256, 4, 4);
+
+        ex.run_until(async {
+            let broadcaster = Broadcaster::spawn_sharded(&rt, [modrpc::WorkerId::local()], 64).await;
+            let channel_ids = vec![(ChannelId { channel_id: 1 }, ChannelId { channel_id: 1 })];
+
+            let source = broadcaster.add_tcp(SpokeEgressQueue::new(SpokeEgressConfig::default())).await;
+            broadcaster.add_next_hop_to_channels(source, channel_ids.clone()).await;
+
+            // Subscribed to topic 0, and to topic 1 events addressed to endpoint 7.
+            let sink_egress = SpokeEgressQueue::new(SpokeEgressConfig::default());
+            let sink = broadcaster.add_tcp(sink_egress.clone()).await;
+            broadcaster.add_next_hop_to_channels(sink, channel_ids.clone()).await;
+            broadcaster.set_topic_subscriptions(
+                sink,
+                Some(EndpointAddr { endpoint: 7 }),
+                vec![TopicSubscription::all(0), TopicSubscription::addressed(1, 0)],
+            )
+            .await;
+
+            // Never subscribed, so receives everything.
+            let all_egress = SpokeEgressQueue::new(SpokeEgressConfig::default());
+            let all = broadcaster.add_tcp(all_egress.clone()).await;
+            broadcaster.add_next_hop_to_channels(all, channel_ids).await;
+
+            let to_7 = transmit_packet(1, &7u64.to_le_bytes());
+            let to_8 = transmit_packet(1, &8u64.to_le_bytes());
+            let bundles = [
+                [transmit_packet(0, b"a"), transmit_packet(2, b"b")].concat(),
+                [to_8.clone(), to_7.clone()].concat(),
+                transmit_packet(2, b"c"),
+                transmit_packet(0, b"d"),
+            ];
+            for bundle in &bundles {
+                broadcaster.send_in_packet(InPacket {
+                    transport: source,
+                    channel_id: 1,
+                    packet: new_packet(&buffer_pool, bundle),
+                })
+                .await
+                .unwrap();
+            }
+
+            let expected = [transmit_packet(0, b"a"), to_7, transmit_packet(0, b"d")];
+            for expected in expected {
+                assert_eq!(sink_egress.pop().await.unwrap().payload.to_vec(), expected);
+            }
+            for bundle in bundles {
+                assert_eq!(all_egress.pop().await.unwrap().payload.to_vec(), bundle);
+            }
+            assert_eq!(sink_egress.stats().queue_depth, 0);
+        });
+
+        drop(buffer_pool);
+        ex.run_until(rt_shutdown.shutdown());
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    cell::RefCell,
+    net::IpAddr,
+    rc::Rc,
+    time::Duration,
+};
+
+use modrpc::{
+    CompressionConfig,
+    CompressionNegotiation,
+    KeepaliveConfig,
+    WorkerContext,
+};
+use mproto::Encode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    shutdown::{wait_notified, TaskTracker},
+    spawn_tcp_spoke,
+    BroadcasterHandle,
+    ChannelId,
+    FederationHello,
+    FederationWelcome,
+    SpokeConfig,
+    SpokeEgressConfig,
+    SpokeHandle,
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+pub struct FederationConfig {
+    /// Must be unique among every hub in the federation.
+    pub hub_id: u32,
+    /// Channels to exchange bundles on with linked hubs, until more are added with
+    /// `Federation::advertise_channels`. A link only carries the channels both hubs advertise.
+    pub channel_ids: Vec<u32>,
+    pub buffer_pool: bab::HeapBufferPool,
+    pub max_packet_size: usize,
+    pub keepalive: Option<KeepaliveConfig>,
+    pub compression: Option<CompressionConfig>,
+    pub spoke_egress: SpokeEgressConfig,
+    /// Addresses downstream hubs may link from. Linked hubs may send anything on the federated
+    /// channels, so `None` - accepting a link from anyone who can reach the listener - is only
+    /// safe on a private network.
+    pub allowed_peers: Option<Vec<IpAddr>>,
+}
+
+/// Links a hub's broadcaster to other hubs.
+///
+/// Each hub has at most one upstream hub, so federated hubs form a tree. Links are ordinary
+/// broadcaster spokes, and the broadcaster never sends a bundle back to the spoke it came from, so
+/// every hub in the tree receives each bundle exactly once. A hub refuses downstream links until
+/// it knows its own path to the root of the tree, and rejects any hub that already appears on that
+/// path.
+///
+/// Linked hubs exchange the channels they relay in their handshake, and advertise them again over
+/// the link whenever they start relaying more. Channels are never withdrawn.
+pub struct Federation {
+    config: FederationConfig,
+    broadcaster_handle: BroadcasterHandle,
+    // Channels advertised to linked hubs.
+    channel_ids: RefCell<Vec<u32>>,
+    links: RefCell<Vec<Rc<FederationLink>>>,
+    // This hub followed by its upstream hubs. None while the upstream link is down.
+    path: RefCell<Option<Vec<u32>>>,
+    path_waiters: localq::WaiterQueue,
+    downstream_hubs: RefCell<Vec<u32>>,
+    shutdown_signal: bab::SignalTree,
+    tasks: Rc<TaskTracker>,
+}
+
+impl Federation {
+    pub fn new(config: FederationConfig, broadcaster_handle: BroadcasterHandle) -> Rc<Self> {
+        let path = vec![config.hub_id];
+        let channel_ids = config.channel_ids.clone();
+        Rc::new(Self {
+            config,
+            broadcaster_handle,
+            channel_ids: RefCell::new(channel_ids),
+            links: RefCell::new(Vec::new()),
+            path: RefCell::new(Some(path)),
+            path_waiters: localq::WaiterQueue::new(),
+            downstream_hubs: RefCell::new(Vec::new()),
+            shutdown_signal: bab::SignalTree::new(),
+            tasks: TaskTracker::new(),
+        })
+    }
+
+    /// Stop accepting and making links and close every link, resolving once they've all closed.
+    pub async fn shutdown(&self) {
+        self.shutdown_signal.notify();
+        self.tasks.idle().await;
+    }
+
+    pub fn hub_id(&self) -> u32 {
+        self.config.hub_id
+    }
+
+    /// This hub's ID followed by the IDs of its upstream hubs, ending at the root of the tree.
+    /// `None` while the link to the upstream hub is down.
+    pub fn upstream_path(&self) -> Option<Vec<u32>> {
+        self.path.borrow().clone()
+    }
+
+    /// IDs of the hubs currently linked to this one as their upstream.
+    pub fn downstream_hubs(&self) -> Vec<u32> {
+        self.downstream_hubs.borrow().clone()
+    }
+
+    /// Channels this hub exchanges bundles on with linked hubs that advertise them too.
+    pub fn channel_ids(&self) -> Vec<u32> {
+        self.channel_ids.borrow().clone()
+    }
+
+    /// Start exchanging bundles on more channels, advertising them to every linked hub.
+    pub async fn advertise_channels(&self, channel_ids: impl IntoIterator<Item = u32>) {
+        let added = {
+            let mut advertised = self.channel_ids.borrow_mut();
+            let advertised_count = advertised.len();
+            for channel_id in channel_ids {
+                if !advertised.contains(&channel_id) {
+                    advertised.push(channel_id);
+                }
+            }
+            advertised.len() != advertised_count
+        };
+        if !added {
+            return;
+        }
+
+        let channel_ids = self.channel_ids();
+        let links = self.links.borrow().clone();
+        for link in links {
+            if link.spoke.advertise_channels(channel_ids.clone()).await.is_ok() {
+                self.link_channels(&link).await;
+            }
+        }
+    }
+
+    /// Accept links from downstream hubs on `listener`, from the addresses in
+    /// `FederationConfig::allowed_peers`.
+    pub fn spawn_listener(self: &Rc<Self>, worker_cx: &WorkerContext, listener: tokio::net::TcpListener) {
+        let this = self.clone();
+        let worker_cx = worker_cx.clone();
+        worker_cx.clone().spawn(self.tasks.track(async move {
+            if let Ok(local_addr) = listener.local_addr() {
+                log::info!("Accepting modrpc_hub federation links on tcp://{local_addr}");
+            }
+
+            let accept_loop = async {
+                loop {
+                    let (stream, peer_addr) = match listener.accept().await {
+                        Ok(s) => s,
+                        Err(e) => {
+                            log::error!("Failed to accept federation link: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Some(allowed_peers) = &this.config.allowed_peers
+                        && !allowed_peers.contains(&peer_addr.ip())
+                    {
+                        log::warn!("Refusing federation link from {peer_addr}: not an allowed peer");
+                        continue;
+                    }
+
+                    worker_cx.spawn(this.tasks.track({
+                        let this = this.clone();
+                        let worker_cx = worker_cx.clone();
+                        async move {
+                            if let Err(e) = this.accept_downstream(&worker_cx, stream).await {
+                                log::error!("Failed to link downstream hub {peer_addr}: {e}");
+                            }
+                        }
+                    }));
+                }
+            };
+            futures_lite::future::or(accept_loop, wait_notified(&this.shutdown_signal)).await;
+        }));
+    }
+
+    /// Keep a link to an upstream hub's federation listener, reconnecting whenever it drops.
+    pub fn spawn_upstream(
+        self: &Rc<Self>,
+        worker_cx: &WorkerContext,
+        upstream_addr: std::net::SocketAddr,
+    ) {
+        // Downstream links have to wait until we know where we are in the tree.
+        *self.path.borrow_mut() = None;
+
+        let this = self.clone();
+        let worker_cx = worker_cx.clone();
+        worker_cx.clone().spawn(self.tasks.track(async move {
+            while !this.shutdown_signal.is_notified() {
+                match this.connect_upstream(&worker_cx, upstream_addr).await {
+                    Ok(link_shutdown) => {
+                        wait_notified(&link_shutdown).await;
+                        log::warn!("Lost link to upstream hub {upstream_addr}");
+                        *this.path.borrow_mut() = None;
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to link upstream hub {upstream_addr}: {e}");
+                    }
+                }
+                futures_lite::future::or(
+                    worker_cx.sleep(RECONNECT_DELAY),
+                    wait_notified(&this.shutdown_signal),
+                )
+                .await;
+            }
+        }));
+    }
+
+    async fn accept_downstream(
+        self: &Rc<Self>,
+        worker_cx: &WorkerContext,
+        mut stream: tokio::net::TcpStream,
+    ) -> std::io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        let (hello, channel_ids) = self.until_shutdown(async {
+            let hello_bytes = with_timeout(worker_cx, read_message(&mut stream)).await?;
+            let hello: FederationHello = mproto::decode_value(&hello_bytes)
+                .map_err(std::io::Error::other)?;
+
+            let path = self.path_waiters
+                .wait_for(|| self.path.borrow().clone())
+                .await;
+
+            if path.contains(&hello.hub_id) {
+                write_message(&mut stream, FederationWelcome {
+                    accepted: false,
+                    upstream_path: path,
+                    channel_ids: Vec::new(),
+                })
+                .await?;
+                return Err(std::io::Error::other(format!(
+                    "hub {} is already upstream of this hub", hello.hub_id,
+                )));
+            }
+
+            let channel_ids = self.channel_ids();
+            write_message(&mut stream, FederationWelcome {
+                accepted: true,
+                upstream_path: path,
+                channel_ids: channel_ids.clone(),
+            })
+            .await?;
+
+            Ok((hello, channel_ids))
+        })
+        .await?;
+
+        log::info!("Linked downstream hub {}", hello.hub_id);
+        let link_shutdown = self
+            .add_link(
+                worker_cx,
+                stream,
+                CompressionNegotiation::Offer,
+                channel_ids,
+                hello.channel_ids,
+            )
+            .await;
+        self.downstream_hubs.borrow_mut().push(hello.hub_id);
+
+        let this = self.clone();
+        worker_cx.spawn(self.tasks.track(async move {
+            wait_notified(&link_shutdown).await;
+            log::info!("Lost link to downstream hub {}", hello.hub_id);
+            this.downstream_hubs.borrow_mut().retain(|&hub_id| hub_id != hello.hub_id);
+        }));
+
+        Ok(())
+    }
+
+    async fn connect_upstream(
+        self: &Rc<Self>,
+        worker_cx: &WorkerContext,
+        upstream_addr: std::net::SocketAddr,
+    ) -> std::io::Result<bab::SignalTree> {
+        let (stream, channel_ids, welcome) = self.until_shutdown(async {
+            let mut stream = tokio::net::TcpStream::connect(upstream_addr).await?;
+            stream.set_nodelay(true)?;
+
+            let channel_ids = self.channel_ids();
+            write_message(&mut stream, FederationHello {
+                hub_id: self.config.hub_id,
+                channel_ids: channel_ids.clone(),
+            })
+            .await?;
+
+            let welcome_bytes = with_timeout(worker_cx, read_message(&mut stream)).await?;
+            let welcome: FederationWelcome = mproto::decode_value(&welcome_bytes)
+                .map_err(std::io::Error::other)?;
+
+            Ok((stream, channel_ids, welcome))
+        })
+        .await?;
+
+        if !welcome.accepted || welcome.upstream_path.contains(&self.config.hub_id) {
+            return Err(std::io::Error::other(format!(
+                "linking would create a loop through hubs {:?}", welcome.upstream_path,
+            )));
+        }
+
+        log::info!("Linked upstream hub path={:?}", welcome.upstream_path);
+
+        let link_shutdown = self
+            .add_link(
+                worker_cx,
+                stream,
+                CompressionNegotiation::Answer,
+                channel_ids,
+                welcome.channel_ids,
+            )
+            .await;
+
+        let mut path = vec![self.config.hub_id];
+        path.extend(welcome.upstream_path);
+        *self.path.borrow_mut() = Some(path);
+        self.path_waiters.notify_all();
+
+        Ok(link_shutdown)
+    }
+
+    /// Start a spoke for a link whose handshake advertised `channel_ids` to the linked hub, which
+    /// advertised `peer_channel_ids`.
+    async fn add_link(
+        self: &Rc<Self>,
+        worker_cx: &WorkerContext,
+        stream: tokio::net::TcpStream,
+        compression_negotiation: CompressionNegotiation,
+        channel_ids: Vec<u32>,
+        peer_channel_ids: Vec<u32>,
+    ) -> bab::SignalTree {
+        let config = &self.config;
+        let spoke = spawn_tcp_spoke(
+            worker_cx,
+            self.broadcaster_handle.clone(),
+            config.buffer_pool.clone(),
+            stream,
+            config.max_packet_size,
+            SpokeConfig {
+                keepalive: config.keepalive,
+                compression: config.compression,
+                compression_negotiation,
+                egress: config.spoke_egress,
+                ingress_policy: None,
+            },
+        )
+        .await;
+
+        let link = Rc::new(FederationLink {
+            spoke: spoke.clone(),
+            peer_channel_ids: RefCell::new(peer_channel_ids),
+            linked_channel_ids: RefCell::new(Vec::new()),
+        });
+        self.links.borrow_mut().push(link.clone());
+
+        // Catch the linked hub up on channels added during the handshake.
+        if self.channel_ids() != channel_ids {
+            let _ = spoke.advertise_channels(self.channel_ids()).await;
+        }
+        self.link_channels(&link).await;
+
+        let this = self.clone();
+        worker_cx.spawn(self.tasks.track(async move {
+            let advertisements = async {
+                loop {
+                    let peer_channel_ids = link.spoke.next_advertised_channels().await;
+                    *link.peer_channel_ids.borrow_mut() = peer_channel_ids;
+                    this.link_channels(&link).await;
+                }
+            };
+            let link_closed = futures_lite::future::or(
+                wait_notified(link.spoke.shutdown_signal()),
+                wait_notified(&this.shutdown_signal),
+            );
+            futures_lite::future::or(advertisements, link_closed).await;
+            this.links.borrow_mut().retain(|other| !Rc::ptr_eq(other, &link));
+            link.spoke.close();
+            link.spoke.exited().await;
+        }));
+
+        spoke.shutdown_signal().clone()
+    }
+
+    /// Run a link's handshake, giving up if the federation shuts down first.
+    async fn until_shutdown<T>(
+        &self,
+        handshake: impl Future<Output = std::io::Result<T>>,
+    ) -> std::io::Result<T> {
+        futures_lite::future::or(handshake, async {
+            wait_notified(&self.shutdown_signal).await;
+            Err(std::io::ErrorKind::ConnectionAborted.into())
+        })
+        .await
+    }
+
+    /// Relay the channels both ends of a link advertise that aren't relayed over it yet.
+    async fn link_channels(&self, link: &FederationLink) {
+        let channel_ids: Vec<_> = {
+            let peer_channel_ids = link.peer_channel_ids.borrow();
+            let mut linked_channel_ids = link.linked_channel_ids.borrow_mut();
+            let channel_ids: Vec<u32> = self.channel_ids.borrow().iter()
+                .copied()
+                .filter(|channel_id| {
+                    peer_channel_ids.contains(channel_id) && !linked_channel_ids.contains(channel_id)
+                })
+                .collect();
+            linked_channel_ids.extend(&channel_ids);
+            channel_ids
+        };
+        if channel_ids.is_empty() {
+            return;
+        }
+
+        let channel_ids = channel_ids.into_iter()
+            .map(|channel_id| (ChannelId { channel_id }, ChannelId { channel_id }))
+            .collect();
+        self.broadcaster_handle.add_next_hop_to_channels(link.spoke.transport(), channel_ids).await;
+    }
+}
+
+struct FederationLink {
+    spoke: SpokeHandle,
+    // Channels the linked hub advertised most recently.
+    peer_channel_ids: RefCell<Vec<u32>>,
+    // Channels relayed over the link so far.
+    linked_channel_ids: RefCell<Vec<u32>>,
+}
+
+async fn with_timeout<T>(
+    worker_cx: &WorkerContext,
+    future: impl Future<Output = std::io::Result<T>>,
+) -> std::io::Result<T> {
+    futures_lite::future::or(future, async {
+        worker_cx.sleep(HANDSHAKE_TIMEOUT).await;
+        Err(std::io::ErrorKind::TimedOut.into())
+    })
+    .await
+}
+
+async fn write_message(
+    stream: &mut tokio::net::TcpStream,
+    message: impl Encode,
+) -> std::io::Result<()> {
+    let message_len = mproto::encoded_len(&message);
+    let mut buf = vec![0u8; 2 + message_len];
+    buf[..2].copy_from_slice(&(message_len as u16).to_le_bytes());
+    mproto::encode_value(message, &mut buf[2..]);
+    stream.write_all(&buf).await
+}
+
+async fn read_message(stream: &mut tokio::net::TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 2];
+    stream.read_exact(&mut len_bytes).await?;
+    let mut message = vec![0u8; u16::from_le_bytes(len_bytes) as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_welcome_roundtrip() {
+        let welcome = FederationWelcome {
+            accepted: true,
+            upstream_path: vec![2, 1],
+            channel_ids: vec![0x42424242],
+        };
+        let bytes = mproto::encode_value_vec(&welcome);
+        let decoded: FederationWelcome = mproto::decode_value(&bytes).unwrap();
+        assert!(decoded.accepted);
+        assert_eq!(decoded.upstream_path, vec![2, 1]);
+        assert_eq!(decoded.channel_ids, vec![0x42424242]);
+    }
+}
@@ -0,0 +1,497 @@
+use core::convert::TryFrom;
+use mproto::{
+    BaseLen, Compatible, Decode, DecodeCursor, DecodeError, DecodeResult, Encode, EncodeCursor,
+    Lazy, Owned,
+};
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct FederationHello {
+    pub hub_id: u32,
+    pub channel_ids: Vec<u32>,
+}
+
+pub struct FederationHelloLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct FederationHelloGen<ChannelIds: Encode + Compatible<Vec<u32>>> {
+    pub hub_id: u32,
+    pub channel_ids: ChannelIds,
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Compatible<FederationHello>
+    for FederationHelloGen<ChannelIds>
+{
+}
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Compatible<FederationHelloGen<ChannelIds>>
+    for FederationHello
+{
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> BaseLen for FederationHelloGen<ChannelIds> {
+    const BASE_LEN: usize = 4 + ChannelIds::BASE_LEN;
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Encode for FederationHelloGen<ChannelIds> {
+    fn scratch_len(&self) -> usize {
+        self.hub_id.scratch_len() + self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.hub_id.encode(cursor);
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl Owned for FederationHello {
+    type Lazy<'a> = FederationHelloLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for FederationHelloLazy<'a> {
+    type Owned = FederationHello;
+}
+
+impl<'a> Compatible<FederationHelloLazy<'a>> for FederationHelloLazy<'a> {}
+impl<'a> Compatible<FederationHelloLazy<'a>> for FederationHello {}
+impl Compatible<FederationHello> for FederationHello {}
+impl<'a> Compatible<FederationHello> for FederationHelloLazy<'a> {}
+
+impl<'a> FederationHelloLazy<'a> {
+    pub fn hub_id(&self) -> DecodeResult<u32> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn channel_ids(&self) -> DecodeResult<mproto::ListLazy<'a, u32>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4))
+    }
+}
+
+impl BaseLen for FederationHello {
+    const BASE_LEN: usize = 12;
+}
+
+impl Encode for FederationHello {
+    fn scratch_len(&self) -> usize {
+        self.hub_id.scratch_len() + self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.hub_id.encode(cursor);
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationHello {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let hub_id = Decode::decode(cursor)?;
+        let channel_ids = Decode::decode(cursor)?;
+
+        Ok(FederationHello {
+            hub_id,
+            channel_ids,
+        })
+    }
+}
+
+impl<'a> BaseLen for FederationHelloLazy<'a> {
+    const BASE_LEN: usize = 12;
+}
+
+impl<'a> Encode for FederationHelloLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let hub_id: u32 =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        hub_id.scratch_len() + channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let hub_id: u32 =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        hub_id.encode(cursor);
+        channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationHelloLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(FederationHelloLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<FederationHelloLazy<'a>> for FederationHello {
+    type Error = DecodeError;
+
+    fn try_from(other: FederationHelloLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for FederationHelloLazy<'a> {}
+
+impl<'a> Clone for FederationHelloLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for FederationHelloLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FederationHelloLazy").finish()
+    }
+}
+
+impl<'a> PartialEq for FederationHelloLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.hub_id().unwrap() == other.hub_id().unwrap()
+            && self.channel_ids().unwrap() == other.channel_ids().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct FederationWelcome {
+    pub accepted: bool,
+    pub upstream_path: Vec<u32>,
+    pub channel_ids: Vec<u32>,
+}
+
+pub struct FederationWelcomeLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct FederationWelcomeGen<
+    UpstreamPath: Encode + Compatible<Vec<u32>>,
+    ChannelIds: Encode + Compatible<Vec<u32>>,
+> {
+    pub accepted: bool,
+    pub upstream_path: UpstreamPath,
+    pub channel_ids: ChannelIds,
+}
+
+impl<UpstreamPath: Encode + Compatible<Vec<u32>>, ChannelIds: Encode + Compatible<Vec<u32>>>
+    Compatible<FederationWelcome> for FederationWelcomeGen<UpstreamPath, ChannelIds>
+{
+}
+impl<UpstreamPath: Encode + Compatible<Vec<u32>>, ChannelIds: Encode + Compatible<Vec<u32>>>
+    Compatible<FederationWelcomeGen<UpstreamPath, ChannelIds>> for FederationWelcome
+{
+}
+
+impl<UpstreamPath: Encode + Compatible<Vec<u32>>, ChannelIds: Encode + Compatible<Vec<u32>>> BaseLen
+    for FederationWelcomeGen<UpstreamPath, ChannelIds>
+{
+    const BASE_LEN: usize = 1 + UpstreamPath::BASE_LEN + ChannelIds::BASE_LEN;
+}
+
+impl<UpstreamPath: Encode + Compatible<Vec<u32>>, ChannelIds: Encode + Compatible<Vec<u32>>> Encode
+    for FederationWelcomeGen<UpstreamPath, ChannelIds>
+{
+    fn scratch_len(&self) -> usize {
+        self.accepted.scratch_len()
+            + self.upstream_path.scratch_len()
+            + self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.accepted.encode(cursor);
+        self.upstream_path.encode(cursor);
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl Owned for FederationWelcome {
+    type Lazy<'a> = FederationWelcomeLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for FederationWelcomeLazy<'a> {
+    type Owned = FederationWelcome;
+}
+
+impl<'a> Compatible<FederationWelcomeLazy<'a>> for FederationWelcomeLazy<'a> {}
+impl<'a> Compatible<FederationWelcomeLazy<'a>> for FederationWelcome {}
+impl Compatible<FederationWelcome> for FederationWelcome {}
+impl<'a> Compatible<FederationWelcome> for FederationWelcomeLazy<'a> {}
+
+impl<'a> FederationWelcomeLazy<'a> {
+    pub fn accepted(&self) -> DecodeResult<bool> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn upstream_path(&self) -> DecodeResult<mproto::ListLazy<'a, u32>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 1))
+    }
+
+    pub fn channel_ids(&self) -> DecodeResult<mproto::ListLazy<'a, u32>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9))
+    }
+}
+
+impl BaseLen for FederationWelcome {
+    const BASE_LEN: usize = 17;
+}
+
+impl Encode for FederationWelcome {
+    fn scratch_len(&self) -> usize {
+        self.accepted.scratch_len()
+            + self.upstream_path.scratch_len()
+            + self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.accepted.encode(cursor);
+        self.upstream_path.encode(cursor);
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationWelcome {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let accepted = Decode::decode(cursor)?;
+        let upstream_path = Decode::decode(cursor)?;
+        let channel_ids = Decode::decode(cursor)?;
+
+        Ok(FederationWelcome {
+            accepted,
+            upstream_path,
+            channel_ids,
+        })
+    }
+}
+
+impl<'a> BaseLen for FederationWelcomeLazy<'a> {
+    const BASE_LEN: usize = 17;
+}
+
+impl<'a> Encode for FederationWelcomeLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let accepted: bool =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let upstream_path: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 1)).unwrap();
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9)).unwrap();
+        accepted.scratch_len() + upstream_path.scratch_len() + channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let accepted: bool =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let upstream_path: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 1)).unwrap();
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9)).unwrap();
+        accepted.encode(cursor);
+        upstream_path.encode(cursor);
+        channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationWelcomeLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(FederationWelcomeLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<FederationWelcomeLazy<'a>> for FederationWelcome {
+    type Error = DecodeError;
+
+    fn try_from(other: FederationWelcomeLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for FederationWelcomeLazy<'a> {}
+
+impl<'a> Clone for FederationWelcomeLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for FederationWelcomeLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FederationWelcomeLazy").finish()
+    }
+}
+
+impl<'a> PartialEq for FederationWelcomeLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.accepted().unwrap() == other.accepted().unwrap()
+            && self.upstream_path().unwrap() == other.upstream_path().unwrap()
+            && self.channel_ids().unwrap() == other.channel_ids().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct FederationChannels {
+    pub channel_ids: Vec<u32>,
+}
+
+pub struct FederationChannelsLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct FederationChannelsGen<ChannelIds: Encode + Compatible<Vec<u32>>> {
+    pub channel_ids: ChannelIds,
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Compatible<FederationChannels>
+    for FederationChannelsGen<ChannelIds>
+{
+}
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Compatible<FederationChannelsGen<ChannelIds>>
+    for FederationChannels
+{
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> BaseLen for FederationChannelsGen<ChannelIds> {
+    const BASE_LEN: usize = ChannelIds::BASE_LEN;
+}
+
+impl<ChannelIds: Encode + Compatible<Vec<u32>>> Encode for FederationChannelsGen<ChannelIds> {
+    fn scratch_len(&self) -> usize {
+        self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl Owned for FederationChannels {
+    type Lazy<'a> = FederationChannelsLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for FederationChannelsLazy<'a> {
+    type Owned = FederationChannels;
+}
+
+impl<'a> Compatible<FederationChannelsLazy<'a>> for FederationChannelsLazy<'a> {}
+impl<'a> Compatible<FederationChannelsLazy<'a>> for FederationChannels {}
+impl Compatible<FederationChannels> for FederationChannels {}
+impl<'a> Compatible<FederationChannels> for FederationChannelsLazy<'a> {}
+
+impl<'a> FederationChannelsLazy<'a> {
+    pub fn channel_ids(&self) -> DecodeResult<mproto::ListLazy<'a, u32>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+}
+
+impl BaseLen for FederationChannels {
+    const BASE_LEN: usize = 8;
+}
+
+impl Encode for FederationChannels {
+    fn scratch_len(&self) -> usize {
+        self.channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationChannels {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let channel_ids = Decode::decode(cursor)?;
+
+        Ok(FederationChannels { channel_ids })
+    }
+}
+
+impl<'a> BaseLen for FederationChannelsLazy<'a> {
+    const BASE_LEN: usize = 8;
+}
+
+impl<'a> Encode for FederationChannelsLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        channel_ids.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let channel_ids: mproto::ListLazy<'a, u32> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        channel_ids.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for FederationChannelsLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(FederationChannelsLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<FederationChannelsLazy<'a>> for FederationChannels {
+    type Error = DecodeError;
+
+    fn try_from(other: FederationChannelsLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for FederationChannelsLazy<'a> {}
+
+impl<'a> Clone for FederationChannelsLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for FederationChannelsLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FederationChannelsLazy").finish()
+    }
+}
+
+impl<'a> PartialEq for FederationChannelsLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.channel_ids().unwrap() == other.channel_ids().unwrap()
+    }
+}
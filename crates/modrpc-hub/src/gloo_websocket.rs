@@ -7,6 +7,7 @@ use mproto::BaseLen;
 
 use crate::{
     broadcaster::InPacket,
+    spoke_egress::{SpokeEgressConfig, SpokeEgressQueue},
     BroadcasterHandle,
     TransportIndex,
 };
@@ -17,12 +18,35 @@ pub async fn spawn_gloo_websocket_spoke(
     buffer_pool: bab::HeapBufferPool,
     websocket: gloo_net::websocket::futures::WebSocket,
     max_packet_size: usize,
+    egress: SpokeEgressConfig,
 ) -> TransportIndex {
-    use futures_util::StreamExt;
+    use futures_util::{SinkExt, StreamExt};
+    use gloo_net::websocket::Message;
 
-    let (ws_tx, ws_rx) = websocket.split();
-    let broadcaster_spoke = broadcaster_handle.add_gloo_ws(Box::new(ws_tx)).await;
-    let to_broadcaster = broadcaster_handle.in_packet_sender().clone();
+    let (mut ws_tx, ws_rx) = websocket.split();
+    let egress = SpokeEgressQueue::new(egress);
+    let broadcaster_spoke = broadcaster_handle.add_gloo_ws(egress.clone()).await;
+    let to_broadcaster = broadcaster_handle.clone();
+    let shutdown_signal = bab::SignalTree::new();
+
+    worker_context.spawn({
+        let shutdown_signal = shutdown_signal.clone();
+        async move {
+            while let Some(bundle) = egress.pop().await {
+                let mut message = Vec::with_capacity(PacketBundle::BASE_LEN + bundle.payload.len());
+                message.extend_from_slice(&bundle.encode_header());
+                message.extend_from_slice(&bundle.payload);
+
+                if let Err(_) = ws_tx.send(Message::Bytes(message)).await {
+                    log::debug!("Gloo WebSocket transport closed: {:?}", broadcaster_spoke);
+                    break;
+                }
+            }
+
+            egress.close();
+            shutdown_signal.notify();
+        }
+    });
 
     let mut ingress = WebSocketIngress::new(
         ws_rx,
@@ -30,28 +54,44 @@ pub async fn spawn_gloo_websocket_spoke(
         max_packet_size,
     );
 
+    let metrics = worker_context.rt().metrics().clone();
     worker_context.spawn(async move {
-        while let Ok(packet_bundle) = ingress.receive().await {
-            let Ok(header) =
-                mproto::decode_value::<PacketBundle>(&packet_bundle[..])
-            else {
-                continue;
-            };
-
-            packet_bundle.advance(PacketBundle::BASE_LEN);
-
-            if let Err(_) =
-                to_broadcaster.send(InPacket {
-                    transport: broadcaster_spoke,
-                    channel_id: header.channel_id,
-                    packet: packet_bundle,
-                })
-                .await
-            {
-                break;
+        let rx_loop = async {
+            loop {
+                let packet_bundle = match ingress.receive().await {
+                    Ok(packet_bundle) => packet_bundle,
+                    Err(e) => {
+                        modrpc::report_transport_error(&metrics, "websocket", &e);
+                        break;
+                    }
+                };
+                // Bundles are relayed whole, so their packets have to be checked here.
+                let header = match modrpc::validate_packet_bundle(&packet_bundle[..]) {
+                    Ok(header) => header,
+                    Err(e) => {
+                        modrpc::report_transport_error(&metrics, "websocket", &e);
+                        break;
+                    }
+                };
+
+                packet_bundle.advance(PacketBundle::BASE_LEN);
+
+                if let Err(_) =
+                    to_broadcaster.send_in_packet(InPacket {
+                        transport: broadcaster_spoke,
+                        channel_id: header.channel_id,
+                        packet: packet_bundle,
+                    })
+                    .await
+                {
+                    break;
+                }
             }
-        }
+        };
+        // Stop reading if the egress task shut the spoke down.
+        futures_lite::future::or(rx_loop, shutdown_signal.wait()).await;
 
+        shutdown_signal.notify();
         broadcaster_handle.remove_transport(broadcaster_spoke).await;
     });
 
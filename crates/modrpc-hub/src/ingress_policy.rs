@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use modrpc::{EndpointAddr, InterfaceBuilder, TransmitPacket};
+use mproto::BaseLen;
+
+use crate::RateLimit;
+
+/// Restricts the bundles a client spoke may send to the plane it joined at handshake.
+#[derive(Clone, Debug)]
+pub struct IngressPolicy {
+    pub plane_id: u32,
+    /// Channels the plane is relayed over.
+    pub channel_ids: Vec<u32>,
+    /// The address the hub assigned the client. Packets claiming any other source are dropped.
+    pub source: EndpointAddr,
+    /// Topics the client may send on, or `None` for any topic.
+    pub allowed_topics: Option<HashSet<u32>>,
+    /// The client is disconnected if it sends faster than this.
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl IngressPolicy {
+    /// Topics of an interface that at least one of `roles` may send on. Topics declared without
+    /// their senders are allowed for every role.
+    pub fn topics_for_roles(ib: &InterfaceBuilder, roles: &[&str]) -> HashSet<u32> {
+        (0..ib.topic_count())
+            .filter(|&topic| match ib.topic_senders(topic) {
+                Some(senders) => senders.iter().any(|sender| roles.contains(sender)),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Whether every packet in a bundle's payload may be relayed. Bundles are dropped whole - a
+    /// well-behaved client never mixes in a packet it isn't allowed to send.
+    pub fn allows_bundle(&self, channel_id: u32, payload: &[u8]) -> bool {
+        if !self.channel_ids.contains(&channel_id) {
+            return false;
+        }
+
+        let mut cursor = 0;
+        while cursor < payload.len() {
+            if cursor + TransmitPacket::BASE_LEN > payload.len() {
+                return false;
+            }
+            let Ok(header) = mproto::decode_value::<TransmitPacket>(&payload[cursor..]) else {
+                return false;
+            };
+            if header.plane_id != self.plane_id || header.source != self.source {
+                return false;
+            }
+            // Key exchanges for encrypted planes aren't sent on any of the interface's topics.
+            if let Some(allowed_topics) = &self.allowed_topics
+                && header.infra_id != modrpc::KEY_EXCHANGE_INFRA_ID
+                && !allowed_topics.contains(&header.topic)
+            {
+                return false;
+            }
+            cursor += TransmitPacket::BASE_LEN + header.payload_length as usize;
+        }
+
+        cursor == payload.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use modrpc::InterfaceEvent;
+
+    use super::*;
+
+    fn packet(plane_id: u32, topic: u32, source: u64, payload: &[u8]) -> Vec<u8> {
+        infra_packet(0, plane_id, topic, source, payload)
+    }
+
+    fn infra_packet(infra_id: u16, plane_id: u32, topic: u32, source: u64, payload: &[u8]) -> Vec<u8> {
+        let mut packet = mproto::encode_value_vec(TransmitPacket {
+            payload_length: payload.len() as u16,
+            infra_id,
+            plane_id,
+            topic,
+            source: EndpointAddr { endpoint: source },
+        });
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn test_allows_bundle() {
+        let mut ib = InterfaceBuilder::new();
+        let request: InterfaceEvent<()> = ib.event_from("request", &["Client"]);
+        let response: InterfaceEvent<()> = ib.event_from("response", &["Server"]);
+
+        let policy = IngressPolicy {
+            plane_id: 5,
+            channel_ids: vec![5, 9],
+            source: EndpointAddr { endpoint: 7 },
+            allowed_topics: Some(IngressPolicy::topics_for_roles(&ib, &["Client"])),
+            rate_limit: None,
+        };
+
+        let mut bundle = packet(5, request.topic, 7, b"hi");
+        bundle.extend(packet(5, request.topic, 7, b"again"));
+        assert!(policy.allows_bundle(5, &bundle));
+        assert!(policy.allows_bundle(9, &bundle));
+
+        // Wrong channel
+        assert!(!policy.allows_bundle(6, &bundle));
+        // Spoofed source
+        assert!(!policy.allows_bundle(5, &packet(5, request.topic, 8, b"hi")));
+        // Another plane
+        assert!(!policy.allows_bundle(5, &packet(6, request.topic, 7, b"hi")));
+        // Key exchanges are allowed alongside the role's topics, but still need the right source.
+        let key_exchange_topic = ib.topic_count() as u32;
+        let mut keyed = bundle.clone();
+        keyed.extend(infra_packet(modrpc::KEY_EXCHANGE_INFRA_ID, 5, key_exchange_topic, 7, b"key"));
+        assert!(policy.allows_bundle(5, &keyed));
+        let spoofed = infra_packet(modrpc::KEY_EXCHANGE_INFRA_ID, 5, key_exchange_topic, 8, b"key");
+        assert!(!policy.allows_bundle(5, &spoofed));
+        // Topic only the server may send
+        bundle.extend(packet(5, response.topic, 7, b"hi"));
+        assert!(!policy.allows_bundle(5, &bundle));
+        // Truncated packet
+        let truncated = packet(5, request.topic, 7, b"hi");
+        assert!(!policy.allows_bundle(5, &truncated[..truncated.len() - 1]));
+        // Truncated header
+        assert!(!policy.allows_bundle(5, &truncated[..4]));
+    }
+}
@@ -0,0 +1,34 @@
+use modrpc::{
+    KeepaliveConfig,
+    WorkerContext,
+};
+
+use crate::{
+    shutdown::wait_notified,
+    spoke::SpokeTaskGuard,
+    BroadcasterHandle,
+    TransportIndex,
+};
+
+/// Periodically ask the broadcaster to send a keepalive bundle to a spoke so that the client can
+/// tell the hub is still alive even when nothing is being broadcast to it.
+pub(crate) fn spawn_spoke_keepalive(
+    worker_context: &WorkerContext,
+    broadcaster_handle: BroadcasterHandle,
+    spoke: TransportIndex,
+    keepalive: KeepaliveConfig,
+    shutdown_signal: bab::SignalTree,
+    task_guard: std::rc::Rc<SpokeTaskGuard>,
+) {
+    let worker_cx = worker_context.clone();
+    worker_context.spawn(futures_lite::future::or(
+        async move {
+            let _task_guard = task_guard;
+            loop {
+                worker_cx.sleep(keepalive.interval).await;
+                broadcaster_handle.send_keepalive(spoke).await;
+            }
+        },
+        async move { wait_notified(&shutdown_signal).await },
+    ));
+}
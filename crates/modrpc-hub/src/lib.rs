@@ -1,4 +1,5 @@
 pub use app_hub::{
+    AppHub,
     AppHubBuilder,
     AppHubDelegate,
 };
@@ -6,10 +7,47 @@ pub use broadcaster::{
     ChannelId,
     Broadcaster,
     BroadcasterHandle,
+    BroadcasterShutdown,
+    InPacket,
     TransportIndex,
 };
+pub use federation_proto::{
+    FederationChannels,
+    FederationChannelsGen,
+    FederationChannelsLazy,
+    FederationHello,
+    FederationHelloGen,
+    FederationHelloLazy,
+    FederationWelcome,
+    FederationWelcomeGen,
+    FederationWelcomeLazy,
+};
+pub use ingress_policy::IngressPolicy;
+pub use limits::{
+    ConnectionLimits,
+    ConnectionStats,
+    RateLimit,
+};
 pub use local::LocalHubTransport;
+pub use shutdown::HubShutdownConfig;
+pub use spoke::{
+    CHANNEL_ADVERTISEMENT_CHANNEL_ID,
+    SpokeConfig,
+    SpokeHandle,
+};
+pub use spoke_egress::{
+    EgressPushError,
+    OverflowPolicy,
+    SpokeEgressConfig,
+    SpokeEgressQueue,
+    SpokeEgressStats,
+};
 
+#[cfg(feature = "tcp-transport")]
+pub use federation::{
+    Federation,
+    FederationConfig,
+};
 #[cfg(feature = "tcp-transport")]
 pub use tcp::spawn_tcp_spoke;
 
@@ -20,8 +58,17 @@ pub use gloo_websocket::spawn_gloo_websocket_spoke;
 
 pub mod app_hub;
 mod broadcaster;
+mod federation_proto;
+mod ingress_policy;
+mod keepalive;
+mod limits;
 mod local;
+mod shutdown;
+mod spoke;
+mod spoke_egress;
 
+#[cfg(feature = "tcp-transport")]
+pub mod federation;
 #[cfg(feature = "tcp-transport")]
 pub mod tcp;
 #[cfg(feature = "websocket-transport")]
@@ -0,0 +1,247 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    net::IpAddr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use modrpc::{Counter, MetricsRegistry, PacketBundle, TransmitPacket};
+use mproto::BaseLen;
+
+/// Limits a hub enforces on its clients. Every limit is off by default.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ConnectionLimits {
+    /// Clients beyond this many, counting those still handshaking, are disconnected on accept.
+    pub max_connections: Option<usize>,
+    pub max_connections_per_ip: Option<usize>,
+    /// How long a client has to send its hello and be assigned a plane.
+    pub handshake_timeout: Option<Duration>,
+    /// Rates each client spoke is disconnected for exceeding.
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// Sustained ingress rates for a spoke. A spoke may burst up to one second's worth.
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimit {
+    pub packets_per_second: u32,
+    /// Counts whole bundles, headers included.
+    pub bytes_per_second: u32,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    pub connections: usize,
+    /// Connections closed on accept for exceeding `max_connections` or `max_connections_per_ip`.
+    pub rejected_connections: u64,
+    pub handshake_timeouts: u64,
+    /// Clients disconnected for exceeding `ConnectionLimits::rate_limit`.
+    pub rate_limited_disconnects: u64,
+}
+
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    packet_tokens: f64,
+    byte_tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            packet_tokens: limit.packets_per_second as f64,
+            byte_tokens: limit.bytes_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Account for a bundle received from the spoke. Returns false once the spoke exceeds its
+    /// limit.
+    pub fn allow_bundle(&mut self, packet_bundle: &[u8]) -> bool {
+        self.allow(Instant::now(), count_packets(packet_bundle), packet_bundle.len())
+    }
+
+    fn allow(&mut self, now: Instant, packets: usize, bytes: usize) -> bool {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+
+        let packets_per_second = self.limit.packets_per_second as f64;
+        let bytes_per_second = self.limit.bytes_per_second as f64;
+        self.packet_tokens =
+            (self.packet_tokens + elapsed * packets_per_second).min(packets_per_second);
+        self.byte_tokens = (self.byte_tokens + elapsed * bytes_per_second).min(bytes_per_second);
+
+        self.packet_tokens -= packets as f64;
+        self.byte_tokens -= bytes as f64;
+        self.packet_tokens >= 0.0 && self.byte_tokens >= 0.0
+    }
+}
+
+fn count_packets(packet_bundle: &[u8]) -> usize {
+    let mut count = 0;
+    let mut cursor = PacketBundle::BASE_LEN;
+    while cursor + TransmitPacket::BASE_LEN <= packet_bundle.len() {
+        let Ok(header) = mproto::decode_value::<TransmitPacket>(&packet_bundle[cursor..]) else {
+            break;
+        };
+        count += 1;
+        cursor += TransmitPacket::BASE_LEN + header.payload_length as usize;
+    }
+    count
+}
+
+/// Counts a hub's connections against its `ConnectionLimits`.
+pub(crate) struct ConnectionTracker {
+    limits: ConnectionLimits,
+    connections_per_ip: RefCell<HashMap<IpAddr, usize>>,
+    stats: Cell<ConnectionStats>,
+    rejected_connections: Counter,
+    handshake_timeouts: Counter,
+    rate_limited_disconnects: Counter,
+}
+
+impl ConnectionTracker {
+    /// Rejections, handshake timeouts and rate limited disconnects are also counted in `metrics`.
+    pub fn new(limits: ConnectionLimits, metrics: &MetricsRegistry) -> Rc<Self> {
+        Rc::new(Self {
+            limits,
+            connections_per_ip: RefCell::new(HashMap::new()),
+            stats: Cell::new(ConnectionStats::default()),
+            rejected_connections: metrics.counter(
+                "modrpc_hub_rejected_connections",
+                "Clients closed on accept for exceeding the hub's connection limits.",
+                &[],
+            ),
+            handshake_timeouts: metrics.counter(
+                "modrpc_hub_handshake_timeouts",
+                "Clients disconnected for not finishing their handshake in time.",
+                &[],
+            ),
+            rate_limited_disconnects: metrics.counter(
+                "modrpc_hub_rate_limited_disconnects",
+                "Clients disconnected for exceeding the hub's ingress rate limit.",
+                &[],
+            ),
+        })
+    }
+
+    pub fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        self.stats.get()
+    }
+
+    /// Admit a new connection from `ip`, or `None` if that would exceed a limit. The connection
+    /// is counted until the permit is dropped.
+    pub fn try_admit(self: &Rc<Self>, ip: IpAddr) -> Option<ConnectionPermit> {
+        let mut stats = self.stats.get();
+        let mut connections_per_ip = self.connections_per_ip.borrow_mut();
+        let ip_connections = connections_per_ip.get(&ip).copied().unwrap_or(0);
+
+        let over_limit =
+            self.limits.max_connections.is_some_and(|max| stats.connections >= max)
+            || self.limits.max_connections_per_ip.is_some_and(|max| ip_connections >= max);
+        if over_limit {
+            stats.rejected_connections += 1;
+            self.stats.set(stats);
+            self.rejected_connections.inc();
+            return None;
+        }
+
+        stats.connections += 1;
+        self.stats.set(stats);
+        connections_per_ip.insert(ip, ip_connections + 1);
+
+        Some(ConnectionPermit { tracker: self.clone(), ip })
+    }
+
+    pub fn handshake_timed_out(&self) {
+        let mut stats = self.stats.get();
+        stats.handshake_timeouts += 1;
+        self.stats.set(stats);
+        self.handshake_timeouts.inc();
+    }
+
+    pub fn rate_limited_disconnect(&self) {
+        let mut stats = self.stats.get();
+        stats.rate_limited_disconnects += 1;
+        self.stats.set(stats);
+        self.rate_limited_disconnects.inc();
+    }
+}
+
+pub(crate) struct ConnectionPermit {
+    tracker: Rc<ConnectionTracker>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut stats = self.tracker.stats.get();
+        stats.connections -= 1;
+        self.tracker.stats.set(stats);
+
+        let mut connections_per_ip = self.tracker.connections_per_ip.borrow_mut();
+        if let Some(ip_connections) = connections_per_ip.get_mut(&self.ip) {
+            *ip_connections -= 1;
+            if *ip_connections == 0 {
+                connections_per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(RateLimit { packets_per_second: 10, bytes_per_second: 100 });
+        let start = limiter.last_refill;
+
+        // A full second's burst is allowed, then nothing more until the buckets refill.
+        assert!(limiter.allow(start, 10, 50));
+        assert!(!limiter.allow(start, 1, 1));
+        // Half a second refills 5 packets, but the failed attempt above still counts.
+        assert!(limiter.allow(start + Duration::from_millis(500), 4, 10));
+        assert!(!limiter.allow(start + Duration::from_millis(500), 0, 100));
+    }
+
+    #[test]
+    fn test_connection_tracker() {
+        let metrics = MetricsRegistry::new();
+        let tracker = ConnectionTracker::new(
+            ConnectionLimits {
+                max_connections: Some(3),
+                max_connections_per_ip: Some(2),
+                ..Default::default()
+            },
+            &metrics,
+        );
+        let a: IpAddr = [10, 0, 0, 1].into();
+        let b: IpAddr = [10, 0, 0, 2].into();
+
+        let a1 = tracker.try_admit(a).unwrap();
+        let _a2 = tracker.try_admit(a).unwrap();
+        assert!(tracker.try_admit(a).is_none());
+        let _b1 = tracker.try_admit(b).unwrap();
+        assert!(tracker.try_admit(b).is_none());
+
+        drop(a1);
+        let _a3 = tracker.try_admit(a).unwrap();
+        assert_eq!(
+            tracker.stats(),
+            ConnectionStats {
+                connections: 3,
+                rejected_connections: 2,
+                handshake_timeouts: 0,
+                rate_limited_disconnects: 0,
+            },
+        );
+        assert_eq!(metrics.counter("modrpc_hub_rejected_connections", "", &[]).get(), 2);
+    }
+}
@@ -31,10 +31,15 @@ impl TransportBuilder for LocalHubTransport {
             panic!("modrpc runtime must have a local worker to create a LocalHubTransport.");
         };
 
+        let shutdown_signal = bab::SignalTree::new();
+
         let (buffer_pool, broadcaster_handle, broadcaster_transport) = match self {
             Self::Static { buffer_pool, broadcaster_handle, channel_ids } => {
-                let broadcaster_transport =
-                    broadcaster_handle.add_local(worker_cx.local_packet_tx().clone()).await;
+                // The broadcaster may be running on other workers - forward the packets it
+                // broadcasts to us to this worker's packet processor.
+                let (local_tx, local_rx) = burstq::mpmc(64);
+                spawn_local_forwarder(worker_cx, local_rx, shutdown_signal.clone());
+                let broadcaster_transport = broadcaster_handle.add_local(local_tx).await;
 
                 for channel_id in channel_ids {
                     broadcaster_handle.add_next_hop_to_channels(
@@ -53,8 +58,7 @@ impl TransportBuilder for LocalHubTransport {
             }
         };
 
-        let shutdown_signal = bab::SignalTree::new();
-        let broadcaster_sender = broadcaster_handle.in_packet_sender().clone();
+        let broadcaster_sender = broadcaster_handle.clone();
 
         let (writer_flush_sender, mut writer_flush_receiver) = bab::new_writer_flusher();
         let writer_config = WriterConfig::LocalFlush {
@@ -80,7 +84,7 @@ impl TransportBuilder for LocalHubTransport {
                                         packet: flush.into(),
                                     };
                                     probius::trace_branch_start();
-                                    if let Err(e) = broadcaster_sender.send(in_packet).await {
+                                    if let Err(e) = broadcaster_sender.send_in_packet(in_packet).await {
                                         probius::trace_label("send_fail_abort");
                                         probius::trace_branch_end();
                                         return Err(e);
@@ -104,10 +108,27 @@ impl TransportBuilder for LocalHubTransport {
             }
         });
 
-        TransportHandle {
-            buffer_pool,
-            writer_config,
-            shutdown_signal,
-        }
+        TransportHandle::new(buffer_pool, writer_config, shutdown_signal)
     }
 }
+
+fn spawn_local_forwarder(
+    worker_cx: &modrpc::WorkerContext,
+    local_rx: burstq::Receiver<bab::SendPacket>,
+    shutdown_signal: bab::SignalTree,
+) {
+    let local_packet_tx = worker_cx.local_packet_tx().clone();
+    worker_cx.spawn(futures_lite::future::or(
+        async move {
+            let mut packets = Vec::with_capacity(32);
+            while local_rx.recv(32, |r| packets.extend(r.into_iter())).await.is_ok() {
+                for packet in packets.drain(..) {
+                    if local_packet_tx.send(packet.receive()).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        },
+        shutdown_signal.wait_owned(),
+    ));
+}
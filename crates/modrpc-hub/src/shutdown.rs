@@ -0,0 +1,98 @@
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+use waitq::WaiterQueue;
+
+/// How `AppHub::shutdown` says goodbye to connected clients.
+#[derive(Copy, Clone, Debug)]
+pub struct HubShutdownConfig {
+    /// Send each client a goodbye bundle so it closes its transport right away instead of
+    /// waiting for the stream to end.
+    pub goodbye: bool,
+    /// How long to keep writing bundles already queued for clients before closing them anyway.
+    pub drain_timeout: Duration,
+}
+
+impl Default for HubShutdownConfig {
+    fn default() -> Self {
+        Self {
+            goodbye: true,
+            drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Wait for a signal that other tasks on this worker also wait on. Waiters cancelled on the same
+/// thread can strand the rest of a signal's queue, so this waits on a child signal with a queue
+/// of its own.
+pub(crate) async fn wait_notified(signal: &bab::SignalTree) {
+    let notified = bab::SignalTree::new();
+    signal.add_child(notified.clone());
+    notified.wait().await;
+}
+
+/// Counts the tasks a hub has spawned so shutdown can wait for them to exit.
+pub(crate) struct TaskTracker {
+    running: Cell<usize>,
+    idle: WaiterQueue<()>,
+}
+
+impl TaskTracker {
+    pub fn new() -> Rc<Self> {
+        Rc::new(Self {
+            running: Cell::new(0),
+            idle: WaiterQueue::new(),
+        })
+    }
+
+    pub fn track<F: Future>(
+        self: &Rc<Self>,
+        future: F,
+    ) -> impl Future<Output = F::Output> + use<F> {
+        self.running.set(self.running.get() + 1);
+        let guard = TaskGuard { tracker: self.clone() };
+        async move {
+            let _guard = guard;
+            future.await
+        }
+    }
+
+    /// Resolves once every tracked task has exited.
+    pub async fn idle(&self) {
+        self.idle.wait_for(|| (self.running.get() == 0).then_some(())).await
+    }
+}
+
+struct TaskGuard {
+    tracker: Rc<TaskTracker>,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        let running = self.tracker.running.get() - 1;
+        self.tracker.running.set(running);
+        if running == 0 {
+            self.tracker.idle.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_task_tracker() {
+        let tracker = TaskTracker::new();
+        let task = tracker.track(async {});
+        assert_eq!(tracker.running.get(), 1);
+
+        futures_lite::future::block_on(async {
+            futures_lite::future::zip(tracker.idle(), task).await;
+        });
+        assert_eq!(tracker.running.get(), 0);
+
+        // Tasks dropped without running to completion count as exited too.
+        drop(tracker.track(async {}));
+        futures_lite::future::block_on(tracker.idle());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use modrpc::{
+    CompressionConfig,
+    CompressionNegotiation,
+    KeepaliveConfig,
+};
+
+use crate::{
+    federation_proto::FederationChannels,
+    shutdown::wait_notified,
+    spoke_egress::{EgressBundle, EgressPayload, EgressPushError, SpokeEgressConfig, SpokeEgressQueue},
+    IngressPolicy,
+    TransportIndex,
+};
+
+/// Control channel of the bundles federated hubs advertise the channels they relay with. The
+/// payload is a `FederationChannels`.
+pub const CHANNEL_ADVERTISEMENT_CHANNEL_ID: u32 = u32::MAX - 4;
+
+/// How a tcp or websocket spoke talks to its peer.
+#[derive(Clone, Debug)]
+pub struct SpokeConfig {
+    pub keepalive: Option<KeepaliveConfig>,
+    /// Compression the peer may use for the bundles it sends us.
+    pub compression: Option<CompressionConfig>,
+    /// Whether the spoke offers compression or waits for the peer's offer. Websocket spokes
+    /// always offer.
+    pub compression_negotiation: CompressionNegotiation,
+    pub egress: SpokeEgressConfig,
+    /// Bundles from the peer that don't pass the policy are dropped.
+    pub ingress_policy: Option<IngressPolicy>,
+}
+
+impl Default for SpokeConfig {
+    fn default() -> Self {
+        Self {
+            keepalive: None,
+            compression: None,
+            compression_negotiation: CompressionNegotiation::Offer,
+            egress: SpokeEgressConfig::default(),
+            ingress_policy: None,
+        }
+    }
+}
+
+/// A running tcp or websocket spoke.
+#[derive(Clone)]
+pub struct SpokeHandle {
+    transport: TransportIndex,
+    egress: SpokeEgressQueue,
+    shutdown_signal: bab::SignalTree,
+    exited: bab::SignalTree,
+    advertised_channels: Rc<AdvertisedChannels>,
+    rate_limited: Rc<Cell<bool>>,
+}
+
+/// The latest channel advertisement received from a spoke's peer that hasn't been taken yet.
+struct AdvertisedChannels {
+    channel_ids: RefCell<Option<Vec<u32>>>,
+    waiters: localq::WaiterQueue,
+}
+
+impl SpokeHandle {
+    pub(crate) fn new(
+        transport: TransportIndex,
+        egress: SpokeEgressQueue,
+        shutdown_signal: bab::SignalTree,
+        exited: bab::SignalTree,
+    ) -> Self {
+        Self {
+            transport,
+            egress,
+            shutdown_signal,
+            exited,
+            advertised_channels: Rc::new(AdvertisedChannels {
+                channel_ids: RefCell::new(None),
+                waiters: localq::WaiterQueue::new(),
+            }),
+            rate_limited: Rc::new(Cell::new(false)),
+        }
+    }
+
+    pub fn transport(&self) -> TransportIndex {
+        self.transport
+    }
+
+    /// Notified once the spoke starts shutting down, whether it was closed or its peer went away.
+    pub fn shutdown_signal(&self) -> &bab::SignalTree {
+        &self.shutdown_signal
+    }
+
+    /// Queue a goodbye bundle behind the bundles already queued for the peer.
+    pub async fn send_goodbye(&self) -> Result<(), EgressPushError> {
+        self.egress.push(EgressBundle {
+            channel_id: modrpc::GOODBYE_CHANNEL_ID,
+            payload: EgressPayload::Empty,
+        })
+        .await
+    }
+
+    /// Queue an advertisement of the channels this hub relays, for a federated peer to link the
+    /// channels it relays too. See `Federation`.
+    pub async fn advertise_channels(&self, channel_ids: Vec<u32>) -> Result<(), EgressPushError> {
+        let advertisement = mproto::encode_value_vec(FederationChannels { channel_ids });
+        self.egress.push(EgressBundle {
+            channel_id: CHANNEL_ADVERTISEMENT_CHANNEL_ID,
+            payload: EgressPayload::Shared(advertisement.into()),
+        })
+        .await
+    }
+
+    /// Wait for the peer's next channel advertisement. Only the latest one is kept if several
+    /// arrive in between calls.
+    pub async fn next_advertised_channels(&self) -> Vec<u32> {
+        let advertised_channels = &self.advertised_channels;
+        advertised_channels.waiters
+            .wait_for(|| advertised_channels.channel_ids.borrow_mut().take())
+            .await
+    }
+
+    /// Called by the spoke's ingress task when it reads a channel advertisement.
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    pub(crate) fn receive_channel_advertisement(&self, packet_bundle: &[u8]) {
+        if let Some(channel_ids) = decode_channel_advertisement(packet_bundle) {
+            *self.advertised_channels.channel_ids.borrow_mut() = Some(channel_ids);
+            self.advertised_channels.waiters.notify_all();
+        }
+    }
+
+    /// Whether the spoke was closed for exceeding its ingress policy's rate limit.
+    pub fn was_rate_limited(&self) -> bool {
+        self.rate_limited.get()
+    }
+
+    /// Called by the spoke's ingress task before it closes the spoke for exceeding its rate
+    /// limit.
+    #[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+    pub(crate) fn set_rate_limited(&self) {
+        self.rate_limited.set(true);
+    }
+
+    /// Stop queueing broadcast bundles for the spoke, and close it once the bundles already
+    /// queued have been written.
+    pub fn drain(&self) {
+        self.egress.finish();
+    }
+
+    /// Close the spoke now, discarding any bundles still queued for it.
+    pub fn close(&self) {
+        self.shutdown_signal.notify();
+    }
+
+    /// Resolves once every task the spoke spawned has exited.
+    pub async fn exited(&self) {
+        wait_notified(&self.exited).await;
+    }
+}
+
+#[cfg(any(feature = "tcp-transport", feature = "websocket-transport"))]
+fn decode_channel_advertisement(packet_bundle: &[u8]) -> Option<Vec<u32>> {
+    use mproto::BaseLen;
+
+    let header: modrpc::PacketBundle = mproto::decode_value(packet_bundle).ok()?;
+    if header.channel_id != CHANNEL_ADVERTISEMENT_CHANNEL_ID {
+        return None;
+    }
+    let payload = packet_bundle
+        .get(modrpc::PacketBundle::BASE_LEN..modrpc::PacketBundle::BASE_LEN + header.length as usize)?;
+    let advertisement: FederationChannels = mproto::decode_value(payload).ok()?;
+    Some(advertisement.channel_ids)
+}
+
+/// Held by each of a spoke's tasks. Notifies `SpokeHandle::exited` once the last one is dropped.
+pub(crate) struct SpokeTaskGuard {
+    exited: bab::SignalTree,
+}
+
+impl SpokeTaskGuard {
+    pub fn new(exited: bab::SignalTree) -> std::rc::Rc<Self> {
+        std::rc::Rc::new(Self { exited })
+    }
+}
+
+impl Drop for SpokeTaskGuard {
+    fn drop(&mut self) {
+        self.exited.notify();
+    }
+}
@@ -0,0 +1,300 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use waitq::WaiterQueue;
+
+/// What a spoke's egress queue does with a new bundle when it's already full.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued bundle to make room. The spoke silently misses bundles.
+    DropOldest,
+    /// Disconnect the slow spoke.
+    Disconnect,
+    /// Wait for the spoke to catch up. This stalls delivery to every other spoke.
+    Block,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SpokeEgressConfig {
+    /// Max number of bundles queued for a spoke.
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+
+impl SpokeEgressConfig {
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        assert!(capacity > 0);
+        Self { capacity, overflow }
+    }
+}
+
+impl Default for SpokeEgressConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1024,
+            overflow: OverflowPolicy::Disconnect,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpokeEgressStats {
+    pub queue_depth: usize,
+    /// Deepest the queue has been since the spoke was added.
+    pub max_queue_depth: usize,
+    /// Bundles discarded by `OverflowPolicy::DropOldest`.
+    pub dropped_bundles: u64,
+}
+
+pub enum EgressPayload {
+    Packet(bab::SendPacket),
+    // Compressed payloads are shared by every spoke they're broadcast to.
+    Shared(Arc<[u8]>),
+    Empty,
+}
+
+impl core::ops::Deref for EgressPayload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            EgressPayload::Packet(packet) => &packet[..],
+            EgressPayload::Shared(payload) => payload,
+            EgressPayload::Empty => &[],
+        }
+    }
+}
+
+pub struct EgressBundle {
+    pub channel_id: u32,
+    pub payload: EgressPayload,
+}
+
+impl EgressBundle {
+    pub fn encode_header(&self) -> [u8; <modrpc::PacketBundle as mproto::BaseLen>::BASE_LEN] {
+        let mut header = [0u8; <modrpc::PacketBundle as mproto::BaseLen>::BASE_LEN];
+        mproto::encode_value(
+            modrpc::PacketBundle {
+                channel_id: self.channel_id,
+                length: self.payload.len() as u16,
+            },
+            &mut header[..],
+        );
+        header
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum EgressPushError {
+    /// The queue was full and the overflow policy is `OverflowPolicy::Disconnect`.
+    Overflow,
+    Closed,
+}
+
+/// Bounded queue of bundles waiting to be written to a single spoke. Broadcaster shards push to
+/// it from their own workers while the spoke's writer task pops from the spoke's worker.
+#[derive(Clone)]
+pub struct SpokeEgressQueue {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    config: SpokeEgressConfig,
+    state: spin::Mutex<State>,
+    waiting_receiver: WaiterQueue<()>,
+    waiting_senders: WaiterQueue<()>,
+}
+
+struct State {
+    bundles: VecDeque<EgressBundle>,
+    is_closed: bool,
+    is_draining: bool,
+    max_queue_depth: usize,
+    dropped_bundles: u64,
+}
+
+impl SpokeEgressQueue {
+    pub fn new(config: SpokeEgressConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                state: spin::Mutex::new(State {
+                    bundles: VecDeque::with_capacity(config.capacity),
+                    is_closed: false,
+                    is_draining: false,
+                    max_queue_depth: 0,
+                    dropped_bundles: 0,
+                }),
+                waiting_receiver: WaiterQueue::new(),
+                waiting_senders: WaiterQueue::new(),
+            }),
+        }
+    }
+
+    pub async fn push(&self, bundle: EgressBundle) -> Result<(), EgressPushError> {
+        let inner = &self.inner;
+        let mut bundle = Some(bundle);
+
+        let result = inner
+            .waiting_senders
+            .wait_for(|| {
+                let mut state = inner.state.lock();
+                if state.is_closed || state.is_draining {
+                    return Some(Err(EgressPushError::Closed));
+                }
+
+                if state.bundles.len() >= inner.config.capacity {
+                    match inner.config.overflow {
+                        OverflowPolicy::DropOldest => {
+                            state.bundles.pop_front();
+                            state.dropped_bundles += 1;
+                        }
+                        OverflowPolicy::Disconnect => {
+                            state.is_closed = true;
+                            state.bundles.clear();
+                            return Some(Err(EgressPushError::Overflow));
+                        }
+                        OverflowPolicy::Block => return None,
+                    }
+                }
+
+                state.bundles.push_back(bundle.take().expect("egress bundle already pushed"));
+                state.max_queue_depth = state.max_queue_depth.max(state.bundles.len());
+                Some(Ok(()))
+            })
+            .await;
+
+        if result == Err(EgressPushError::Overflow) {
+            inner.waiting_senders.notify_all();
+        }
+        inner.waiting_receiver.notify_all();
+
+        result
+    }
+
+    /// Wait for the next bundle to write. Returns `None` once the queue has been closed, or once
+    /// it's been drained after `finish`.
+    pub async fn pop(&self) -> Option<EgressBundle> {
+        let inner = &self.inner;
+        let bundle = inner
+            .waiting_receiver
+            .wait_for(|| {
+                let mut state = inner.state.lock();
+                if state.is_closed {
+                    return Some(None);
+                }
+                match state.bundles.pop_front() {
+                    Some(bundle) => Some(Some(bundle)),
+                    None if state.is_draining => Some(None),
+                    None => None,
+                }
+            })
+            .await;
+        inner.waiting_senders.notify_all();
+        bundle
+    }
+
+    /// Stop accepting bundles and discard any that are still queued.
+    pub fn close(&self) {
+        let mut state = self.inner.state.lock();
+        state.is_closed = true;
+        state.bundles.clear();
+        drop(state);
+
+        self.inner.waiting_receiver.notify_all();
+        self.inner.waiting_senders.notify_all();
+    }
+
+    /// Stop accepting bundles, but keep the ones that are still queued for `pop`.
+    pub fn finish(&self) {
+        self.inner.state.lock().is_draining = true;
+
+        self.inner.waiting_receiver.notify_all();
+        self.inner.waiting_senders.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.state.lock().is_closed
+    }
+
+    pub fn stats(&self) -> SpokeEgressStats {
+        let state = self.inner.state.lock();
+        SpokeEgressStats {
+            queue_depth: state.bundles.len(),
+            max_queue_depth: state.max_queue_depth,
+            dropped_bundles: state.dropped_bundles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bundle(channel_id: u32) -> EgressBundle {
+        EgressBundle {
+            channel_id,
+            payload: EgressPayload::Empty,
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = SpokeEgressQueue::new(SpokeEgressConfig::new(2, OverflowPolicy::DropOldest));
+        futures_lite::future::block_on(async {
+            for channel_id in 0..4 {
+                queue.push(bundle(channel_id)).await.unwrap();
+            }
+            assert_eq!(
+                queue.stats(),
+                SpokeEgressStats {
+                    queue_depth: 2,
+                    max_queue_depth: 2,
+                    dropped_bundles: 2,
+                }
+            );
+            assert_eq!(queue.pop().await.unwrap().channel_id, 2);
+            assert_eq!(queue.pop().await.unwrap().channel_id, 3);
+        });
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let queue = SpokeEgressQueue::new(SpokeEgressConfig::new(2, OverflowPolicy::Disconnect));
+        futures_lite::future::block_on(async {
+            queue.push(bundle(0)).await.unwrap();
+            queue.push(bundle(1)).await.unwrap();
+            assert_eq!(queue.push(bundle(2)).await, Err(EgressPushError::Overflow));
+            assert!(queue.is_closed());
+            assert!(queue.pop().await.is_none());
+            assert_eq!(queue.push(bundle(3)).await, Err(EgressPushError::Closed));
+        });
+    }
+
+    #[test]
+    fn test_finish() {
+        let queue = SpokeEgressQueue::new(SpokeEgressConfig::default());
+        futures_lite::future::block_on(async {
+            queue.push(bundle(0)).await.unwrap();
+            queue.finish();
+            assert_eq!(queue.push(bundle(1)).await, Err(EgressPushError::Closed));
+            assert_eq!(queue.pop().await.unwrap().channel_id, 0);
+            assert!(queue.pop().await.is_none());
+        });
+    }
+
+    #[test]
+    fn test_block() {
+        let queue = SpokeEgressQueue::new(SpokeEgressConfig::new(1, OverflowPolicy::Block));
+        futures_lite::future::block_on(async {
+            queue.push(bundle(0)).await.unwrap();
+            let (pushed, popped) = futures_lite::future::zip(
+                queue.push(bundle(1)),
+                async { queue.pop().await.unwrap().channel_id },
+            )
+            .await;
+            assert_eq!(pushed, Ok(()));
+            assert_eq!(popped, 0);
+            assert_eq!(queue.pop().await.unwrap().channel_id, 1);
+        });
+    }
+}
@@ -1,4 +1,6 @@
 use modrpc::{
+    CompressionNegotiation,
+    IdleTimer,
     PacketBundle,
     TcpIngress,
     WorkerContext,
@@ -7,8 +9,13 @@ use mproto::BaseLen;
 
 use crate::{
     broadcaster::InPacket,
+    keepalive::spawn_spoke_keepalive,
+    limits::RateLimiter,
+    shutdown::wait_notified,
+    spoke::{SpokeConfig, SpokeHandle, SpokeTaskGuard},
+    spoke_egress::{EgressBundle, EgressPayload, SpokeEgressQueue},
     BroadcasterHandle,
-    TransportIndex,
+    BroadcasterShutdown,
 };
 
 pub async fn spawn_tcp_spoke(
@@ -18,51 +25,198 @@ pub async fn spawn_tcp_spoke(
     buffer_pool: bab::HeapBufferPool,
     stream: tokio::net::TcpStream,
     max_packet_size: usize,
-) -> (TransportIndex, bab::SignalTree) {
-    let (tcp_read, tcp_write) = stream.into_split();
+    config: SpokeConfig,
+) -> SpokeHandle {
+    let SpokeConfig {
+        keepalive,
+        compression,
+        compression_negotiation,
+        egress,
+        ingress_policy,
+    } = config;
+    let (tcp_read, mut tcp_write) = stream.into_split();
 
-    let broadcaster_spoke = broadcaster_handle.add_tcp(tcp_write).await;
-    let to_broadcaster = broadcaster_handle.in_packet_sender().clone();
+    if compression.is_some() && compression_negotiation == CompressionNegotiation::Offer {
+        use tokio::io::AsyncWriteExt;
+
+        // Let the peer know it may compress the bundles it sends us.
+        let _ = tcp_write.write_all(&modrpc::encode_compression_hello()).await;
+    }
+
+    let egress = SpokeEgressQueue::new(egress);
+    let answer_egress = egress.clone();
+    let broadcaster_spoke = broadcaster_handle.add_tcp(egress.clone()).await;
+    let to_broadcaster = broadcaster_handle.clone();
     let shutdown_signal = bab::SignalTree::new();
+    let exited = bab::SignalTree::new();
+    let task_guard = SpokeTaskGuard::new(exited.clone());
+    let spoke =
+        SpokeHandle::new(broadcaster_spoke, egress.clone(), shutdown_signal.clone(), exited);
+
+    worker_context.spawn(probius::enter_component_async(
+        "tcp-egress-task", {
+            let shutdown_signal = shutdown_signal.clone();
+            let task_guard = task_guard.clone();
+            async move {
+                use tokio::io::AsyncWriteExt;
+                let _task_guard = task_guard;
+
+                let tx_loop = async {
+                    while let Some(bundle) = egress.pop().await {
+                        // TODO vectored write
+                        if tcp_write.write_all(&bundle.encode_header()).await.is_err()
+                            || tcp_write.write_all(&bundle.payload).await.is_err()
+                        {
+                            log::debug!("TransportHub tcp transport closed: {:?}", broadcaster_spoke);
+                            break;
+                        }
+                    }
+                };
+                futures_lite::future::or(tx_loop, wait_notified(&shutdown_signal)).await;
+
+                egress.close();
+                shutdown_signal.notify();
+                let _ = tcp_write.shutdown().await;
+            }
+        },
+    ));
 
     let mut ingress = TcpIngress::new(
         tcp_read,
         buffer_pool,
         max_packet_size,
     );
+    ingress.set_decompression(compression.is_some());
+
+    if let Some(keepalive) = keepalive {
+        spawn_spoke_keepalive(
+            worker_context,
+            broadcaster_handle.clone(),
+            broadcaster_spoke,
+            keepalive,
+            shutdown_signal.clone(),
+            task_guard.clone(),
+        );
+    }
 
     worker_context.spawn(probius::enter_component_async(
         "tcp-ingress-task", {
             let shutdown_signal = shutdown_signal.clone();
+            let worker_context = worker_context.clone();
+            let ingress_spoke = spoke.clone();
+            let mut compression_answered = false;
+            let idle_timer = keepalive.map(|k| IdleTimer::new(k.idle_timeout));
+            let mut rate_limiter = ingress_policy.as_ref()
+                .and_then(|policy| policy.rate_limit)
+                .map(RateLimiter::new);
             async move {
+                let _task_guard = task_guard;
                 let tracer = probius::new_trace_source("loop");
 
-                while let Ok(packet_bundle) = ingress.receive().await {
-                    let result: Result<(), localq::mpsc::SendError<_>> = tracer.trace_future(async {
-                        let Ok(header) =
-                            mproto::decode_value::<PacketBundle>(&packet_bundle[..])
-                        else {
-                            return Ok(());
+                let rx_loop = async {
+                    loop {
+                        let packet_bundle = match ingress.receive().await {
+                            Ok(packet_bundle) => packet_bundle,
+                            Err(e) => {
+                                modrpc::report_transport_error(worker_context.rt().metrics(), "tcp", &e);
+                                break;
+                            }
+                        };
+                        if let Some(idle_timer) = &idle_timer {
+                            idle_timer.reset();
+                        }
+                        if modrpc::is_control_bundle(&packet_bundle[..]) {
+                            if let Some(config) = compression
+                                && let Some(algorithms) = modrpc::decode_compression_hello(&packet_bundle[..])
+                            {
+                                if compression_negotiation == CompressionNegotiation::Answer
+                                    && !compression_answered
+                                {
+                                    // Answer the peer's offer with what we can decompress.
+                                    compression_answered = true;
+                                    let _ = answer_egress.push(EgressBundle {
+                                        channel_id: modrpc::COMPRESSION_HELLO_CHANNEL_ID,
+                                        payload: EgressPayload::Shared(
+                                            [modrpc::SUPPORTED_COMPRESSION].into(),
+                                        ),
+                                    })
+                                    .await;
+                                }
+                                if modrpc::peer_accepts_compression(algorithms) {
+                                    broadcaster_handle.enable_compression(broadcaster_spoke, config).await;
+                                }
+                            }
+                            if let Some(subscriptions) = modrpc::decode_topic_subscriptions(&packet_bundle[..]) {
+                                let endpoint = ingress_policy.as_ref().map(|policy| policy.source);
+                                broadcaster_handle
+                                    .set_topic_subscriptions(broadcaster_spoke, endpoint, subscriptions)
+                                    .await;
+                            }
+                            ingress_spoke.receive_channel_advertisement(&packet_bundle[..]);
+                            continue;
+                        }
+
+                        if let Some(rate_limiter) = &mut rate_limiter
+                            && !rate_limiter.allow_bundle(&packet_bundle[..])
+                        {
+                            log::warn!("Disconnecting tcp spoke {:?}: rate limit exceeded", broadcaster_spoke);
+                            tracer.trace(|| {
+                                probius::trace_metric("rate_limit_exceeded", 1);
+                            });
+                            ingress_spoke.set_rate_limited();
+                            break;
+                        }
+
+                        // Bundles are relayed whole, so their packets have to be checked here.
+                        let header = match modrpc::validate_packet_bundle(&packet_bundle[..]) {
+                            Ok(header) => header,
+                            Err(e) => {
+                                modrpc::report_transport_error(worker_context.rt().metrics(), "tcp", &e);
+                                break;
+                            }
                         };
-                        packet_bundle.advance(PacketBundle::BASE_LEN);
 
-                        tracer.trace(|| {
-                            probius::trace_metric("bundle_payload_size", packet_bundle.len() as i64);
-                        });
+                        let result: Result<(), BroadcasterShutdown> = tracer.trace_future(async {
+                            packet_bundle.advance(PacketBundle::BASE_LEN);
+
+                            if let Some(policy) = &ingress_policy
+                                && !policy.allows_bundle(header.channel_id, &packet_bundle[..])
+                            {
+                                log::warn!("Dropping disallowed bundle from spoke {:?}", broadcaster_spoke);
+                                return Ok(());
+                            }
 
-                        to_broadcaster.send(InPacket {
-                            transport: broadcaster_spoke,
-                            channel_id: header.channel_id,
-                            packet: packet_bundle,
+                            tracer.trace(|| {
+                                probius::trace_metric("bundle_payload_size", packet_bundle.len() as i64);
+                            });
+
+                            to_broadcaster.send_in_packet(InPacket {
+                                transport: broadcaster_spoke,
+                                channel_id: header.channel_id,
+                                packet: packet_bundle,
+                            })
+                            .await?;
+
+                            Ok(())
                         })
-                        .await?;
+                        .await;
+                        if let Err(_) = result {
+                            break;
+                        }
+                    }
+                };
 
-                        Ok(())
+                // Stop reading if the egress task shut the spoke down.
+                let rx_loop = futures_lite::future::or(rx_loop, wait_notified(&shutdown_signal));
+
+                if let Some(idle_timer) = &idle_timer {
+                    futures_lite::future::or(rx_loop, async {
+                        idle_timer.expired(&worker_context).await;
+                        log::info!("Closing idle tcp spoke {:?}", broadcaster_spoke);
                     })
                     .await;
-                    if let Err(_) = result {
-                        break;
-                    }
+                } else {
+                    rx_loop.await;
                 }
 
                 shutdown_signal.notify();
@@ -71,5 +225,5 @@ pub async fn spawn_tcp_spoke(
         },
     ));
 
-    (broadcaster_spoke, shutdown_signal)
+    spoke
 }
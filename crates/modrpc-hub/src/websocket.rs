@@ -2,6 +2,7 @@ use std::marker::Unpin;
 
 use tokio::io::{AsyncRead, AsyncWrite};
 use modrpc::{
+    IdleTimer,
     PacketBundle,
     WebSocketIngress,
     WorkerContext,
@@ -10,8 +11,13 @@ use mproto::BaseLen;
 
 use crate::{
     broadcaster::InPacket,
+    keepalive::spawn_spoke_keepalive,
+    limits::RateLimiter,
+    shutdown::wait_notified,
+    spoke::{SpokeConfig, SpokeHandle, SpokeTaskGuard},
+    spoke_egress::SpokeEgressQueue,
     BroadcasterHandle,
-    TransportIndex,
+    BroadcasterShutdown,
 };
 
 pub async fn spawn_websocket_spoke<S>(
@@ -20,59 +26,188 @@ pub async fn spawn_websocket_spoke<S>(
     buffer_pool: bab::HeapBufferPool,
     websocket: tokio_tungstenite::WebSocketStream<S>,
     max_packet_size: usize,
-) -> (TransportIndex, bab::SignalTree)
+    config: SpokeConfig,
+) -> SpokeHandle
 where S: AsyncRead + AsyncWrite + Unpin + Send + 'static
 {
-    use futures_util::StreamExt;
+    let SpokeConfig {
+        keepalive,
+        compression,
+        egress,
+        ingress_policy,
+        ..
+    } = config;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::protocol::Message;
 
-    let (ws_tx, ws_rx) = websocket.split();
-    let broadcaster_spoke = broadcaster_handle.add_ws(Box::new(ws_tx)).await;
-    let to_broadcaster = broadcaster_handle.in_packet_sender().clone();
+    let (mut ws_tx, ws_rx) = websocket.split();
+
+    if compression.is_some() {
+        // Let the client know it may compress the bundles it sends us.
+        let hello = modrpc::encode_compression_hello().to_vec();
+        let _ = ws_tx.send(Message::Binary(hello.into())).await;
+    }
+
+    let egress = SpokeEgressQueue::new(egress);
+    let broadcaster_spoke = broadcaster_handle.add_ws(egress.clone()).await;
+    let to_broadcaster = broadcaster_handle.clone();
     let shutdown_signal = bab::SignalTree::new();
+    let exited = bab::SignalTree::new();
+    let task_guard = SpokeTaskGuard::new(exited.clone());
+    let spoke =
+        SpokeHandle::new(broadcaster_spoke, egress.clone(), shutdown_signal.clone(), exited);
+
+    worker_context.spawn({
+        let shutdown_signal = shutdown_signal.clone();
+        let task_guard = task_guard.clone();
+        async move {
+            let _task_guard = task_guard;
+            let tx_loop = async {
+                while let Some(bundle) = egress.pop().await {
+                    let mut message = Vec::with_capacity(PacketBundle::BASE_LEN + bundle.payload.len());
+                    message.extend_from_slice(&bundle.encode_header());
+                    message.extend_from_slice(&bundle.payload);
+
+                    if let Err(_) = ws_tx.send(Message::Binary(message.into())).await {
+                        log::debug!("WebSocket transport closed: {:?}", broadcaster_spoke);
+                        break;
+                    }
+                }
+            };
+            futures_lite::future::or(tx_loop, wait_notified(&shutdown_signal)).await;
+
+            egress.close();
+            shutdown_signal.notify();
+            let _ = ws_tx.close().await;
+        }
+    });
 
     let mut ingress = WebSocketIngress::new(
         ws_rx,
         buffer_pool.clone(),
         max_packet_size,
     );
+    ingress.set_decompression(compression.is_some());
+
+    if let Some(keepalive) = keepalive {
+        spawn_spoke_keepalive(
+            worker_context,
+            broadcaster_handle.clone(),
+            broadcaster_spoke,
+            keepalive,
+            shutdown_signal.clone(),
+            task_guard.clone(),
+        );
+    }
 
     worker_context.spawn_traced("hub-ws-ingress", core::time::Duration::from_millis(500), {
         let shutdown_signal = shutdown_signal.clone();
+        let worker_context = worker_context.clone();
+        let ingress_spoke = spoke.clone();
+        let idle_timer = keepalive.map(|k| IdleTimer::new(k.idle_timeout));
+        let mut rate_limiter = ingress_policy.as_ref()
+            .and_then(|policy| policy.rate_limit)
+            .map(RateLimiter::new);
         async move |tracer| {
-            while let Ok(packet_bundle) = ingress.receive().await {
-                let result: Result<(), localq::mpsc::SendError<_>> = tracer.trace_future(async {
-                    let Ok(header) =
-                        mproto::decode_value::<PacketBundle>(&packet_bundle[..])
-                    else {
-                        log::info!("failed to decode websocket bundle");
-                        return Ok(());
+            let _task_guard = task_guard;
+            let rx_loop = async {
+                loop {
+                    let packet_bundle = match ingress.receive().await {
+                        Ok(packet_bundle) => packet_bundle,
+                        Err(e) => {
+                            modrpc::report_transport_error(worker_context.rt().metrics(), "websocket", &e);
+                            break;
+                        }
                     };
-                    packet_bundle.advance(PacketBundle::BASE_LEN);
+                    if let Some(idle_timer) = &idle_timer {
+                        idle_timer.reset();
+                    }
+                    if modrpc::is_control_bundle(&packet_bundle[..]) {
+                        if let Some(config) = compression
+                            && let Some(algorithms) = modrpc::decode_compression_hello(&packet_bundle[..])
+                            && modrpc::peer_accepts_compression(algorithms)
+                        {
+                            broadcaster_handle.enable_compression(broadcaster_spoke, config).await;
+                        }
+                        if let Some(subscriptions) = modrpc::decode_topic_subscriptions(&packet_bundle[..]) {
+                            let endpoint = ingress_policy.as_ref().map(|policy| policy.source);
+                            broadcaster_handle
+                                .set_topic_subscriptions(broadcaster_spoke, endpoint, subscriptions)
+                                .await;
+                        }
+                        ingress_spoke.receive_channel_advertisement(&packet_bundle[..]);
+                        continue;
+                    }
 
-                    probius::trace_metric("bundle_payload_size", packet_bundle.len() as i64);
+                    if let Some(rate_limiter) = &mut rate_limiter
+                        && !rate_limiter.allow_bundle(&packet_bundle[..])
+                    {
+                        log::warn!("Disconnecting websocket spoke {:?}: rate limit exceeded", broadcaster_spoke);
+                        tracer.trace(|| {
+                            probius::trace_metric("rate_limit_exceeded", 1);
+                        });
+                        ingress_spoke.set_rate_limited();
+                        break;
+                    }
 
-                    to_broadcaster.send(InPacket {
-                        transport: broadcaster_spoke,
-                        channel_id: header.channel_id,
-                        packet: packet_bundle,
+                    // Bundles are relayed whole, so their packets have to be checked here.
+                    let header = match modrpc::validate_packet_bundle(&packet_bundle[..]) {
+                        Ok(header) => header,
+                        Err(e) => {
+                            modrpc::report_transport_error(worker_context.rt().metrics(), "websocket", &e);
+                            break;
+                        }
+                    };
+
+                    let result: Result<(), BroadcasterShutdown> = tracer.trace_future(async {
+                        packet_bundle.advance(PacketBundle::BASE_LEN);
+
+                        if let Some(policy) = &ingress_policy
+                            && !policy.allows_bundle(header.channel_id, &packet_bundle[..])
+                        {
+                            log::warn!("Dropping disallowed bundle from spoke {:?}", broadcaster_spoke);
+                            return Ok(());
+                        }
+
+                        probius::trace_metric("bundle_payload_size", packet_bundle.len() as i64);
+
+                        to_broadcaster.send_in_packet(InPacket {
+                            transport: broadcaster_spoke,
+                            channel_id: header.channel_id,
+                            packet: packet_bundle,
+                        })
+                        .await?;
+
+                        probius::trace_label("send_success");
+
+                        Ok(())
                     })
-                    .await?;
+                    .await;
+                    if let Err(e) = result {
+                        log::info!("websocket ingress error: {e:?}");
+                        break;
+                    }
+                }
+            };
 
-                    probius::trace_label("send_success");
+            // Stop reading if the egress task shut the spoke down.
+            let rx_loop = futures_lite::future::or(rx_loop, wait_notified(&shutdown_signal));
 
-                    Ok(())
+            if let Some(idle_timer) = &idle_timer {
+                futures_lite::future::or(rx_loop, async {
+                    idle_timer.expired(&worker_context).await;
+                    log::info!("Closing idle websocket spoke {:?}", broadcaster_spoke);
                 })
                 .await;
-                if let Err(e) = result {
-                    log::info!("websocket ingress error: {e:?}");
-                    break;
-                }
+            } else {
+                rx_loop.await;
             }
+
             shutdown_signal.notify();
             broadcaster_handle.remove_transport(broadcaster_spoke).await;
         }
     });
 
-    (broadcaster_spoke, shutdown_signal)
+    spoke
 }
 
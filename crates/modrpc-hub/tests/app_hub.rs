@@ -0,0 +1,574 @@
+#![cfg(feature = "tcp-transport")]
+
+mod common;
+
+use std::{
+    cell::{OnceCell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+
+use modrpc_executor::ModrpcExecutor;
+use modrpc_hub::{
+    AppHub, AppHubBuilder, AppHubDelegate, ConnectionLimits, ConnectionStats, HubShutdownConfig,
+    RateLimit,
+};
+use modrpc::KeepaliveConfig;
+use std_modrpc::{
+    MembershipHost,
+    MembershipHostBuilder,
+    MembershipHostConfig,
+    MembershipHostRole,
+    MembershipInitState,
+    MembershipObserver,
+    MembershipObserverBuilder,
+    MembershipObserverConfig,
+    MembershipObserverRole,
+    RequestClientBuilder,
+    RequestClientConfig,
+    RequestClientRole,
+    RequestInitState,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use common::{REQUEST_PLANE_ID, RequestPlaneDelegate, localhost, wait_until};
+
+struct TokenDelegate;
+
+impl AppHubDelegate for TokenDelegate {
+    type Init<'a> = u32;
+    type Hello<'a> = &'a str;
+    type Identity = String;
+
+    const CLIENT_HELLO: bool = true;
+
+    async fn authenticate(
+        &self,
+        _endpoint_addr: modrpc::EndpointAddr,
+        token: Option<Self::Hello<'_>>,
+    ) -> Result<String, String> {
+        match token {
+            Some("alice-token") => Ok("alice".into()),
+            _ => Err("unknown token".into()),
+        }
+    }
+
+    async fn client_handshake(
+        &self,
+        _hub: &AppHub<Self>,
+        _endpoint_addr: modrpc::EndpointAddr,
+        identity: &String,
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, u32) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        handshake_fn(0x100, identity.len() as u32).await
+    }
+
+    async fn client_disconnected(&self, _endpoint_addr: modrpc::EndpointAddr) { }
+}
+
+const MEMBERSHIP_PLANE_ID: u32 = 0x200;
+
+/// Clients join the membership plane under the name they send as their hello.
+struct MembershipDelegate {
+    host: Rc<OnceCell<MembershipHost<String>>>,
+}
+
+impl AppHubDelegate for MembershipDelegate {
+    type Init<'a> = MembershipInitState<String>;
+    type Hello<'a> = &'a str;
+    type Identity = String;
+
+    const CLIENT_HELLO: bool = true;
+
+    async fn authenticate(
+        &self,
+        _endpoint_addr: modrpc::EndpointAddr,
+        name: Option<Self::Hello<'_>>,
+    ) -> Result<String, String> {
+        name.map(String::from).ok_or_else(|| "missing name".into())
+    }
+
+    async fn client_handshake(
+        &self,
+        _hub: &AppHub<Self>,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _identity: &String,
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, MembershipInitState<String>) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        handshake_fn(MEMBERSHIP_PLANE_ID, self.host.get().unwrap().init_state()).await
+    }
+
+    async fn client_disconnected(&self, _endpoint_addr: modrpc::EndpointAddr) { }
+}
+
+async fn read_plane_handshake<Init: for<'a> mproto::Decode<'a>>(
+    stream: &mut tokio::net::TcpStream,
+) -> modrpc::PlaneHandshake<Init> {
+    let mut len_bytes = [0u8; 2];
+    stream.read_exact(&mut len_bytes).await.unwrap();
+    let mut handshake_bytes = vec![0u8; u16::from_le_bytes(len_bytes) as usize];
+    stream.read_exact(&mut handshake_bytes).await.unwrap();
+    mproto::decode_value(&handshake_bytes).unwrap()
+}
+
+#[test]
+fn test_client_hello() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .hub_id(3)
+            .with_tcp(localhost())
+            .build(TokenDelegate)
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let e = modrpc::tcp_client_hello(&mut stream, "mallory-token").await.unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(e.to_string(), "unknown token");
+
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut stream, "alice-token").await.unwrap();
+        let handshake = read_plane_handshake::<u32>(&mut stream).await;
+        assert_eq!(handshake.plane_id, 0x100);
+        assert_eq!(handshake.init, 5);
+        assert_eq!(handshake.endpoint_addr.endpoint >> 32, 3);
+
+        worker_cx.sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            hub.client_identity(handshake.endpoint_addr).as_deref(),
+            Some(&"alice".to_string()),
+        );
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_membership() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let host = Rc::new(OnceCell::new());
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .build(MembershipDelegate { host: host.clone() })
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+        hub.start_plane::<MembershipHostRole<String>>(
+            MEMBERSHIP_PLANE_ID,
+            MembershipHostConfig { },
+            MembershipInitState { members: vec![] },
+        )
+        .await
+        .local_async({
+            let host = host.clone();
+            move |cx| {
+                let builder = MembershipHostBuilder::new(
+                    "membership", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone(),
+                );
+                let _ = host.set(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }
+        }).await;
+        hub.host_membership(MEMBERSHIP_PLANE_ID, host.get().unwrap().clone(), |name| name.clone());
+        worker_cx.sleep(Duration::from_millis(50)).await;
+
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut stream, "alice").await.unwrap();
+        let (alice_addr, _transport, alice) =
+            modrpc::tcp_connect_builder::<MembershipObserverRole<String>, _>(
+                &rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                MembershipObserverConfig { },
+                modrpc::TransportOptions::default(),
+                stream,
+                async |start_role| {
+                    let observer = OnceCell::new();
+                    start_role.local_async(|cx| {
+                        let builder = MembershipObserverBuilder::new(
+                            "membership", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone(),
+                        );
+                        let _ = observer.set(builder.create_handle(cx.setup));
+                        builder.build(cx.setup);
+                    }).await;
+                    observer.into_inner().unwrap()
+                },
+            )
+            .await
+            .unwrap();
+        let left = Rc::new(RefCell::new(Vec::new()));
+        alice.on_left({
+            let left = left.clone();
+            move |endpoint| left.borrow_mut().push(endpoint)
+        });
+
+        let names = |observer: &MembershipObserver<String>| -> Vec<String> {
+            observer.members().into_iter().map(|member| member.metadata).collect()
+        };
+        wait_until(worker_cx, || names(&alice) == ["alice"]).await;
+        assert!(alice.is_member(alice_addr.endpoint));
+        let joined = Rc::new(RefCell::new(Vec::new()));
+        alice.on_joined({
+            let joined = joined.clone();
+            move |member| joined.borrow_mut().push(member.endpoint)
+        });
+
+        // Bob joins with the existing members in his init.
+        let mut bob = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut bob, "bob").await.unwrap();
+        let handshake = read_plane_handshake::<MembershipInitState<String>>(&mut bob).await;
+        assert_eq!(handshake.init.members.len(), 1);
+        assert_eq!(handshake.init.members[0].metadata, "alice");
+        let bob_addr = handshake.endpoint_addr;
+
+        wait_until(worker_cx, || names(&alice) == ["alice", "bob"]).await;
+
+        drop(bob);
+        wait_until(worker_cx, || names(&alice) == ["alice"]).await;
+        assert_eq!(*joined.borrow(), [bob_addr.endpoint]);
+        assert_eq!(*left.borrow(), [bob_addr.endpoint]);
+        assert_eq!(host.get().unwrap().members().len(), 1);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+/// Whether the hub has closed a connection, waiting up to a second for it to.
+async fn is_closed(worker_cx: &modrpc::WorkerContext, stream: &mut tokio::net::TcpStream) -> bool {
+    let mut buf = [0u8; 64];
+    let read_to_end = async {
+        loop {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => break true,
+                Ok(_) => {}
+            }
+        }
+    };
+    futures_lite::future::or(read_to_end, async {
+        worker_cx.sleep(Duration::from_secs(1)).await;
+        false
+    })
+    .await
+}
+
+#[test]
+fn test_connection_limits() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .connection_limits(ConnectionLimits {
+                max_connections_per_ip: Some(1),
+                handshake_timeout: Some(Duration::from_millis(100)),
+                rate_limit: Some(RateLimit { packets_per_second: 5, bytes_per_second: 10_000 }),
+                ..Default::default()
+            })
+            .build(TokenDelegate)
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        // A second connection from the same IP is turned away, and the first is disconnected
+        // for never saying hello.
+        let mut silent = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let mut rejected = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        assert!(is_closed(worker_cx, &mut rejected).await);
+        assert!(is_closed(worker_cx, &mut silent).await);
+        wait_until(worker_cx, || hub.connection_stats().connections == 0).await;
+        assert_eq!(
+            hub.connection_stats(),
+            ConnectionStats {
+                connections: 0,
+                rejected_connections: 1,
+                handshake_timeouts: 1,
+                rate_limited_disconnects: 0,
+            },
+        );
+
+        // A client flooding the hub is disconnected.
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut stream, "alice-token").await.unwrap();
+        let handshake = read_plane_handshake::<u32>(&mut stream).await;
+        let packet = mproto::encode_value_vec(modrpc::TransmitPacket {
+            payload_length: 0,
+            infra_id: 0,
+            plane_id: handshake.plane_id,
+            topic: 0,
+            source: handshake.endpoint_addr,
+        });
+        let mut bundle = mproto::encode_value_vec(modrpc::PacketBundle {
+            channel_id: handshake.plane_id,
+            length: packet.len() as u16,
+        });
+        bundle.extend(packet);
+        for _ in 0..10 {
+            stream.write_all(&bundle).await.unwrap();
+        }
+        assert!(is_closed(worker_cx, &mut stream).await);
+        wait_until(worker_cx, || hub.connection_stats().connections == 0).await;
+        assert_eq!(hub.connection_stats().rate_limited_disconnects, 1);
+
+        // The runtime's metrics count the same events.
+        let metrics = rt.metrics().encode_openmetrics();
+        assert!(metrics.contains("modrpc_hub_rejected_connections_total 1"), "{metrics}");
+        assert!(metrics.contains("modrpc_hub_handshake_timeouts_total 1"), "{metrics}");
+        assert!(metrics.contains("modrpc_hub_rate_limited_disconnects_total 1"), "{metrics}");
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_shutdown() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .build(TokenDelegate)
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        let mut joined = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut joined, "alice-token").await.unwrap();
+        let handshake = read_plane_handshake::<u32>(&mut joined).await;
+        let mut handshaking = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        wait_until(worker_cx, || hub.client_identity(handshake.endpoint_addr).is_some()).await;
+
+        let read_goodbye = async {
+            let mut goodbye = [0u8; 64];
+            let len = joined.read(&mut goodbye).await.unwrap();
+            modrpc::is_goodbye_bundle(&goodbye[..len])
+        };
+        let ((), said_goodbye) = futures_lite::future::zip(
+            hub.shutdown(HubShutdownConfig::default()),
+            read_goodbye,
+        )
+        .await;
+        assert!(said_goodbye);
+
+        assert!(is_closed(worker_cx, &mut joined).await);
+        assert!(is_closed(worker_cx, &mut handshaking).await);
+        assert_eq!(hub.connection_stats().connections, 0);
+        assert!(tokio::net::TcpStream::connect(hub_addr).await.is_err());
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_idle_timeout() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 8, 8);
+    let idle_timeout = Duration::from_millis(100);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let delegate = RequestPlaneDelegate::default();
+        let disconnected = delegate.disconnected.clone();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .keepalive(KeepaliveConfig::new(Duration::from_millis(20), idle_timeout))
+            .build(delegate)
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        // A client started with keepalives stays connected while it has nothing to send.
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (alice_addr, _transport, ()) =
+            modrpc::tcp_connect_builder::<RequestClientRole<u32, u32>, _>(
+                &rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                RequestClientConfig { },
+                modrpc::TransportOptions {
+                    keepalive: Some(KeepaliveConfig::new(Duration::from_millis(20), idle_timeout)),
+                    ..Default::default()
+                },
+                stream,
+                async |start_role| {
+                    start_role.local_async(|_cx| {}).await;
+                },
+            )
+            .await
+            .unwrap();
+
+        // A client that never sends anything is closed within two idle timeouts.
+        let mut silent = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let silent_addr = read_plane_handshake::<RequestInitState>(&mut silent).await.endpoint_addr;
+        let silent_since = std::time::Instant::now();
+        assert!(is_closed(worker_cx, &mut silent).await);
+        let closed_after = silent_since.elapsed();
+        assert!(closed_after >= idle_timeout, "closed after {closed_after:?}");
+        assert!(closed_after < 2 * idle_timeout + Duration::from_millis(100), "closed after {closed_after:?}");
+        wait_until(worker_cx, || *disconnected.borrow() == [silent_addr]).await;
+
+        worker_cx.sleep(3 * idle_timeout).await;
+        assert_eq!(*disconnected.borrow(), [silent_addr]);
+        assert!(hub.client_identity(alice_addr).is_some());
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_start_plane_advertises_to_federation() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (client_rt, client_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        // The upstream hub starts out with no planes at all.
+        let upstream = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .hub_id(1)
+            .with_federation(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .hub_id(2)
+            .connect_upstream(upstream.federation_addr().unwrap())
+            .federate_plane(REQUEST_PLANE_ID)
+            .with_tcp(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+        let upstream_federation = upstream.federation().unwrap();
+        wait_until(worker_cx, || upstream_federation.downstream_hubs() == [2]).await;
+
+        // Starting the plane after the link is up advertises it to the downstream hub.
+        upstream.start_plane::<RequestServerRole<u32, u32>>(
+            REQUEST_PLANE_ID,
+            RequestServerConfig { },
+            RequestInitState { },
+        )
+        .await
+        .local_async(|cx| {
+            RequestServerBuilder::new("request", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone())
+                .build(cx.setup, async |_source, request: u32| request * 2);
+        }).await;
+        assert_eq!(upstream_federation.channel_ids(), [REQUEST_PLANE_ID]);
+        worker_cx.sleep(Duration::from_millis(50)).await;
+
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (_, _transport, client) =
+            modrpc::tcp_connect_builder::<RequestClientRole<u32, u32>, _>(
+                &client_rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                RequestClientConfig { },
+                modrpc::TransportOptions::default(),
+                stream,
+                async |start_role| {
+                    let client = OnceCell::new();
+                    start_role.local_async(|cx| {
+                        let builder = RequestClientBuilder::new(
+                            "request", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone(),
+                        );
+                        let _ = client.set(builder.create_handle(cx.setup));
+                        builder.build(cx.setup);
+                    }).await;
+                    client.into_inner().unwrap()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(client.call(21u32).await, 42);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(client_rt_shutdown.shutdown());
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_shutdown_closes_federation_links() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let upstream = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .hub_id(1)
+            .with_federation(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .hub_id(2)
+            .connect_upstream(upstream.federation_addr().unwrap())
+            .with_federation(localhost())
+            .federate_plane(REQUEST_PLANE_ID)
+            .with_tcp(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        hub.start_plane::<RequestServerRole<u32, u32>>(
+            REQUEST_PLANE_ID,
+            RequestServerConfig { },
+            RequestInitState { },
+        )
+        .await
+        .local_async(|cx| {
+            RequestServerBuilder::new("request", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone())
+                .build(cx.setup, async |_source, request: u32| request * 2);
+        }).await;
+        let federation_addr = hub.federation_addr().unwrap();
+        let upstream_federation = upstream.federation().unwrap();
+        wait_until(worker_cx, || upstream_federation.downstream_hubs() == [2]).await;
+
+        hub.shutdown(HubShutdownConfig::default()).await;
+
+        // The upstream link is gone and the hub no longer accepts downstream hubs.
+        assert_eq!(hub.federation().unwrap().upstream_path(), None);
+        wait_until(worker_cx, || upstream_federation.downstream_hubs().is_empty()).await;
+        assert!(tokio::net::TcpStream::connect(federation_addr).await.is_err());
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
@@ -0,0 +1,125 @@
+//! Fixtures shared by the hub's integration tests. Not every test uses all of them.
+#![allow(dead_code)]
+
+use std::{
+    cell::{OnceCell, RefCell},
+    io::Write,
+    net::SocketAddr,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use modrpc_hub::{AppHub, AppHubDelegate};
+use std_modrpc::{
+    RequestClient,
+    RequestClientBuilder,
+    RequestClientConfig,
+    RequestClientRole,
+    RequestInitState,
+};
+
+pub const REQUEST_PLANE_ID: u32 = 0x100;
+
+/// Accepts every client onto a plane of `std.Request`, recording who disconnects.
+#[derive(Default)]
+pub struct RequestPlaneDelegate {
+    pub disconnected: Rc<RefCell<Vec<modrpc::EndpointAddr>>>,
+}
+
+impl AppHubDelegate for RequestPlaneDelegate {
+    type Init<'a> = RequestInitState;
+    type Hello<'a> = ();
+    type Identity = ();
+
+    async fn authenticate(
+        &self,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _hello: Option<Self::Hello<'_>>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn client_handshake(
+        &self,
+        _hub: &AppHub<Self>,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _identity: &(),
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, RequestInitState) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        handshake_fn(REQUEST_PLANE_ID, RequestInitState { }).await
+    }
+
+    async fn client_disconnected(&self, endpoint_addr: modrpc::EndpointAddr) {
+        self.disconnected.borrow_mut().push(endpoint_addr);
+    }
+}
+
+pub fn localhost() -> SocketAddr {
+    "127.0.0.1:0".parse().unwrap()
+}
+
+/// Collects a capture in memory.
+#[derive(Clone, Default)]
+pub struct CaptureBuf(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for CaptureBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Config for clients that all share one keypair.
+#[cfg(feature = "encryption")]
+pub fn shared_encryption_config() -> modrpc::PlaneEncryptionConfig {
+    let keypair = modrpc::NoiseKeypair::generate();
+    let public = keypair.public.clone();
+    modrpc::PlaneEncryptionConfig::new(keypair, vec![public])
+}
+
+/// Join the plane served over `stream` as a `std.Request` client.
+pub async fn connect_client(
+    rt: &modrpc::RuntimeHandle,
+    buffer_pool: &modrpc::HeapBufferPool,
+    options: modrpc::TransportOptions,
+    stream: tokio::net::TcpStream,
+) -> (modrpc::EndpointAddr, modrpc::TransportHandle, RequestClient<u32, u32>) {
+    modrpc::tcp_connect_builder::<RequestClientRole<u32, u32>, _>(
+        rt,
+        buffer_pool.clone(),
+        buffer_pool.clone(),
+        modrpc::WorkerId::local(),
+        RequestClientConfig { },
+        options,
+        stream,
+        async |start_role| {
+            let client = OnceCell::new();
+            start_role.local_async(|cx| {
+                let builder = RequestClientBuilder::new(
+                    "request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init,
+                );
+                let _ = client.set(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }).await;
+            client.into_inner().unwrap()
+        },
+    )
+    .await
+    .unwrap()
+}
+
+/// Poll `condition` every 10ms, panicking if it isn't true within 5s.
+pub async fn wait_until(worker_cx: &modrpc::WorkerContext, mut condition: impl FnMut() -> bool) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+        worker_cx.sleep(Duration::from_millis(10)).await;
+    }
+    panic!("timed out");
+}
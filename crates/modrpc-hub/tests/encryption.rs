@@ -0,0 +1,297 @@
+#![cfg(all(feature = "tcp-transport", feature = "encryption"))]
+
+mod common;
+
+use std::time::Duration;
+
+use modrpc_executor::ModrpcExecutor;
+use modrpc_hub::{AppHub, AppHubBuilder, AppHubDelegate};
+use std_modrpc::{
+    RequestClient,
+    RequestInitState,
+    RequestInterface,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+};
+
+use common::{
+    REQUEST_PLANE_ID,
+    RequestPlaneDelegate,
+    connect_client,
+    localhost,
+    shared_encryption_config,
+};
+
+/// Lets each client act only as the role it names in its hello.
+struct RoleDelegate;
+
+impl AppHubDelegate for RoleDelegate {
+    type Init<'a> = RequestInitState;
+    type Hello<'a> = &'a str;
+    type Identity = &'static str;
+
+    const CLIENT_HELLO: bool = true;
+
+    async fn authenticate(
+        &self,
+        _endpoint_addr: modrpc::EndpointAddr,
+        role: Option<Self::Hello<'_>>,
+    ) -> Result<&'static str, String> {
+        match role {
+            Some("Client") => Ok("Client"),
+            Some("Server") => Ok("Server"),
+            _ => Err("unknown role".into()),
+        }
+    }
+
+    async fn client_handshake(
+        &self,
+        _hub: &AppHub<Self>,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _identity: &&'static str,
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, RequestInitState) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        handshake_fn(REQUEST_PLANE_ID, RequestInitState { }).await
+    }
+
+    fn client_roles(&self, _plane_id: u32, role: &&'static str) -> Option<Vec<&'static str>> {
+        Some(vec![*role])
+    }
+
+    async fn client_disconnected(&self, _endpoint_addr: modrpc::EndpointAddr) { }
+}
+
+fn encryption_options(config: &modrpc::PlaneEncryptionConfig) -> modrpc::TransportOptions {
+    modrpc::TransportOptions {
+        encryption: Some(config.clone()),
+        ..Default::default()
+    }
+}
+
+/// Whether `client` gets a response to a request within 200ms.
+async fn is_answered(worker_cx: &modrpc::WorkerContext, client: &RequestClient<u32, u32>) -> bool {
+    futures_lite::future::or(
+        async {
+            client.call(1u32).await;
+            true
+        },
+        async {
+            worker_cx.sleep(Duration::from_millis(200)).await;
+            false
+        },
+    )
+    .await
+}
+
+#[test]
+fn test_encrypted_tcp_server() {
+    // Every endpoint gets its own runtime - endpoints sharing a worker would deliver their packets
+    // to each other locally instead of over the encrypted transport.
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (server_rt, server_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (client_rt, client_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let server_keypair = modrpc::NoiseKeypair::generate();
+        let client_keypair = modrpc::NoiseKeypair::generate();
+        let server_config = modrpc::PlaneEncryptionConfig::new(
+            server_keypair.clone(),
+            vec![client_keypair.public.clone()],
+        );
+        let client_config =
+            modrpc::PlaneEncryptionConfig::new(client_keypair, vec![server_keypair.public]);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+        let connect = tokio::net::TcpStream::connect(server_addr);
+        let ((server_stream, _), client_stream) =
+            futures_lite::future::zip(async { listener.accept().await.unwrap() }, async {
+                connect.await.unwrap()
+            })
+            .await;
+
+        let server = modrpc::TcpServer::new().with_encryption(server_config);
+        let _ = server
+            .accept_local::<RequestServerRole<u32, u32>>(
+                &server_rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                server_stream,
+                |cx| {
+                    RequestServerBuilder::new(
+                        "request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init,
+                    )
+                    .build(cx.setup, async |_source, request: u32| request * 2);
+                },
+                RequestServerConfig { },
+                RequestInitState { },
+            )
+            .await
+            .unwrap();
+
+        let (_, transport, client) =
+            connect_client(&client_rt, &buffer_pool, encryption_options(&client_config), client_stream)
+                .await;
+        transport
+            .plane_keys
+            .as_ref()
+            .unwrap()
+            .wait_for_peer(0, modrpc::EndpointAddr { endpoint: 0 })
+            .await;
+        assert_eq!(client.call(21u32).await, 42);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(client_rt_shutdown.shutdown());
+    ex.run_until(server_rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_encrypted_plane_through_hub() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (alice_rt, alice_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (bob_rt, bob_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (mallory_rt, mallory_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        // The hub only relays the plane - it never holds any of the keys.
+        let config = shared_encryption_config();
+
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (alice_addr, _alice_transport, ()) =
+            modrpc::tcp_connect_builder::<RequestServerRole<u32, u32>, _>(
+                &alice_rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                RequestServerConfig { },
+                encryption_options(&config),
+                stream,
+                async |start_role| {
+                    start_role.local_async(|cx| {
+                        RequestServerBuilder::new(
+                            "request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init,
+                        )
+                        .build(cx.setup, async |_source, request: u32| request * 2);
+                    }).await;
+                },
+            )
+            .await
+            .unwrap();
+
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (_, bob_transport, bob) =
+            connect_client(&bob_rt, &buffer_pool, encryption_options(&config), stream).await;
+        bob_transport
+            .plane_keys
+            .as_ref()
+            .unwrap()
+            .wait_for_peer(REQUEST_PLANE_ID, alice_addr)
+            .await;
+        assert_eq!(bob.call(21u32).await, 42);
+
+        // A client that doesn't take part in the key exchange is ignored.
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (_, _mallory_transport, mallory) =
+            connect_client(&mallory_rt, &buffer_pool, modrpc::TransportOptions::default(), stream)
+                .await;
+        assert!(!is_answered(worker_cx, &mallory).await);
+        assert!(is_answered(worker_cx, &bob).await);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(mallory_rt_shutdown.shutdown());
+    ex.run_until(bob_rt_shutdown.shutdown());
+    ex.run_until(alice_rt_shutdown.shutdown());
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_encrypted_plane_through_role_restricted_hub() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (alice_rt, alice_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (bob_rt, bob_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .build(RoleDelegate)
+            .await;
+        hub.declare_plane_interface::<RequestInterface<u32, u32>>(REQUEST_PLANE_ID);
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        let config = shared_encryption_config();
+
+        // Alice may only send the server's events, but her key exchange still gets through.
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut stream, "Server").await.unwrap();
+        let (alice_addr, _alice_transport, ()) =
+            modrpc::tcp_connect_builder::<RequestServerRole<u32, u32>, _>(
+                &alice_rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                RequestServerConfig { },
+                encryption_options(&config),
+                stream,
+                async |start_role| {
+                    start_role.local_async(|cx| {
+                        RequestServerBuilder::new(
+                            "request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init,
+                        )
+                        .build(cx.setup, async |_source, request: u32| request * 2);
+                    }).await;
+                },
+            )
+            .await
+            .unwrap();
+
+        let mut stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        modrpc::tcp_client_hello(&mut stream, "Client").await.unwrap();
+        let (_, bob_transport, bob) =
+            connect_client(&bob_rt, &buffer_pool, encryption_options(&config), stream).await;
+        bob_transport
+            .plane_keys
+            .as_ref()
+            .unwrap()
+            .wait_for_peer(REQUEST_PLANE_ID, alice_addr)
+            .await;
+        assert_eq!(bob.call(21u32).await, 42);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(bob_rt_shutdown.shutdown());
+    ex.run_until(alice_rt_shutdown.shutdown());
+    ex.run_until(rt_shutdown.shutdown());
+}
@@ -0,0 +1,244 @@
+#![cfg(feature = "tcp-transport")]
+
+mod common;
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    rc::Rc,
+    time::Duration,
+};
+
+use modrpc_executor::ModrpcExecutor;
+use mproto::BaseLen;
+use modrpc_hub::{
+    Broadcaster,
+    BroadcasterHandle,
+    ChannelId,
+    Federation,
+    FederationConfig,
+    FederationHello,
+    InPacket,
+    SpokeEgressConfig,
+    SpokeEgressQueue,
+    TransportIndex,
+};
+
+use common::wait_until;
+
+const CHANNEL_ID: u32 = 7;
+
+struct TestHub {
+    federation: Rc<Federation>,
+    // Where the hub accepts federation links, if it does.
+    listen_addr: Option<SocketAddr>,
+    broadcaster: BroadcasterHandle,
+    client: TransportIndex,
+    client_egress: SpokeEgressQueue,
+}
+
+async fn new_hub(
+    rt: &modrpc::RuntimeHandle,
+    buffer_pool: &bab::HeapBufferPool,
+    hub_id: u32,
+    listen: bool,
+    upstream_addr: Option<SocketAddr>,
+    allowed_peers: Option<Vec<IpAddr>>,
+) -> TestHub {
+    let worker_cx = rt.local_worker_context().unwrap();
+    let broadcaster = Broadcaster::spawn_sharded(rt, [modrpc::WorkerId::local()], 64).await;
+
+    let client_egress = SpokeEgressQueue::new(SpokeEgressConfig::default());
+    let client = broadcaster.add_tcp(client_egress.clone()).await;
+    broadcaster.add_next_hop_to_channels(
+        client,
+        vec![(ChannelId { channel_id: CHANNEL_ID }, ChannelId { channel_id: CHANNEL_ID })],
+    )
+    .await;
+
+    let federation = Federation::new(
+        FederationConfig {
+            hub_id,
+            channel_ids: vec![CHANNEL_ID],
+            buffer_pool: buffer_pool.clone(),
+            max_packet_size: buffer_pool.buffer_size(),
+            keepalive: None,
+            compression: None,
+            spoke_egress: SpokeEgressConfig::default(),
+            allowed_peers,
+        },
+        broadcaster.clone(),
+    );
+    if let Some(upstream_addr) = upstream_addr {
+        federation.spawn_upstream(&worker_cx, upstream_addr);
+    }
+    let mut listen_addr = None;
+    if listen {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listen_addr = Some(listener.local_addr().unwrap());
+        federation.spawn_listener(&worker_cx, listener);
+    }
+
+    TestHub { federation, listen_addr, broadcaster, client, client_egress }
+}
+
+fn new_packet(buffer_pool: &bab::HeapBufferPool, payload: &[u8]) -> modrpc::Packet {
+    let buffer = buffer_pool.try_acquire().unwrap();
+    unsafe {
+        buffer.initialize_rc(1, 0, 0);
+        core::ptr::copy_nonoverlapping(payload.as_ptr(), buffer.data(), payload.len());
+        modrpc::Packet::new(buffer, 0, payload.len())
+    }
+}
+
+// Links only relay whole packets.
+fn transmit_packet(payload: &[u8]) -> Vec<u8> {
+    let mut packet = mproto::encode_value_vec(modrpc::TransmitPacket {
+        payload_length: payload.len() as u16,
+        infra_id: 0,
+        plane_id: 0,
+        topic: 0,
+        source: modrpc::EndpointAddr { endpoint: 0 },
+    });
+    packet.extend_from_slice(payload);
+    packet
+}
+
+#[test]
+fn test_three_hub_chain() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = bab::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub1 = new_hub(&rt, &buffer_pool, 1, true, None, None).await;
+        let hub2 = new_hub(&rt, &buffer_pool, 2, true, hub1.listen_addr, None).await;
+        let hub3 = new_hub(&rt, &buffer_pool, 3, false, hub2.listen_addr, None).await;
+
+        wait_until(&worker_cx, || {
+            hub1.federation.downstream_hubs() == [2]
+                && hub2.federation.downstream_hubs() == [3]
+        })
+        .await;
+        assert_eq!(hub2.federation.upstream_path(), Some(vec![2, 1]));
+        assert_eq!(hub3.federation.upstream_path(), Some(vec![3, 2, 1]));
+
+        let hubs = [&hub1, &hub2, &hub3];
+        for (i, hub) in hubs.iter().enumerate() {
+            hub.broadcaster.send_in_packet(InPacket {
+                transport: hub.client,
+                channel_id: CHANNEL_ID,
+                packet: new_packet(&buffer_pool, &transmit_packet(&[i as u8])),
+            })
+            .await
+            .unwrap();
+        }
+
+        for (i, hub) in hubs.iter().enumerate() {
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                let bundle = hub.client_egress.pop().await.unwrap();
+                assert_eq!(bundle.channel_id, CHANNEL_ID);
+                received.push(bundle.payload[modrpc::TransmitPacket::BASE_LEN]);
+            }
+            received.sort();
+            let expected: Vec<u8> = (0..3).filter(|&j| j != i as u8).collect();
+            assert_eq!(received, expected);
+        }
+
+        // Nothing loops back around.
+        worker_cx.sleep(Duration::from_millis(100)).await;
+        for hub in hubs {
+            assert_eq!(hub.client_egress.stats().queue_depth, 0);
+        }
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_reject_duplicate_hub_id() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = bab::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub1 = new_hub(&rt, &buffer_pool, 1, true, None, None).await;
+        let imposter = new_hub(&rt, &buffer_pool, 1, false, hub1.listen_addr, None).await;
+
+        worker_cx.sleep(Duration::from_millis(200)).await;
+        assert!(hub1.federation.downstream_hubs().is_empty());
+        assert_eq!(imposter.federation.upstream_path(), None);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_refuse_unlisted_peer() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = bab::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let localhost: IpAddr = [127, 0, 0, 1].into();
+        let elsewhere: IpAddr = [10, 0, 0, 1].into();
+
+        let hub1 = new_hub(&rt, &buffer_pool, 1, true, None, Some(vec![localhost])).await;
+        let _hub2 = new_hub(&rt, &buffer_pool, 2, false, hub1.listen_addr, None).await;
+        wait_until(&worker_cx, || hub1.federation.downstream_hubs() == [2]).await;
+
+        let hub3 = new_hub(&rt, &buffer_pool, 3, true, None, Some(vec![elsewhere])).await;
+        let hub4 = new_hub(&rt, &buffer_pool, 4, false, hub3.listen_addr, None).await;
+        worker_cx.sleep(Duration::from_millis(200)).await;
+        assert!(hub3.federation.downstream_hubs().is_empty());
+        assert_eq!(hub4.federation.upstream_path(), None);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
+
+#[test]
+fn test_malformed_bundle_closes_link() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = bab::HeapBufferPool::new(256, 8, 8);
+
+    ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub1 = new_hub(&rt, &buffer_pool, 1, true, None, None).await;
+
+        // Link to the hub by hand.
+        let mut stream = tokio::net::TcpStream::connect(hub1.listen_addr.unwrap()).await.unwrap();
+        let hello = mproto::encode_value_vec(FederationHello { hub_id: 2, channel_ids: vec![CHANNEL_ID] });
+        stream.write_all(&(hello.len() as u16).to_le_bytes()).await.unwrap();
+        stream.write_all(&hello).await.unwrap();
+        wait_until(&worker_cx, || hub1.federation.downstream_hubs() == [2]).await;
+
+        // A bundle that isn't made up of whole packets.
+        let mut bundle = mproto::encode_value_vec(modrpc::PacketBundle { channel_id: CHANNEL_ID, length: 3 });
+        bundle.extend_from_slice(&[0; 3]);
+        stream.write_all(&bundle).await.unwrap();
+
+        wait_until(&worker_cx, || hub1.federation.downstream_hubs().is_empty()).await;
+        assert_eq!(hub1.client_egress.stats().queue_depth, 0);
+    });
+
+    drop(buffer_pool);
+    ex.run_until(rt_shutdown.shutdown());
+}
@@ -0,0 +1,183 @@
+#![cfg(feature = "tcp-transport")]
+
+mod common;
+
+use std::cell::OnceCell;
+
+use foo_modrpc::{
+    FooClientConfig,
+    FooClientRole,
+    FooClientStubs,
+    FooInitState,
+    FooInterface,
+    FooServerConfig,
+    FooServerHooks,
+    FooServerRole,
+};
+use modrpc::{InterfaceBuilder, InterfaceSchema};
+use modrpc_executor::ModrpcExecutor;
+use modrpc_hub::{AppHub, AppHubBuilder, AppHubDelegate};
+use mproto::BaseLen;
+use std_modrpc::PropertyInitState;
+
+use common::{CaptureBuf, localhost};
+
+const FOO_PLANE_ID: u32 = 0x100;
+const BAR_CHANNEL_ID: u32 = 0x101;
+
+struct FooPlaneDelegate;
+
+impl AppHubDelegate for FooPlaneDelegate {
+    type Init<'a> = FooInitState;
+    type Hello<'a> = ();
+    type Identity = ();
+
+    async fn authenticate(
+        &self,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _hello: Option<Self::Hello<'_>>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    async fn client_handshake(
+        &self,
+        _hub: &AppHub<Self>,
+        _endpoint_addr: modrpc::EndpointAddr,
+        _identity: &(),
+        handshake_fn: impl for<'a> AsyncFnOnce(u32, FooInitState) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        handshake_fn(FOO_PLANE_ID, FooInitState { fooness: PropertyInitState { value: 0 } }).await
+    }
+
+    async fn client_disconnected(&self, _endpoint_addr: modrpc::EndpointAddr) { }
+}
+
+/// `bar_the_foo` gets its own channel, everything else goes out on the plane's channel.
+fn foo_topic_channels() -> modrpc::TopicChannels {
+    modrpc::TopicChannels::MultiChannel {
+        default_channel_id: FOO_PLANE_ID,
+        routes: vec![("bar_the_foo".into(), BAR_CHANNEL_ID)],
+    }
+}
+
+fn foo_options() -> modrpc::TransportOptions {
+    modrpc::TransportOptions {
+        topic_channels: Some(foo_topic_channels()),
+        ..Default::default()
+    }
+}
+
+/// `(channel_id, topic)` of every packet sent in a capture.
+fn sent_packets(capture: &[u8]) -> Vec<(u32, u32)> {
+    let mut reader = modrpc::CaptureReader::new(capture).unwrap();
+    let mut packets = Vec::new();
+    while let Some(record) = reader.next_record().unwrap() {
+        let header: modrpc::PacketBundle = mproto::decode_value(&record.bundle).unwrap();
+        if record.direction != modrpc::CaptureDirection::Outbound
+            || header.channel_id >= modrpc::FIRST_CONTROL_CHANNEL_ID
+        {
+            continue;
+        }
+
+        let mut cursor = modrpc::PacketBundle::BASE_LEN;
+        while cursor < record.bundle.len() {
+            let packet: modrpc::TransmitPacket =
+                mproto::decode_value(&record.bundle[cursor..]).unwrap();
+            packets.push((header.channel_id, packet.topic));
+            cursor += modrpc::TransmitPacket::BASE_LEN + packet.payload_length as usize;
+        }
+    }
+    packets
+}
+
+#[test]
+fn test_multi_channel_plane_through_hub() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (callee_rt, callee_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let capture_buf = CaptureBuf::default();
+    let (caller_rt, caller_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .with_capture(modrpc::PacketCapture::new(capture_buf.clone()).unwrap())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    ex.run_until(async {
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .plane_channels(FOO_PLANE_ID, foo_topic_channels())
+            .with_tcp(localhost())
+            .build(FooPlaneDelegate)
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let _ = modrpc::tcp_connect_builder::<FooClientRole, _>(
+            &callee_rt,
+            buffer_pool.clone(),
+            buffer_pool.clone(),
+            modrpc::WorkerId::local(),
+            FooClientConfig { },
+            foo_options(),
+            stream,
+            async |start_role| {
+                start_role.local_async(|cx| {
+                    let FooClientStubs { foo_the_bar, bar_the_foo } = cx.stubs;
+                    foo_the_bar.build(cx.setup, async |_source, request: u32| Ok(request as u64 * 2));
+                    bar_the_foo.build(cx.setup, async |_source, request: &str| {
+                        Ok(request.to_uppercase())
+                    });
+                }).await;
+            },
+        )
+        .await
+        .unwrap();
+
+        let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+        let (_, _caller_transport, caller) =
+            modrpc::tcp_connect_builder::<FooServerRole, _>(
+                &caller_rt,
+                buffer_pool.clone(),
+                buffer_pool.clone(),
+                modrpc::WorkerId::local(),
+                FooServerConfig { },
+                foo_options(),
+                stream,
+                async |start_role| {
+                    let hooks = OnceCell::new();
+                    start_role.local_async(|cx| {
+                        let _ = hooks.set(cx.hooks.clone());
+                        cx.stubs.fooness.build(cx.setup);
+                    }).await;
+                    hooks.into_inner().unwrap()
+                },
+            )
+            .await
+            .unwrap();
+        let caller: FooServerHooks = caller;
+
+        // Both channels are relayed by the hub.
+        assert_eq!(caller.foo_the_bar.call(21u32).await, Ok(42));
+        assert_eq!(caller.bar_the_foo.call("foo".to_string()).await, Ok("FOO".to_string()));
+    });
+
+    caller_rt.capture().unwrap().flush().unwrap();
+    let mut ib = InterfaceBuilder::new();
+    let foo = FooInterface::new(&mut ib);
+    let sent = sent_packets(&capture_buf.0.lock().unwrap());
+    assert!(sent.contains(&(FOO_PLANE_ID, foo.foo_the_bar.request.topic)));
+    assert!(sent.contains(&(BAR_CHANNEL_ID, foo.bar_the_foo.request.topic)));
+    // Each topic was only ever written to its own channel's writer.
+    assert!(sent.iter().all(|&(channel_id, topic)| {
+        (channel_id == BAR_CHANNEL_ID) == (topic == foo.bar_the_foo.request.topic)
+    }));
+
+    drop(buffer_pool);
+    ex.run_until(caller_rt_shutdown.shutdown());
+    ex.run_until(callee_rt_shutdown.shutdown());
+    ex.run_until(rt_shutdown.shutdown());
+}
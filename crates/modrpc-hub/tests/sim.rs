@@ -0,0 +1,131 @@
+use std::{cell::RefCell, rc::Rc};
+
+use modrpc_executor::{ModrpcExecutor, SimExecutor};
+use modrpc_hub::{Broadcaster, BroadcasterHandle, LocalHubTransport};
+use std_modrpc::{
+    RequestClient,
+    RequestClientBuilder,
+    RequestClientConfig,
+    RequestClientRole,
+    RequestInitState,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+};
+
+const PLANE_ID: u32 = 0x100;
+
+fn role_config<Role: modrpc::InterfaceRole>(
+    transport: modrpc::TransportHandle,
+    endpoint: u64,
+    config: Role::Config,
+    init: Role::Init,
+) -> modrpc::RoleConfig<Role> {
+    modrpc::RoleConfig {
+        plane_id: PLANE_ID,
+        endpoint_addr: modrpc::EndpointAddr { endpoint },
+        transport,
+        topic_channels: modrpc::TopicChannels::SingleChannel { channel_id: PLANE_ID },
+        config,
+        init,
+    }
+}
+
+async fn join_hub(
+    rt: &modrpc::RuntimeHandle,
+    buffer_pool: &modrpc::HeapBufferPool,
+    broadcaster: &BroadcasterHandle,
+) -> modrpc::TransportHandle {
+    rt.add_transport(LocalHubTransport::Static {
+        buffer_pool: buffer_pool.clone(),
+        broadcaster_handle: broadcaster.clone(),
+        channel_ids: vec![PLANE_ID],
+    })
+    .await
+}
+
+async fn start_client(
+    rt: &modrpc::RuntimeHandle,
+    buffer_pool: &modrpc::HeapBufferPool,
+    broadcaster: &BroadcasterHandle,
+    endpoint: u64,
+) -> RequestClient<u32, u32> {
+    let transport = join_hub(rt, buffer_pool, broadcaster).await;
+    let mut client = None;
+    let _ =
+        rt.start_role::<RequestClientRole<u32, u32>>(
+            role_config(transport, endpoint, RequestClientConfig { }, RequestInitState { }),
+        )
+        .local_async(|cx| {
+            let builder = RequestClientBuilder::new("request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+            client = Some(builder.create_handle(cx.setup));
+            builder.build(cx.setup);
+        }).await;
+    client.unwrap()
+}
+
+/// Have two clients call a server through a hub at the same time, returning `(endpoint, request)`
+/// in the order the server handled them.
+fn run_requests_through_hub(seed: u64) -> Vec<(u64, u32)> {
+    let mut ex = SimExecutor::with_seed(seed);
+    let (hub_rt, _hub_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (server_rt, _server_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (alice_rt, _alice_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (bob_rt, _bob_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+    let handled = Rc::new(RefCell::new(Vec::new()));
+
+    ex.run_until(async {
+        let broadcaster =
+            Broadcaster::spawn_sharded(&hub_rt, [modrpc::WorkerId::local()], 64).await;
+
+        let transport = join_hub(&server_rt, &buffer_pool, &broadcaster).await;
+        let _ =
+            server_rt.start_role::<RequestServerRole<u32, u32>>(
+                role_config(transport, 1, RequestServerConfig { }, RequestInitState { }),
+            )
+            .local_async(|cx| {
+                let handled = handled.clone();
+                RequestServerBuilder::new("request", cx.hooks.clone(), cx.stubs, cx.config, *cx.init)
+                    .build(cx.setup, async move |source: modrpc::EndpointAddr, request: u32| {
+                        handled.borrow_mut().push((source.endpoint, request));
+                        request * 2
+                    });
+            }).await;
+
+        let alice = start_client(&alice_rt, &buffer_pool, &broadcaster, 2).await;
+        let bob = start_client(&bob_rt, &buffer_pool, &broadcaster, 3).await;
+
+        let alice_calls = async {
+            for request in 0..5u32 {
+                assert_eq!(alice.call(request).await, request * 2);
+            }
+        };
+        let bob_calls = async {
+            for request in 100..105u32 {
+                assert_eq!(bob.call(request).await, request * 2);
+            }
+        };
+        futures_lite::future::zip(alice_calls, bob_calls).await;
+    });
+
+    handled.take()
+}
+
+#[test]
+fn test_requests_through_hub() {
+    let handled = run_requests_through_hub(7);
+
+    let requests_from = |endpoint| {
+        handled.iter()
+            .filter(|(source, _)| *source == endpoint)
+            .map(|(_, request)| *request)
+            .collect::<Vec<_>>()
+    };
+    // Each client's requests reached the server, and every response found its way back.
+    assert_eq!(requests_from(2), [0, 1, 2, 3, 4]);
+    assert_eq!(requests_from(3), [100, 101, 102, 103, 104]);
+
+    // The same seed interleaves the clients the same way.
+    assert_eq!(run_requests_through_hub(7), handled);
+}
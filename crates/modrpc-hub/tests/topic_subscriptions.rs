@@ -0,0 +1,128 @@
+#![cfg(feature = "tcp-transport")]
+
+mod common;
+
+use std::{net::SocketAddr, time::Duration};
+
+use modrpc::{InterfaceBuilder, InterfaceSchema};
+use modrpc_executor::ModrpcExecutor;
+use modrpc_hub::AppHubBuilder;
+use mproto::BaseLen;
+use std_modrpc::{
+    RequestClient,
+    RequestInitState,
+    RequestInterface,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+    Response,
+};
+
+use common::{
+    CaptureBuf,
+    REQUEST_PLANE_ID,
+    RequestPlaneDelegate,
+    connect_client,
+    localhost,
+};
+
+/// `requester` of every response received in a capture.
+fn received_responses(capture: &[u8], response_topic: u32) -> Vec<u64> {
+    let mut reader = modrpc::CaptureReader::new(capture).unwrap();
+    let mut requesters = Vec::new();
+    while let Some(record) = reader.next_record().unwrap() {
+        let header: modrpc::PacketBundle = mproto::decode_value(&record.bundle).unwrap();
+        if record.direction != modrpc::CaptureDirection::Inbound
+            || header.channel_id >= modrpc::FIRST_CONTROL_CHANNEL_ID
+        {
+            continue;
+        }
+
+        let mut cursor = modrpc::PacketBundle::BASE_LEN;
+        while cursor < record.bundle.len() {
+            let packet: modrpc::TransmitPacket =
+                mproto::decode_value(&record.bundle[cursor..]).unwrap();
+            let payload = &record.bundle[cursor + modrpc::TransmitPacket::BASE_LEN..];
+            if packet.topic == response_topic {
+                let response: Response<u32> = mproto::decode_value(payload).unwrap();
+                requesters.push(response.requester);
+            }
+            cursor += modrpc::TransmitPacket::BASE_LEN + packet.payload_length as usize;
+        }
+    }
+    requesters
+}
+
+/// Join the hub as a client that subscribes to the topics its role receives.
+async fn connect_subscribed_client(
+    rt: &modrpc::RuntimeHandle,
+    buffer_pool: &modrpc::HeapBufferPool,
+    hub_addr: SocketAddr,
+) -> (modrpc::EndpointAddr, modrpc::TransportHandle, RequestClient<u32, u32>) {
+    let stream = tokio::net::TcpStream::connect(hub_addr).await.unwrap();
+    let options = modrpc::TransportOptions {
+        subscribe_topics: true,
+        ..Default::default()
+    };
+    connect_client(rt, buffer_pool, options, stream).await
+}
+
+#[test]
+fn test_responses_only_reach_their_requester() {
+    let mut ex = modrpc_executor::TokioExecutor::new();
+    let (rt, rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let (alice_rt, alice_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .start::<modrpc_executor::TokioExecutor>();
+    let capture_buf = CaptureBuf::default();
+    let (bob_rt, bob_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .with_capture(modrpc::PacketCapture::new(capture_buf.clone()).unwrap())
+        .start::<modrpc_executor::TokioExecutor>();
+    let buffer_pool = modrpc::HeapBufferPool::new(256, 16, 16);
+
+    let bob_addr = ex.run_until(async {
+        let worker_cx = rt.local_worker_context().unwrap();
+        let hub = AppHubBuilder::new(buffer_pool.clone(), rt.clone())
+            .with_tcp(localhost())
+            .build(RequestPlaneDelegate::default())
+            .await;
+        let hub_addr = hub.tcp_addr().unwrap();
+        hub.start_plane::<RequestServerRole<u32, u32>>(
+            REQUEST_PLANE_ID,
+            RequestServerConfig { },
+            RequestInitState { },
+        )
+        .await
+        .local_async(|cx| {
+            RequestServerBuilder::new("request", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone())
+                .build(cx.setup, async |_source, request: u32| request * 2);
+        }).await;
+
+        let (_, _alice_transport, alice) = connect_subscribed_client(&alice_rt, &buffer_pool, hub_addr).await;
+        let (bob_addr, _bob_transport, bob) = connect_subscribed_client(&bob_rt, &buffer_pool, hub_addr).await;
+        // Let the hub apply both clients' subscriptions.
+        worker_cx.sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(alice.call(21u32).await, 42);
+        assert_eq!(bob.call(1u32).await, 2);
+        assert_eq!(alice.call(5u32).await, 10);
+        worker_cx.sleep(Duration::from_millis(50)).await;
+
+        bob_addr
+    });
+
+    // Bob only ever received the response to his own request.
+    bob_rt.capture().unwrap().flush().unwrap();
+    let mut ib = InterfaceBuilder::new();
+    let request = RequestInterface::<u32, u32>::new(&mut ib);
+    let requesters = received_responses(&capture_buf.0.lock().unwrap(), request.response.topic);
+    assert_eq!(requesters, [bob_addr.endpoint]);
+
+    drop(buffer_pool);
+    ex.run_until(bob_rt_shutdown.shutdown());
+    ex.run_until(alice_rt_shutdown.shutdown());
+    ex.run_until(rt_shutdown.shutdown());
+}
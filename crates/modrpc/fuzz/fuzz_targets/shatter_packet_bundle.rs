@@ -0,0 +1,28 @@
+#![no_main]
+
+use core::mem::MaybeUninit;
+
+use libfuzzer_sys::fuzz_target;
+use modrpc::Packet;
+
+fuzz_target!(|data: &[u8]| {
+    let buffer_pool = bab::HeapBufferPool::new(1024, 1, 1);
+    if data.len() > buffer_pool.buffer_size() {
+        return;
+    }
+
+    let packet = unsafe {
+        let buffer = buffer_pool.try_acquire().unwrap();
+        buffer.initialize_rc(1, 0, 0);
+        buffer.slice_mut(0..data.len()).copy_from_slice(data);
+        Packet::new(buffer, 0, data.len())
+    };
+
+    let mut offsets = Vec::new();
+    let mut out_packets: Vec<MaybeUninit<Packet>> = Vec::new();
+    if modrpc::shatter_packet_bundle(packet, &mut offsets, &mut out_packets).is_ok() {
+        for packet in out_packets.drain(..) {
+            drop(unsafe { packet.assume_init() });
+        }
+    }
+});
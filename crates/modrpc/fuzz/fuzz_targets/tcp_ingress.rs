@@ -0,0 +1,29 @@
+#![no_main]
+
+use core::mem::MaybeUninit;
+
+use libfuzzer_sys::fuzz_target;
+use modrpc::{Packet, TcpIngress};
+
+// Feed a byte stream to the ingress and shatter what it frames, as `TcpTransport` does.
+fuzz_target!(|data: &[u8]| {
+    let buffer_pool = bab::HeapBufferPool::new(1024, 1, 4);
+    let mut ingress = TcpIngress::new(data, buffer_pool, 512);
+    ingress.set_decompression(true);
+
+    let mut offsets = Vec::new();
+    let mut out_packets: Vec<MaybeUninit<Packet>> = Vec::new();
+    futures_lite::future::block_on(async {
+        while let Ok(packet_bundle) = ingress.receive().await {
+            if modrpc::is_control_bundle(&packet_bundle[..]) {
+                continue;
+            }
+            if modrpc::shatter_packet_bundle(packet_bundle, &mut offsets, &mut out_packets).is_err() {
+                break;
+            }
+            for packet in out_packets.drain(..) {
+                drop(unsafe { packet.assume_init() });
+            }
+        }
+    });
+});
@@ -0,0 +1,42 @@
+#![no_main]
+
+use core::mem::MaybeUninit;
+
+use libfuzzer_sys::fuzz_target;
+use modrpc::{Packet, WebSocketIngress};
+use tokio_tungstenite::tungstenite::{Error, protocol::Message};
+
+// The input is a sequence of WebSocket messages, each prefixed with its little-endian u16 length.
+fn split_messages(mut data: &[u8]) -> Vec<Result<Message, Error>> {
+    let mut messages = Vec::new();
+    while data.len() >= 2 {
+        let len = std::cmp::min(u16::from_le_bytes([data[0], data[1]]) as usize, data.len() - 2);
+        messages.push(Ok(Message::Binary(data[2..2 + len].to_vec().into())));
+        data = &data[2 + len..];
+    }
+    messages
+}
+
+// Feed messages to the ingress and shatter what it frames, as `WebSocketTransport` does.
+fuzz_target!(|data: &[u8]| {
+    let buffer_pool = bab::HeapBufferPool::new(1024, 1, 4);
+    let ws = futures_util::stream::iter(split_messages(data));
+    let mut ingress = WebSocketIngress::new(ws, buffer_pool, 512);
+    ingress.set_decompression(true);
+
+    let mut offsets = Vec::new();
+    let mut out_packets: Vec<MaybeUninit<Packet>> = Vec::new();
+    futures_lite::future::block_on(async {
+        while let Ok(packet_bundle) = ingress.receive().await {
+            if modrpc::is_control_bundle(&packet_bundle[..]) {
+                continue;
+            }
+            if modrpc::shatter_packet_bundle(packet_bundle, &mut offsets, &mut out_packets).is_err() {
+                break;
+            }
+            for packet in out_packets.drain(..) {
+                drop(unsafe { packet.assume_init() });
+            }
+        }
+    });
+});
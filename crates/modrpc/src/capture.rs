@@ -0,0 +1,548 @@
+use std::{
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use mproto::BaseLen;
+
+use crate::{
+    Packet, PacketBundle, TransportError, TransportHandle, WorkerContext, is_control_bundle,
+    shatter_packet_bundle,
+};
+
+// Captures are pcapng files holding one interface per transport and one enhanced packet block per
+// bundle, so they can also be opened by tools that read pcapng.
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+// LINKTYPE_USER0 - bundles have no registered link type.
+const LINKTYPE_MODRPC: u16 = 147;
+
+const OPT_END: u16 = 0;
+const OPT_SHB_USERAPPL: u16 = 4;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+const OPT_EPB_FLAGS: u16 = 2;
+
+const EPB_FLAGS_INBOUND: u32 = 0b01;
+const EPB_FLAGS_OUTBOUND: u32 = 0b10;
+
+// Far larger than any bundle - guards against allocating for a corrupt block length.
+const MAX_BLOCK_LEN: usize = 1 << 20;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum CaptureDirection {
+    /// Received from the peer.
+    Inbound,
+    /// Sent to the peer.
+    Outbound,
+}
+
+/// Records the bundles sent and received by a runtime's transports. Install it with
+/// `RuntimeBuilder::with_capture` - each transport added to the runtime afterwards records its
+/// traffic under a new transport id. Compressed bundles are recorded uncompressed.
+#[derive(Clone)]
+pub struct PacketCapture {
+    inner: Arc<Mutex<CaptureWriter>>,
+}
+
+struct CaptureWriter {
+    // `None` once a write has failed.
+    out: Option<Box<dyn Write + Send>>,
+    transport_count: u32,
+    block: Vec<u8>,
+}
+
+impl PacketCapture {
+    pub fn new(out: impl Write + Send + 'static) -> io::Result<Self> {
+        let mut writer = CaptureWriter {
+            out: Some(Box::new(out)),
+            transport_count: 0,
+            block: Vec::new(),
+        };
+
+        let body = writer.start_block(BLOCK_SECTION_HEADER);
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // Section length is unknown.
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        push_option(body, OPT_SHB_USERAPPL, b"modrpc");
+        push_option(body, OPT_END, &[]);
+        writer.write_block()?;
+
+        Ok(Self { inner: Arc::new(Mutex::new(writer)) })
+    }
+
+    /// Capture to a new file at `path`, replacing any existing file.
+    pub fn create(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        Self::new(io::BufWriter::new(std::fs::File::create(path)?))
+    }
+
+    /// Start recording a transport. `name` describes the kind of transport, e.g. `"tcp"`.
+    pub fn add_transport(&self, name: &str) -> TransportCapture {
+        let mut writer = self.inner.lock().expect("modrpc capture lock poisoned");
+        let transport_id = writer.transport_count;
+        writer.transport_count += 1;
+
+        let body = writer.start_block(BLOCK_INTERFACE_DESCRIPTION);
+        body.extend_from_slice(&LINKTYPE_MODRPC.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        // No snap length - bundles are recorded whole.
+        body.extend_from_slice(&0u32.to_le_bytes());
+        push_option(body, OPT_IF_NAME, name.as_bytes());
+        // Nanosecond timestamps.
+        push_option(body, OPT_IF_TSRESOL, &[9]);
+        push_option(body, OPT_END, &[]);
+        writer.write_block_or_stop();
+
+        TransportCapture {
+            capture: self.clone(),
+            transport_id,
+        }
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        let mut writer = self.inner.lock().expect("modrpc capture lock poisoned");
+        match &mut writer.out {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CaptureWriter {
+    fn start_block(&mut self, block_type: u32) -> &mut Vec<u8> {
+        self.block.clear();
+        self.block.extend_from_slice(&block_type.to_le_bytes());
+        // Total length - filled in by `write_block`.
+        self.block.extend_from_slice(&0u32.to_le_bytes());
+        &mut self.block
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        let Some(out) = &mut self.out else {
+            return Ok(());
+        };
+        let block_len = (self.block.len() + 4) as u32;
+        self.block[4..8].copy_from_slice(&block_len.to_le_bytes());
+        self.block.extend_from_slice(&block_len.to_le_bytes());
+        out.write_all(&self.block)
+    }
+
+    fn write_block_or_stop(&mut self) {
+        if let Err(e) = self.write_block() {
+            log::warn!("Stopping packet capture: {e}");
+            self.out = None;
+        }
+    }
+}
+
+/// Records the bundles of one transport to a `PacketCapture`.
+#[derive(Clone)]
+pub struct TransportCapture {
+    capture: PacketCapture,
+    transport_id: u32,
+}
+
+impl TransportCapture {
+    pub fn transport_id(&self) -> u32 {
+        self.transport_id
+    }
+
+    /// Record a whole bundle, header included.
+    pub fn record(&self, direction: CaptureDirection, packet_bundle: &[u8]) {
+        self.record_parts(direction, &[], packet_bundle);
+    }
+
+    /// Record an uncompressed bundle on `channel_id` holding `payload`.
+    pub fn record_bundle(&self, direction: CaptureDirection, channel_id: u32, payload: &[u8]) {
+        let mut header = [0u8; PacketBundle::BASE_LEN];
+        mproto::encode_value(
+            PacketBundle {
+                channel_id,
+                length: payload.len() as u16,
+            },
+            &mut header[..],
+        );
+        self.record_parts(direction, &header, payload);
+    }
+
+    fn record_parts(&self, direction: CaptureDirection, header: &[u8], payload: &[u8]) {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let captured_len = (header.len() + payload.len()) as u32;
+        let flags = match direction {
+            CaptureDirection::Inbound => EPB_FLAGS_INBOUND,
+            CaptureDirection::Outbound => EPB_FLAGS_OUTBOUND,
+        };
+
+        let mut writer = self.capture.inner.lock().expect("modrpc capture lock poisoned");
+        if writer.out.is_none() {
+            return;
+        }
+        let body = writer.start_block(BLOCK_ENHANCED_PACKET);
+        body.extend_from_slice(&self.transport_id.to_le_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&captured_len.to_le_bytes());
+        body.extend_from_slice(&captured_len.to_le_bytes());
+        body.extend_from_slice(header);
+        body.extend_from_slice(payload);
+        pad_to_word(body);
+        push_option(body, OPT_EPB_FLAGS, &flags.to_le_bytes());
+        push_option(body, OPT_END, &[]);
+        writer.write_block_or_stop();
+    }
+}
+
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad_to_word(body);
+}
+
+fn pad_to_word(body: &mut Vec<u8>) {
+    body.resize(body.len().next_multiple_of(4), 0);
+}
+
+/// A bundle read back from a capture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureRecord {
+    pub transport_id: u32,
+    /// Time since the Unix epoch.
+    pub timestamp: Duration,
+    pub direction: CaptureDirection,
+    /// The bundle's header followed by its payload.
+    pub bundle: Vec<u8>,
+}
+
+/// Reads the bundles recorded by a `PacketCapture`.
+pub struct CaptureReader<R> {
+    reader: R,
+    transports: Vec<CaptureTransport>,
+    block: Vec<u8>,
+}
+
+struct CaptureTransport {
+    name: String,
+    // Timestamp units per second.
+    ticks_per_second: u64,
+}
+
+impl CaptureReader<io::BufReader<std::fs::File>> {
+    pub fn open(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        Self::new(io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut capture_reader = Self {
+            reader,
+            transports: Vec::new(),
+            block: Vec::new(),
+        };
+        match capture_reader.read_block()? {
+            Some(BLOCK_SECTION_HEADER) => {
+                capture_reader.read_section_header()?;
+                Ok(capture_reader)
+            }
+            _ => Err(invalid_data("not a pcapng capture")),
+        }
+    }
+
+    /// The name the transport recorded under `transport_id` was added with.
+    pub fn transport_name(&self, transport_id: u32) -> Option<&str> {
+        self.transports
+            .get(transport_id as usize)
+            .map(|transport| transport.name.as_str())
+    }
+
+    /// Read the next bundle, or `None` at the end of the capture.
+    pub fn next_record(&mut self) -> io::Result<Option<CaptureRecord>> {
+        loop {
+            match self.read_block()? {
+                None => return Ok(None),
+                Some(BLOCK_SECTION_HEADER) => {
+                    // Transport ids are numbered from zero again in each section.
+                    self.read_section_header()?;
+                    self.transports.clear();
+                }
+                Some(BLOCK_INTERFACE_DESCRIPTION) => self.read_interface_description()?,
+                Some(BLOCK_ENHANCED_PACKET) => return self.read_enhanced_packet().map(Some),
+                // Skip blocks added by other tools.
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Reads a block into `self.block`, returning its type.
+    fn read_block(&mut self) -> io::Result<Option<u32>> {
+        let mut block_header = [0u8; 8];
+        match self.reader.read_exact(&mut block_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let block_type = u32::from_le_bytes(block_header[..4].try_into().unwrap());
+        let block_len = u32::from_le_bytes(block_header[4..].try_into().unwrap()) as usize;
+        if block_len < 12 || !block_len.is_multiple_of(4) || block_len > MAX_BLOCK_LEN {
+            return Err(invalid_data("invalid block length"));
+        }
+
+        self.block.resize(block_len - 8, 0);
+        self.reader.read_exact(&mut self.block)?;
+        if self.block[block_len - 12..] != block_header[4..] {
+            return Err(invalid_data("block lengths disagree"));
+        }
+        self.block.truncate(block_len - 12);
+
+        Ok(Some(block_type))
+    }
+
+    fn read_section_header(&mut self) -> io::Result<()> {
+        match self.block.get(..4) {
+            Some(magic) if magic == BYTE_ORDER_MAGIC.to_le_bytes() => Ok(()),
+            Some(magic) if magic == BYTE_ORDER_MAGIC.to_be_bytes() => {
+                Err(invalid_data("big-endian captures are not supported"))
+            }
+            _ => Err(invalid_data("invalid section header")),
+        }
+    }
+
+    fn read_interface_description(&mut self) -> io::Result<()> {
+        let mut transport = CaptureTransport {
+            name: String::new(),
+            ticks_per_second: 1_000_000,
+        };
+        let options = self.block.get(8..).ok_or_else(|| invalid_data("truncated interface"))?;
+        for (code, value) in Options::new(options) {
+            match (code?, value) {
+                (OPT_IF_NAME, name) => transport.name = String::from_utf8_lossy(name).into_owned(),
+                (OPT_IF_TSRESOL, &[resolution]) => {
+                    let exponent = (resolution & 0x7f) as u32;
+                    let base: u64 = if resolution & 0x80 == 0 { 10 } else { 2 };
+                    transport.ticks_per_second = base
+                        .checked_pow(exponent)
+                        .ok_or_else(|| invalid_data("invalid timestamp resolution"))?;
+                }
+                _ => {}
+            }
+        }
+        self.transports.push(transport);
+
+        Ok(())
+    }
+
+    fn read_enhanced_packet(&mut self) -> io::Result<CaptureRecord> {
+        let block = &self.block[..];
+        if block.len() < 20 {
+            return Err(invalid_data("truncated packet"));
+        }
+        let field = |i: usize| u32::from_le_bytes(block[i * 4..i * 4 + 4].try_into().unwrap());
+        let transport_id = field(0);
+        let ticks = ((field(1) as u64) << 32) | field(2) as u64;
+        let captured_len = field(3) as usize;
+        let Some(bundle) = block.get(20..20 + captured_len) else {
+            return Err(invalid_data("truncated packet"));
+        };
+        let Some(transport) = self.transports.get(transport_id as usize) else {
+            return Err(invalid_data("packet from undescribed transport"));
+        };
+
+        let mut direction = None;
+        let options = &block[(20 + captured_len).next_multiple_of(4).min(block.len())..];
+        for (code, value) in Options::new(options) {
+            if let (OPT_EPB_FLAGS, &[flags, _, _, _]) = (code?, value) {
+                direction = match flags as u32 & 0b11 {
+                    EPB_FLAGS_INBOUND => Some(CaptureDirection::Inbound),
+                    EPB_FLAGS_OUTBOUND => Some(CaptureDirection::Outbound),
+                    _ => None,
+                };
+            }
+        }
+        let direction = direction.ok_or_else(|| invalid_data("packet has no direction"))?;
+
+        let seconds = ticks / transport.ticks_per_second;
+        let subsec_ticks = (ticks % transport.ticks_per_second) as u128;
+        let subsec_nanos = subsec_ticks * 1_000_000_000 / transport.ticks_per_second as u128;
+
+        Ok(CaptureRecord {
+            transport_id,
+            timestamp: Duration::new(seconds, subsec_nanos as u32),
+            direction,
+            bundle: bundle.to_vec(),
+        })
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+// Iterates the options at the end of a block.
+struct Options<'a> {
+    options: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    fn new(options: &'a [u8]) -> Self {
+        Self { options }
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (io::Result<u16>, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.options.len() < 4 {
+            return None;
+        }
+        let code = u16::from_le_bytes([self.options[0], self.options[1]]);
+        let len = u16::from_le_bytes([self.options[2], self.options[3]]) as usize;
+        if code == OPT_END {
+            return None;
+        }
+        let Some(value) = self.options.get(4..4 + len) else {
+            self.options = &[];
+            return Some((Err(invalid_data("truncated option")), &[]));
+        };
+        self.options = self.options.get((4 + len).next_multiple_of(4)..).unwrap_or(&[]);
+
+        Some((Ok(code), value))
+    }
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Hand captured bundles to `worker_cx`'s roles as if `transport` had received them, e.g. to
+/// replay recorded traffic into a runtime with a `LocalTransport` in a regression test. Control
+/// bundles are skipped. Callers pick which transport and direction to replay by filtering
+/// `records`.
+pub async fn replay_capture(
+    worker_cx: &WorkerContext,
+    transport: &TransportHandle,
+    records: impl IntoIterator<Item = CaptureRecord>,
+) -> Result<(), TransportError> {
+    let buffer_pool = &transport.buffer_pool;
+    let _buffer_pool_thread_guard = buffer_pool.register_thread();
+    let process_packet_fn = worker_cx.get_packet_processor();
+
+    let mut shatter_offsets: Vec<usize> = Vec::new();
+    let mut shatter_out_packets: Vec<MaybeUninit<Packet>> = Vec::new();
+
+    for record in records {
+        if is_control_bundle(&record.bundle) {
+            continue;
+        }
+        if record.bundle.len() > buffer_pool.buffer_size() {
+            return Err(TransportError::BundleTooLarge {
+                len: record.bundle.len(),
+                max_len: buffer_pool.buffer_size(),
+            });
+        }
+
+        let packet_bundle = unsafe {
+            let buffer = buffer_pool.acquire().await;
+            buffer.initialize_rc(1, 0, 0);
+            buffer.slice_mut(0..record.bundle.len()).copy_from_slice(&record.bundle);
+            Packet::new(buffer, 0, record.bundle.len())
+        };
+        shatter_packet_bundle(packet_bundle, &mut shatter_offsets, &mut shatter_out_packets)?;
+
+        for packet in shatter_out_packets.drain(..) {
+            let packet = unsafe { packet.assume_init() };
+            process_packet_fn(&packet).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_capture_round_trip() {
+        let out = SharedBuffer::default();
+        let capture = PacketCapture::new(out.clone()).unwrap();
+        let tcp = capture.add_transport("tcp");
+        let memory = capture.add_transport("memory");
+        assert_eq!(tcp.transport_id(), 0);
+        assert_eq!(memory.transport_id(), 1);
+
+        tcp.record_bundle(CaptureDirection::Outbound, 3, b"hello");
+        memory.record(CaptureDirection::Inbound, &crate::encode_keepalive_bundle());
+        capture.flush().unwrap();
+
+        let bytes = out.0.lock().unwrap().clone();
+        assert_eq!(bytes.len() % 4, 0);
+        let mut reader = CaptureReader::new(&bytes[..]).unwrap();
+        let outbound = reader.next_record().unwrap().unwrap();
+        assert_eq!(reader.transport_name(0), Some("tcp"));
+        assert_eq!(outbound.transport_id, 0);
+        assert_eq!(outbound.direction, CaptureDirection::Outbound);
+        assert_eq!(&outbound.bundle[PacketBundle::BASE_LEN..], b"hello");
+        let header: PacketBundle = mproto::decode_value(&outbound.bundle[..]).unwrap();
+        assert_eq!((header.channel_id, header.length), (3, 5));
+        assert!(outbound.timestamp > Duration::from_secs(1_600_000_000));
+
+        let inbound = reader.next_record().unwrap().unwrap();
+        assert_eq!(reader.transport_name(1), Some("memory"));
+        assert_eq!(inbound.transport_id, 1);
+        assert_eq!(inbound.direction, CaptureDirection::Inbound);
+        assert!(crate::is_keepalive_bundle(&inbound.bundle));
+        assert!(inbound.timestamp >= outbound.timestamp);
+
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_corrupt_capture() {
+        assert!(CaptureReader::new(&b"not a capture"[..]).is_err());
+
+        let out = SharedBuffer::default();
+        let capture = PacketCapture::new(out.clone()).unwrap();
+        capture.add_transport("tcp").record_bundle(CaptureDirection::Inbound, 0, &[1, 2, 3]);
+        let bytes = out.0.lock().unwrap().clone();
+
+        // A capture cut off mid-block.
+        let mut reader = CaptureReader::new(&bytes[..bytes.len() - 2]).unwrap();
+        assert!(reader.next_record().is_err());
+
+        // A packet's length running past the end of its block.
+        let mut corrupt = bytes.clone();
+        let packet_block = corrupt.len() - 56;
+        corrupt[packet_block + 20..packet_block + 24].copy_from_slice(&1000u32.to_le_bytes());
+        let mut reader = CaptureReader::new(&corrupt[..]).unwrap();
+        assert_eq!(reader.next_record().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,95 @@
+/// A server's reply to a client hello. `Err` carries the reason the client was rejected.
+pub type ClientHelloReply = Result<(), String>;
+
+#[cfg(any(feature = "tcp-transport", feature = "ws-transport", feature = "web-ws-transport"))]
+fn reply_to_io_result(reply_bytes: &[u8]) -> std::io::Result<()> {
+    let reply: ClientHelloReply =
+        mproto::decode_value(reply_bytes).map_err(std::io::Error::other)?;
+    reply.map_err(|reason| std::io::Error::new(std::io::ErrorKind::PermissionDenied, reason))
+}
+
+/// Send credentials to a server that authenticates clients before its plane handshake, such as a
+/// modrpc_hub whose delegate sets `CLIENT_HELLO`. Call this before `tcp_connect`. Fails with `ErrorKind::PermissionDenied` if the
+/// server rejects the client.
+#[cfg(feature = "tcp-transport")]
+pub async fn tcp_client_hello(
+    stream: &mut tokio::net::TcpStream,
+    hello: impl mproto::Encode,
+) -> std::io::Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let hello_len = mproto::encoded_len(&hello);
+    let mut hello_buf = vec![0u8; 2 + hello_len];
+    hello_buf[..2].copy_from_slice(&(hello_len as u16).to_le_bytes());
+    mproto::encode_value(hello, &mut hello_buf[2..]);
+    stream.write_all(&hello_buf).await?;
+
+    let mut reply_len_bytes = [0u8; 2];
+    stream.read_exact(&mut reply_len_bytes).await?;
+    let mut reply_bytes = vec![0u8; u16::from_le_bytes(reply_len_bytes) as usize];
+    stream.read_exact(&mut reply_bytes).await?;
+
+    reply_to_io_result(&reply_bytes)
+}
+
+/// Like `tcp_client_hello`, for websocket connections.
+#[cfg(feature = "ws-transport")]
+pub async fn ws_client_hello<Ws>(websocket: &mut Ws, hello: impl mproto::Encode) -> std::io::Result<()>
+where
+    Ws: futures_util::Stream<
+            Item = Result<
+                tokio_tungstenite::tungstenite::Message,
+                tokio_tungstenite::tungstenite::Error,
+            >,
+        > + futures_util::Sink<
+            tokio_tungstenite::tungstenite::Message,
+            Error = tokio_tungstenite::tungstenite::Error,
+        > + Unpin,
+{
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    websocket
+        .send(Message::Binary(mproto::encode_value_vec(hello).into()))
+        .await
+        .map_err(std::io::Error::other)?;
+
+    loop {
+        let message = websocket
+            .next()
+            .await
+            .ok_or(std::io::ErrorKind::UnexpectedEof)?
+            .map_err(std::io::Error::other)?;
+        match message {
+            Message::Binary(reply_bytes) => return reply_to_io_result(&reply_bytes),
+            Message::Ping(_) | Message::Pong(_) => {}
+            _ => return Err(std::io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+/// Like `tcp_client_hello`, for browser websocket connections.
+#[cfg(feature = "web-ws-transport")]
+pub async fn web_ws_client_hello(
+    websocket: &mut gloo_net::websocket::futures::WebSocket,
+    hello: impl mproto::Encode,
+) -> std::io::Result<()> {
+    use futures_util::{SinkExt, StreamExt};
+    use gloo_net::websocket::Message;
+
+    websocket
+        .send(Message::Bytes(mproto::encode_value_vec(hello)))
+        .await
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    let message = websocket
+        .next()
+        .await
+        .ok_or(std::io::ErrorKind::UnexpectedEof)?
+        .map_err(|e| std::io::Error::other(e.to_string()))?;
+    let Message::Bytes(reply_bytes) = message else {
+        return Err(std::io::ErrorKind::InvalidData.into());
+    };
+
+    reply_to_io_result(&reply_bytes)
+}
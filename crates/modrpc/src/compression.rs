@@ -0,0 +1,339 @@
+use core::cell::Cell;
+
+use mproto::BaseLen;
+
+use crate::{FIRST_CONTROL_CHANNEL_ID, PacketBundle};
+
+/// Set in `PacketBundle::channel_id` when the bundle payload is lz4-compressed. The `length` of a
+/// compressed bundle is the length of the compressed payload. Carrying the flag in the channel ID
+/// leaves the bundle header, and so the framing of every uncompressed bundle, unchanged. Only
+/// channels below this bit can carry compressed bundles, and control channels never do.
+pub const COMPRESSED_CHANNEL_BIT: u32 = 1 << 31;
+
+/// Highest channel ID a plane's topics may be sent on. Roles on higher channels are refused, as a
+/// peer that negotiated compression would read `COMPRESSED_CHANNEL_BIT` as a compressed bundle.
+pub const MAX_CHANNEL_ID: u32 = COMPRESSED_CHANNEL_BIT - 1;
+
+/// Channel ID reserved for the compression hello bundle. Its one-byte payload is the set of
+/// algorithms the sender is able to decompress. See `CompressionHandshake`.
+pub const COMPRESSION_HELLO_CHANNEL_ID: u32 = u32::MAX - 1;
+
+/// Bit in the compression hello payload advertising lz4 block decompression.
+pub const COMPRESSION_LZ4: u8 = 1 << 0;
+
+/// Algorithms this build is able to decompress.
+#[cfg(feature = "compression")]
+pub const SUPPORTED_COMPRESSION: u8 = COMPRESSION_LZ4;
+#[cfg(not(feature = "compression"))]
+pub const SUPPORTED_COMPRESSION: u8 = 0;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CompressionConfig {
+    /// Bundles with smaller payloads than this are always sent uncompressed.
+    pub min_bundle_size: usize,
+}
+
+impl CompressionConfig {
+    pub fn new(min_bundle_size: usize) -> Self {
+        Self { min_bundle_size }
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            min_bundle_size: 256,
+        }
+    }
+}
+
+/// Encode a hello bundle advertising the algorithms this build can decompress. The peer will only
+/// compress bundles after it receives our hello.
+pub fn encode_compression_hello() -> [u8; PacketBundle::BASE_LEN + 1] {
+    let mut buf = [0u8; PacketBundle::BASE_LEN + 1];
+    mproto::encode_value(
+        PacketBundle {
+            channel_id: COMPRESSION_HELLO_CHANNEL_ID,
+            length: 1,
+        },
+        &mut buf[..PacketBundle::BASE_LEN],
+    );
+    buf[PacketBundle::BASE_LEN] = SUPPORTED_COMPRESSION;
+    buf
+}
+
+/// If `packet_bundle` is a compression hello, returns the set of algorithms the peer can
+/// decompress.
+pub fn decode_compression_hello(packet_bundle: &[u8]) -> Option<u8> {
+    let header = mproto::decode_value::<PacketBundle>(packet_bundle).ok()?;
+    if header.channel_id != COMPRESSION_HELLO_CHANNEL_ID || header.length < 1 {
+        return None;
+    }
+    packet_bundle.get(PacketBundle::BASE_LEN).copied()
+}
+
+/// Whether a peer that sent a hello with the given algorithms can receive our compressed bundles.
+pub fn peer_accepts_compression(peer_algorithms: u8) -> bool {
+    SUPPORTED_COMPRESSION & peer_algorithms & COMPRESSION_LZ4 != 0
+}
+
+/// Which end of a connection opens the compression handshake.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CompressionNegotiation {
+    /// Offer compression as soon as the transport starts. Used by the accepting end.
+    Offer,
+    /// Only send a hello in answer to the peer's offer. Used by the connecting end.
+    Answer,
+}
+
+/// One end of the compression handshake. The accepting end offers the algorithms it can
+/// decompress as soon as the transport starts, and the connecting end answers with the algorithms
+/// it can decompress in turn. Each end only compresses once it has the other's hello, and an end
+/// without compression enabled never sends one - so a peer that doesn't take part is never sent a
+/// compressed bundle.
+pub struct CompressionHandshake {
+    enabled: bool,
+    negotiation: CompressionNegotiation,
+    peer_decompresses: Cell<bool>,
+    answered: Cell<bool>,
+    answer_due: bab::Signal,
+}
+
+impl CompressionHandshake {
+    pub fn new(enabled: bool, negotiation: CompressionNegotiation) -> Self {
+        Self {
+            enabled,
+            negotiation,
+            peer_decompresses: Cell::new(false),
+            answered: Cell::new(false),
+            answer_due: bab::Signal::new(),
+        }
+    }
+
+    /// The hello to send before anything else, if this end opens the handshake.
+    pub fn offer(&self) -> Option<[u8; PacketBundle::BASE_LEN + 1]> {
+        (self.enabled && self.negotiation == CompressionNegotiation::Offer)
+            .then(encode_compression_hello)
+    }
+
+    /// Handle a hello received from the peer.
+    pub fn receive_hello(&self, peer_algorithms: u8) {
+        if !self.enabled {
+            return;
+        }
+        self.peer_decompresses.set(peer_accepts_compression(peer_algorithms));
+        if self.negotiation == CompressionNegotiation::Answer {
+            self.answer_due.notify();
+        }
+    }
+
+    /// Resolves once the peer's offer has been received and should be answered with
+    /// `take_answer`. Never resolves again after that.
+    pub async fn answer_due(&self) {
+        if self.answered.get() {
+            core::future::pending::<()>().await;
+        }
+        self.answer_due.wait().await;
+    }
+
+    /// The hello to answer the peer's offer with, if one is due.
+    pub fn take_answer(&self) -> Option<[u8; PacketBundle::BASE_LEN + 1]> {
+        if !self.answer_due.is_notified() || self.answered.replace(true) {
+            return None;
+        }
+        Some(encode_compression_hello())
+    }
+
+    /// Whether bundles may be sent to the peer compressed.
+    pub fn peer_decompresses(&self) -> bool {
+        self.peer_decompresses.get()
+    }
+}
+
+/// The channel ID to send a compressed bundle of `channel_id` under, if it can carry one.
+pub fn compressed_channel_id(channel_id: u32) -> Option<u32> {
+    (channel_id & COMPRESSED_CHANNEL_BIT == 0).then_some(channel_id | COMPRESSED_CHANNEL_BIT)
+}
+
+/// Compresses outgoing bundle payloads, reusing a single output buffer.
+pub struct BundleCompressor {
+    config: CompressionConfig,
+    #[cfg_attr(not(feature = "compression"), allow(dead_code))]
+    buf: Vec<u8>,
+}
+
+impl BundleCompressor {
+    pub fn new(config: CompressionConfig) -> Self {
+        Self {
+            config,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Compress a bundle payload. Returns `None` if the payload should be sent as-is - either it's
+    /// below the size threshold or compressing it didn't make it any smaller.
+    pub fn compress(&mut self, payload: &[u8]) -> Option<&[u8]> {
+        if payload.len() < self.config.min_bundle_size {
+            return None;
+        }
+
+        #[cfg(feature = "compression")]
+        {
+            self.buf
+                .resize(lz4_flex::block::get_maximum_output_size(payload.len()), 0);
+            let compressed_len = lz4_flex::block::compress_into(payload, &mut self.buf).ok()?;
+            if compressed_len >= payload.len() {
+                return None;
+            }
+            Some(&self.buf[..compressed_len])
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            None
+        }
+    }
+}
+
+/// Decompress the compressed bundle `packet_bundle` into `out`, writing an uncompressed bundle
+/// header followed by the decompressed payload. Returns the total length written to `out`.
+pub fn decompress_bundle(packet_bundle: &[u8], out: &mut [u8]) -> std::io::Result<usize> {
+    let header: PacketBundle = mproto::decode_value(packet_bundle)
+        .map_err(|_| invalid_data("failed to decode compressed bundle header"))?;
+    let Some(payload) =
+        packet_bundle.get(PacketBundle::BASE_LEN..PacketBundle::BASE_LEN + header.length as usize)
+    else {
+        return Err(invalid_data("truncated compressed bundle"));
+    };
+    if out.len() < PacketBundle::BASE_LEN {
+        return Err(invalid_data("no room for decompressed bundle"));
+    }
+
+    let payload_len = decompress_payload(payload, &mut out[PacketBundle::BASE_LEN..])?;
+
+    mproto::encode_value(
+        PacketBundle {
+            channel_id: header.channel_id & !COMPRESSED_CHANNEL_BIT,
+            length: payload_len as u16,
+        },
+        &mut out[..PacketBundle::BASE_LEN],
+    );
+
+    Ok(PacketBundle::BASE_LEN + payload_len)
+}
+
+#[cfg(feature = "compression")]
+fn decompress_payload(payload: &[u8], out: &mut [u8]) -> std::io::Result<usize> {
+    lz4_flex::block::decompress_into(payload, out)
+        .map_err(|e| invalid_data(&format!("failed to decompress bundle: {e}")))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress_payload(_payload: &[u8], _out: &mut [u8]) -> std::io::Result<usize> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "received compressed bundle but compression support is disabled",
+    ))
+}
+
+pub fn is_compressed_bundle(header: &PacketBundle) -> bool {
+    header.channel_id & COMPRESSED_CHANNEL_BIT != 0 && header.channel_id < FIRST_CONTROL_CHANNEL_ID
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compression_hello() {
+        let hello = encode_compression_hello();
+        assert_eq!(
+            decode_compression_hello(&hello[..]),
+            Some(SUPPORTED_COMPRESSION)
+        );
+        assert!(crate::transport::is_control_bundle(&hello[..]));
+        assert_eq!(
+            peer_accepts_compression(SUPPORTED_COMPRESSION),
+            cfg!(feature = "compression")
+        );
+        assert!(!peer_accepts_compression(0));
+
+        let keepalive = crate::encode_keepalive_bundle();
+        assert_eq!(decode_compression_hello(&keepalive[..]), None);
+    }
+
+    #[test]
+    fn test_compression_handshake() {
+        let offerer = CompressionHandshake::new(true, CompressionNegotiation::Offer);
+        let answerer = CompressionHandshake::new(true, CompressionNegotiation::Answer);
+        assert!(answerer.offer().is_none());
+        assert!(answerer.take_answer().is_none());
+
+        let offer = offerer.offer().unwrap();
+        answerer.receive_hello(decode_compression_hello(&offer[..]).unwrap());
+        let answer = answerer.take_answer().unwrap();
+        assert!(answerer.take_answer().is_none());
+        offerer.receive_hello(decode_compression_hello(&answer[..]).unwrap());
+        assert!(offerer.take_answer().is_none());
+
+        assert_eq!(offerer.peer_decompresses(), cfg!(feature = "compression"));
+        assert_eq!(answerer.peer_decompresses(), cfg!(feature = "compression"));
+
+        // An end without compression neither offers nor answers, so its peer never compresses.
+        let disabled = CompressionHandshake::new(false, CompressionNegotiation::Answer);
+        disabled.receive_hello(SUPPORTED_COMPRESSION);
+        assert!(disabled.take_answer().is_none());
+        assert!(!disabled.peer_decompresses());
+        assert!(CompressionHandshake::new(false, CompressionNegotiation::Offer).offer().is_none());
+    }
+
+    #[test]
+    fn test_compressed_channel_id() {
+        let channel_id = compressed_channel_id(7).unwrap();
+        assert!(is_compressed_bundle(&PacketBundle { channel_id, length: 0 }));
+        assert!(!is_compressed_bundle(&PacketBundle { channel_id: 7, length: 0 }));
+        assert_eq!(compressed_channel_id(channel_id), None);
+        // Control bundles are never compressed.
+        assert!(!is_compressed_bundle(&PacketBundle {
+            channel_id: COMPRESSION_HELLO_CHANNEL_ID,
+            length: 1,
+        }));
+    }
+
+    #[test]
+    fn test_compression_threshold() {
+        let mut compressor = BundleCompressor::new(CompressionConfig::new(64));
+        assert!(compressor.compress(&[0u8; 32]).is_none());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_bundle_roundtrip() {
+        let payload: Vec<u8> = (0..1024).map(|i| (i % 7) as u8).collect();
+
+        let mut compressor = BundleCompressor::new(CompressionConfig::new(64));
+        let compressed = compressor.compress(&payload).unwrap();
+        assert!(compressed.len() < payload.len());
+
+        let mut bundle = vec![0u8; PacketBundle::BASE_LEN + compressed.len()];
+        mproto::encode_value(
+            PacketBundle {
+                channel_id: compressed_channel_id(7).unwrap(),
+                length: compressed.len() as u16,
+            },
+            &mut bundle[..PacketBundle::BASE_LEN],
+        );
+        bundle[PacketBundle::BASE_LEN..].copy_from_slice(compressed);
+
+        let mut out = vec![0u8; 2048];
+        let len = decompress_bundle(&bundle, &mut out).unwrap();
+        let header: PacketBundle = mproto::decode_value(&out[..]).unwrap();
+        assert_eq!(header.channel_id, 7);
+        assert_eq!(header.length as usize, payload.len());
+        assert!(!is_compressed_bundle(&header));
+        assert_eq!(&out[PacketBundle::BASE_LEN..len], &payload[..]);
+    }
+}
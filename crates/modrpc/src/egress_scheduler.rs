@@ -0,0 +1,171 @@
+use std::collections::{HashMap, VecDeque};
+
+/// Bytes a channel of weight 1 may send per scheduling round.
+const QUANTUM_BYTES: usize = 4096;
+
+/// Relative egress priority of a transport's channels. Channels share the transport in
+/// proportion to their weights, so a channel carrying small latency-sensitive messages never has
+/// to wait behind more than one bundle of each busier channel.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EgressConfig {
+    /// Weight of channels that aren't given one explicitly.
+    pub default_weight: u32,
+    pub channel_weights: HashMap<u32, u32>,
+}
+
+impl EgressConfig {
+    pub fn new(default_weight: u32) -> Self {
+        Self {
+            default_weight,
+            channel_weights: HashMap::new(),
+        }
+    }
+
+    pub fn channel_weight(mut self, channel_id: u32, weight: u32) -> Self {
+        self.channel_weights.insert(channel_id, weight);
+        self
+    }
+
+    fn weight(&self, channel_id: u32) -> u32 {
+        self.channel_weights
+            .get(&channel_id)
+            .copied()
+            .unwrap_or(self.default_weight)
+            .max(1)
+    }
+}
+
+impl Default for EgressConfig {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+struct ChannelQueue<T> {
+    bundles: VecDeque<T>,
+    deficit: usize,
+}
+
+/// Deficit round robin scheduler over the bundles flushed for each channel. Bundles on the same
+/// channel are always sent in the order they were flushed.
+pub struct EgressScheduler<T> {
+    config: EgressConfig,
+    channels: HashMap<u32, ChannelQueue<T>>,
+    // Channels with queued bundles, in round robin order.
+    active: VecDeque<u32>,
+}
+
+impl<T: core::ops::Deref<Target = [u8]>> EgressScheduler<T> {
+    pub fn new(config: EgressConfig) -> Self {
+        Self {
+            config,
+            channels: HashMap::new(),
+            active: VecDeque::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    pub fn push(&mut self, channel_id: u32, bundle: T) {
+        let channel = self
+            .channels
+            .entry(channel_id)
+            .or_insert_with(|| ChannelQueue {
+                bundles: VecDeque::new(),
+                deficit: 0,
+            });
+        if channel.bundles.is_empty() {
+            self.active.push_back(channel_id);
+        }
+        channel.bundles.push_back(bundle);
+    }
+
+    /// Take the next bundle to send, along with the channel it was flushed on.
+    pub fn pop(&mut self) -> Option<(u32, T)> {
+        loop {
+            let channel_id = *self.active.front()?;
+            let channel = self
+                .channels
+                .get_mut(&channel_id)
+                .expect("active egress channel");
+            let bundle_len = channel.bundles.front().map_or(0, |bundle| bundle.len());
+
+            if channel.deficit >= bundle_len {
+                channel.deficit -= bundle_len;
+                let bundle = channel.bundles.pop_front().expect("active egress channel");
+                if channel.bundles.is_empty() {
+                    // Idle channels don't get to bank credit.
+                    channel.deficit = 0;
+                    self.active.pop_front();
+                }
+                return Some((channel_id, bundle));
+            }
+
+            // Out of credit for this round - top up and let the next channel go.
+            channel.deficit += QUANTUM_BYTES * self.config.weight(channel_id) as usize;
+            self.active.rotate_left(1);
+        }
+    }
+}
+
+impl EgressScheduler<bab::Flush> {
+    pub fn push_flushes(&mut self, flushes: impl Iterator<Item = bab::Flush>) {
+        for flush in flushes {
+            if !flush.is_empty() {
+                self.push(flush.writer_id() as u32, flush);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_channel_order() {
+        let mut scheduler = EgressScheduler::new(EgressConfig::default());
+        for i in 0..4u8 {
+            scheduler.push(1, vec![i; 100]);
+        }
+
+        for i in 0..4u8 {
+            assert_eq!(scheduler.pop(), Some((1, vec![i; 100])));
+        }
+        assert!(scheduler.pop().is_none());
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn test_small_bundles_skip_bulk_queue() {
+        let mut scheduler = EgressScheduler::new(EgressConfig::default());
+        for _ in 0..100 {
+            scheduler.push(1, vec![0u8; 16_000]);
+        }
+        scheduler.pop();
+        scheduler.push(2, vec![0u8; 64]);
+
+        // The bulk channel spent its credit on the first bundle, so the small one goes next.
+        let next_channels: Vec<u32> = (0..3).map(|_| scheduler.pop().unwrap().0).collect();
+        assert_eq!(next_channels, [2, 1, 1]);
+    }
+
+    #[test]
+    fn test_weighted_share() {
+        let mut scheduler = EgressScheduler::new(EgressConfig::default().channel_weight(2, 3));
+        for _ in 0..400 {
+            scheduler.push(1, vec![0u8; 1000]);
+            scheduler.push(2, vec![0u8; 1000]);
+        }
+
+        let mut sent = HashMap::<u32, usize>::new();
+        for _ in 0..400 {
+            let (channel_id, bundle) = scheduler.pop().unwrap();
+            *sent.entry(channel_id).or_default() += bundle.len();
+        }
+        let ratio = sent[&2] as f64 / sent[&1] as f64;
+        assert!((2.5..3.5).contains(&ratio), "ratio={ratio}");
+    }
+}
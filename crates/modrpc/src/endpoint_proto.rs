@@ -697,3 +697,183 @@ impl<'a, T: Owned> PartialEq for PlaneHandshakeLazy<'a, T> {
             && self.init().unwrap() == other.init().unwrap()
     }
 }
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct PlaneKeyExchange {
+    pub to: EndpointAddr,
+    pub step: u8,
+    pub message: Vec<u8>,
+}
+
+pub struct PlaneKeyExchangeLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct PlaneKeyExchangeGen<
+    To: Encode + Compatible<EndpointAddr>,
+    Message: Encode + Compatible<Vec<u8>>,
+> {
+    pub to: To,
+    pub step: u8,
+    pub message: Message,
+}
+
+impl<To: Encode + Compatible<EndpointAddr>, Message: Encode + Compatible<Vec<u8>>>
+    Compatible<PlaneKeyExchange> for PlaneKeyExchangeGen<To, Message>
+{
+}
+impl<To: Encode + Compatible<EndpointAddr>, Message: Encode + Compatible<Vec<u8>>>
+    Compatible<PlaneKeyExchangeGen<To, Message>> for PlaneKeyExchange
+{
+}
+
+impl<To: Encode + Compatible<EndpointAddr>, Message: Encode + Compatible<Vec<u8>>> BaseLen
+    for PlaneKeyExchangeGen<To, Message>
+{
+    const BASE_LEN: usize = 1 + To::BASE_LEN + Message::BASE_LEN;
+}
+
+impl<To: Encode + Compatible<EndpointAddr>, Message: Encode + Compatible<Vec<u8>>> Encode
+    for PlaneKeyExchangeGen<To, Message>
+{
+    fn scratch_len(&self) -> usize {
+        self.to.scratch_len() + self.step.scratch_len() + self.message.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.to.encode(cursor);
+        self.step.encode(cursor);
+        self.message.encode(cursor);
+    }
+}
+
+impl Owned for PlaneKeyExchange {
+    type Lazy<'a> = PlaneKeyExchangeLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for PlaneKeyExchangeLazy<'a> {
+    type Owned = PlaneKeyExchange;
+}
+
+impl<'a> Compatible<PlaneKeyExchangeLazy<'a>> for PlaneKeyExchangeLazy<'a> {}
+impl<'a> Compatible<PlaneKeyExchangeLazy<'a>> for PlaneKeyExchange {}
+impl Compatible<PlaneKeyExchange> for PlaneKeyExchange {}
+impl<'a> Compatible<PlaneKeyExchange> for PlaneKeyExchangeLazy<'a> {}
+
+impl<'a> PlaneKeyExchangeLazy<'a> {
+    pub fn to(&self) -> DecodeResult<EndpointAddrLazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn step(&self) -> DecodeResult<u8> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
+    }
+
+    pub fn message(&self) -> DecodeResult<mproto::ListLazy<'a, u8>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9))
+    }
+}
+
+impl BaseLen for PlaneKeyExchange {
+    const BASE_LEN: usize = 17;
+}
+
+impl Encode for PlaneKeyExchange {
+    fn scratch_len(&self) -> usize {
+        self.to.scratch_len() + self.step.scratch_len() + self.message.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.to.encode(cursor);
+        self.step.encode(cursor);
+        self.message.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for PlaneKeyExchange {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let to = Decode::decode(cursor)?;
+        let step = Decode::decode(cursor)?;
+        let message = Decode::decode(cursor)?;
+
+        Ok(PlaneKeyExchange { to, step, message })
+    }
+}
+
+impl<'a> BaseLen for PlaneKeyExchangeLazy<'a> {
+    const BASE_LEN: usize = 17;
+}
+
+impl<'a> Encode for PlaneKeyExchangeLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let to: EndpointAddrLazy<'a> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let step: u8 =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        let message: mproto::ListLazy<'a, u8> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9)).unwrap();
+        to.scratch_len() + step.scratch_len() + message.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let to: EndpointAddrLazy<'a> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let step: u8 =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        let message: mproto::ListLazy<'a, u8> =
+            Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 9)).unwrap();
+        to.encode(cursor);
+        step.encode(cursor);
+        message.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for PlaneKeyExchangeLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(PlaneKeyExchangeLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<PlaneKeyExchangeLazy<'a>> for PlaneKeyExchange {
+    type Error = DecodeError;
+
+    fn try_from(other: PlaneKeyExchangeLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for PlaneKeyExchangeLazy<'a> {}
+
+impl<'a> Clone for PlaneKeyExchangeLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for PlaneKeyExchangeLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PlaneKeyExchangeLazy").finish()
+    }
+}
+
+impl<'a> PartialEq for PlaneKeyExchangeLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.to().unwrap() == other.to().unwrap()
+            && self.step().unwrap() == other.step().unwrap()
+            && self.message().unwrap() == other.message().unwrap()
+    }
+}
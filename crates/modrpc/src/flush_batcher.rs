@@ -7,6 +7,8 @@ use std::{
     time::{Duration, Instant},
 };
 
+use crate::metrics::{Histogram, MetricsRegistry};
+
 pub enum FlushBatcherStatus {
     Snooze { duration: Duration },
     FlushNow,
@@ -26,21 +28,41 @@ pub struct FlushBatcher {
 
 struct Inner {
     max_flush_delay: Duration,
+    max_unflushed_bytes: usize,
+    flush_bytes: Histogram,
     next_flush_time: Cell<Option<NextFlushTime>>,
     waker: Cell<Option<Waker>>,
 }
 
 impl FlushBatcher {
-    pub fn new(max_flush_delay: Duration) -> Self {
+    pub fn new(max_flush_delay: Duration, max_unflushed_bytes: usize, metrics: &MetricsRegistry) -> Self {
         Self {
             inner: Rc::new(Inner {
                 max_flush_delay,
+                max_unflushed_bytes,
+                flush_bytes: metrics.histogram(
+                    "modrpc_transport_flush_bytes",
+                    "Sizes of the buffers writers flushed to transports.",
+                    &[],
+                    &[64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0],
+                ),
                 next_flush_time: Cell::new(None),
                 waker: Cell::new(None),
             }),
         }
     }
 
+    /// Writers should flush right away rather than wait for the batcher once this many bytes
+    /// are waiting.
+    pub fn max_unflushed_bytes(&self) -> usize {
+        self.inner.max_unflushed_bytes
+    }
+
+    /// Note a writer flushing `bytes` to the transport.
+    pub fn record_flush(&self, bytes: usize) {
+        self.inner.flush_bytes.observe(bytes as f64);
+    }
+
     pub fn schedule_flush(&self) {
         if self.inner.next_flush_time.get().is_some() {
             return;
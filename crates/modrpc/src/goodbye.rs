@@ -0,0 +1,36 @@
+use crate::PacketBundle;
+
+/// Channel ID reserved for the goodbye bundle a peer sends just before it closes the transport on
+/// purpose, e.g. when a hub shuts down. Transports that receive one close without waiting for the
+/// stream to end.
+pub const GOODBYE_CHANNEL_ID: u32 = u32::MAX - 3;
+
+pub fn encode_goodbye_bundle() -> [u8; <PacketBundle as mproto::BaseLen>::BASE_LEN] {
+    let mut buf = [0u8; <PacketBundle as mproto::BaseLen>::BASE_LEN];
+    mproto::encode_value(
+        PacketBundle {
+            channel_id: GOODBYE_CHANNEL_ID,
+            length: 0,
+        },
+        &mut buf[..],
+    );
+    buf
+}
+
+pub fn is_goodbye_bundle(packet_bundle: &[u8]) -> bool {
+    mproto::decode_value::<PacketBundle>(packet_bundle)
+        .is_ok_and(|header| header.channel_id == GOODBYE_CHANNEL_ID)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_goodbye_bundle() {
+        let buf = encode_goodbye_bundle();
+        assert!(crate::is_control_bundle(&buf[..]));
+        assert!(is_goodbye_bundle(&buf[..]));
+        assert!(!is_goodbye_bundle(&crate::encode_keepalive_bundle()[..]));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::TopicSubscription;
+
 pub struct InterfaceEvent<Ty> {
     pub name: &'static str,
     pub topic: u32,
@@ -17,16 +19,30 @@ impl<Ty> Copy for InterfaceEvent<Ty> {}
 
 pub struct InterfaceBuilder {
     next_topic: u32,
+    // Roles allowed to send on each topic, named after the outermost interface's roles. `None` if
+    // the event was declared without its senders.
+    topic_senders: Vec<Option<Vec<&'static str>>>,
+    // Byte offset into each topic's payload of the endpoint it's addressed to, if any.
+    topic_addresses: Vec<Option<u16>>,
+    // Maps the roles of the object currently being built to the outermost interface's roles.
+    role_map: Option<Vec<(&'static str, Vec<&'static str>)>>,
 }
 
 impl InterfaceBuilder {
     pub fn new() -> InterfaceBuilder {
-        InterfaceBuilder { next_topic: 0 }
+        InterfaceBuilder {
+            next_topic: 0,
+            topic_senders: Vec::new(),
+            topic_addresses: Vec::new(),
+            role_map: None,
+        }
     }
 
     pub fn event<Ty>(&mut self, name: &'static str) -> InterfaceEvent<Ty> {
         let topic = self.next_topic;
         self.next_topic += 1;
+        self.topic_senders.push(None);
+        self.topic_addresses.push(None);
 
         InterfaceEvent {
             name,
@@ -34,4 +50,134 @@ impl InterfaceBuilder {
             _ty: std::marker::PhantomData,
         }
     }
+
+    /// Declare an event that only `from_roles` may send.
+    pub fn event_from<Ty>(
+        &mut self,
+        name: &'static str,
+        from_roles: &[&'static str],
+    ) -> InterfaceEvent<Ty> {
+        let event = self.event(name);
+
+        let mut senders = Vec::new();
+        for &role in from_roles {
+            senders.extend(self.outer_roles(role));
+        }
+        self.topic_senders[event.topic as usize] = Some(senders);
+
+        event
+    }
+
+    /// Declare an event that only `from_roles` may send, addressed to the endpoint whose
+    /// `EndpointAddr` is encoded `addressed_at` bytes into its payload. Relays only deliver it to
+    /// that endpoint - see `topic_subscriptions`.
+    pub fn addressed_event_from<Ty>(
+        &mut self,
+        name: &'static str,
+        from_roles: &[&'static str],
+        addressed_at: u16,
+    ) -> InterfaceEvent<Ty> {
+        let event = self.event_from(name, from_roles);
+        self.topic_addresses[event.topic as usize] = Some(addressed_at);
+
+        event
+    }
+
+    /// Build an object whose interface declares `object_roles`, bound positionally to
+    /// `role_args` of the interface being built.
+    pub fn object<T>(
+        &mut self,
+        object_roles: &[&'static str],
+        role_args: &[&'static str],
+        build_fn: impl FnOnce(&mut InterfaceBuilder) -> T,
+    ) -> T {
+        let object_role_map = object_roles.iter().zip(role_args)
+            .map(|(&object_role, &role_arg)| (object_role, self.outer_roles(role_arg)))
+            .collect();
+        let parent_role_map = self.role_map.replace(object_role_map);
+        let object = build_fn(self);
+        self.role_map = parent_role_map;
+
+        object
+    }
+
+    /// Roles allowed to send on a topic, or `None` if any role may.
+    pub fn topic_senders(&self, topic: u32) -> Option<&[&'static str]> {
+        self.topic_senders.get(topic as usize)?.as_deref()
+    }
+
+    /// Offset into a topic's payload of the endpoint it's addressed to, if it's addressed.
+    pub fn topic_addressed_at(&self, topic: u32) -> Option<u16> {
+        self.topic_addresses.get(topic as usize).copied().flatten()
+    }
+
+    /// Subscriptions to every topic declared so far, for an endpoint that only wants the
+    /// addressed ones sent to it.
+    pub fn topic_subscriptions(&self) -> Vec<TopicSubscription> {
+        (0..self.next_topic)
+            .map(|topic| TopicSubscription {
+                topic,
+                addressed_at: self.topic_addressed_at(topic),
+            })
+            .collect()
+    }
+
+    /// Number of topics declared so far.
+    pub fn topic_count(&self) -> u32 {
+        self.next_topic
+    }
+
+    fn outer_roles(&self, role: &'static str) -> Vec<&'static str> {
+        let Some(role_map) = &self.role_map else {
+            return vec![role];
+        };
+        role_map.iter()
+            .filter(|(object_role, _)| *object_role == role)
+            .flat_map(|(_, outer_roles)| outer_roles.iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_object_topic_senders() {
+        let mut ib = InterfaceBuilder::new();
+        let update: InterfaceEvent<()> = ib.event_from("update", &["Owner"]);
+        let (request, response) = ib.object(&["Client", "Server"], &["Player", "Host"], |ib| {
+            let request: InterfaceEvent<()> = ib.event_from("request", &["Client"]);
+            let response: InterfaceEvent<()> = ib.event_from("response", &["Server"]);
+            (request, response)
+        });
+        let untyped: InterfaceEvent<()> = ib.event("untyped");
+
+        assert_eq!(ib.topic_senders(update.topic), Some(&["Owner"][..]));
+        assert_eq!(ib.topic_senders(request.topic), Some(&["Player"][..]));
+        assert_eq!(ib.topic_senders(response.topic), Some(&["Host"][..]));
+        assert_eq!(ib.topic_senders(untyped.topic), None);
+        assert_eq!(ib.topic_count(), 4);
+    }
+
+    #[test]
+    fn test_topic_subscriptions() {
+        let mut ib = InterfaceBuilder::new();
+        let (request, response) = ib.object(&["Client", "Server"], &["Player", "Host"], |ib| {
+            let request: InterfaceEvent<()> = ib.event_from("request", &["Client"]);
+            let response: InterfaceEvent<()> = ib.addressed_event_from("response", &["Server"], 4);
+            (request, response)
+        });
+
+        assert_eq!(ib.topic_senders(response.topic), Some(&["Host"][..]));
+        assert_eq!(ib.topic_addressed_at(request.topic), None);
+        assert_eq!(ib.topic_addressed_at(response.topic), Some(4));
+        assert_eq!(
+            ib.topic_subscriptions(),
+            [
+                TopicSubscription::all(request.topic),
+                TopicSubscription::addressed(response.topic, 4),
+            ],
+        );
+    }
 }
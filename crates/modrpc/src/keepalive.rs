@@ -0,0 +1,111 @@
+use core::{cell::Cell, time::Duration};
+
+use crate::{PacketBundle, WorkerContext};
+
+/// Channel ID reserved for transport-level keepalive bundles. Keepalive bundles carry no packets
+/// and are never handed to the packet processor or forwarded by hubs.
+pub const KEEPALIVE_CHANNEL_ID: u32 = u32::MAX;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct KeepaliveConfig {
+    /// Send a keepalive bundle to the peer after this long without any other egress traffic.
+    pub interval: Duration,
+    /// Close the transport if nothing at all has been received from the peer for this long. The
+    /// peer is only checked once per timeout, so a silent peer is closed between one and two
+    /// timeouts after the last bundle it sent - see `IdleTimer`.
+    pub idle_timeout: Duration,
+}
+
+impl KeepaliveConfig {
+    pub fn new(interval: Duration, idle_timeout: Duration) -> Self {
+        Self {
+            interval,
+            idle_timeout,
+        }
+    }
+}
+
+pub fn encode_keepalive_bundle() -> [u8; <PacketBundle as mproto::BaseLen>::BASE_LEN] {
+    let mut buf = [0u8; <PacketBundle as mproto::BaseLen>::BASE_LEN];
+    mproto::encode_value(
+        PacketBundle {
+            channel_id: KEEPALIVE_CHANNEL_ID,
+            length: 0,
+        },
+        &mut buf[..],
+    );
+    buf
+}
+
+pub fn is_keepalive_bundle(packet_bundle: &[u8]) -> bool {
+    mproto::decode_value::<PacketBundle>(packet_bundle)
+        .is_ok_and(|header| header.channel_id == KEEPALIVE_CHANNEL_ID)
+}
+
+/// Detects peers that have stopped sending anything at all.
+///
+/// Activity is sampled once per timeout period rather than timestamped, so this works the same on
+/// every executor (including wasm32, where `std::time::Instant` is unavailable). The cost is
+/// precision: a peer that goes silent just after a sample is only detected at the end of the next
+/// period, so detection takes up to twice the timeout. A peer sending keepalives at any interval shorter
+/// than the timeout is never mistaken for a dead one.
+pub struct IdleTimer {
+    timeout: Duration,
+    saw_activity: Cell<bool>,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            saw_activity: Cell::new(true),
+        }
+    }
+
+    /// Record that something was received from the peer.
+    pub fn reset(&self) {
+        self.saw_activity.set(true);
+    }
+
+    fn take_activity(&self) -> bool {
+        self.saw_activity.replace(false)
+    }
+
+    /// Resolves once a full timeout period passes without any activity.
+    pub async fn expired(&self, worker_cx: &WorkerContext) {
+        loop {
+            worker_cx.sleep(self.timeout).await;
+            if !self.take_activity() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_idle_timer_activity() {
+        let idle_timer = IdleTimer::new(Duration::from_secs(10));
+
+        // A freshly-started transport gets a full period before it can be considered idle.
+        assert!(idle_timer.take_activity());
+        assert!(!idle_timer.take_activity());
+
+        idle_timer.reset();
+        idle_timer.reset();
+        assert!(idle_timer.take_activity());
+        assert!(!idle_timer.take_activity());
+    }
+
+    #[test]
+    fn test_keepalive_bundle() {
+        let buf = encode_keepalive_bundle();
+        let header: PacketBundle = mproto::decode_value(&buf[..]).unwrap();
+        assert_eq!(header.channel_id, KEEPALIVE_CHANNEL_ID);
+        assert_eq!(header.length, 0);
+        assert!(is_keepalive_bundle(&buf[..]));
+    }
+}
@@ -1,22 +1,51 @@
 pub use bab::{BufferPtr, HeapBufferPool, Packet, SendPacket, WriterFlushSender};
 pub use ispawn::LocalSpawner;
 
+pub use capture::{
+    CaptureDirection, CaptureReader, CaptureRecord, PacketCapture, TransportCapture,
+    replay_capture,
+};
+pub use client_hello::ClientHelloReply;
+pub use compression::{
+    BundleCompressor, COMPRESSED_CHANNEL_BIT, COMPRESSION_HELLO_CHANNEL_ID, COMPRESSION_LZ4,
+    CompressionConfig, CompressionHandshake, CompressionNegotiation, MAX_CHANNEL_ID,
+    SUPPORTED_COMPRESSION,
+    compressed_channel_id, decode_compression_hello, decompress_bundle, encode_compression_hello,
+    is_compressed_bundle, peer_accepts_compression,
+};
 pub use context_map::ContextClass;
+pub use egress_scheduler::{EgressConfig, EgressScheduler};
 pub use endpoint_proto::{
     EndpointAddr, PacketBundle, PacketBundleLazy, PlaneHandshake, PlaneHandshakeGen,
-    PlaneHandshakeLazy, TransmitPacket, TransmitPacketLazy,
+    PlaneHandshakeLazy, PlaneKeyExchange, PlaneKeyExchangeGen, PlaneKeyExchangeLazy,
+    TransmitPacket, TransmitPacketLazy,
 };
+pub use goodbye::{GOODBYE_CHANNEL_ID, encode_goodbye_bundle, is_goodbye_bundle};
 pub use interface_builder::{InterfaceBuilder, InterfaceEvent};
-pub use packet_sender::{MultiChannelSender, PacketSender, SingleChannelSender};
+pub use keepalive::{
+    IdleTimer, KEEPALIVE_CHANNEL_ID, KeepaliveConfig, encode_keepalive_bundle, is_keepalive_bundle,
+};
+pub use memory_transport::{MemoryLink, MemoryNetwork, MemoryNetworkConfig, MemoryTransport};
+pub use metrics::{Counter, Gauge, Histogram, MetricsRegistry};
+pub use packet_sender::{
+    MultiChannelSender, PacketSender, SendBufferError, SingleChannelSender, TxChannel,
+};
 pub use role::{InterfaceRole, InterfaceSchema, RoleSpawner, RoleStartFn, RoleWorkerContext};
 pub use role_setup::{EventRxBuilder, EventTx, RoleSetup, add_topic_subscription};
 pub use rt::{
     RoleConfig, RuntimeBuilder, RuntimeHandle, StartRoleHandle, TopicChannels, WorkerGroup,
     WorkerHandle,
 };
+pub use runtime_config::{RoleQueueConfig, RuntimeConfig, TransportConfig};
+pub use topic_subscription::{
+    TOPIC_SUBSCRIPTIONS_CHANNEL_ID, TopicSubscription, decode_topic_subscriptions,
+    encode_topic_subscriptions,
+};
 pub use transport::{
-    LocalTransport, ShatterPacketBundle, TransportBuilder, TransportContext, TransportHandle,
-    WriterConfig, shatter_packet_bundle,
+    FIRST_CONTROL_CHANNEL_ID, KEY_EXCHANGE_INFRA_ID, LocalTransport, ShatterPacketBundle, TransportBuilder,
+    TransportContext, TransportError, TransportHandle, TransportOptions, WriterConfig,
+    is_control_bundle, next_flushes, report_transport_error, shatter_packet_bundle,
+    validate_packet_bundle,
 };
 pub use worker::{WorkerContext, WorkerId};
 
@@ -26,18 +55,38 @@ pub struct InstanceChannel {
     pub channel_id: u32,
 }
 
+mod capture;
+mod client_hello;
+mod compression;
 mod context_map;
+mod egress_scheduler;
 mod endpoint_proto;
 mod flush_batcher;
+mod goodbye;
 mod interface_builder;
+mod keepalive;
 mod load_balancer;
+mod memory_transport;
+mod metrics;
 mod packet_processor;
 mod packet_sender;
 mod role;
 mod role_setup;
 mod rt;
+mod runtime_config;
+mod thread_slots;
+mod topic_subscription;
 mod transport;
 mod worker;
+mod worker_set;
+
+#[cfg(feature = "encryption")]
+mod plane_encryption;
+#[cfg(feature = "encryption")]
+pub use plane_encryption::{
+    NoiseKeypair, PLANE_ENCRYPTION_OVERHEAD, PacketOpener, PacketSealer, PlaneEncryptionConfig,
+    PlaneKeyring, SenderKey,
+};
 
 #[cfg(feature = "tcp-transport")]
 mod tcp_transport;
@@ -53,6 +102,8 @@ pub use tcp_ingress::TcpIngress;
 mod tcp_client_server;
 #[cfg(feature = "tcp-transport")]
 pub use tcp_client_server::{TcpConnection, TcpServer, tcp_connect, tcp_connect_builder};
+#[cfg(feature = "tcp-transport")]
+pub use client_hello::tcp_client_hello;
 
 #[cfg(feature = "ws-transport")]
 mod ws_transport;
@@ -62,6 +113,8 @@ pub use ws_transport::WebSocketTransport;
 mod ws_ingress;
 #[cfg(feature = "ws-transport")]
 pub use ws_ingress::WebSocketIngress;
+#[cfg(feature = "ws-transport")]
+pub use client_hello::ws_client_hello;
 
 #[cfg(feature = "web-ws-transport")]
 mod web_ws_ingress;
@@ -72,6 +125,10 @@ pub use web_ws_transport::WebSocketTransport;
 #[cfg(feature = "web-ws-transport")]
 mod web_ws_client;
 #[cfg(feature = "web-ws-transport")]
-pub use web_ws_client::{WebSocketConnection, web_ws_client_handshake, web_ws_connect};
+pub use web_ws_client::{
+    WebSocketConnection, web_ws_client_handshake, web_ws_connect, web_ws_connect_with_hello,
+};
+#[cfg(feature = "web-ws-transport")]
+pub use client_hello::web_ws_client_hello;
 #[cfg(feature = "web-ws-transport")]
 pub use web_ws_ingress::WebSocketIngress;
@@ -2,16 +2,27 @@ use core::{cell::Cell, mem::MaybeUninit};
 use std::rc::Rc;
 
 use crate::{
-    Packet, RoleSetup, SendPacket,
+    Packet, RoleQueueConfig, RoleSetup, SendPacket,
     packet_processor::PACKET_PROCESSOR_SOURCE_NEW,
     worker::{get_global_queue_receiver, get_global_queue_sender},
 };
 
 pub struct LoadBalancerConfig {
+    pub capacity: usize,
     pub rx_burst_size: usize,
     pub tx_burst_size: usize,
 }
 
+impl LoadBalancerConfig {
+    pub fn new(queue_config: &RoleQueueConfig) -> Self {
+        Self {
+            capacity: queue_config.load_balancer_capacity,
+            rx_burst_size: queue_config.load_balancer_burst_size,
+            tx_burst_size: queue_config.load_balancer_burst_size,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Hash, Eq, PartialEq)]
 struct LoadBalancerKey {
     plane_id: u32,
@@ -29,9 +40,21 @@ pub fn spawn_load_balancer(
     let is_flushing_overflow = Rc::new(Cell::new(false));
 
     let key = LoadBalancerKey { plane_id, topic };
-    let capacity = 64;
-    let shared_tx = get_global_queue_sender(setup, key, capacity, config.tx_burst_size);
-    let shared_rx = get_global_queue_receiver(setup, key, capacity);
+    let shared_tx = get_global_queue_sender(setup, key, config.capacity, config.tx_burst_size);
+    let shared_rx = get_global_queue_receiver(setup, key, config.capacity);
+
+    // Packets that overflow this worker's queue go to the shared queue for any worker to take.
+    let plane_label = plane_id.to_string();
+    let topic_label = topic.to_string();
+    let balanced_packets = |queue| {
+        setup.worker_context().rt().metrics().counter(
+            "modrpc_load_balancer_packets",
+            "Packets a load balancer queued on the receiving worker or on the shared overflow queue.",
+            &[("plane", &plane_label), ("topic", &topic_label), ("queue", queue)],
+        )
+    };
+    let local_packets = balanced_packets("local");
+    let overflow_packets = balanced_packets("overflow");
 
     setup.worker_context().route_to_local_queue(
         &object_path,
@@ -46,16 +69,19 @@ pub fn spawn_load_balancer(
                     if !is_flushing_overflow.get() {
                         match local_queue_tx.try_send(packet.clone()) {
                             Ok(_) => {
+                                local_packets.inc();
                                 probius::trace_metric("local", 1);
                                 None
                             }
                             Err(localq::mpsc::TrySendError::Full(_packet))
                             | Err(localq::mpsc::TrySendError::Shutdown(_packet)) => {
+                                overflow_packets.inc();
                                 probius::trace_metric("start_batch", 1);
                                 Some(shared_tx.clone())
                             }
                         }
                     } else {
+                        overflow_packets.inc();
                         probius::trace_metric("append_batch", 1);
                         Some(shared_tx.clone())
                     }
@@ -112,11 +138,9 @@ pub fn spawn_load_balancer(
 
 pub fn proxy_load_balancer(setup: &RoleSetup, plane_id: u32, topic: u32, object_path: String) {
     let key = LoadBalancerKey { plane_id, topic };
-    // TODO configurable
-    let capacity = 64;
-    let tx_burst_size = 32;
+    let config = LoadBalancerConfig::new(&setup.queue_config());
 
-    let sender = get_global_queue_sender(setup, key, capacity, tx_burst_size);
+    let sender = get_global_queue_sender(setup, key, config.capacity, config.tx_burst_size);
     setup.worker_context().add_local_queue(
         &object_path,
         PACKET_PROCESSOR_SOURCE_NEW,
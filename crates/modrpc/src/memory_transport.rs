@@ -0,0 +1,607 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures_lite::future;
+use mproto::BaseLen;
+use waitq::WaiterQueue;
+
+use crate::{
+    CaptureDirection, HeapBufferPool, Packet, PacketBundle, ShatterPacketBundle, TransportBuilder,
+    TransportContext, TransportHandle, WorkerId, next_flushes, transport::WriterConfig,
+};
+
+/// How the links of a `MemoryNetwork` mistreat the buffers sent over them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemoryNetworkConfig {
+    /// How long a flushed buffer takes to reach the other end of a link.
+    pub latency: Duration,
+    /// Up to this much extra delay is picked at random for each buffer, so buffers sent close
+    /// together may arrive out of order.
+    pub jitter: Duration,
+    /// The chance, from 0 to 1, that a flushed buffer is lost.
+    pub drop_probability: f64,
+    /// Seeds the random choices of jitter and drops.
+    pub seed: u64,
+}
+
+impl Default for MemoryNetworkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            drop_probability: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+/// A simulated network of named endpoints that runtimes in the same process talk over with
+/// `MemoryTransport`s. Partitions between endpoints can be opened and healed at any time; buffers
+/// sent while two endpoints are partitioned are lost.
+#[derive(Clone)]
+pub struct MemoryNetwork {
+    inner: Arc<spin::Mutex<NetworkState>>,
+}
+
+struct NetworkState {
+    config: MemoryNetworkConfig,
+    rng: u64,
+    partitions: HashSet<(Arc<str>, Arc<str>)>,
+}
+
+impl MemoryNetwork {
+    pub fn new(config: MemoryNetworkConfig) -> Self {
+        Self {
+            inner: Arc::new(spin::Mutex::new(NetworkState {
+                config,
+                rng: config.seed,
+                partitions: HashSet::new(),
+            })),
+        }
+    }
+
+    /// Changes the latency, jitter and drop rate of every link. The RNG is not reseeded.
+    pub fn set_config(&self, config: MemoryNetworkConfig) {
+        self.inner.lock().config = config;
+    }
+
+    /// Create a link between endpoints `a` and `b`, returning `a`'s end and then `b`'s end.
+    pub fn connect(&self, a: &str, b: &str) -> (MemoryLink, MemoryLink) {
+        let a: Arc<str> = a.into();
+        let b: Arc<str> = b.into();
+        let a_to_b = Arc::new(LinkQueue::new());
+        let b_to_a = Arc::new(LinkQueue::new());
+
+        (
+            MemoryLink {
+                network: self.clone(),
+                local: a.clone(),
+                peer: b.clone(),
+                tx: a_to_b.clone(),
+                rx: b_to_a.clone(),
+            },
+            MemoryLink {
+                network: self.clone(),
+                local: b,
+                peer: a,
+                tx: b_to_a,
+                rx: a_to_b,
+            },
+        )
+    }
+
+    /// Lose everything sent between endpoints `a` and `b` until the partition is healed.
+    pub fn partition(&self, a: &str, b: &str) {
+        self.inner.lock().partitions.insert(partition_key(a, b));
+    }
+
+    pub fn heal(&self, a: &str, b: &str) {
+        self.inner.lock().partitions.remove(&partition_key(a, b));
+    }
+
+    pub fn heal_all(&self) {
+        self.inner.lock().partitions.clear();
+    }
+
+    /// Decide the fate of one buffer sent from `from` to `to` - `None` if it's lost, otherwise
+    /// how long it takes to arrive.
+    fn route(&self, from: &str, to: &str) -> Option<Duration> {
+        let mut state = self.inner.lock();
+        if state.partitions.contains(&partition_key(from, to)) {
+            return None;
+        }
+
+        let config = state.config;
+        if config.drop_probability > 0.0
+            && (state.next_random() as f64 / u64::MAX as f64) < config.drop_probability
+        {
+            return None;
+        }
+
+        let jitter_nanos = config.jitter.as_nanos() as u64;
+        let jitter = if jitter_nanos > 0 {
+            Duration::from_nanos(state.next_random() % (jitter_nanos + 1))
+        } else {
+            Duration::ZERO
+        };
+
+        Some(config.latency + jitter)
+    }
+}
+
+impl NetworkState {
+    fn next_random(&mut self) -> u64 {
+        // SplitMix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn partition_key(a: &str, b: &str) -> (Arc<str>, Arc<str>) {
+    if a <= b {
+        (a.into(), b.into())
+    } else {
+        (b.into(), a.into())
+    }
+}
+
+/// One endpoint's end of a link in a `MemoryNetwork`.
+pub struct MemoryLink {
+    network: MemoryNetwork,
+    local: Arc<str>,
+    peer: Arc<str>,
+    tx: Arc<LinkQueue>,
+    rx: Arc<LinkQueue>,
+}
+
+impl MemoryLink {
+    pub fn local_name(&self) -> &str {
+        &self.local
+    }
+
+    pub fn peer_name(&self) -> &str {
+        &self.peer
+    }
+
+    /// Disconnect both ends of the link. Transports using it shut down.
+    pub fn close(&self) {
+        self.tx.close();
+        self.rx.close();
+    }
+}
+
+struct LinkQueue {
+    state: spin::Mutex<LinkQueueState>,
+    waiting_receiver: WaiterQueue<()>,
+}
+
+struct LinkQueueState {
+    buffers: VecDeque<Vec<u8>>,
+    /// Buffers sent but still waiting out their latency.
+    in_flight: usize,
+    is_finished: bool,
+    is_closed: bool,
+}
+
+impl LinkQueue {
+    fn new() -> Self {
+        Self {
+            state: spin::Mutex::new(LinkQueueState {
+                buffers: VecDeque::new(),
+                in_flight: 0,
+                is_finished: false,
+                is_closed: false,
+            }),
+            waiting_receiver: WaiterQueue::new(),
+        }
+    }
+
+    fn push(&self, buffer: Vec<u8>) {
+        {
+            let mut state = self.state.lock();
+            if state.is_closed {
+                return;
+            }
+            state.buffers.push_back(buffer);
+        }
+        self.waiting_receiver.notify_all();
+    }
+
+    /// Note a buffer that will be pushed once it has waited out its latency.
+    fn send_later(&self) {
+        self.state.lock().in_flight += 1;
+    }
+
+    fn push_sent(&self, buffer: Vec<u8>) {
+        self.state.lock().in_flight -= 1;
+        self.push(buffer);
+    }
+
+    /// Wait until every buffer sent with `send_later` has been pushed.
+    async fn delivered(&self) {
+        self.waiting_receiver
+            .wait_for(|| {
+                let state = self.state.lock();
+                (state.in_flight == 0 || state.is_closed).then_some(())
+            })
+            .await
+    }
+
+    /// Wait for the next buffer to arrive. Returns `None` once the link has been closed, or once
+    /// it has been finished and every buffer pushed before that has been received.
+    async fn pop(&self) -> Option<Vec<u8>> {
+        self.waiting_receiver
+            .wait_for(|| {
+                let mut state = self.state.lock();
+                if state.is_closed {
+                    return Some(None);
+                }
+                match state.buffers.pop_front() {
+                    Some(buffer) => Some(Some(buffer)),
+                    None if state.is_finished => Some(None),
+                    None => None,
+                }
+            })
+            .await
+    }
+
+    /// Stop sending, letting the receiver have what has already been pushed.
+    fn finish(&self) {
+        self.state.lock().is_finished = true;
+        self.waiting_receiver.notify_all();
+    }
+
+    fn close(&self) {
+        {
+            let mut state = self.state.lock();
+            state.is_closed = true;
+            state.buffers.clear();
+        }
+        self.waiting_receiver.notify_all();
+    }
+}
+
+/// A transport over a `MemoryLink`. Buffers arriving from the peer are copied into
+/// `in_buffer_pool`, so its buffers must be at least as large as the peer's `out_buffer_pool`
+/// buffers - larger ones are lost.
+pub struct MemoryTransport {
+    pub in_buffer_pool: HeapBufferPool,
+    pub out_buffer_pool: HeapBufferPool,
+    pub worker_id: WorkerId,
+    pub link: MemoryLink,
+}
+
+impl TransportBuilder for MemoryTransport {
+    async fn start_transport(self, cx: TransportContext<'_>) -> TransportHandle {
+        let shutdown_signal = bab::SignalTree::new();
+        let drain_signal = bab::SignalTree::new();
+        let link = Arc::new(self.link);
+        let capture = cx
+            .rt
+            .capture()
+            .map(|capture| capture.add_transport("memory"));
+
+        let writer_flush_sender = cx
+            .rt
+            .get_worker(self.worker_id)
+            .run_once({
+                let shutdown_signal = shutdown_signal.clone();
+                let drain_signal = drain_signal.clone();
+                let in_buffer_pool = self.in_buffer_pool;
+                let out_buffer_pool = self.out_buffer_pool.clone();
+                move |worker_cx| {
+                    let (writer_flush_sender, mut writer_flush_receiver) =
+                        bab::new_writer_flusher();
+
+                    // Spawn task to send egress buffers across the link
+                    worker_cx.spawn({
+                        let shutdown_signal = shutdown_signal.clone();
+                        let shutdown_waiter = shutdown_signal.clone();
+                        let worker_cx = worker_cx.clone();
+                        let link = link.clone();
+                        let capture = capture.clone();
+                        let drain_signal = drain_signal.clone();
+                        future::or(
+                            async move {
+                                while let Some(flushes) =
+                                    next_flushes(&mut writer_flush_receiver, &drain_signal).await
+                                {
+                                    for flush in flushes {
+                                        let channel_id = flush.writer_id() as u32;
+                                        if let Some(capture) = &capture {
+                                            capture.record_bundle(
+                                                CaptureDirection::Outbound,
+                                                channel_id,
+                                                &flush[..],
+                                            );
+                                        }
+                                        let Some(delay) =
+                                            link.network.route(&link.local, &link.peer)
+                                        else {
+                                            continue;
+                                        };
+                                        // Links carry whole bundles, like the wire.
+                                        let mut buffer =
+                                            vec![0u8; PacketBundle::BASE_LEN + flush.len()];
+                                        mproto::encode_value(
+                                            PacketBundle {
+                                                channel_id,
+                                                length: flush.len() as u16,
+                                            },
+                                            &mut buffer[..PacketBundle::BASE_LEN],
+                                        );
+                                        buffer[PacketBundle::BASE_LEN..].copy_from_slice(&flush);
+                                        drop(flush);
+
+                                        if delay.is_zero() {
+                                            link.tx.push(buffer);
+                                            continue;
+                                        }
+                                        link.tx.send_later();
+                                        worker_cx.spawn({
+                                            let shutdown_waiter = shutdown_signal.clone();
+                                            let worker_cx = worker_cx.clone();
+                                            let tx = link.tx.clone();
+                                            future::or(
+                                                async move {
+                                                    worker_cx.sleep(delay).await;
+                                                    tx.push_sent(buffer);
+                                                },
+                                                shutdown_waiter.wait_owned(),
+                                            )
+                                        });
+                                    }
+                                }
+                                // Drained - wait for the buffers still on the wire.
+                                link.tx.delivered().await;
+                                shutdown_signal.notify();
+                            },
+                            async move {
+                                let _buffer_pool_thread_guard = out_buffer_pool.register_thread();
+                                shutdown_waiter.wait().await;
+                            },
+                        )
+                    });
+
+                    // Spawn task to receive ingress buffers from the link
+                    worker_cx.spawn({
+                        let shutdown_notifier = shutdown_signal.clone();
+                        let shutdown_waiter = shutdown_signal.clone();
+                        let process_packet_fn = worker_cx.get_packet_processor();
+                        async move {
+                            future::or(
+                                async {
+                                    while let Some(buffer) = link.rx.pop().await {
+                                        if buffer.len() > in_buffer_pool.buffer_size() {
+                                            log::warn!(
+                                                "Dropping {} byte bundle from {} - larger than the {} byte ingress buffers",
+                                                buffer.len(),
+                                                link.peer,
+                                                in_buffer_pool.buffer_size(),
+                                            );
+                                            continue;
+                                        }
+                                        if let Some(capture) = &capture {
+                                            capture.record(CaptureDirection::Inbound, &buffer);
+                                        }
+                                        // Skip the bundle header.
+                                        let packet = unsafe {
+                                            let in_buffer = in_buffer_pool.acquire().await;
+                                            in_buffer.initialize_rc(1, 0, 0);
+                                            in_buffer.slice_mut(0..buffer.len()).copy_from_slice(&buffer);
+                                            Packet::new(
+                                                in_buffer,
+                                                PacketBundle::BASE_LEN,
+                                                buffer.len() - PacketBundle::BASE_LEN,
+                                            )
+                                        };
+                                        for packet in ShatterPacketBundle::new(&packet) {
+                                            process_packet_fn(&packet).await;
+                                        }
+                                    }
+                                    shutdown_notifier.notify();
+                                },
+                                async {
+                                    let _buffer_pool_thread_guard = in_buffer_pool.register_thread();
+                                    shutdown_waiter.wait().await;
+                                },
+                            )
+                            .await;
+
+                            // Let the peer know we're gone, once it has received what we sent.
+                            link.tx.finish();
+                            link.rx.close();
+                        }
+                    });
+
+                    writer_flush_sender
+                }
+            })
+            .await;
+
+        let mut transport = TransportHandle::new(
+            self.out_buffer_pool,
+            WriterConfig::LocalFlush {
+                writer_flush_sender,
+            },
+            shutdown_signal,
+        );
+        transport.drain_signal = Some(drain_signal);
+        transport
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        EndpointAddr, RuntimeHandle, TransmitPacket, packet_processor::PACKET_PROCESSOR_SOURCE_ANY,
+    };
+    use modrpc_executor::{ModrpcExecutor, SimExecutor};
+    use std::{cell::RefCell, rc::Rc};
+
+    const PLANE_ID: u32 = 1;
+    const TOPIC: u32 = 2;
+
+    fn lost_count(network: &MemoryNetwork, from: &str, to: &str) -> usize {
+        (0..1000)
+            .filter(|_| network.route(from, to).is_none())
+            .count()
+    }
+
+    #[test]
+    fn test_route() {
+        let network = MemoryNetwork::new(MemoryNetworkConfig {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(5),
+            ..MemoryNetworkConfig::default()
+        });
+        for _ in 0..1000 {
+            let delay = network.route("a", "b").unwrap();
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(15));
+        }
+
+        network.partition("b", "a");
+        assert_eq!(lost_count(&network, "a", "b"), 1000);
+        assert_eq!(lost_count(&network, "b", "a"), 1000);
+        assert_eq!(lost_count(&network, "a", "c"), 0);
+        network.heal("a", "b");
+        assert_eq!(lost_count(&network, "a", "b"), 0);
+
+        let lossy_config = MemoryNetworkConfig {
+            drop_probability: 0.25,
+            seed: 42,
+            ..MemoryNetworkConfig::default()
+        };
+        let lost = lost_count(&MemoryNetwork::new(lossy_config), "a", "b");
+        assert!(lost > 150 && lost < 350);
+        assert_eq!(
+            lost_count(&MemoryNetwork::new(lossy_config), "a", "b"),
+            lost
+        );
+    }
+
+    /// Send each payload in its own bundle from one runtime to another over a `MemoryNetwork`,
+    /// returning the first byte of each payload that arrived, in the order they arrived.
+    fn exchange(config: MemoryNetworkConfig, in_buffer_size: usize, payloads: &[&[u8]]) -> Vec<u8> {
+        let mut ex = SimExecutor::with_seed(7);
+        let (sender_rt, _sender_rt_shutdown) = RuntimeHandle::single_threaded(&mut ex);
+        let (receiver_rt, _receiver_rt_shutdown) = RuntimeHandle::single_threaded(&mut ex);
+        let network = MemoryNetwork::new(config);
+        let (sender_link, receiver_link) = network.connect("sender", "receiver");
+        let received = Rc::new(RefCell::new(Vec::new()));
+
+        ex.run_until(async {
+            let receiver_cx = receiver_rt.local_worker_context().unwrap();
+            receiver_cx.add_handler(
+                "test-topic",
+                PACKET_PROCESSOR_SOURCE_ANY,
+                PLANE_ID,
+                TOPIC,
+                {
+                    let received = received.clone();
+                    move |_, packet| received.borrow_mut().push(packet[TransmitPacket::BASE_LEN])
+                },
+            );
+            crate::add_topic_subscription(receiver_cx, "test-topic", PLANE_ID, TOPIC);
+            receiver_cx.subscriptions_installed().await;
+            receiver_rt
+                .add_transport(MemoryTransport {
+                    in_buffer_pool: HeapBufferPool::new(in_buffer_size, 4, 4),
+                    out_buffer_pool: HeapBufferPool::new(256, 4, 4),
+                    worker_id: WorkerId::local(),
+                    link: receiver_link,
+                })
+                .await;
+
+            let sender_cx = sender_rt.local_worker_context().unwrap();
+            let transport = sender_rt
+                .add_transport(MemoryTransport {
+                    in_buffer_pool: HeapBufferPool::new(256, 4, 4),
+                    out_buffer_pool: HeapBufferPool::new(256, 4, 4),
+                    worker_id: WorkerId::local(),
+                    link: sender_link,
+                })
+                .await;
+            for payload in payloads {
+                let (writer, _) = transport.new_writer(sender_cx, PLANE_ID);
+                let mut buffer = writer
+                    .reserve(TransmitPacket::BASE_LEN + payload.len())
+                    .await;
+                mproto::encode_value(
+                    TransmitPacket {
+                        payload_length: payload.len() as u16,
+                        infra_id: 0,
+                        plane_id: PLANE_ID,
+                        topic: TOPIC,
+                        source: EndpointAddr { endpoint: 1 },
+                    },
+                    &mut buffer[..TransmitPacket::BASE_LEN],
+                );
+                buffer[TransmitPacket::BASE_LEN..].copy_from_slice(payload);
+                let _: Packet = buffer.into();
+                writer.flush();
+                // Let the writer's buffer go out before the next packet is written.
+                SimExecutor::sleep(Duration::from_millis(1)).await;
+            }
+
+            SimExecutor::sleep(Duration::from_secs(1)).await;
+        });
+
+        received.take()
+    }
+
+    #[test]
+    fn test_transport_reordering() {
+        let sent: Vec<u8> = (0..20).collect();
+        let payloads: Vec<&[u8]> = sent.chunks(1).collect();
+
+        let in_order = exchange(MemoryNetworkConfig::default(), 256, &payloads);
+        assert_eq!(in_order, sent);
+
+        let config = MemoryNetworkConfig {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(10),
+            ..MemoryNetworkConfig::default()
+        };
+        let mut reordered = exchange(config, 256, &payloads);
+        assert_ne!(reordered, sent);
+        // The same seed reorders them the same way.
+        assert_eq!(exchange(config, 256, &payloads), reordered);
+        // Nothing was lost.
+        reordered.sort();
+        assert_eq!(reordered, sent);
+    }
+
+    #[test]
+    fn test_transport_drops() {
+        let sent: Vec<u8> = (0..100).collect();
+        let payloads: Vec<&[u8]> = sent.chunks(1).collect();
+        let config = MemoryNetworkConfig {
+            drop_probability: 0.5,
+            seed: 42,
+            ..MemoryNetworkConfig::default()
+        };
+
+        let received = exchange(config, 256, &payloads);
+        assert!(received.len() > 25 && received.len() < 75);
+        // Survivors still arrive in order.
+        assert!(received.is_sorted());
+        assert_eq!(exchange(config, 256, &payloads), received);
+
+        // Bundles too large for the receiver's buffers are dropped too.
+        let bundle_len = PacketBundle::BASE_LEN + TransmitPacket::BASE_LEN + 1;
+        let received = exchange(
+            MemoryNetworkConfig::default(),
+            bundle_len,
+            &[&[0], &[1; 8], &[2]],
+        );
+        assert_eq!(received, [0, 2]);
+    }
+}
@@ -0,0 +1,337 @@
+use core::fmt::Write;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicI64, AtomicU64, Ordering},
+    },
+};
+
+/// Counters, gauges and histograms describing a runtime. Registering a metric returns a cheap
+/// handle that updates it without locking, so hot paths should register their metrics up front.
+/// Registering the same name and labels again returns a handle to the same metric.
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    families: Arc<Mutex<BTreeMap<String, Family>>>,
+}
+
+struct Family {
+    help: String,
+    series: BTreeMap<Vec<(String, String)>, Metric>,
+}
+
+#[derive(Clone)]
+enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl Metric {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Metric::Counter(_) => "counter",
+            Metric::Gauge(_) => "gauge",
+            Metric::Histogram(_) => "histogram",
+        }
+    }
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `name` is the metric family's name - the `_total` suffix is added when encoding.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        let metric = self.register(name, help, labels, || Metric::Counter(Counter::default()));
+        let Metric::Counter(counter) = metric else {
+            panic!("modrpc metric {name} is not a counter");
+        };
+        counter
+    }
+
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        let metric = self.register(name, help, labels, || Metric::Gauge(Gauge::default()));
+        let Metric::Gauge(gauge) = metric else {
+            panic!("modrpc metric {name} is not a gauge");
+        };
+        gauge
+    }
+
+    /// `buckets` are the upper bounds of the histogram's buckets in increasing order. They're
+    /// ignored if the histogram already exists.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        buckets: &[f64],
+    ) -> Histogram {
+        let metric = self.register(name, help, labels, || {
+            Metric::Histogram(Histogram::new(buckets))
+        });
+        let Metric::Histogram(histogram) = metric else {
+            panic!("modrpc metric {name} is not a histogram");
+        };
+        histogram
+    }
+
+    fn register(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        new_metric: impl FnOnce() -> Metric,
+    ) -> Metric {
+        let mut families = self.families.lock().expect("modrpc metrics lock poisoned");
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            series: BTreeMap::new(),
+        });
+
+        let labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        family.series.entry(labels).or_insert_with(new_metric).clone()
+    }
+
+    /// Render every metric in the OpenMetrics text format.
+    pub fn encode_openmetrics(&self) -> String {
+        let families = self.families.lock().expect("modrpc metrics lock poisoned");
+        let mut out = String::new();
+
+        for (name, family) in families.iter() {
+            let Some(first) = family.series.values().next() else {
+                continue;
+            };
+            let _ = writeln!(out, "# TYPE {name} {}", first.type_name());
+            let _ = writeln!(out, "# HELP {name} {}", escape(&family.help, false));
+
+            for (labels, metric) in &family.series {
+                match metric {
+                    Metric::Counter(counter) => {
+                        write_sample(&mut out, name, "_total", labels, None, &counter.get().to_string());
+                    }
+                    Metric::Gauge(gauge) => {
+                        write_sample(&mut out, name, "", labels, None, &gauge.get().to_string());
+                    }
+                    Metric::Histogram(histogram) => {
+                        let mut cumulative_count = 0;
+                        for (bound, count) in histogram.inner.bounds.iter().zip(&histogram.inner.buckets) {
+                            cumulative_count += count.load(Ordering::Relaxed);
+                            write_sample(
+                                &mut out,
+                                name,
+                                "_bucket",
+                                labels,
+                                Some(&format_float(*bound)),
+                                &cumulative_count.to_string(),
+                            );
+                        }
+                        let count = histogram.count();
+                        write_sample(&mut out, name, "_bucket", labels, Some("+Inf"), &count.to_string());
+                        write_sample(&mut out, name, "_count", labels, None, &count.to_string());
+                        write_sample(&mut out, name, "_sum", labels, None, &format_float(histogram.sum()));
+                    }
+                }
+            }
+        }
+        out.push_str("# EOF\n");
+
+        out
+    }
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &[(String, String)],
+    le: Option<&str>,
+    value: &str,
+) {
+    let _ = write!(out, "{name}{suffix}");
+
+    let mut labels = labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .peekable();
+    if labels.peek().is_some() {
+        out.push('{');
+        for (i, (key, value)) in labels.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{key}=\"{}\"", escape(value, true));
+        }
+        out.push('}');
+    }
+
+    let _ = writeln!(out, " {value}");
+}
+
+fn escape(s: &str, escape_quotes: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '"' if escape_quotes => escaped.push_str("\\\""),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_finite() && value.fract() == 0.0 {
+        format!("{value:.1}")
+    } else {
+        format!("{value}")
+    }
+}
+
+/// A value that only goes up.
+#[derive(Clone, Default)]
+pub struct Counter {
+    value: Arc<AtomicU64>,
+}
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Clone, Default)]
+pub struct Gauge {
+    value: Arc<AtomicI64>,
+}
+
+impl Gauge {
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: i64) {
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn sub(&self, n: i64) {
+        self.value.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts observed values in buckets by upper bound, along with their total count and sum.
+#[derive(Clone)]
+pub struct Histogram {
+    inner: Arc<HistogramInner>,
+}
+
+struct HistogramInner {
+    bounds: Box<[f64]>,
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    // f64 bits
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            inner: Arc::new(HistogramInner {
+                bounds: bounds.into(),
+                buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+                count: AtomicU64::new(0),
+                sum: AtomicU64::new(0f64.to_bits()),
+            }),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let inner = &self.inner;
+        if let Some(bucket) = inner.bounds.iter().position(|bound| value <= *bound) {
+            inner.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        inner.count.fetch_add(1, Ordering::Relaxed);
+        let _ = inner.sum.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sum| {
+            Some((f64::from_bits(sum) + value).to_bits())
+        });
+    }
+
+    pub fn count(&self) -> u64 {
+        self.inner.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.inner.sum.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode_openmetrics() {
+        let metrics = MetricsRegistry::new();
+
+        let packets = metrics.counter("test_packets", "Packets seen.", &[("plane", "1")]);
+        packets.add(3);
+        metrics.counter("test_packets", "Packets seen.", &[("plane", "1")]).inc();
+        metrics.counter("test_packets", "Packets seen.", &[("plane", "2")]);
+
+        let depth = metrics.gauge("test_depth", "Queue \"depth\".", &[]);
+        depth.add(5);
+        depth.sub(2);
+
+        let sizes = metrics.histogram("test_sizes", "Sizes.", &[("name", "a\"b")], &[1.0, 2.5]);
+        sizes.observe(0.5);
+        sizes.observe(2.0);
+        sizes.observe(10.0);
+
+        assert_eq!(packets.get(), 4);
+        assert_eq!(
+            metrics.encode_openmetrics(),
+            "# TYPE test_depth gauge\n\
+             # HELP test_depth Queue \"depth\".\n\
+             test_depth 3\n\
+             # TYPE test_packets counter\n\
+             # HELP test_packets Packets seen.\n\
+             test_packets_total{plane=\"1\"} 4\n\
+             test_packets_total{plane=\"2\"} 0\n\
+             # TYPE test_sizes histogram\n\
+             # HELP test_sizes Sizes.\n\
+             test_sizes_bucket{name=\"a\\\"b\",le=\"1.0\"} 1\n\
+             test_sizes_bucket{name=\"a\\\"b\",le=\"2.5\"} 2\n\
+             test_sizes_bucket{name=\"a\\\"b\",le=\"+Inf\"} 3\n\
+             test_sizes_count{name=\"a\\\"b\"} 3\n\
+             test_sizes_sum{name=\"a\\\"b\"} 12.5\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a gauge")]
+    fn test_metric_type_mismatch() {
+        let metrics = MetricsRegistry::new();
+        metrics.counter("test_metric", "", &[]);
+        metrics.gauge("test_metric", "", &[]);
+    }
+}
@@ -2,10 +2,14 @@ use core::cell::{Cell, RefCell, UnsafeCell};
 use std::collections::{HashMap, HashSet};
 
 use bab::Packet;
-use mproto::Decode;
+use mproto::{BaseLen, Decode};
 use probius::TraceSource;
 
-use crate::{EndpointAddr, TransmitPacket, WorkerId};
+use crate::{
+    EndpointAddr, TransmitPacket, WorkerId,
+    metrics::{Counter, MetricsRegistry},
+    worker_set::WorkerSet,
+};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct PacketProcessorSource(u8);
@@ -48,6 +52,10 @@ enum NodeBody {
 struct NodeChain {
     head: Node,
     tracer: TraceSource,
+    // Only count packets new to the endpoint so that redirects between workers aren't counted
+    // twice.
+    new_packets: Counter,
+    new_packet_bytes: Counter,
     is_in_handler: Cell<bool>,
     plane_next_topic: Option<u32>,
 }
@@ -67,6 +75,9 @@ enum Mutation {
 
 pub struct PacketProcessor {
     probius_component: probius::Component,
+    metrics: MetricsRegistry,
+    no_handlers: Counter,
+    malformed: Counter,
     handlers: localq::RwLock<HashMap<(u32, u32), NodeChain>>,
     existing_topics: RefCell<HashSet<(u32, u32)>>,
     mutations: UnsafeCell<Vec<Mutation>>,
@@ -75,25 +86,65 @@ pub struct PacketProcessor {
     // plane we walk the stack.
     plane_topic_chain: RefCell<HashMap<u32, u32>>,
     local_worker_queue: localq::mpsc::Sender<Packet>,
-    inter_worker_senders: Vec<Option<localq::mpsc::Sender<Packet>>>,
+    // Indexed by worker ID. Workers can join and leave the runtime, so this is never borrowed
+    // across an await point.
+    inter_worker_senders: RefCell<Vec<Option<localq::mpsc::Sender<Packet>>>>,
 }
 
 impl PacketProcessor {
     pub fn new(
         local_worker_queue: localq::mpsc::Sender<Packet>,
         inter_worker_senders: Vec<Option<localq::mpsc::Sender<Packet>>>,
+        metrics: &MetricsRegistry,
     ) -> Self {
         PacketProcessor {
             probius_component: probius::new_component("PacketProcessor"),
+            no_handlers: metrics.counter(
+                "modrpc_packets_dropped",
+                "Packets dropped by the packet processor.",
+                &[("reason", "no_handlers")],
+            ),
+            malformed: metrics.counter(
+                "modrpc_packets_dropped",
+                "Packets dropped by the packet processor.",
+                &[("reason", "malformed")],
+            ),
+            metrics: metrics.clone(),
             handlers: localq::RwLock::new(HashMap::new()),
             existing_topics: RefCell::new(HashSet::new()),
             mutations: UnsafeCell::new(Vec::new()),
             plane_topic_chain: RefCell::new(HashMap::new()),
             local_worker_queue,
-            inter_worker_senders,
+            inter_worker_senders: RefCell::new(inter_worker_senders),
         }
     }
 
+    /// Set the queue that packets redirected to `worker_id` are sent on, or remove it with `None`
+    /// when the worker leaves the runtime.
+    pub fn set_inter_worker_sender(
+        &self,
+        worker_id: WorkerId,
+        sender: Option<localq::mpsc::Sender<Packet>>,
+    ) {
+        let mut inter_worker_senders = self.inter_worker_senders.borrow_mut();
+        let worker_index = worker_id.0 as usize;
+        if inter_worker_senders.len() <= worker_index {
+            inter_worker_senders.resize(worker_index + 1, None);
+        }
+        inter_worker_senders[worker_index] = sender;
+        while let Some(None) = inter_worker_senders.last() {
+            inter_worker_senders.pop();
+        }
+    }
+
+    fn inter_worker_sender(&self, worker_id: WorkerId) -> Option<localq::mpsc::Sender<Packet>> {
+        self.inter_worker_senders
+            .borrow()
+            .get(worker_id.0 as usize)
+            .cloned()
+            .flatten()
+    }
+
     pub fn add_handler(
         &self,
         topic_name: &str,
@@ -186,18 +237,35 @@ impl PacketProcessor {
     }
 
     pub async fn handle_packet(&self, source: PacketProcessorSource, packet: &Packet) {
-        let header = TransmitPacket::decode(&mproto::DecodeCursor::new(packet))
-            .expect("decode packet header");
+        let header = if packet.len() >= TransmitPacket::BASE_LEN {
+            TransmitPacket::decode(&mproto::DecodeCursor::new(packet)).ok()
+        } else {
+            None
+        };
+        let Some(header) = header else {
+            self.malformed.inc();
+            log::warn!("Dropping packet with a truncated header ({} bytes)", packet.len());
+            return;
+        };
+        if header.infra_id == crate::KEY_EXCHANGE_INFRA_ID {
+            return;
+        }
 
         probius::trace_branch_start();
 
         let handlers = self.handlers.read().await;
         let Some(node_chain) = handlers.get(&(header.plane_id, header.topic)) else {
+            self.no_handlers.inc();
             probius::trace_metric("no_handlers", 1);
             probius::trace_branch_end();
             return;
         };
 
+        if source == PACKET_PROCESSOR_SOURCE_NEW {
+            node_chain.new_packets.inc();
+            node_chain.new_packet_bytes.add(packet.len() as u64);
+        }
+
         if node_chain.is_in_handler.get() {
             // If a handler for a topic can generate a new packet for itself, we can't allow the
             // handler to be called recursively - doing so would create concurrent `&mut FnMut()`
@@ -217,7 +285,7 @@ impl PacketProcessor {
                 probius::trace_metric("packet_size", packet.len() as i64);
 
                 let mut next_node = Some(&node_chain.head);
-                let mut sent_to_workers = 0;
+                let mut sent_to_workers = WorkerSet::new();
 
                 while let Some(node) = next_node {
                     next_node = node.next.as_deref();
@@ -257,14 +325,10 @@ impl PacketProcessor {
                         NodeBody::ToWorker { to_worker_id } => {
                             probius::trace_label("to_worker");
 
-                            let mask = 1 << to_worker_id.0;
-                            if sent_to_workers & mask == 0 {
-                                sent_to_workers |= mask;
-                                if let Some(Some(queue)) =
-                                    self.inter_worker_senders.get(to_worker_id.0 as usize)
-                                {
-                                    let _ = queue.send(packet.clone()).await;
-                                }
+                            if sent_to_workers.insert(*to_worker_id)
+                                && let Some(queue) = self.inter_worker_sender(*to_worker_id)
+                            {
+                                let _ = queue.send(packet.clone()).await;
                             }
                         }
                         NodeBody::RouteToWorker { handler } => {
@@ -277,16 +341,11 @@ impl PacketProcessor {
 
                             node_chain.is_in_handler.replace(false);
 
-                            if let Some(to_worker_id) = maybe_to_worker_id {
-                                let mask = 1 << to_worker_id.0;
-                                if sent_to_workers & mask == 0 {
-                                    sent_to_workers |= mask;
-                                    if let Some(Some(queue)) =
-                                        self.inter_worker_senders.get(to_worker_id.0 as usize)
-                                    {
-                                        let _ = queue.send(packet.clone()).await;
-                                    }
-                                }
+                            if let Some(to_worker_id) = maybe_to_worker_id
+                                && sent_to_workers.insert(to_worker_id)
+                                && let Some(queue) = self.inter_worker_sender(to_worker_id)
+                            {
+                                let _ = queue.send(packet.clone()).await;
                             }
                         }
                     }
@@ -418,6 +477,9 @@ impl PacketProcessor {
                 let prev_plane_topic_head = plane_topic_chain.insert(plane_id, topic);
 
                 // Create the topic's NodeChain
+                let plane_label = plane_id.to_string();
+                let topic_label = topic.to_string();
+                let labels = [("plane", plane_label.as_str()), ("topic", topic_label.as_str())];
                 entry.insert(NodeChain {
                     head: Node {
                         source_filter,
@@ -425,6 +487,16 @@ impl PacketProcessor {
                         body,
                     },
                     tracer,
+                    new_packets: self.metrics.counter(
+                        "modrpc_packets",
+                        "Packets received or created by the endpoint.",
+                        &labels,
+                    ),
+                    new_packet_bytes: self.metrics.counter(
+                        "modrpc_packet_bytes",
+                        "Bytes of packets received or created by the endpoint, headers included.",
+                        &labels,
+                    ),
                     is_in_handler: Cell::new(false),
                     plane_next_topic: prev_plane_topic_head,
                 });
@@ -440,7 +512,8 @@ mod test {
     #[test]
     fn test_packet_processor() {
         let (local_tx, _) = localq::mpsc::channel(1);
-        let pp = PacketProcessor::new(local_tx, vec![]);
+        let metrics = MetricsRegistry::new();
+        let pp = PacketProcessor::new(local_tx, vec![], &metrics);
         let buffer_pool = bab::HeapBufferPool::new(64, 2, 2);
         let buffer = buffer_pool.try_acquire().unwrap();
 
@@ -457,5 +530,28 @@ mod test {
         }
         let packet = unsafe { Packet::new(buffer, 0, 64) };
         pollster::block_on(pp.handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &packet));
+        pollster::block_on(pp.handle_packet(PACKET_PROCESSOR_SOURCE_INTER_WORKER, &packet));
+
+        let labels = [("plane", "0"), ("topic", "0")];
+        assert_eq!(metrics.counter("modrpc_packets", "", &labels).get(), 1);
+        assert_eq!(metrics.counter("modrpc_packet_bytes", "", &labels).get(), 64);
+    }
+
+    #[test]
+    fn test_truncated_packet() {
+        let (local_tx, _) = localq::mpsc::channel(1);
+        let metrics = MetricsRegistry::new();
+        let pp = PacketProcessor::new(local_tx, vec![], &metrics);
+        let buffer_pool = bab::HeapBufferPool::new(64, 2, 2);
+        let buffer = buffer_pool.try_acquire().unwrap();
+
+        unsafe {
+            buffer.initialize_rc(1, 0, 0);
+        }
+        let packet = unsafe { Packet::new(buffer, 0, TransmitPacket::BASE_LEN - 1) };
+        pollster::block_on(pp.handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &packet));
+
+        let malformed = metrics.counter("modrpc_packets_dropped", "", &[("reason", "malformed")]);
+        assert_eq!(malformed.get(), 1);
     }
 }
@@ -10,39 +10,146 @@ use crate::{
     packet_processor::{PACKET_PROCESSOR_SOURCE_NEW, PacketProcessor},
 };
 
+/// A writer on the transport along with the batcher that schedules its flushes.
+#[derive(Clone)]
+pub struct TxChannel {
+    pub writer: DynWriter,
+    pub flush_batcher: Option<FlushBatcher>,
+}
+
 pub trait ChannelSelector {
-    fn select_channel(&self, topic: u32) -> &DynWriter;
+    fn select_channel(&self, topic: u32) -> &TxChannel;
+
+    /// Called while setting up a role for each topic it sends on. `object_path` is the path of the
+    /// topic's event relative to the role, e.g. `"uploads.blob"`.
+    fn assign_topic(&self, _topic: u32, _object_path: &str) {}
 }
 
 pub struct SingleChannelSelector {
-    tx_channel: DynWriter,
+    tx_channel: TxChannel,
 }
 
 impl ChannelSelector for SingleChannelSelector {
-    fn select_channel(&self, _topic: u32) -> &DynWriter {
+    fn select_channel(&self, _topic: u32) -> &TxChannel {
         &self.tx_channel
     }
 }
 
-#[derive(Clone)]
 pub struct MultiChannelSelector {
-    topic_channels: Rc<RefCell<HashMap<u32, usize>>>,
-    channels: Vec<DynWriter>,
+    topic_channels: RefCell<HashMap<u32, usize>>,
+    routes: Vec<(String, usize)>,
+    channels: Vec<TxChannel>,
+    default_channel: usize,
 }
 
 impl MultiChannelSelector {
-    // TODO MultiChannelSelector isn't really tested / supported at the moment
-    #[allow(unused)]
-    pub fn set_topic_channel(&self, topic: u32, channel_id: usize) {
+    pub fn set_topic_channel(&self, topic: u32, channel_index: usize) {
         let mut topic_channels = self.topic_channels.borrow_mut();
-        topic_channels.insert(topic, channel_id);
+        topic_channels.insert(topic, channel_index);
     }
 }
 
 impl ChannelSelector for MultiChannelSelector {
-    fn select_channel(&self, topic: u32) -> &DynWriter {
-        let channel_id = *self.topic_channels.borrow().get(&topic).unwrap();
-        &self.channels[channel_id]
+    fn select_channel(&self, topic: u32) -> &TxChannel {
+        let channel_index = self
+            .topic_channels
+            .borrow()
+            .get(&topic)
+            .copied()
+            .unwrap_or(self.default_channel);
+        &self.channels[channel_index]
+    }
+
+    fn assign_topic(&self, topic: u32, object_path: &str) {
+        if let Some(channel_index) = route_object_path(&self.routes, object_path) {
+            self.set_topic_channel(topic, channel_index);
+        }
+    }
+}
+
+/// Find the route with the longest object path prefix matching `object_path`. Prefixes only match
+/// whole path segments - `"upload"` matches `"upload.blob"` but not `"uploads.blob"`.
+fn route_object_path<T: Copy>(routes: &[(String, T)], object_path: &str) -> Option<T> {
+    routes
+        .iter()
+        .filter(|(prefix, _)| {
+            object_path
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, route)| *route)
+}
+
+/// Returned by `PacketSender::send_buffer` when a buffer can't be sent as-is.
+#[derive(Debug)]
+pub enum SendBufferError {
+    /// The plane is encrypted and the buffer doesn't have room for the `PLANE_ENCRYPTION_OVERHEAD`
+    /// bytes appended when sealing it in place. The buffer has been released.
+    InsufficientHeadroom { required: usize, available: usize },
+}
+
+impl core::fmt::Display for SendBufferError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InsufficientHeadroom {
+                required,
+                available,
+            } => write!(
+                f,
+                "buffer needs {required} bytes to be sealed in place but only {available} fit"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SendBufferError {}
+
+/// Seals packets sent on an encrypted plane. Locally-processed packets stay in plaintext in their
+/// own buffer while the encrypted copy is written to the transport.
+#[cfg(feature = "encryption")]
+#[derive(Clone)]
+pub struct PacketEncryption {
+    sealer: crate::PacketSealer,
+    plaintext_writer: DynWriter,
+    // The largest buffer the transport's writers will flush - see `TransportHandle::new_writer`.
+    max_buffer_len: usize,
+}
+
+#[cfg(feature = "encryption")]
+impl PacketEncryption {
+    pub fn new(sealer: crate::PacketSealer, transport: &crate::TransportHandle) -> Self {
+        Self {
+            sealer,
+            plaintext_writer: bab::Writer::new_local_noflush(transport.buffer_pool.clone(), 0, 0)
+                .to_dyn(),
+            max_buffer_len: transport.buffer_pool.buffer_size()
+                - <crate::PacketBundle as mproto::BaseLen>::BASE_LEN,
+        }
+    }
+
+    async fn write_plaintext(
+        &self,
+        header: TransmitPacket,
+        len: usize,
+        f: impl FnOnce(&mut [u8]),
+    ) -> bab::Packet {
+        let header_length = <TransmitPacket as mproto::BaseLen>::BASE_LEN;
+
+        let mut packet = self.plaintext_writer.reserve(header_length + len).await;
+        mproto::encode_value(header, &mut *packet);
+        f(&mut packet.as_mut()[header_length..]);
+
+        packet.into()
+    }
+
+    async fn write_encrypted(&self, channel: &TxChannel, plaintext: &[u8]) {
+        let mut packet = channel
+            .writer
+            .reserve(plaintext.len() + crate::PLANE_ENCRYPTION_OVERHEAD)
+            .await;
+        self.sealer.seal_packet(plaintext, &mut packet);
+        let _: bab::Packet = packet.into();
     }
 }
 
@@ -52,6 +159,8 @@ pub struct SingleChannelSender {
     pub tx_channel: DynWriter,
     pub pp: Rc<PacketProcessor>,
     pub flush_batcher: Option<FlushBatcher>,
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<PacketEncryption>,
 }
 
 impl Into<PacketSender> for SingleChannelSender {
@@ -60,10 +169,14 @@ impl Into<PacketSender> for SingleChannelSender {
             inner: Rc::new(PacketSenderInner {
                 endpoint_addr: self.endpoint_addr,
                 channel_selector: Box::new(SingleChannelSelector {
-                    tx_channel: self.tx_channel,
+                    tx_channel: TxChannel {
+                        writer: self.tx_channel,
+                        flush_batcher: self.flush_batcher,
+                    },
                 }),
                 pp: self.pp,
-                flush_batcher: self.flush_batcher,
+                #[cfg(feature = "encryption")]
+                encryption: self.encryption,
             }),
         }
     }
@@ -72,10 +185,14 @@ impl Into<PacketSender> for SingleChannelSender {
 #[derive(Clone)]
 pub struct MultiChannelSender {
     pub endpoint_addr: EndpointAddr,
-    pub topic_channels: HashMap<u32, usize>,
-    pub channels: Vec<DynWriter>,
+    /// `(object path prefix, index into channels)` - see `ChannelSelector::assign_topic`.
+    pub routes: Vec<(String, usize)>,
+    pub channels: Vec<TxChannel>,
+    /// Index of the channel used for topics that don't match any route.
+    pub default_channel: usize,
     pub pp: Rc<PacketProcessor>,
-    pub flush_batcher: Option<FlushBatcher>,
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<PacketEncryption>,
 }
 
 impl Into<PacketSender> for MultiChannelSender {
@@ -84,11 +201,14 @@ impl Into<PacketSender> for MultiChannelSender {
             inner: Rc::new(PacketSenderInner {
                 endpoint_addr: self.endpoint_addr,
                 channel_selector: Box::new(MultiChannelSelector {
-                    topic_channels: Rc::new(RefCell::new(self.topic_channels.clone())),
-                    channels: self.channels.into_iter().map(|x| x.into()).collect(),
+                    topic_channels: RefCell::new(HashMap::new()),
+                    routes: self.routes,
+                    channels: self.channels,
+                    default_channel: self.default_channel,
                 }),
                 pp: self.pp,
-                flush_batcher: self.flush_batcher,
+                #[cfg(feature = "encryption")]
+                encryption: self.encryption,
             }),
         }
     }
@@ -98,7 +218,8 @@ pub struct PacketSenderInner {
     endpoint_addr: EndpointAddr,
     channel_selector: Box<dyn ChannelSelector>,
     pp: Rc<PacketProcessor>,
-    flush_batcher: Option<FlushBatcher>,
+    #[cfg(feature = "encryption")]
+    encryption: Option<PacketEncryption>,
 }
 
 pub struct PacketSender {
@@ -110,6 +231,11 @@ impl PacketSender {
         &*self.inner as *const _ as usize
     }
 
+    /// Route `topic` according to the channel mapping configured for this sender, if any.
+    pub fn assign_topic(&self, topic: u32, object_path: &str) {
+        self.inner.channel_selector.assign_topic(topic, object_path);
+    }
+
     pub fn try_send(&self, plane_id: u32, topic: u32, payload: impl mproto::Encode) -> bool {
         futures_util::FutureExt::now_or_never(self.send(plane_id, topic, payload)).is_some()
     }
@@ -128,8 +254,6 @@ impl PacketSender {
 
         let header_length = <TransmitPacket as mproto::BaseLen>::BASE_LEN;
 
-        let mut packet = channel.reserve(header_length + len).await;
-
         let tx_packet = TransmitPacket {
             infra_id: 0,
             plane_id,
@@ -137,6 +261,22 @@ impl PacketSender {
             source: self.inner.endpoint_addr.clone(),
             payload_length: len as u16,
         };
+
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.inner.encryption {
+            let packet = encryption.write_plaintext(tx_packet, len, f).await;
+            encryption.write_encrypted(channel, &packet).await;
+
+            self.inner
+                .pp
+                .handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &packet)
+                .await;
+            self.schedule_flush(channel);
+            return;
+        }
+
+        let mut packet = channel.writer.reserve(header_length + len).await;
+
         mproto::encode_value(tx_packet, &mut *packet);
         f(&mut packet.as_mut()[header_length..]);
 
@@ -145,19 +285,19 @@ impl PacketSender {
             .handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &packet.into())
             .await;
 
-        // TODO immediately flush under low-load
-        if let Some(flush_batcher) = &self.inner.flush_batcher {
-            // TODO configurable
-            if channel.unflushed_bytes() >= 32_000 {
-                channel.flush();
-                flush_batcher.cancel_flush();
-            } else {
-                flush_batcher.schedule_flush();
-            }
-        }
+        self.schedule_flush(channel);
     }
 
-    pub async fn send_buffer(&self, plane_id: u32, topic: u32, buffer: bab::BufferPtr) {
+    /// Send a complete buffer written outside of a `bab::Writer`, starting with
+    /// `TransmitPacket::BASE_LEN` bytes of headroom for the packet header. On an encrypted plane
+    /// the buffer is sealed in place, so it must also have `PLANE_ENCRYPTION_OVERHEAD` bytes of
+    /// room after the payload.
+    pub async fn send_buffer(
+        &self,
+        plane_id: u32,
+        topic: u32,
+        buffer: bab::BufferPtr,
+    ) -> Result<(), SendBufferError> {
         let channel = self.inner.channel_selector.select_channel(topic);
 
         let header_length = <TransmitPacket as mproto::BaseLen>::BASE_LEN;
@@ -172,17 +312,57 @@ impl PacketSender {
         };
         mproto::encode_value(header, unsafe { buffer.slice_mut(0..header_length) });
 
-        let packet = channel.ingest_complete_buffer(buffer, buffer_length);
+        #[cfg(feature = "encryption")]
+        if let Some(encryption) = &self.inner.encryption {
+            let sealed_length = buffer_length + crate::PLANE_ENCRYPTION_OVERHEAD;
+            if sealed_length > encryption.max_buffer_len {
+                unsafe {
+                    buffer.release();
+                }
+                return Err(SendBufferError::InsufficientHeadroom {
+                    required: sealed_length,
+                    available: encryption.max_buffer_len,
+                });
+            }
+
+            // Local roles get a plaintext copy, while the buffer itself is sealed in place and
+            // handed to the transport.
+            let plaintext = encryption
+                .write_plaintext(header, buffer_length - header_length, |payload| {
+                    payload.copy_from_slice(unsafe { buffer.slice(header_length..buffer_length) });
+                })
+                .await;
+            encryption
+                .sealer
+                .seal_packet_in_place(unsafe { buffer.slice_mut(0..sealed_length) });
+            bab::WriterFlushSender::mark_complete_buffer(buffer, sealed_length as u32);
+            let _ = channel.writer.ingest_complete_buffer(buffer, sealed_length);
+
+            self.inner
+                .pp
+                .handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &plaintext)
+                .await;
+            self.schedule_flush(channel);
+            return Ok(());
+        }
+
+        let packet = channel.writer.ingest_complete_buffer(buffer, buffer_length);
         self.inner
             .pp
             .handle_packet(PACKET_PROCESSOR_SOURCE_NEW, &packet.into())
             .await;
 
+        self.schedule_flush(channel);
+        Ok(())
+    }
+
+    fn schedule_flush(&self, channel: &TxChannel) {
         // TODO immediately flush under low-load
-        if let Some(flush_batcher) = &self.inner.flush_batcher {
-            // TODO configurable
-            if channel.unflushed_bytes() >= 32_000 {
-                channel.flush();
+        if let Some(flush_batcher) = &channel.flush_batcher {
+            let unflushed_bytes = channel.writer.unflushed_bytes();
+            if unflushed_bytes >= flush_batcher.max_unflushed_bytes() {
+                flush_batcher.record_flush(unflushed_bytes);
+                channel.writer.flush();
                 flush_batcher.cancel_flush();
             } else {
                 flush_batcher.schedule_flush();
@@ -198,3 +378,24 @@ impl Clone for PacketSender {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_route_object_path() {
+        let routes = vec![
+            ("upload".to_string(), 1),
+            ("upload.blob".to_string(), 2),
+            ("rpc".to_string(), 3),
+        ];
+
+        assert_eq!(route_object_path(&routes, "upload.blob"), Some(2));
+        assert_eq!(route_object_path(&routes, "upload.blob_ack"), Some(1));
+        assert_eq!(route_object_path(&routes, "upload"), Some(1));
+        assert_eq!(route_object_path(&routes, "uploads.blob"), None);
+        assert_eq!(route_object_path(&routes, "rpc.request"), Some(3));
+        assert_eq!(route_object_path(&routes, "other.request"), None);
+    }
+}
@@ -0,0 +1,1008 @@
+use std::collections::HashMap;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, KeyInit, Nonce,
+    aead::{AeadInPlace, OsRng, rand_core::RngCore},
+};
+use mproto::BaseLen;
+
+use crate::{
+    EndpointAddr, KEY_EXCHANGE_INFRA_ID, PacketBundle, TransmitPacket, TransportHandle,
+    WorkerContext, endpoint_proto::PlaneKeyExchange,
+};
+
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 8;
+
+/// Number of bytes that encryption adds to the payload of every packet on an encrypted plane.
+pub const PLANE_ENCRYPTION_OVERHEAD: usize = TAG_LEN + NONCE_LEN;
+
+// How far behind the highest nonce received from a peer a packet may be and still be accepted.
+const REPLAY_WINDOW: u64 = 64;
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+const NOISE_MAX_MESSAGE_LEN: usize = 65535;
+const NOISE_PROLOGUE: &[u8] = b"modrpc plane key exchange";
+
+// Key exchange steps. An endpoint joining a plane announces itself to every other endpoint, then
+// each pair runs a Noise XX handshake started by the endpoint with the lower address.
+const STEP_ANNOUNCE: u8 = 0;
+// -> e
+const STEP_HANDSHAKE_1: u8 = 1;
+// <- e, ee, s, es
+const STEP_HANDSHAKE_2: u8 = 2;
+// -> s, se, carrying the initiator's sender key
+const STEP_HANDSHAKE_3: u8 = 3;
+// <- the responder's sender key
+const STEP_SENDER_KEY: u8 = 4;
+
+const BROADCAST_ADDR: EndpointAddr = EndpointAddr { endpoint: u64::MAX };
+
+/// Symmetric key that one endpoint encrypts everything it sends on a plane with. Every endpoint
+/// generates its own, and only hands it to the peers it has authenticated.
+#[derive(Clone, Eq, PartialEq)]
+pub struct SenderKey([u8; 32]);
+
+impl SenderKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl core::fmt::Debug for SenderKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("SenderKey(..)")
+    }
+}
+
+/// Static x25519 keypair identifying an endpoint during the Noise XX key exchange.
+#[derive(Clone)]
+pub struct NoiseKeypair {
+    pub private: Vec<u8>,
+    pub public: Vec<u8>,
+}
+
+impl core::fmt::Debug for NoiseKeypair {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("NoiseKeypair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NoiseKeypair {
+    pub fn generate() -> Self {
+        let keypair = snow::Builder::new(noise_params())
+            .generate_keypair()
+            .expect("generate noise keypair");
+        Self {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+}
+
+/// How an endpoint authenticates itself and its peers when it exchanges sender keys on a plane.
+#[derive(Clone, Debug)]
+pub struct PlaneEncryptionConfig {
+    pub keypair: NoiseKeypair,
+    /// Static public keys of the peers to exchange keys with - any other peer is refused, so
+    /// an endpoint that trusts no keys never exchanges keys with anyone. Endpoints sharing a
+    /// keypair must trust its public key.
+    pub trusted_peers: Vec<Vec<u8>>,
+}
+
+impl PlaneEncryptionConfig {
+    pub fn new(keypair: NoiseKeypair, trusted_peers: Vec<Vec<u8>>) -> Self {
+        Self {
+            keypair,
+            trusted_peers,
+        }
+    }
+
+    pub fn trust_peer(mut self, public_key: Vec<u8>) -> Self {
+        self.trusted_peers.push(public_key);
+        self
+    }
+}
+
+/// Encrypts and authenticates the packets an endpoint sends on a plane under its sender key.
+///
+/// The `TransmitPacket` header stays in plaintext so that relays can route the packet, but it is
+/// authenticated as associated data - a relay can neither read the payload nor alter the source
+/// `EndpointAddr`, plane, or topic of a packet without it being rejected by the receiver. Every
+/// packet is sealed under the next nonce from a counter, which receivers use to reject replays.
+#[derive(Clone)]
+pub struct PacketSealer {
+    aead: ChaCha20Poly1305,
+    next_nonce: Arc<AtomicU64>,
+}
+
+impl PacketSealer {
+    pub fn new(key: &SenderKey) -> Self {
+        Self {
+            aead: ChaCha20Poly1305::new(key.as_bytes().into()),
+            next_nonce: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Encrypt the plaintext packet (header + payload) at the start of `buf` in place. `buf` must
+    /// be exactly `PLANE_ENCRYPTION_OVERHEAD` bytes longer than the packet - the tag and nonce are
+    /// appended after the payload.
+    pub fn seal_packet_in_place(&self, buf: &mut [u8]) {
+        let mut header: TransmitPacket =
+            mproto::decode_value(&*buf).expect("decode plaintext packet header");
+        let payload_len = buf.len() - TransmitPacket::BASE_LEN - PLANE_ENCRYPTION_OVERHEAD;
+        header.payload_length = (payload_len + PLANE_ENCRYPTION_OVERHEAD) as u16;
+        mproto::encode_value(header, &mut buf[..TransmitPacket::BASE_LEN]);
+
+        let nonce = self.next_nonce.fetch_add(1, Ordering::Relaxed);
+        let (header, body) = buf.split_at_mut(TransmitPacket::BASE_LEN);
+        let (payload, trailer) = body.split_at_mut(payload_len);
+        let tag = self
+            .aead
+            .encrypt_in_place_detached(&aead_nonce(nonce), header, payload)
+            .expect("encrypt packet payload");
+        trailer[..TAG_LEN].copy_from_slice(&tag);
+        trailer[TAG_LEN..].copy_from_slice(&nonce.to_le_bytes());
+    }
+
+    /// Encrypt a complete plaintext packet (header + payload) into `out`, which must be exactly
+    /// `PLANE_ENCRYPTION_OVERHEAD` bytes longer than `packet`.
+    pub fn seal_packet(&self, packet: &[u8], out: &mut [u8]) {
+        assert_eq!(out.len(), packet.len() + PLANE_ENCRYPTION_OVERHEAD);
+        out[..packet.len()].copy_from_slice(packet);
+        self.seal_packet_in_place(out);
+    }
+}
+
+/// Decrypts the packets one peer sends on a plane, rejecting any that fail authentication or
+/// reuse a nonce.
+pub struct PacketOpener {
+    aead: ChaCha20Poly1305,
+    replay_window: spin::Mutex<ReplayWindow>,
+}
+
+impl PacketOpener {
+    pub fn new(key: &SenderKey) -> Self {
+        Self {
+            aead: ChaCha20Poly1305::new(key.as_bytes().into()),
+            replay_window: spin::Mutex::new(ReplayWindow::default()),
+        }
+    }
+
+    /// Decrypt a sealed packet in place, leaving a plaintext packet (header + payload) at the
+    /// start of `packet`. Returns the length of the plaintext packet, or `None` if the packet was
+    /// malformed, failed authentication, or was a replay.
+    pub fn open_packet_in_place(&self, packet: &mut [u8]) -> Option<usize> {
+        let mut header: TransmitPacket = mproto::decode_value(&*packet).ok()?;
+        let payload_len =
+            (header.payload_length as usize).checked_sub(PLANE_ENCRYPTION_OVERHEAD)?;
+        let packet_len = TransmitPacket::BASE_LEN + payload_len + PLANE_ENCRYPTION_OVERHEAD;
+        if packet.len() < packet_len {
+            return None;
+        }
+
+        let (packet_header, body) = packet[..packet_len].split_at_mut(TransmitPacket::BASE_LEN);
+        let (ciphertext, trailer) = body.split_at_mut(payload_len);
+        let (tag, nonce) = trailer.split_at(TAG_LEN);
+        let nonce = u64::from_le_bytes(nonce.try_into().ok()?);
+
+        let mut replay_window = self.replay_window.lock();
+        if !replay_window.check(nonce) {
+            return None;
+        }
+        self.aead
+            .decrypt_in_place_detached(&aead_nonce(nonce), packet_header, ciphertext, tag.into())
+            .ok()?;
+        replay_window.accept(nonce);
+        drop(replay_window);
+
+        header.payload_length = payload_len as u16;
+        mproto::encode_value(header, &mut packet[..TransmitPacket::BASE_LEN]);
+
+        Some(TransmitPacket::BASE_LEN + payload_len)
+    }
+}
+
+fn aead_nonce(nonce: u64) -> Nonce {
+    let mut aead_nonce = Nonce::default();
+    aead_nonce[..NONCE_LEN].copy_from_slice(&nonce.to_le_bytes());
+    aead_nonce
+}
+
+/// Tracks which of the last `REPLAY_WINDOW` nonces have been received from a peer.
+#[derive(Default)]
+struct ReplayWindow {
+    // One past the highest nonce received.
+    next: u64,
+    // Bit `i` is set if nonce `next - 1 - i` has been received.
+    received: u64,
+}
+
+impl ReplayWindow {
+    fn check(&self, nonce: u64) -> bool {
+        if nonce >= self.next {
+            return nonce != u64::MAX;
+        }
+        let age = self.next - 1 - nonce;
+        age < REPLAY_WINDOW && self.received & (1 << age) == 0
+    }
+
+    fn accept(&mut self, nonce: u64) {
+        if nonce >= self.next {
+            let shift = nonce - self.next + 1;
+            self.received = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.received << shift
+            };
+            self.received |= 1;
+            self.next = nonce + 1;
+        } else {
+            self.received |= 1 << (self.next - 1 - nonce);
+        }
+    }
+}
+
+/// The encrypted planes carried by one transport. Plane IDs are only unique per transport, so
+/// every network transport that carries encrypted planes gets its own keyring.
+///
+/// Keys are exchanged over the plane itself: an endpoint joining a plane announces itself, and
+/// every pair of endpoints on the plane runs a Noise XX handshake, relayed by any hubs in between,
+/// over which they hand each other their sender keys. Peers are authenticated by their static
+/// keys, which must be among `PlaneEncryptionConfig::trusted_peers`, so relays only ever see
+/// ciphertext and can neither forge nor replay packets. A packet is only accepted from a peer
+/// once keys have been exchanged with it, see `PlaneKeyring::wait_for_peer`. When a peer rejoins
+/// the plane, its old keys are kept until the new handshake has authenticated it.
+///
+/// Each endpoint's sender key is shared with every peer it has authenticated, so endpoints on the
+/// same plane are trusted not to impersonate one another.
+#[derive(Clone)]
+pub struct PlaneKeyring {
+    inner: Arc<PlaneKeyringInner>,
+}
+
+struct PlaneKeyringInner {
+    planes: spin::RwLock<HashMap<u32, EncryptedPlane>>,
+    // Key exchange messages waiting to be written to the transport.
+    outgoing: spin::Mutex<Vec<OutgoingKeyExchange>>,
+    outgoing_ready: bab::Signal,
+    peer_keyed: waitq::WaiterQueue<()>,
+}
+
+struct OutgoingKeyExchange {
+    plane_id: u32,
+    channel_id: u32,
+    source: EndpointAddr,
+    payload: Vec<u8>,
+}
+
+impl Default for PlaneKeyring {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(PlaneKeyringInner {
+                planes: spin::RwLock::new(HashMap::new()),
+                outgoing: spin::Mutex::new(Vec::new()),
+                outgoing_ready: bab::Signal::new(),
+                peer_keyed: waitq::WaiterQueue::new(),
+            }),
+        }
+    }
+}
+
+impl PlaneKeyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encrypt everything `endpoint_addr` sends on `plane_id` under a fresh sender key, and
+    /// exchange keys with the plane's other endpoints over `channel_id`. This must be done before
+    /// the transport starts and before any roles on the plane are started - senders pick up the
+    /// plane's key when they're created, and peers announcing themselves before the plane is
+    /// joined would be missed.
+    pub fn join_plane(
+        &self,
+        plane_id: u32,
+        channel_id: u32,
+        endpoint_addr: EndpointAddr,
+        config: PlaneEncryptionConfig,
+    ) {
+        let sender_key = SenderKey::generate();
+        let plane = EncryptedPlane {
+            plane_id,
+            channel_id,
+            endpoint_addr,
+            config,
+            sealer: PacketSealer::new(&sender_key),
+            sender_key,
+            peers: HashMap::new(),
+            handshakes: HashMap::new(),
+        };
+        self.queue_key_exchange(&plane, BROADCAST_ADDR, STEP_ANNOUNCE, Vec::new());
+        self.inner.planes.write().insert(plane_id, plane);
+    }
+
+    pub fn leave_plane(&self, plane_id: u32) {
+        self.inner.planes.write().remove(&plane_id);
+    }
+
+    pub fn sealer(&self, plane_id: u32) -> Option<PacketSealer> {
+        self.inner
+            .planes
+            .read()
+            .get(&plane_id)
+            .map(|plane| plane.sealer.clone())
+    }
+
+    /// Whether keys have been exchanged with `peer` on `plane_id`, so that each accepts the
+    /// other's packets.
+    pub fn is_peer_keyed(&self, plane_id: u32, peer: EndpointAddr) -> bool {
+        self.inner
+            .planes
+            .read()
+            .get(&plane_id)
+            .is_some_and(|plane| plane.peers.contains_key(&peer.endpoint))
+    }
+
+    /// Resolves once keys have been exchanged with `peer` on `plane_id`.
+    pub async fn wait_for_peer(&self, plane_id: u32, peer: EndpointAddr) {
+        self.inner
+            .peer_keyed
+            .wait_until(|| self.is_peer_keyed(plane_id, peer))
+            .await;
+    }
+
+    /// Decrypt every packet in a received bundle that belongs to an encrypted plane, compacting
+    /// the bundle in place. Packets on encrypted planes that fail authentication or come from a
+    /// peer that keys haven't been exchanged with are dropped, and key exchange packets are
+    /// consumed. Returns the new length of the bundle, including its header.
+    pub fn decrypt_bundle_in_place(&self, bundle: &mut [u8]) -> usize {
+        let Ok(mut bundle_header) = mproto::decode_value::<PacketBundle>(&*bundle) else {
+            return bundle.len();
+        };
+        if bundle_header.channel_id >= crate::transport::FIRST_CONTROL_CHANNEL_ID {
+            return bundle.len();
+        }
+        let bundle_end = std::cmp::min(
+            bundle.len(),
+            PacketBundle::BASE_LEN + bundle_header.length as usize,
+        );
+
+        let mut key_exchanges = Vec::new();
+        let planes = self.inner.planes.read();
+        if planes.is_empty() {
+            return bundle.len();
+        }
+
+        let mut read_cursor = PacketBundle::BASE_LEN;
+        let mut write_cursor = PacketBundle::BASE_LEN;
+        while read_cursor + TransmitPacket::BASE_LEN <= bundle_end {
+            let Ok(packet_header) = mproto::decode_value::<TransmitPacket>(&bundle[read_cursor..])
+            else {
+                break;
+            };
+            let packet_len = TransmitPacket::BASE_LEN + packet_header.payload_length as usize;
+            if read_cursor + packet_len > bundle_end {
+                break;
+            }
+
+            let packet = &mut bundle[read_cursor..read_cursor + packet_len];
+            let plaintext_len = match planes.get(&packet_header.plane_id) {
+                Some(_) if packet_header.infra_id == KEY_EXCHANGE_INFRA_ID => {
+                    key_exchanges.push((
+                        packet_header.plane_id,
+                        packet_header.source,
+                        packet[TransmitPacket::BASE_LEN..].to_vec(),
+                    ));
+                    None
+                }
+                Some(plane) => plane
+                    .peers
+                    .get(&packet_header.source.endpoint)
+                    .and_then(|opener| opener.open_packet_in_place(packet)),
+                None => Some(packet_len),
+            };
+            if let Some(plaintext_len) = plaintext_len {
+                bundle.copy_within(read_cursor..read_cursor + plaintext_len, write_cursor);
+                write_cursor += plaintext_len;
+            }
+            read_cursor += packet_len;
+        }
+        drop(planes);
+
+        for (plane_id, source, payload) in key_exchanges {
+            self.receive_key_exchange(plane_id, source, &payload);
+        }
+
+        bundle_header.length = (write_cursor - PacketBundle::BASE_LEN) as u16;
+        mproto::encode_value(bundle_header, &mut bundle[..PacketBundle::BASE_LEN]);
+
+        write_cursor
+    }
+
+    fn receive_key_exchange(&self, plane_id: u32, source: EndpointAddr, payload: &[u8]) {
+        let Ok(message) = mproto::decode_value::<PlaneKeyExchange>(payload) else {
+            log::warn!("Dropping malformed key exchange from {source:?} on plane {plane_id}");
+            return;
+        };
+
+        let mut planes = self.inner.planes.write();
+        let Some(plane) = planes.get_mut(&plane_id) else {
+            return;
+        };
+        if source == plane.endpoint_addr
+            || (message.to != plane.endpoint_addr && message.to != BROADCAST_ADDR)
+        {
+            return;
+        }
+
+        let was_keyed = plane.peers.contains_key(&source.endpoint);
+        match plane.receive_key_exchange(source, &message) {
+            Ok(Some((step, reply))) => {
+                self.queue_key_exchange(plane, source, step, reply);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                plane.handshakes.remove(&source.endpoint);
+                log::warn!("Key exchange with {source:?} on plane {plane_id} failed: {e}");
+            }
+        }
+        let is_keyed = plane.peers.contains_key(&source.endpoint);
+        drop(planes);
+
+        if is_keyed && !was_keyed {
+            log::debug!("Exchanged keys with {source:?} on plane {plane_id}");
+            self.inner.peer_keyed.notify_all();
+        }
+    }
+
+    fn queue_key_exchange(
+        &self,
+        plane: &EncryptedPlane,
+        to: EndpointAddr,
+        step: u8,
+        message: Vec<u8>,
+    ) {
+        self.inner.outgoing.lock().push(OutgoingKeyExchange {
+            plane_id: plane.plane_id,
+            channel_id: plane.channel_id,
+            source: plane.endpoint_addr,
+            payload: mproto::encode_value_vec(PlaneKeyExchange { to, step, message }),
+        });
+        self.inner.outgoing_ready.notify();
+    }
+}
+
+/// Write the key exchange messages queued by `keyring` to `transport` until it shuts down.
+pub(crate) async fn send_key_exchanges(
+    worker_cx: WorkerContext,
+    transport: TransportHandle,
+    keyring: PlaneKeyring,
+) {
+    let mut writers = HashMap::new();
+    let send_loop = async {
+        loop {
+            keyring.inner.outgoing_ready.wait().await;
+            keyring.inner.outgoing_ready.reset();
+            let outgoing = core::mem::take(&mut *keyring.inner.outgoing.lock());
+
+            for key_exchange in outgoing {
+                let (writer, _) = writers
+                    .entry(key_exchange.channel_id)
+                    .or_insert_with(|| transport.new_writer(&worker_cx, key_exchange.channel_id));
+                let header = TransmitPacket {
+                    payload_length: key_exchange.payload.len() as u16,
+                    infra_id: KEY_EXCHANGE_INFRA_ID,
+                    plane_id: key_exchange.plane_id,
+                    topic: 0,
+                    source: key_exchange.source,
+                };
+                let mut packet = writer
+                    .reserve(TransmitPacket::BASE_LEN + key_exchange.payload.len())
+                    .await;
+                mproto::encode_value(header, &mut *packet);
+                packet.as_mut()[TransmitPacket::BASE_LEN..].copy_from_slice(&key_exchange.payload);
+                let _: bab::Packet = packet.into();
+                writer.flush();
+            }
+        }
+    };
+    futures_lite::future::or(send_loop, transport.shutdown_signal.wait()).await;
+}
+
+struct EncryptedPlane {
+    plane_id: u32,
+    channel_id: u32,
+    endpoint_addr: EndpointAddr,
+    config: PlaneEncryptionConfig,
+    sender_key: SenderKey,
+    sealer: PacketSealer,
+    // Keyed by the peer's endpoint.
+    peers: HashMap<u64, PacketOpener>,
+    handshakes: HashMap<u64, PeerHandshake>,
+}
+
+enum PeerHandshake {
+    Initiating(Box<snow::HandshakeState>),
+    Responding(Box<snow::HandshakeState>),
+    AwaitingSenderKey(Box<snow::TransportState>),
+}
+
+impl EncryptedPlane {
+    /// Advance the key exchange with `peer`. Returns the step and message to reply with, if any.
+    fn receive_key_exchange(
+        &mut self,
+        peer: EndpointAddr,
+        message: &PlaneKeyExchange,
+    ) -> std::io::Result<Option<(u8, Vec<u8>)>> {
+        let mut buf = vec![0u8; NOISE_MAX_MESSAGE_LEN];
+
+        match message.step {
+            STEP_ANNOUNCE => {
+                // An announcement to everyone means the peer just joined the plane, so exchange keys
+                // again even if we have some from a previous session. Anyone can announce, so the
+                // old keys stay in place until the new handshake completes.
+                if message.to != BROADCAST_ADDR
+                    && (self.peers.contains_key(&peer.endpoint)
+                        || self.handshakes.contains_key(&peer.endpoint))
+                {
+                    return Ok(None);
+                }
+
+                if self.endpoint_addr.endpoint > peer.endpoint {
+                    // Let the peer know we're here so that it starts the handshake.
+                    return Ok(Some((STEP_ANNOUNCE, Vec::new())));
+                }
+
+                let mut handshake = self.build_handshake(peer, true)?;
+                let len = handshake
+                    .write_message(&[], &mut buf)
+                    .map_err(noise_error)?;
+                self.handshakes.insert(
+                    peer.endpoint,
+                    PeerHandshake::Initiating(Box::new(handshake)),
+                );
+                Ok(Some((STEP_HANDSHAKE_1, buf[..len].to_vec())))
+            }
+            STEP_HANDSHAKE_1 => {
+                if self.endpoint_addr.endpoint < peer.endpoint {
+                    return Err(unexpected_step(message.step));
+                }
+
+                let mut handshake = self.build_handshake(peer, false)?;
+                handshake
+                    .read_message(&message.message, &mut buf)
+                    .map_err(noise_error)?;
+                let len = handshake
+                    .write_message(&[], &mut buf)
+                    .map_err(noise_error)?;
+                self.handshakes.insert(
+                    peer.endpoint,
+                    PeerHandshake::Responding(Box::new(handshake)),
+                );
+                Ok(Some((STEP_HANDSHAKE_2, buf[..len].to_vec())))
+            }
+            STEP_HANDSHAKE_2 => {
+                let Some(PeerHandshake::Initiating(mut handshake)) =
+                    self.handshakes.remove(&peer.endpoint)
+                else {
+                    return Err(unexpected_step(message.step));
+                };
+                handshake
+                    .read_message(&message.message, &mut buf)
+                    .map_err(noise_error)?;
+                self.check_trusted(handshake.get_remote_static())?;
+                let len = handshake
+                    .write_message(self.sender_key.as_bytes(), &mut buf)
+                    .map_err(noise_error)?;
+                let transport = handshake.into_transport_mode().map_err(noise_error)?;
+                self.handshakes.insert(
+                    peer.endpoint,
+                    PeerHandshake::AwaitingSenderKey(Box::new(transport)),
+                );
+                Ok(Some((STEP_HANDSHAKE_3, buf[..len].to_vec())))
+            }
+            STEP_HANDSHAKE_3 => {
+                let Some(PeerHandshake::Responding(mut handshake)) =
+                    self.handshakes.remove(&peer.endpoint)
+                else {
+                    return Err(unexpected_step(message.step));
+                };
+                let len = handshake
+                    .read_message(&message.message, &mut buf)
+                    .map_err(noise_error)?;
+                self.check_trusted(handshake.get_remote_static())?;
+                let peer_key = sender_key_from_message(&buf[..len])?;
+
+                let mut transport = handshake.into_transport_mode().map_err(noise_error)?;
+                let len = transport
+                    .write_message(self.sender_key.as_bytes(), &mut buf)
+                    .map_err(noise_error)?;
+                self.peers
+                    .insert(peer.endpoint, PacketOpener::new(&peer_key));
+                Ok(Some((STEP_SENDER_KEY, buf[..len].to_vec())))
+            }
+            STEP_SENDER_KEY => {
+                let Some(PeerHandshake::AwaitingSenderKey(mut transport)) =
+                    self.handshakes.remove(&peer.endpoint)
+                else {
+                    return Err(unexpected_step(message.step));
+                };
+                let len = transport
+                    .read_message(&message.message, &mut buf)
+                    .map_err(noise_error)?;
+                let peer_key = sender_key_from_message(&buf[..len])?;
+                self.peers
+                    .insert(peer.endpoint, PacketOpener::new(&peer_key));
+                Ok(None)
+            }
+            step => Err(unexpected_step(step)),
+        }
+    }
+
+    fn build_handshake(
+        &self,
+        peer: EndpointAddr,
+        initiator: bool,
+    ) -> std::io::Result<snow::HandshakeState> {
+        // Bind the handshake to the plane and both endpoint addresses, so that a relay can't
+        // splice it into another plane or pass one endpoint off as another.
+        let (initiator_addr, responder_addr) = if initiator {
+            (self.endpoint_addr, peer)
+        } else {
+            (peer, self.endpoint_addr)
+        };
+        let mut prologue = NOISE_PROLOGUE.to_vec();
+        prologue.extend_from_slice(&self.plane_id.to_le_bytes());
+        prologue.extend_from_slice(&initiator_addr.endpoint.to_le_bytes());
+        prologue.extend_from_slice(&responder_addr.endpoint.to_le_bytes());
+
+        let builder = snow::Builder::new(noise_params())
+            .local_private_key(&self.config.keypair.private)
+            .and_then(|b| b.prologue(&prologue));
+        if initiator {
+            builder.and_then(|b| b.build_initiator())
+        } else {
+            builder.and_then(|b| b.build_responder())
+        }
+        .map_err(noise_error)
+    }
+
+    fn check_trusted(&self, remote_static: Option<&[u8]>) -> std::io::Result<()> {
+        let trusted_peers = &self.config.trusted_peers;
+        if remote_static.is_some_and(|key| trusted_peers.iter().any(|trusted| trusted == key)) {
+            Ok(())
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "plane key exchange: untrusted remote static key",
+            ))
+        }
+    }
+}
+
+fn sender_key_from_message(message: &[u8]) -> std::io::Result<SenderKey> {
+    let key: [u8; 32] = message.try_into().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "plane key exchange: bad sender key length",
+        )
+    })?;
+    Ok(SenderKey::from_bytes(key))
+}
+
+fn unexpected_step(step: u8) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("plane key exchange: unexpected step {step}"),
+    )
+}
+
+fn noise_params() -> snow::params::NoiseParams {
+    NOISE_PARAMS.parse().expect("parse noise params")
+}
+
+fn noise_error(e: snow::Error) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("plane key exchange: {e}"),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode_packet(plane_id: u32, endpoint: u64, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; TransmitPacket::BASE_LEN + payload.len()];
+        mproto::encode_value(
+            TransmitPacket {
+                payload_length: payload.len() as u16,
+                infra_id: 0,
+                plane_id,
+                topic: 3,
+                source: EndpointAddr { endpoint },
+            },
+            &mut packet[..TransmitPacket::BASE_LEN],
+        );
+        packet[TransmitPacket::BASE_LEN..].copy_from_slice(payload);
+        packet
+    }
+
+    fn seal(sealer: &PacketSealer, packet: &[u8]) -> Vec<u8> {
+        let mut out = vec![0u8; packet.len() + PLANE_ENCRYPTION_OVERHEAD];
+        sealer.seal_packet(packet, &mut out);
+        out
+    }
+
+    fn encode_bundle(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut bundle = vec![0u8; PacketBundle::BASE_LEN];
+        for packet in packets {
+            bundle.extend_from_slice(packet);
+        }
+        mproto::encode_value(
+            PacketBundle {
+                channel_id: 1,
+                length: (bundle.len() - PacketBundle::BASE_LEN) as u16,
+            },
+            &mut bundle[..PacketBundle::BASE_LEN],
+        );
+        bundle
+    }
+
+    fn decrypt_bundle(keyring: &PlaneKeyring, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut bundle = encode_bundle(packets);
+        let len = keyring.decrypt_bundle_in_place(&mut bundle);
+        let header: PacketBundle = mproto::decode_value(&bundle[..]).unwrap();
+        assert_eq!(header.length as usize, len - PacketBundle::BASE_LEN);
+        bundle[PacketBundle::BASE_LEN..len].to_vec()
+    }
+
+    // Deliver the key exchanges queued by every keyring to every other keyring, like a hub
+    // relaying the plane, until none are left.
+    fn exchange_keys(keyrings: &[&PlaneKeyring]) {
+        loop {
+            let mut delivered = false;
+            for (i, from) in keyrings.iter().enumerate() {
+                let outgoing = core::mem::take(&mut *from.inner.outgoing.lock());
+                for key_exchange in outgoing {
+                    let mut packet = vec![0u8; TransmitPacket::BASE_LEN];
+                    mproto::encode_value(
+                        TransmitPacket {
+                            payload_length: key_exchange.payload.len() as u16,
+                            infra_id: KEY_EXCHANGE_INFRA_ID,
+                            plane_id: key_exchange.plane_id,
+                            topic: 0,
+                            source: key_exchange.source,
+                        },
+                        &mut packet[..],
+                    );
+                    packet.extend_from_slice(&key_exchange.payload);
+                    for (j, to) in keyrings.iter().enumerate() {
+                        if i != j {
+                            // Key exchange packets are consumed by the keyring.
+                            assert!(decrypt_bundle(to, &[packet.clone()]).is_empty());
+                        }
+                    }
+                    delivered = true;
+                }
+            }
+            if !delivered {
+                break;
+            }
+        }
+    }
+
+    fn join(plane_id: u32, endpoint: u64, config: &PlaneEncryptionConfig) -> PlaneKeyring {
+        let keyring = PlaneKeyring::new();
+        keyring.join_plane(
+            plane_id,
+            plane_id,
+            EndpointAddr { endpoint },
+            config.clone(),
+        );
+        keyring
+    }
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let key = SenderKey::generate();
+        let sealer = PacketSealer::new(&key);
+        let opener = PacketOpener::new(&key);
+        let packet = encode_packet(1, 7, b"hello world");
+
+        let mut sealed = seal(&sealer, &packet);
+        assert!(!sealed.windows(5).any(|w| w == b"hello"));
+
+        let len = opener.open_packet_in_place(&mut sealed).unwrap();
+        assert_eq!(&sealed[..len], &packet[..]);
+    }
+
+    #[test]
+    fn test_packet_source_is_authenticated() {
+        let key = SenderKey::generate();
+        let sealer = PacketSealer::new(&key);
+        let opener = PacketOpener::new(&key);
+        let mut sealed = seal(&sealer, &encode_packet(1, 7, b"hello world"));
+
+        // A relay rewriting the source endpoint must be detected.
+        let mut header: TransmitPacket = mproto::decode_value(&sealed[..]).unwrap();
+        header.source = EndpointAddr { endpoint: 8 };
+        mproto::encode_value(header, &mut sealed[..TransmitPacket::BASE_LEN]);
+        assert_eq!(opener.open_packet_in_place(&mut sealed), None);
+
+        // As must a packet sealed with some other key.
+        let other_sealer = PacketSealer::new(&SenderKey::generate());
+        let mut sealed = seal(&other_sealer, &encode_packet(1, 7, b"hello world"));
+        assert_eq!(opener.open_packet_in_place(&mut sealed), None);
+    }
+
+    #[test]
+    fn test_replayed_packets_are_rejected() {
+        let key = SenderKey::generate();
+        let sealer = PacketSealer::new(&key);
+        let opener = PacketOpener::new(&key);
+        let packet = encode_packet(1, 7, b"hello world");
+
+        let sealed: Vec<Vec<u8>> = (0..REPLAY_WINDOW + 4)
+            .map(|_| seal(&sealer, &packet))
+            .collect();
+
+        // Packets may arrive out of order within the window, but only once.
+        for i in [1, 0, 3] {
+            assert!(
+                opener
+                    .open_packet_in_place(&mut sealed[i].clone())
+                    .is_some()
+            );
+            assert_eq!(opener.open_packet_in_place(&mut sealed[i].clone()), None);
+        }
+
+        // Once the window has moved past a packet it's rejected even if it was never received.
+        assert!(
+            opener
+                .open_packet_in_place(&mut sealed[REPLAY_WINDOW as usize + 3].clone())
+                .is_some()
+        );
+        assert_eq!(opener.open_packet_in_place(&mut sealed[2].clone()), None);
+        assert_eq!(opener.open_packet_in_place(&mut sealed[1].clone()), None);
+    }
+
+    // Config for endpoints that all share one keypair.
+    fn shared_config() -> PlaneEncryptionConfig {
+        let keypair = NoiseKeypair::generate();
+        let public = keypair.public.clone();
+        PlaneEncryptionConfig::new(keypair, vec![public])
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let config = shared_config();
+        let a = join(1, 0, &config);
+        let b = join(1, 1, &config);
+        // Relays can't join the key exchange, so everything they inject is dropped.
+        let relay = join(1, 2, &shared_config());
+        exchange_keys(&[&a, &b]);
+
+        assert!(a.is_peer_keyed(1, EndpointAddr { endpoint: 1 }));
+        assert!(b.is_peer_keyed(1, EndpointAddr { endpoint: 0 }));
+        pollster::block_on(b.wait_for_peer(1, EndpointAddr { endpoint: 0 }));
+
+        let packet = encode_packet(1, 0, b"secret");
+        let forged = encode_packet(1, 0, b"forged");
+        let other_plane = encode_packet(2, 0, b"public");
+        let sealed = seal(&a.sealer(1).unwrap(), &packet);
+        let sealed_by_relay = seal(&relay.sealer(1).unwrap(), &forged);
+
+        let received = decrypt_bundle(
+            &b,
+            &[
+                sealed.clone(),
+                forged.clone(),
+                sealed_by_relay,
+                other_plane.clone(),
+            ],
+        );
+        let mut expected = packet.clone();
+        expected.extend_from_slice(&other_plane);
+        assert_eq!(received, expected);
+
+        // Replaying the packet later is rejected.
+        assert!(decrypt_bundle(&b, &[sealed]).is_empty());
+    }
+
+    #[test]
+    fn test_key_exchange_between_many_endpoints() {
+        let config = shared_config();
+        let a = join(1, 0, &config);
+        let b = join(1, 5, &config);
+        exchange_keys(&[&a, &b]);
+
+        // An endpoint joining later exchanges keys with everyone already on the plane.
+        let c = join(1, 3, &config);
+        exchange_keys(&[&a, &b, &c]);
+
+        for (keyring, endpoint) in [(&a, 0), (&b, 5), (&c, 3)] {
+            for peer in [0, 5, 3] {
+                if peer != endpoint {
+                    assert!(keyring.is_peer_keyed(1, EndpointAddr { endpoint: peer }));
+                }
+            }
+        }
+
+        let packet = encode_packet(1, 3, b"hello");
+        let sealed = seal(&c.sealer(1).unwrap(), &packet);
+        assert_eq!(decrypt_bundle(&a, std::slice::from_ref(&sealed)), packet);
+        assert_eq!(decrypt_bundle(&b, &[sealed]), packet);
+    }
+
+    #[test]
+    fn test_key_exchange_rejects_untrusted_peer() {
+        let a_keypair = NoiseKeypair::generate();
+        let b_keypair = NoiseKeypair::generate();
+        let a = join(
+            1,
+            0,
+            &PlaneEncryptionConfig::new(a_keypair, vec![NoiseKeypair::generate().public]),
+        );
+        let b = join(
+            1,
+            1,
+            &PlaneEncryptionConfig::new(b_keypair, vec![NoiseKeypair::generate().public]),
+        );
+        exchange_keys(&[&a, &b]);
+
+        assert!(!a.is_peer_keyed(1, EndpointAddr { endpoint: 1 }));
+        assert!(!b.is_peer_keyed(1, EndpointAddr { endpoint: 0 }));
+    }
+
+    #[test]
+    fn test_forged_key_exchange_keeps_existing_keys() {
+        let config = shared_config();
+        let a = join(1, 0, &config);
+        let b = join(1, 1, &config);
+        exchange_keys(&[&a, &b]);
+
+        // Someone without a trusted key rejoins the plane as `a` and tries to handshake with `b`,
+        // and announces itself to `a` as `b`.
+        let mallory_keypair = NoiseKeypair::generate();
+        let mallory_config =
+            PlaneEncryptionConfig::new(mallory_keypair, config.trusted_peers.clone());
+        let mallory_as_a = join(1, 0, &mallory_config);
+        exchange_keys(&[&mallory_as_a, &b]);
+        let mallory_as_b = join(1, 1, &mallory_config);
+        exchange_keys(&[&mallory_as_b, &a]);
+
+        // Both sessions survive.
+        assert!(a.is_peer_keyed(1, EndpointAddr { endpoint: 1 }));
+        assert!(b.is_peer_keyed(1, EndpointAddr { endpoint: 0 }));
+        let from_a = encode_packet(1, 0, b"from a");
+        let from_b = encode_packet(1, 1, b"from b");
+        let sealed = seal(&a.sealer(1).unwrap(), &from_a);
+        assert_eq!(decrypt_bundle(&b, &[sealed]), from_a);
+        let sealed = seal(&b.sealer(1).unwrap(), &from_b);
+        assert_eq!(decrypt_bundle(&a, &[sealed]), from_b);
+
+        // A genuine rejoin still replaces the old keys once the new handshake completes.
+        let new_a = join(1, 0, &config);
+        exchange_keys(&[&new_a, &b]);
+        let from_new_a = encode_packet(1, 0, b"from new a");
+        let sealed = seal(&new_a.sealer(1).unwrap(), &from_new_a);
+        assert_eq!(decrypt_bundle(&b, &[sealed]), from_new_a);
+    }
+}
@@ -1,8 +1,8 @@
 use core::future::Future;
 
 use crate::{
-    EndpointAddr, InterfaceBuilder, LocalSpawner, PacketSender, RoleSetup, RuntimeHandle,
-    WorkerContext, context_map::ModrpcContextTag,
+    EndpointAddr, InterfaceBuilder, LocalSpawner, PacketSender, RoleQueueConfig, RoleSetup,
+    RuntimeHandle, WorkerContext, context_map::ModrpcContextTag, worker::TaskTracker,
 };
 
 pub trait InterfaceSchema {
@@ -65,6 +65,7 @@ pub struct RoleSpawner {
     ) -> core::pin::Pin<Box<dyn Future<Output = ()> + 'a>>,
     worker_shutdown_signal: bab::SignalTree,
     role_shutdown_signal: bab::SignalTree,
+    tasks: TaskTracker,
 }
 
 impl RoleSpawner {
@@ -76,7 +77,7 @@ impl RoleSpawner {
         let worker_shutdown_signal = self.worker_shutdown_signal.clone();
         let role_shutdown_signal = self.role_shutdown_signal.clone();
         self.spawner
-            .spawn(async move {
+            .spawn(self.tasks.track(async move {
                 futures_lite::future::or(
                     future,
                     // TODO: we probably want to create a single thread-local shutdown signal per-role and
@@ -92,7 +93,7 @@ impl RoleSpawner {
                     ),
                 )
                 .await;
-            })
+            }))
             .unwrap();
     }
 
@@ -100,14 +101,14 @@ impl RoleSpawner {
         let worker_shutdown_signal = self.worker_shutdown_signal.clone();
         let role_shutdown_signal = self.role_shutdown_signal.clone();
         self.spawner
-            .spawn(async move {
+            .spawn(self.tasks.track(async move {
                 futures_lite::future::or(
                     role_shutdown_signal.wait(),
                     worker_shutdown_signal.wait(),
                 )
                 .await;
                 future.await;
-            })
+            }))
             .unwrap();
     }
 
@@ -133,6 +134,7 @@ pub fn run_role_worker<Role>(
     role_shutdown_signal: bab::SignalTree,
     endpoint_addr: EndpointAddr,
     packet_sender: PacketSender,
+    queue_config: RoleQueueConfig,
     config: &<Role as InterfaceRole>::Config,
     init: &<Role as InterfaceRole>::Init,
     start_fn: impl RoleStartFn<Role>,
@@ -146,6 +148,7 @@ where
         interval_fn: worker_cx.interval_fn,
         worker_shutdown_signal,
         role_shutdown_signal: role_shutdown_signal.clone(),
+        tasks: worker_cx.tasks.clone(),
     };
     // XXX: spawning this here is somewhat load-bearing for perf - this will be the first task
     // registered as a waiter for the role's shutdown signal, so it will be registered in waitq's
@@ -195,6 +198,7 @@ where
             role_id,
             endpoint_addr,
             role_shutdown_signal.clone(),
+            queue_config,
         );
         let (stubs, role_handle) = Role::setup_worker(&interface, &mut setup, config, init);
         start_fn(RoleWorkerContext {
@@ -1,15 +1,19 @@
+use std::collections::HashMap;
+
 use crate::{
-    ContextClass, Packet, RoleSpawner,
+    ContextClass, Packet, RoleQueueConfig, RoleSpawner, WorkerId,
     context_map::ModrpcContextTag,
     endpoint_proto::{EndpointAddr, TransmitPacket},
     interface_builder::InterfaceEvent,
     load_balancer,
+    metrics::{Counter, MetricsRegistry},
     packet_processor::{
         PACKET_PROCESSOR_SOURCE_ANY, PACKET_PROCESSOR_SOURCE_INTER_WORKER,
         PACKET_PROCESSOR_SOURCE_NEW,
     },
     packet_sender::PacketSender,
     worker::WorkerContext,
+    worker_set::WorkerSet,
 };
 
 pub struct RoleSetup<'a> {
@@ -20,6 +24,7 @@ pub struct RoleSetup<'a> {
     endpoint_addr: EndpointAddr,
     worker_cx: &'a WorkerContext,
     shutdown_signal: bab::SignalTree,
+    queue_config: RoleQueueConfig,
     object_path: Vec<&'static str>,
 }
 
@@ -33,6 +38,7 @@ impl<'a> RoleSetup<'a> {
         role_id: u64,
         endpoint_addr: EndpointAddr,
         shutdown_signal: bab::SignalTree,
+        queue_config: RoleQueueConfig,
     ) -> Self {
         Self {
             spawner,
@@ -42,6 +48,7 @@ impl<'a> RoleSetup<'a> {
             role_id,
             endpoint_addr,
             shutdown_signal,
+            queue_config,
             object_path: vec![root_object_name],
         }
     }
@@ -74,6 +81,14 @@ impl<'a> RoleSetup<'a> {
         self.worker_cx
     }
 
+    fn decode_failures(&self) -> Counter {
+        decode_failures(self.worker_cx.rt().metrics())
+    }
+
+    pub fn queue_config(&self) -> RoleQueueConfig {
+        self.queue_config
+    }
+
     pub fn push_object_path(&mut self, name: &'static str) {
         self.object_path.push(name);
     }
@@ -96,6 +111,13 @@ impl<'a> RoleSetup<'a> {
     where
         T: mproto::Encode + for<'d> mproto::Decode<'d>,
     {
+        // Channel routes are relative to the role, so leave out the root object name.
+        let object_path = self.get_object_path(spec.name);
+        let relative_path = object_path
+            .split_once('.')
+            .map_or(object_path.as_str(), |(_, path)| path);
+        self.packet_sender.assign_topic(spec.topic, relative_path);
+
         EventTx::new(self.packet_sender.clone(), self.plane_id, spec.topic)
     }
 
@@ -188,10 +210,13 @@ impl<T: mproto::Encode> EventTx<T> {
             .await;
     }
 
-    pub async unsafe fn send_buffer(&self, buffer: bab::BufferPtr) {
+    pub async unsafe fn send_buffer(
+        &self,
+        buffer: bab::BufferPtr,
+    ) -> Result<(), crate::SendBufferError> {
         self.packet_sender
             .send_buffer(self.plane_id, self.topic, buffer)
-            .await;
+            .await
     }
 
     pub fn cast<NewT>(self) -> EventTx<NewT>
@@ -240,8 +265,17 @@ impl<T> Clone for EventTx<T> {
     }
 }
 
+fn decode_failures(metrics: &MetricsRegistry) -> Counter {
+    metrics.counter(
+        "modrpc_decode_failures",
+        "Received packets that event handlers dropped because they failed to decode.",
+        &[],
+    )
+}
+
 pub async fn handle_events_async<T: mproto::Owned>(
     tracer: &probius::TraceSource,
+    decode_failures: Counter,
     mut rx: localq::mpsc::Receiver<Packet>,
     mut handler: impl AsyncFnMut(EndpointAddr, T::Lazy<'_>),
 ) where
@@ -257,6 +291,7 @@ pub async fn handle_events_async<T: mproto::Owned>(
                 // Decode packet header
                 let Ok(header) = mproto::decode_value::<TransmitPacket>(payload_buf.as_ref())
                 else {
+                    decode_failures.inc();
                     probius::trace_label("invalid-header");
                     probius::trace_branch_end();
                     return;
@@ -266,6 +301,7 @@ pub async fn handle_events_async<T: mproto::Owned>(
 
                 // Decode payload
                 let Ok(payload) = mproto::decode_value(&payload_buf.as_ref()[header_len..]) else {
+                    decode_failures.inc();
                     probius::trace_label("invalid-payload");
                     probius::trace_branch_end();
                     return;
@@ -283,6 +319,7 @@ pub async fn handle_events_async<T: mproto::Owned>(
 
 pub async fn handle_untyped_async(
     tracer: &probius::TraceSource,
+    decode_failures: Counter,
     mut rx: localq::mpsc::Receiver<Packet>,
     mut handler: impl AsyncFnMut(EndpointAddr, Packet),
 ) {
@@ -295,6 +332,7 @@ pub async fn handle_untyped_async(
 
                 // Decode packet header
                 let Ok(header) = mproto::decode_value::<TransmitPacket>(packet.as_ref()) else {
+                    decode_failures.inc();
                     probius::trace_label("invalid-header");
                     probius::trace_branch_end();
                     return;
@@ -335,6 +373,7 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
         setup: &'a RoleSetup<'a>,
         mut event_handler: impl FnMut(EndpointAddr, T) + 'static,
     ) -> InlineRxRouterSetup<'a, impl FnMut(EndpointAddr, &Packet) + 'static> {
+        let decode_failures = setup.decode_failures();
         InlineRxRouterSetup {
             setup,
             object_path: self.object_path,
@@ -343,6 +382,7 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
                 use mproto::BaseLen;
 
                 let Ok(payload) = mproto::decode_value(&packet[TransmitPacket::BASE_LEN..]) else {
+                    decode_failures.inc();
                     return;
                 };
 
@@ -356,6 +396,7 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
         setup: &'a RoleSetup<'a>,
         mut event_handler: impl FnMut(EndpointAddr, T::Lazy<'_>) + 'static,
     ) -> InlineRxRouterSetup<'a, impl FnMut(EndpointAddr, &Packet) + 'static> {
+        let decode_failures = setup.decode_failures();
         InlineRxRouterSetup {
             setup,
             object_path: self.object_path,
@@ -364,6 +405,7 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
                 use mproto::BaseLen;
 
                 let Ok(payload) = mproto::decode_value(&packet[TransmitPacket::BASE_LEN..]) else {
+                    decode_failures.inc();
                     return;
                 };
 
@@ -420,12 +462,14 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
         setup: &'a RoleSetup<'a>,
         handler: impl AsyncFnMut(EndpointAddr, T::Lazy<'_>) + 'static,
     ) -> QueuedEventRxRouterSetup<'a> {
-        let (queue_tx, queue_rx) = localq::mpsc::channel(64);
+        let (queue_tx, queue_rx) =
+            localq::mpsc::channel(setup.queue_config.handler_queue_capacity);
 
+        let decode_failures = setup.decode_failures();
         setup.worker_context().spawn_traced(
             &format!("queued:{}", self.object_path),
             core::time::Duration::from_millis(1000),
-            async move |tracer| handle_events_async(tracer, queue_rx, handler).await,
+            async move |tracer| handle_events_async(tracer, decode_failures, queue_rx, handler).await,
         );
 
         QueuedEventRxRouterSetup {
@@ -441,12 +485,16 @@ impl<T: mproto::Owned> EventRxBuilder<T> {
         setup: &'a RoleSetup<'a>,
         event_handler: impl AsyncFnMut(EndpointAddr, Packet) + 'static,
     ) -> QueuedEventRxRouterSetup<'a> {
-        let (queue_tx, queue_rx) = localq::mpsc::channel(64);
+        let (queue_tx, queue_rx) =
+            localq::mpsc::channel(setup.queue_config.handler_queue_capacity);
 
+        let decode_failures = setup.decode_failures();
         setup.worker_context().spawn_traced(
             &self.object_path,
             core::time::Duration::from_millis(1000),
-            async move |tracer| handle_untyped_async(tracer, queue_rx, event_handler).await,
+            async move |tracer| {
+                handle_untyped_async(tracer, decode_failures, queue_rx, event_handler).await
+            },
         );
 
         QueuedEventRxRouterSetup {
@@ -479,10 +527,7 @@ impl<'a> QueuedEventRxRouterSetup<'a> {
     pub fn load_balance(self) {
         load_balancer::spawn_load_balancer(
             &self.setup,
-            load_balancer::LoadBalancerConfig {
-                rx_burst_size: 32,
-                tx_burst_size: 32,
-            },
+            load_balancer::LoadBalancerConfig::new(&self.setup.queue_config),
             self.setup.plane_id,
             self.topic,
             self.object_path,
@@ -568,19 +613,130 @@ where
     }
 }
 
-struct TopicSubscriptions {
-    worker_mask: u64,
+/// The workers subscribed to each topic, kept by the runtime so that workers joining it later
+/// can redirect packets to the existing subscribers.
+#[derive(Default)]
+pub(crate) struct TopicSubscriptions {
+    // (plane id, topic) -> subscription
+    topics: HashMap<(u32, u32), TopicSubscription>,
 }
 
-impl ContextClass for TopicSubscriptions {
-    type Key = (u32, u32); // (plane id, topic)
-    type Params = ();
+pub(crate) struct TopicSubscription {
+    pub object_path: String,
+    pub workers: WorkerSet,
+}
+
+impl TopicSubscriptions {
+    /// Returns whether `worker_id` was newly subscribed to the topic.
+    fn subscribe(&mut self, object_path: &str, plane_id: u32, topic: u32, worker_id: WorkerId) -> bool {
+        let subscription = self
+            .topics
+            .entry((plane_id, topic))
+            .or_insert_with(|| TopicSubscription {
+                object_path: object_path.to_string(),
+                workers: WorkerSet::new(),
+            });
+        subscription.workers.insert(worker_id)
+    }
+
+    pub fn remove_worker(&mut self, worker_id: WorkerId) {
+        self.topics.retain(|_, subscription| {
+            subscription.workers.remove(worker_id);
+            !subscription.workers.is_empty()
+        });
+    }
 
-    fn new(_: &Self::Params) -> Self {
-        TopicSubscriptions { worker_mask: 0 }
+    pub fn iter(&self) -> impl Iterator<Item = (&(u32, u32), &TopicSubscription)> {
+        self.topics.iter()
     }
 }
 
+/// Redirects for a worker's new topic subscriptions that are waiting to be installed on the
+/// other workers. A single task per worker installs them in batches, so setting up a role never
+/// blocks the worker thread.
+#[derive(Default)]
+pub(crate) struct PendingRedirects {
+    redirects: Vec<TopicRedirect>,
+    // Notified once everything queued before they were registered has been installed.
+    waiters: Vec<oneshot::Sender<()>>,
+    installing: bool,
+}
+
+#[derive(Clone)]
+struct TopicRedirect {
+    object_path: String,
+    plane_id: u32,
+    topic: u32,
+}
+
+impl PendingRedirects {
+    fn push(worker_cx: &WorkerContext, redirect: TopicRedirect) {
+        let mut pending = worker_cx.pending_redirects.borrow_mut();
+        pending.redirects.push(redirect);
+        if pending.installing {
+            return;
+        }
+        pending.installing = true;
+
+        let pending = worker_cx.pending_redirects.clone();
+        let rt = worker_cx.rt().clone();
+        let worker_id = worker_cx.worker_id();
+        worker_cx.spawn(async move {
+            loop {
+                let (redirects, waiters) = {
+                    let mut pending = pending.borrow_mut();
+                    if pending.redirects.is_empty() {
+                        pending.installing = false;
+                        for waiter in pending.waiters.drain(..) {
+                            let _ = waiter.send(());
+                        }
+                        break;
+                    }
+                    (
+                        core::mem::take(&mut pending.redirects),
+                        core::mem::take(&mut pending.waiters),
+                    )
+                };
+
+                rt.run_on_all_other_workers(move |worker_cx| {
+                    for redirect in &redirects {
+                        worker_cx.add_redirect_to_worker(
+                            &redirect.object_path,
+                            // Only broadcast packets that are new to this endpoint to avoid
+                            // re-broadcasting.
+                            PACKET_PROCESSOR_SOURCE_NEW,
+                            redirect.plane_id,
+                            redirect.topic,
+                            worker_id,
+                        );
+                    }
+                })
+                .await;
+
+                for waiter in waiters {
+                    let _ = waiter.send(());
+                }
+            }
+        });
+    }
+
+    pub fn installed(&mut self) -> impl Future<Output = ()> + Send + use<> {
+        let (installed_tx, installed_rx) = oneshot::channel();
+        if self.installing {
+            self.waiters.push(installed_tx);
+        } else {
+            let _ = installed_tx.send(());
+        }
+        async move {
+            // The installing task only drops the sender early if its worker is shutting down.
+            let _ = installed_rx.await;
+        }
+    }
+}
+
+/// Subscribe the current worker to a topic. The other workers redirect packets for the topic to
+/// this worker once they have installed the subscription, which happens in the background - see
+/// `WorkerContext::subscriptions_installed`.
 pub fn add_topic_subscription(
     worker_cx: &WorkerContext,
     object_path: &str,
@@ -588,37 +744,21 @@ pub fn add_topic_subscription(
     topic: u32,
 ) {
     let worker_id = worker_cx.worker_id();
-    let needs_handler = worker_cx.with_global(
-        (plane_id, topic),
-        &(),
-        |topic_subscriptions: &mut TopicSubscriptions| {
-            let local_worker_mask = 1 << worker_id.0;
-            if topic_subscriptions.worker_mask & local_worker_mask != 0 {
-                false
-            } else {
-                topic_subscriptions.worker_mask |= local_worker_mask;
-                true
-            }
-        },
-    );
+    let needs_handler = worker_cx
+        .rt()
+        .topic_subscriptions()
+        .lock()
+        .expect("modrpc topic subscriptions lock poisoned")
+        .subscribe(object_path, plane_id, topic, worker_id);
 
     if needs_handler {
-        // Subscribe the current worker to receive packets for this topic unconditionally.
-
-        // TODO this is probably too janky for use-cases where there's a high rate of new planes
-        // being established (e.g. lots of new connections coming in, and a separate plane per
-        // connection). Could we have an unbounded fire-and-forget command queue based on an
-        // intrusive linked list? Or otherwise make plane setup be async?
-        let object_path = object_path.to_string();
-        pollster::block_on(worker_cx.rt().run_on_all_other_workers(move |worker_cx| {
-            worker_cx.add_redirect_to_worker(
-                &object_path,
-                // Only broadcast packets that are new to this endpoint to avoid re-broadcasting.
-                PACKET_PROCESSOR_SOURCE_NEW,
+        PendingRedirects::push(
+            worker_cx,
+            TopicRedirect {
+                object_path: object_path.to_string(),
                 plane_id,
                 topic,
-                worker_id,
-            );
-        }));
+            },
+        );
     }
 }
@@ -1,6 +1,5 @@
 use std::{
-    cell::{RefCell, UnsafeCell},
-    mem::MaybeUninit,
+    cell::RefCell,
     rc::Rc,
     sync::{
         Arc, Mutex,
@@ -9,16 +8,22 @@ use std::{
 };
 
 use crate::{
-    InterfaceRole, LocalSpawner, Packet, PacketSender, SingleChannelSender,
+    InterfaceRole, LocalSpawner, MultiChannelSender, Packet, PacketCapture, PacketSender,
+    SendPacket, SingleChannelSender, TxChannel,
     context_map::{ContextMap, LocalContextMap, ModrpcContextTag},
     endpoint_proto::EndpointAddr,
-    packet_processor::PacketProcessor,
+    metrics::MetricsRegistry,
+    packet_processor::{PACKET_PROCESSOR_SOURCE_NEW, PacketProcessor},
     role::{RoleStartFn, run_role_worker},
+    role_setup::TopicSubscriptions,
+    runtime_config::{RoleQueueConfig, RuntimeConfig, TransportConfig},
+    thread_slots::ThreadSlots,
     transport::{TransportBuilder, TransportContext, TransportHandle},
     worker::{
-        WorkerCommand, WorkerContext, WorkerId, WorkerSpawner, build_inter_worker_batchers,
-        spawn_worker,
+        TaskTracker, WorkerCommand, WorkerContext, WorkerId, WorkerSpawner,
+        build_inter_worker_batchers, spawn_worker,
     },
+    worker_set::WorkerSet,
 };
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -31,23 +36,32 @@ impl WorkerGroup {
     pub fn worker_count(&self) -> u16 {
         self.worker_count
     }
+
+    pub fn worker_ids(&self) -> impl Iterator<Item = WorkerId> + use<> {
+        let first_worker_index = self.first_worker_index;
+        (0..self.worker_count).map(move |i| WorkerId(first_worker_index + i))
+    }
+
+    pub fn contains(&self, worker_id: WorkerId) -> bool {
+        worker_id.0
+            .checked_sub(self.first_worker_index)
+            .is_some_and(|i| i < self.worker_count)
+    }
 }
 
 struct LocalWorker {
     spawner: LocalSpawner,
     shutdown_signal: bab::SignalTree,
-    local_packet_tx: localq::mpsc::Sender<Packet>,
+    tasks: TaskTracker,
     locals: Rc<RefCell<LocalContextMap>>,
 }
 
-struct BuilderLocalWorker {
-    local_worker: LocalWorker,
-    local_packet_rx: localq::mpsc::Receiver<Packet>,
-}
-
 pub struct RuntimeBuilder {
+    config: RuntimeConfig,
+    metrics: MetricsRegistry,
+    capture: Option<PacketCapture>,
     worker_groups: Vec<WorkerGroup>,
-    local_worker: Option<BuilderLocalWorker>,
+    local_worker: Option<LocalWorker>,
     next_worker_index: u16,
 }
 
@@ -55,6 +69,9 @@ impl RuntimeBuilder {
     /// Start building a runtime that is not used on the current thread.
     pub fn new_no_local() -> Self {
         Self {
+            config: RuntimeConfig::default(),
+            metrics: MetricsRegistry::new(),
+            capture: None,
             worker_groups: Vec::new(),
             local_worker: None,
             next_worker_index: 0,
@@ -63,26 +80,41 @@ impl RuntimeBuilder {
 
     /// Start building a runtime can be used on the current thread.
     pub fn new_with_local(spawner: LocalSpawner) -> Self {
-        // TODO configurable capacity
-        let (local_packet_tx, local_packet_rx) = localq::mpsc::channel(64);
         let locals = Rc::new(RefCell::new(LocalContextMap::new()));
         let shutdown_signal = bab::SignalTree::new();
 
         Self {
+            config: RuntimeConfig::default(),
+            metrics: MetricsRegistry::new(),
+            capture: None,
             worker_groups: Vec::new(),
-            local_worker: Some(BuilderLocalWorker {
-                local_worker: LocalWorker {
-                    spawner,
-                    shutdown_signal,
-                    local_packet_tx,
-                    locals,
-                },
-                local_packet_rx,
+            local_worker: Some(LocalWorker {
+                spawner,
+                shutdown_signal,
+                tasks: TaskTracker::new(),
+                locals,
             }),
             next_worker_index: 1,
         }
     }
 
+    pub fn with_config(mut self, config: RuntimeConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Record the runtime's metrics in an existing registry, e.g. one shared with other runtimes.
+    pub fn with_metrics(mut self, metrics: MetricsRegistry) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Record the bundles sent and received by every transport added to the runtime.
+    pub fn with_capture(mut self, capture: PacketCapture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
     pub fn new_worker_group(&mut self, worker_count: u16) -> WorkerGroup {
         let worker_group = WorkerGroup {
             first_worker_index: self.next_worker_index,
@@ -98,244 +130,346 @@ impl RuntimeBuilder {
     pub fn start<E: modrpc_executor::ModrpcExecutor>(
         self,
     ) -> (RuntimeHandle, RuntimeShutdownHandle) {
-        let rt_shutdown_signal = bab::SignalTree::new();
-        let globals = Arc::new(Mutex::new(ContextMap::new()));
         let mut worker_handles = Vec::new();
-        // Start at 1 to account for a potential local worker.
-        let remote_worker_count = self
-            .worker_groups
-            .iter()
-            .map(|g| g.worker_count)
-            .sum::<u16>();
-        let worker_count = if self.local_worker.is_some() {
-            1 + remote_worker_count
-        } else {
-            remote_worker_count
-        };
-
-        let command_queue_capacity = 8;
-        let mut worker_command_receivers = vec![];
+        let mut worker_queues = Vec::new();
 
         // Create handle for local worker.
-        if let Some(BuilderLocalWorker { local_worker, .. }) = &self.local_worker {
-            let (command_tx, command_rx) = burstq::mpmc(command_queue_capacity);
-            worker_command_receivers.push(Some(command_rx));
-            worker_handles.push(WorkerHandle {
-                command_tx,
-                shutdown_signal: local_worker.shutdown_signal.clone(),
-            });
-
-            rt_shutdown_signal.add_child(local_worker.shutdown_signal.clone());
+        if let Some(local_worker) = &self.local_worker {
+            let (worker_handle, queues) =
+                WorkerHandle::new(local_worker.shutdown_signal.clone(), &self.config);
+            worker_handles.push(Some(worker_handle));
+            worker_queues.push(queues);
         }
 
         // Create handles for remote workers.
-        for _ in 0..remote_worker_count {
-            let (command_tx, command_rx) = burstq::mpmc(command_queue_capacity);
-
-            let shutdown_signal = bab::SignalTree::new();
-            rt_shutdown_signal.add_child(shutdown_signal.clone());
-
-            worker_command_receivers.push(Some(command_rx));
-            worker_handles.push(WorkerHandle {
-                command_tx,
-                shutdown_signal: shutdown_signal.clone(),
-            });
+        let remote_worker_ids: Vec<WorkerId> = self
+            .worker_groups
+            .iter()
+            .flat_map(|g| g.worker_ids())
+            .collect();
+        for _ in &remote_worker_ids {
+            let (worker_handle, queues) = WorkerHandle::new(bab::SignalTree::new(), &self.config);
+            worker_handles.push(Some(worker_handle));
+            worker_queues.push(queues);
         }
 
         // Create the runtime handle.
         let rt = RuntimeHandle {
             inner: Arc::new(RuntimeHandleInner {
+                config: self.config,
+                metrics: self.metrics,
+                capture: self.capture,
                 next_role_id: AtomicU64::new(0),
-                globals: globals.clone(),
-                worker_handles,
-                worker_contexts: (0..thid::DEFAULT_MAX_THREADS)
-                    .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
-                    .collect(),
+                globals: Arc::new(Mutex::new(ContextMap::new())),
+                transports: Mutex::new(Vec::new()),
+                topic_subscriptions: Mutex::new(TopicSubscriptions::default()),
+                worker_handles: Mutex::new(worker_handles),
+                free_worker_ids: Mutex::new(WorkerSet::new()),
+                worker_threads: Mutex::new(Vec::new()),
+                idle_worker_threads: Mutex::new(Vec::new()),
+                start_worker_thread_fn: start_worker_thread::<E>,
+                worker_contexts: ThreadSlots::new(),
             }),
         };
 
-        // TODO configurable
-        let inter_worker_queue_capacity = 256;
-        let max_inter_worker_batch_size = 32;
-
-        let mut inter_worker_senders = Vec::with_capacity(worker_count as usize);
-        let mut inter_worker_receivers = Vec::with_capacity(worker_count as usize);
-        for _ in 0..worker_count {
-            let (inter_worker_tx, inter_worker_rx) = burstq::mpmc(inter_worker_queue_capacity);
-            inter_worker_senders.push(inter_worker_tx);
-            inter_worker_receivers.push(Some(inter_worker_rx));
-        }
+        let mut worker_queues = worker_queues.into_iter();
 
-        // Create handle for local worker and start it.
-        if let Some(BuilderLocalWorker {
-            local_worker,
-            local_packet_rx,
-        }) = self.local_worker
-        {
-            let inter_worker_batcher_senders = build_inter_worker_batchers(
-                &WorkerSpawner {
-                    spawner: local_worker.spawner.clone(),
-                    shutdown_signal: local_worker.shutdown_signal.clone(),
+        // Start the local worker.
+        let mut local_tasks = None;
+        if let Some(local_worker) = self.local_worker {
+            local_tasks = Some(local_worker.tasks.clone());
+            start_worker::<E>(
+                &rt,
+                WorkerId(0),
+                WorkerSpawner {
+                    spawner: local_worker.spawner,
+                    shutdown_signal: local_worker.shutdown_signal,
+                    tasks: local_worker.tasks,
                     interval_fn: WorkerContext::interval_loop::<E>,
                 },
-                WorkerId(0),
-                max_inter_worker_batch_size,
-                &inter_worker_senders,
-            );
-            let pp = Rc::new(PacketProcessor::new(
-                local_worker.local_packet_tx.clone(),
-                inter_worker_batcher_senders,
-            ));
-            let worker_context = WorkerContext::new::<E>(
-                rt.clone(),
-                local_worker.spawner.clone(),
-                local_worker.shutdown_signal.clone(),
-                WorkerId(0),
-                worker_count,
-                pp,
-                local_worker.local_packet_tx.clone(),
-                local_worker.locals.clone(),
-                globals.clone(),
+                local_worker.locals,
+                localq::mpsc::channel(rt.config().local_packet_queue_capacity),
+                worker_queues.next().expect("local worker queues"),
             );
+        }
 
-            spawn_worker::<E>(
-                worker_context.clone(),
-                local_packet_rx,
-                worker_command_receivers[0]
-                    .take()
-                    .expect("worker command receiver"),
-                inter_worker_receivers[0]
-                    .take()
-                    .expect("inter-worker packet receiver"),
-            );
-
-            let map_worker_context = unsafe {
-                &mut *rt.inner.worker_contexts[thid::ThreadId::current().as_usize()].get()
-            };
-            map_worker_context.write(worker_context);
-        }
-
-        let mut worker_join_handles = Vec::new();
-        for worker_group in &self.worker_groups {
-            for i in 0..worker_group.worker_count {
-                let worker_index = worker_group.first_worker_index + i;
-                let worker_id = WorkerId(worker_index);
-
-                let rt = rt.clone();
-                let globals = globals.clone();
-                let inter_worker_senders = inter_worker_senders.clone();
-                let (startup_done_tx, startup_done_rx) = oneshot::channel();
-
-                let command_rx = worker_command_receivers[worker_index as usize]
-                    .take()
-                    .expect("worker command receiver");
-                let inter_rx = inter_worker_receivers[worker_index as usize]
-                    .take()
-                    .expect("inter-worker packet receiver");
-
-                let join_handle = std::thread::Builder::new()
-                    .name(format!("worker-{}", worker_id.0))
-                    .spawn(move || {
-                        probius::enter_component(&format!("worker-{}", worker_id.0), move || {
-                            let mut ex = E::new();
-                            // TODO configurable capacity
-                            let (local_packet_tx, local_packet_rx) = localq::mpsc::channel(64);
-                            let locals = Rc::new(RefCell::new(LocalContextMap::new()));
-
-                            let shutdown_signal = rt.inner.worker_handles[worker_index as usize]
-                                .shutdown_signal
-                                .clone();
-
-                            let inter_worker_batcher_senders = build_inter_worker_batchers(
-                                &WorkerSpawner {
-                                    spawner: ex.spawner(),
-                                    shutdown_signal: shutdown_signal.clone(),
-                                    interval_fn: WorkerContext::interval_loop::<E>,
-                                },
-                                worker_id,
-                                max_inter_worker_batch_size,
-                                &inter_worker_senders,
-                            );
-                            let pp = Rc::new(PacketProcessor::new(
-                                local_packet_tx.clone(),
-                                inter_worker_batcher_senders,
-                            ));
-
-                            let worker_context = WorkerContext::new::<E>(
-                                rt.clone(),
-                                ex.spawner(),
-                                shutdown_signal.clone(),
-                                worker_id,
-                                worker_count,
-                                pp,
-                                local_packet_tx,
-                                locals,
-                                globals,
-                            );
-
-                            let map_worker_context = unsafe {
-                                &mut *rt.inner.worker_contexts[thid::ThreadId::current().as_usize()]
-                                    .get()
-                            };
-                            map_worker_context.write(worker_context.clone());
-
-                            spawn_worker::<E>(
-                                worker_context.clone(),
-                                local_packet_rx,
-                                command_rx,
-                                inter_rx,
-                            );
-
-                            startup_done_tx
-                                .send(())
-                                .expect("modrpc::RuntimeBuilder::start worker startup oneshot send");
-
-                            // TODO this is not sufficient - need to add a local semaphore to wait
-                            // for all spawned tasks to be polled to completion.
-                            ex.run_until(shutdown_signal.wait_owned());
-
-                            // Drop RuntimeHandle worker_contexts entry
-                            unsafe {
-                                let map_worker_context = &mut *rt.inner.worker_contexts
-                                    [thid::ThreadId::current().as_usize()]
-                                .get();
-                                map_worker_context.assume_init_drop();
-                            }
-                        });
-                    })
-                    .expect("spawn modrpc worker thread");
-
-                // Ensure the worker_contexts map entry was inserted.
-                startup_done_rx
-                    .recv()
-                    .expect("modrpc::RuntimeBuilder::start worker startup oneshot recv");
-
-                worker_join_handles.push(join_handle);
-            }
+        // Start the remote workers.
+        for (worker_id, queues) in remote_worker_ids.into_iter().zip(worker_queues) {
+            start_worker_thread::<E>(&rt, worker_id, queues);
         }
 
         let rt_shutdown = RuntimeShutdownHandle {
-            rt_shutdown_signal,
-            worker_join_handles,
+            rt: rt.clone(),
+            local_tasks,
         };
 
         (rt, rt_shutdown)
     }
 }
 
+/// The first of `worker_count` consecutive IDs in `free_worker_ids`, if there are that many.
+fn first_free_run(free_worker_ids: &WorkerSet, worker_count: u16) -> Option<u16> {
+    let mut run_start = 0;
+    let mut run_length = 0;
+    for WorkerId(worker_index) in free_worker_ids.iter() {
+        if run_length > 0 && worker_index == run_start + run_length {
+            run_length += 1;
+        } else {
+            run_start = worker_index;
+            run_length = 1;
+        }
+        if run_length == worker_count {
+            return Some(run_start);
+        }
+    }
+    None
+}
+
+/// Create a worker's context on the current thread and spawn its tasks.
+fn start_worker<E: modrpc_executor::ModrpcExecutor>(
+    rt: &RuntimeHandle,
+    worker_id: WorkerId,
+    spawner: WorkerSpawner,
+    locals: Rc<RefCell<LocalContextMap>>,
+    (local_packet_tx, local_packet_rx): (
+        localq::mpsc::Sender<Packet>,
+        localq::mpsc::Receiver<Packet>,
+    ),
+    queues: WorkerQueues,
+) {
+    let inter_worker_senders: Vec<_> = rt
+        .inner
+        .worker_handles
+        .lock()
+        .expect("modrpc worker handles lock poisoned")
+        .iter()
+        .map(|worker| worker.as_ref().map(|worker| worker.inter_worker_tx.clone()))
+        .collect();
+    let inter_worker_batcher_senders = build_inter_worker_batchers(
+        &spawner,
+        worker_id,
+        rt.config().max_inter_worker_batch_size,
+        &inter_worker_senders,
+        rt.metrics(),
+    );
+    let pp = Rc::new(PacketProcessor::new(
+        local_packet_tx.clone(),
+        inter_worker_batcher_senders,
+        rt.metrics(),
+    ));
+
+    let worker_context = WorkerContext::new::<E>(
+        rt.clone(),
+        spawner.spawner,
+        spawner.shutdown_signal,
+        spawner.tasks,
+        worker_id,
+        pp,
+        local_packet_tx,
+        locals,
+        rt.inner.globals.clone(),
+    );
+
+    unsafe {
+        rt.inner.worker_contexts.set(Some(worker_context.clone()));
+    }
+
+    spawn_worker::<E>(
+        worker_context.clone(),
+        local_packet_rx,
+        queues.command_rx,
+        queues.inter_worker_rx,
+    );
+
+    rt.catch_up_worker(&worker_context);
+}
+
+/// Start a remote worker on a thread of its own, reusing the thread of a removed worker if
+/// there is one. Returns once the worker is running.
+fn start_worker_thread<E: modrpc_executor::ModrpcExecutor>(
+    rt: &RuntimeHandle,
+    worker_id: WorkerId,
+    queues: WorkerQueues,
+) {
+    let idle_worker_thread = rt
+        .inner
+        .idle_worker_threads
+        .lock()
+        .expect("modrpc idle worker threads lock poisoned")
+        .pop();
+    let worker_thread = idle_worker_thread.unwrap_or_else(|| spawn_worker_thread::<E>(worker_id));
+
+    let (startup_done_tx, startup_done_rx) = oneshot::channel();
+    worker_thread
+        .assignments
+        .send(WorkerAssignment {
+            rt: rt.clone(),
+            worker_id,
+            queues,
+            startup_done_tx,
+        })
+        .expect("modrpc worker thread exited");
+
+    // Ensure the worker_contexts map entry was inserted.
+    startup_done_rx
+        .recv()
+        .expect("modrpc::RuntimeBuilder::start worker startup oneshot recv");
+
+    rt.inner
+        .worker_threads
+        .lock()
+        .expect("modrpc worker threads lock poisoned")
+        .push((worker_id, worker_thread));
+}
+
+/// A remote worker for a worker thread to run.
+struct WorkerAssignment {
+    rt: RuntimeHandle,
+    worker_id: WorkerId,
+    queues: WorkerQueues,
+    startup_done_tx: oneshot::Sender<()>,
+}
+
+/// A thread that runs remote workers one after another. It exits once its `WorkerThread` is
+/// dropped.
+struct WorkerThread {
+    join_handle: std::thread::JoinHandle<()>,
+    assignments: std::sync::mpsc::Sender<WorkerAssignment>,
+    // Receives once the thread's current worker has stopped and dropped its `RuntimeHandle`.
+    stopped: std::sync::mpsc::Receiver<()>,
+}
+
+impl WorkerThread {
+    /// Wait for the thread's current worker to stop.
+    fn wait_stopped(&self) {
+        self.stopped
+            .recv()
+            .expect("modrpc worker thread exited while running a worker");
+    }
+
+    fn join(self) {
+        drop(self.assignments);
+        self.join_handle.join().unwrap();
+    }
+}
+
+/// The thread is named after the first worker it runs.
+fn spawn_worker_thread<E: modrpc_executor::ModrpcExecutor>(worker_id: WorkerId) -> WorkerThread {
+    let (assignments_tx, assignments_rx) = std::sync::mpsc::channel::<WorkerAssignment>();
+    let (stopped_tx, stopped_rx) = std::sync::mpsc::channel();
+
+    let join_handle = std::thread::Builder::new()
+        .name(format!("worker-{}", worker_id.0))
+        .spawn(move || {
+            while let Ok(assignment) = assignments_rx.recv() {
+                let component = format!("worker-{}", assignment.worker_id.0);
+                probius::enter_component(&component, move || run_worker_thread::<E>(assignment));
+                if stopped_tx.send(()).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("spawn modrpc worker thread");
+
+    WorkerThread {
+        join_handle,
+        assignments: assignments_tx,
+        stopped: stopped_rx,
+    }
+}
+
+fn run_worker_thread<E: modrpc_executor::ModrpcExecutor>(
+    WorkerAssignment {
+        rt,
+        worker_id,
+        queues,
+        startup_done_tx,
+    }: WorkerAssignment,
+) {
+    let shutdown_signal = rt.get_worker(worker_id).shutdown_signal;
+    let mut ex = E::new();
+    let local_packet_channel = localq::mpsc::channel(rt.config().local_packet_queue_capacity);
+    let locals = Rc::new(RefCell::new(LocalContextMap::new()));
+    let tasks = TaskTracker::new();
+
+    start_worker::<E>(
+        &rt,
+        worker_id,
+        WorkerSpawner {
+            spawner: ex.spawner(),
+            shutdown_signal: shutdown_signal.clone(),
+            tasks: tasks.clone(),
+            interval_fn: WorkerContext::interval_loop::<E>,
+        },
+        locals,
+        local_packet_channel,
+        queues,
+    );
+
+    startup_done_tx
+        .send(())
+        .expect("modrpc::RuntimeBuilder::start worker startup oneshot send");
+
+    // Every task spawned on the worker is raced against the shutdown
+    // signal, so they all exit soon after it's notified.
+    ex.run_until(async {
+        shutdown_signal.wait().await;
+        tasks.idle().await;
+    });
+
+    // Drop RuntimeHandle worker_contexts entry
+    unsafe {
+        rt.inner.worker_contexts.set(None);
+    }
+}
+
 pub struct RuntimeShutdownHandle {
-    rt_shutdown_signal: bab::SignalTree,
-    worker_join_handles: Vec<std::thread::JoinHandle<()>>,
+    rt: RuntimeHandle,
+    local_tasks: Option<TaskTracker>,
 }
 
 impl RuntimeShutdownHandle {
+    /// Close every transport, stopping the roles running on them, then stop every worker. Resolves
+    /// once all tasks spawned through `WorkerContext`, `WorkerSpawner` and `RoleSpawner` have
+    /// exited and the worker threads have been joined. With a local worker, this must be awaited
+    /// on the local worker's thread, outside of any task spawned on the runtime.
+    ///
+    /// Everything written before `shutdown` is called is flushed, and transports that support it
+    /// get up to `RuntimeConfig::shutdown_drain_timeout` to write it out before they're closed.
     pub async fn shutdown(self) {
-        // TODO wait for local worker to shutdown
+        self.rt.close_transports().await;
+        // Workers are notified one by one rather than through a parent signal - a worker being
+        // removed from the runtime has its signal notified on its own.
+        for (_, worker) in self.rt.worker_handles() {
+            worker.shutdown_signal.notify();
+        }
 
-        self.rt_shutdown_signal.notify();
+        if let Some(local_tasks) = &self.local_tasks {
+            local_tasks.idle().await;
+        }
 
-        for worker_join_handle in self.worker_join_handles {
-            worker_join_handle.join().unwrap();
+        let worker_threads = core::mem::take(
+            &mut *self
+                .rt
+                .inner
+                .worker_threads
+                .lock()
+                .expect("modrpc worker threads lock poisoned"),
+        );
+        let idle_worker_threads = core::mem::take(
+            &mut *self
+                .rt
+                .inner
+                .idle_worker_threads
+                .lock()
+                .expect("modrpc idle worker threads lock poisoned"),
+        );
+        for (_, worker_thread) in worker_threads {
+            worker_thread.wait_stopped();
+            worker_thread.join();
+        }
+        for worker_thread in idle_worker_threads {
+            worker_thread.join();
         }
     }
 }
@@ -346,12 +480,25 @@ pub struct RuntimeHandle {
 }
 
 pub struct RuntimeHandleInner {
+    config: RuntimeConfig,
+    metrics: MetricsRegistry,
+    capture: Option<PacketCapture>,
     next_role_id: AtomicU64,
     globals: Arc<Mutex<ContextMap>>,
-    worker_handles: Vec<WorkerHandle>,
-    // safety: can only be accessed immutably, and WorkerContexts can only be accessed from their
-    // respective thread.
-    worker_contexts: Vec<UnsafeCell<MaybeUninit<WorkerContext>>>,
+    // Transports added with `add_transport` that are still open.
+    transports: Mutex<Vec<TransportHandle>>,
+    topic_subscriptions: Mutex<TopicSubscriptions>,
+    // Indexed by worker ID. Removed workers leave a `None` behind until their threads are joined,
+    // then their IDs are freed for `add_worker_group` to reuse.
+    worker_handles: Mutex<Vec<Option<WorkerHandle>>>,
+    // Free worker IDs below `worker_handles.len()`. Always locked after `worker_handles`.
+    free_worker_ids: Mutex<WorkerSet>,
+    worker_threads: Mutex<Vec<(WorkerId, WorkerThread)>>,
+    // Threads of removed workers, reused by `add_worker_group`.
+    idle_worker_threads: Mutex<Vec<WorkerThread>>,
+    start_worker_thread_fn: fn(&RuntimeHandle, WorkerId, WorkerQueues),
+    // The context of the worker running on each thread.
+    worker_contexts: ThreadSlots<WorkerContext>,
 }
 
 // safety: see comments in the RuntimeHandle struct definition
@@ -369,64 +516,390 @@ impl RuntimeHandle {
         &self.inner.globals
     }
 
-    pub fn get_worker(&self, worker_id: WorkerId) -> &WorkerHandle {
-        &self.inner.worker_handles[worker_id.0 as usize]
+    pub fn config(&self) -> &RuntimeConfig {
+        &self.inner.config
     }
 
-    pub fn local_worker_context(&self) -> Option<&WorkerContext> {
+    pub fn metrics(&self) -> &MetricsRegistry {
+        &self.inner.metrics
+    }
+
+    /// The capture installed with `RuntimeBuilder::with_capture`. Transports record their traffic
+    /// to it.
+    pub fn capture(&self) -> Option<&PacketCapture> {
+        self.inner.capture.as_ref()
+    }
+
+    /// Panics if the worker has been removed from the runtime.
+    pub fn get_worker(&self, worker_id: WorkerId) -> WorkerHandle {
+        self.inner
+            .worker_handles
+            .lock()
+            .expect("modrpc worker handles lock poisoned")
+            .get(worker_id.0 as usize)
+            .cloned()
+            .flatten()
+            .expect("modrpc::RuntimeHandle::get_worker no such worker")
+    }
+
+    /// The number of workers currently in the runtime.
+    pub fn worker_count(&self) -> u16 {
+        self.inner
+            .worker_handles
+            .lock()
+            .expect("modrpc worker handles lock poisoned")
+            .iter()
+            .filter(|worker| worker.is_some())
+            .count() as u16
+    }
+
+    fn worker_handles(&self) -> Vec<(WorkerId, WorkerHandle)> {
+        self.inner
+            .worker_handles
+            .lock()
+            .expect("modrpc worker handles lock poisoned")
+            .iter()
+            .enumerate()
+            .filter_map(|(worker_index, worker)| {
+                Some((WorkerId(worker_index as u16), worker.clone()?))
+            })
+            .collect()
+    }
+
+    pub(crate) fn topic_subscriptions(&self) -> &Mutex<TopicSubscriptions> {
+        &self.inner.topic_subscriptions
+    }
+
+    /// Start `worker_count` new workers, each on a thread of its own. The new workers are set up
+    /// to flush the runtime's open transports and to redirect packets to the workers subscribed
+    /// to each topic before they handle anything else.
+    pub async fn add_worker_group(&self, worker_count: u16) -> WorkerGroup {
+        let mut new_workers = Vec::with_capacity(worker_count as usize);
+        let worker_group = {
+            let mut worker_handles = self
+                .inner
+                .worker_handles
+                .lock()
+                .expect("modrpc worker handles lock poisoned");
+            let mut free_worker_ids = self
+                .inner
+                .free_worker_ids
+                .lock()
+                .expect("modrpc free worker IDs lock poisoned");
+            // Reuse the IDs of removed workers if enough consecutive ones are free.
+            let first_worker_index = first_free_run(&free_worker_ids, worker_count)
+                .or_else(|| {
+                    u16::try_from(worker_handles.len())
+                        .ok()
+                        .filter(|first_worker_index| {
+                            first_worker_index.checked_add(worker_count).is_some()
+                        })
+                })
+                .expect("modrpc::RuntimeHandle::add_worker_group ran out of worker IDs");
+            for i in 0..worker_count {
+                let worker_id = WorkerId(first_worker_index + i);
+                let (worker_handle, queues) =
+                    WorkerHandle::new(bab::SignalTree::new(), &self.inner.config);
+                new_workers.push((worker_id, worker_handle.inter_worker_tx.clone(), queues));
+                free_worker_ids.remove(worker_id);
+                match worker_handles.get_mut(worker_id.0 as usize) {
+                    Some(slot) => *slot = Some(worker_handle),
+                    None => worker_handles.push(Some(worker_handle)),
+                }
+            }
+
+            WorkerGroup {
+                first_worker_index,
+                worker_count,
+            }
+        };
+
+        // Give the existing workers inter-worker queues to the new workers.
+        let new_worker_senders: Vec<_> = new_workers
+            .iter()
+            .map(|(worker_id, inter_worker_tx, _)| (*worker_id, inter_worker_tx.clone()))
+            .collect();
+        for (worker_id, worker) in self.worker_handles() {
+            if worker_group.contains(worker_id) {
+                continue;
+            }
+            let new_worker_senders = new_worker_senders.clone();
+            worker
+                .run_once(move |worker_cx| {
+                    for (new_worker_id, inter_worker_tx) in &new_worker_senders {
+                        worker_cx.add_inter_worker_batcher(*new_worker_id, inter_worker_tx);
+                    }
+                })
+                .await;
+        }
+
+        for (worker_id, _, queues) in new_workers {
+            (self.inner.start_worker_thread_fn)(self, worker_id, queues);
+        }
+
+        worker_group
+    }
+
+    /// Stop the workers in `worker_group` and remove them from the runtime. Roles stop running on
+    /// them, the remaining workers stop redirecting packets to them, and their threads finish
+    /// running them. The threads and worker IDs are reused by groups added afterwards. This must
+    /// not be called from one of the workers being removed.
+    pub async fn remove_worker_group(&self, worker_group: WorkerGroup) {
+        let (removed_worker_ids, removed_workers): (Vec<WorkerId>, Vec<WorkerHandle>) = {
+            let mut worker_handles = self
+                .inner
+                .worker_handles
+                .lock()
+                .expect("modrpc worker handles lock poisoned");
+            worker_group
+                .worker_ids()
+                .filter_map(|worker_id| {
+                    Some((
+                        worker_id,
+                        worker_handles.get_mut(worker_id.0 as usize)?.take()?,
+                    ))
+                })
+                .unzip()
+        };
+
+        {
+            let mut topic_subscriptions = self
+                .inner
+                .topic_subscriptions
+                .lock()
+                .expect("modrpc topic subscriptions lock poisoned");
+            for &worker_id in &removed_worker_ids {
+                topic_subscriptions.remove_worker(worker_id);
+            }
+        }
+
+        // Close the remaining workers' inter-worker queues to the removed workers.
+        self.run_on_all_workers({
+            let removed_worker_ids = removed_worker_ids.clone();
+            move |worker_cx| {
+                for &worker_id in &removed_worker_ids {
+                    worker_cx.pp.set_inter_worker_sender(worker_id, None);
+                }
+            }
+        })
+        .await;
+
+        for worker in &removed_workers {
+            worker.shutdown_signal.notify();
+        }
+
+        let removed_threads: Vec<_> = {
+            let mut worker_threads = self
+                .inner
+                .worker_threads
+                .lock()
+                .expect("modrpc worker threads lock poisoned");
+            worker_threads
+                .extract_if(.., |(worker_id, _)| removed_worker_ids.contains(worker_id))
+                .collect()
+        };
+        for (_, worker_thread) in &removed_threads {
+            worker_thread.wait_stopped();
+        }
         self.inner
-            .worker_contexts
-            .get(thid::ThreadId::current().as_usize())
-            .map(|worker_cx| unsafe { (&*worker_cx.get()).assume_init_ref() })
+            .idle_worker_threads
+            .lock()
+            .expect("modrpc idle worker threads lock poisoned")
+            .extend(
+                removed_threads
+                    .into_iter()
+                    .map(|(_, worker_thread)| worker_thread),
+            );
+
+        // Free the removed workers' IDs, dropping the table's trailing free slots.
+        let mut worker_handles = self
+            .inner
+            .worker_handles
+            .lock()
+            .expect("modrpc worker handles lock poisoned");
+        let mut free_worker_ids = self
+            .inner
+            .free_worker_ids
+            .lock()
+            .expect("modrpc free worker IDs lock poisoned");
+        for &worker_id in &removed_worker_ids {
+            free_worker_ids.insert(worker_id);
+        }
+        while let Some(None) = worker_handles.last() {
+            let last_worker_id = WorkerId(worker_handles.len() as u16 - 1);
+            if !free_worker_ids.remove(last_worker_id) {
+                break;
+            }
+            worker_handles.pop();
+        }
+    }
+
+    /// Set up a newly started worker for the transports and topic subscriptions that already
+    /// exist in the runtime.
+    fn catch_up_worker(&self, worker_cx: &WorkerContext) {
+        let transports = self
+            .inner
+            .transports
+            .lock()
+            .expect("modrpc transports lock poisoned")
+            .clone();
+        for transport in transports {
+            if transport.shutdown_signal.is_notified() {
+                continue;
+            }
+            if let Some(flush_sender) = transport.writer_flush_sender() {
+                spawn_flush_task(worker_cx, &transport, flush_sender);
+            }
+        }
+
+        let topic_subscriptions = self
+            .inner
+            .topic_subscriptions
+            .lock()
+            .expect("modrpc topic subscriptions lock poisoned");
+        for (&(plane_id, topic), subscription) in topic_subscriptions.iter() {
+            for worker_id in subscription.workers.iter() {
+                if worker_id == worker_cx.worker_id() {
+                    continue;
+                }
+                worker_cx.add_redirect_to_worker(
+                    &subscription.object_path,
+                    // Only broadcast packets that are new to this endpoint to avoid re-broadcasting.
+                    PACKET_PROCESSOR_SOURCE_NEW,
+                    plane_id,
+                    topic,
+                    worker_id,
+                );
+            }
+        }
+    }
+
+    pub fn local_worker_context(&self) -> Option<&WorkerContext> {
+        self.inner.worker_contexts.get()
     }
 
     pub async fn add_transport(&self, builder: impl TransportBuilder) -> TransportHandle {
         let transport_handle = builder.start_transport(TransportContext { rt: self }).await;
+        self.register_transport(transport_handle).await
+    }
+
+    /// Add a transport that flushes according to `config` instead of the runtime's default
+    /// `TransportConfig`.
+    pub async fn add_transport_with_config(
+        &self,
+        builder: impl TransportBuilder,
+        config: TransportConfig,
+    ) -> TransportHandle {
+        let mut transport_handle = builder.start_transport(TransportContext { rt: self }).await;
+        transport_handle.config = Some(config);
+        self.register_transport(transport_handle).await
+    }
+
+    async fn register_transport(&self, transport_handle: TransportHandle) -> TransportHandle {
+
+        {
+            let mut transports = self
+                .inner
+                .transports
+                .lock()
+                .expect("modrpc transports lock poisoned");
+            transports.retain(|transport| !transport.shutdown_signal.is_notified());
+            transports.push(transport_handle.clone());
+        }
 
         if let Some(flush_sender) = transport_handle.writer_flush_sender() {
             // Spawn a task on all workers to periodically flush written buffers.
-
-            // TODO need shutdown logic
+            let transport = transport_handle.clone();
             self.run_on_all_workers(move |worker_cx| {
-                use crate::flush_batcher::FlushBatcherStatus;
-
-                let flush_batcher =
-                    TransportHandle::get_local_flush_batcher(worker_cx, &flush_sender);
-                let mut sleeper = worker_cx.new_sleeper();
-                worker_cx.spawn(async move {
-                    loop {
-                        // Wait until there is data to flush
-                        flush_batcher.wait().await;
-
-                        loop {
-                            match flush_batcher.handle_flush() {
-                                FlushBatcherStatus::Snooze { duration } => {
-                                    sleeper.as_mut().snooze(duration);
-                                    core::future::poll_fn(|cx| sleeper.as_mut().poll_sleep(cx))
-                                        .await;
-                                }
-                                FlushBatcherStatus::FlushNow => {
-                                    flush_sender.flush();
-                                    break;
-                                }
-                                FlushBatcherStatus::DoNotFlush => break,
-                            }
-                        }
-                    }
-                });
+                spawn_flush_task(worker_cx, &transport, flush_sender);
             })
             .await;
         }
 
+        #[cfg(feature = "encryption")]
+        if let Some(plane_keys) = transport_handle.plane_keys.clone() {
+            // Write the key exchanges for the transport's encrypted planes.
+            let transport = transport_handle.clone();
+            self.get_worker(WorkerId::local())
+                .run_once(move |worker_cx| {
+                    worker_cx.spawn(crate::plane_encryption::send_key_exchanges(
+                        worker_cx.clone(),
+                        transport,
+                        plane_keys,
+                    ));
+                })
+                .await;
+        }
+
         transport_handle
     }
 
+    async fn close_transports(&self) {
+        let transports = core::mem::take(
+            &mut *self
+                .inner
+                .transports
+                .lock()
+                .expect("modrpc transports lock poisoned"),
+        );
+
+        // Hand every transport whatever its writers on each worker are still holding on to.
+        self.run_on_all_workers({
+            let transports = transports.clone();
+            move |worker_cx| {
+                for transport in &transports {
+                    transport.flush_writers(worker_cx);
+                }
+            }
+        })
+        .await;
+
+        for transport in &transports {
+            match &transport.drain_signal {
+                Some(drain_signal) => drain_signal.notify(),
+                None => transport.shutdown_signal.notify(),
+            }
+        }
+
+        // Draining transports shut themselves down once they've written everything out - unless
+        // that takes longer than the drain timeout.
+        let draining: Vec<_> = transports
+            .iter()
+            .filter(|transport| transport.drain_signal.is_some())
+            .map(|transport| transport.shutdown_signal.clone())
+            .collect();
+        if let Some((_, worker)) = self.worker_handles().into_iter().next() {
+            worker
+                .run_once(move |worker_cx| {
+                    let drain_timeout = worker_cx.rt().config().shutdown_drain_timeout;
+                    for shutdown_signal in draining {
+                        let worker_cx = worker_cx.clone();
+                        worker_cx.clone().spawn(async move {
+                            futures_lite::future::or(shutdown_signal.wait(), async {
+                                worker_cx.sleep(drain_timeout).await;
+                                shutdown_signal.notify();
+                                // Let the wait above complete rather than dropping it - a
+                                // cancelled waiter can leave the signal's other waiters on this
+                                // thread unwoken.
+                                core::future::pending::<()>().await;
+                            })
+                            .await;
+                        });
+                    }
+                })
+                .await;
+        }
+        for transport in &transports {
+            transport.shutdown_signal.wait().await;
+        }
+    }
+
     pub async fn run_on_all_workers(
         &self,
         f: impl FnOnce(&WorkerContext) + Clone + Send + 'static,
     ) {
         // Note worker_handles also contains a handle for the local worker.
-        for worker in &self.inner.worker_handles {
+        for (_, worker) in self.worker_handles() {
             worker.run_once(f.clone()).await;
         }
     }
@@ -440,8 +913,8 @@ impl RuntimeHandle {
             .worker_id();
 
         // Note worker_handles also contains a handle for the local worker.
-        for (worker_id, worker) in self.inner.worker_handles.iter().enumerate() {
-            if local_worker_id.0 as usize == worker_id {
+        for (worker_id, worker) in self.worker_handles() {
+            if local_worker_id == worker_id {
                 // Skip the local worker's handle
                 continue;
             }
@@ -454,6 +927,13 @@ impl RuntimeHandle {
         Role: InterfaceRole,
         Role::Init: Sync,
     {
+        for channel_id in config.topic_channels.channel_ids() {
+            assert!(
+                channel_id <= crate::MAX_CHANNEL_ID,
+                "channel {channel_id:#x} is above modrpc::MAX_CHANNEL_ID",
+            );
+        }
+
         let role_shutdown_signal = bab::SignalTree::new();
 
         // Shutdown the role when its transport shuts down
@@ -467,6 +947,7 @@ impl RuntimeHandle {
             config,
             role_shutdown_signal,
             role_id: self.inner.next_role_id.fetch_add(1, Ordering::Relaxed),
+            queue_config: self.inner.config.role,
             has_spawned_globals_shutdown: false,
         }
     }
@@ -475,10 +956,34 @@ impl RuntimeHandle {
 #[derive(Clone)]
 pub struct WorkerHandle {
     command_tx: burstq::Sender<WorkerCommand>,
+    inter_worker_tx: burstq::Sender<SendPacket>,
     shutdown_signal: bab::SignalTree,
 }
 
+/// The receiving ends of a worker's queues, handed to the worker when it starts.
+struct WorkerQueues {
+    command_rx: burstq::Receiver<WorkerCommand>,
+    inter_worker_rx: burstq::Receiver<SendPacket>,
+}
+
 impl WorkerHandle {
+    fn new(shutdown_signal: bab::SignalTree, config: &RuntimeConfig) -> (Self, WorkerQueues) {
+        let (command_tx, command_rx) = burstq::mpmc(config.command_queue_capacity);
+        let (inter_worker_tx, inter_worker_rx) = burstq::mpmc(config.inter_worker_queue_capacity);
+
+        (
+            Self {
+                command_tx,
+                inter_worker_tx,
+                shutdown_signal,
+            },
+            WorkerQueues {
+                command_rx,
+                inter_worker_rx,
+            },
+        )
+    }
+
     pub async fn run_once<R: Send + 'static>(
         &self,
         f: impl FnOnce(&WorkerContext) -> R + Send + 'static,
@@ -498,23 +1003,102 @@ impl WorkerHandle {
     }
 }
 
-#[derive(Clone)]
+/// Spawn a task on the worker that periodically flushes the buffers written to a transport.
+fn spawn_flush_task(
+    worker_cx: &WorkerContext,
+    transport: &TransportHandle,
+    flush_sender: bab::WriterFlushSender,
+) {
+    use crate::flush_batcher::FlushBatcherStatus;
+
+    let flush_batcher = transport.get_local_flush_batcher(worker_cx, &flush_sender);
+    let transport_shutdown_signal = transport.shutdown_signal.clone();
+    let mut sleeper = worker_cx.new_sleeper();
+    worker_cx.spawn(async move {
+        let flush_loop = async {
+            loop {
+                // Wait until there is data to flush
+                flush_batcher.wait().await;
+
+                loop {
+                    match flush_batcher.handle_flush() {
+                        FlushBatcherStatus::Snooze { duration } => {
+                            sleeper.as_mut().snooze(duration);
+                            core::future::poll_fn(|cx| sleeper.as_mut().poll_sleep(cx)).await;
+                        }
+                        FlushBatcherStatus::FlushNow => {
+                            flush_sender.flush();
+                            break;
+                        }
+                        FlushBatcherStatus::DoNotFlush => break,
+                    }
+                }
+            }
+        };
+        futures_lite::future::or(flush_loop, transport_shutdown_signal.wait()).await;
+
+        // Hand the transport anything still buffered on its way out.
+        flush_sender.flush();
+    });
+}
+
+#[derive(Clone, Debug)]
 pub enum TopicChannels {
     SingleChannel {
         channel_id: u32,
     },
+    /// Send different parts of the role's interface on separate channels, so that e.g. bulk
+    /// transfers don't hold up latency-sensitive requests.
     MultiChannel {
-        // TODO
+        /// Channel for topics that don't match any route.
+        default_channel_id: u32,
+        /// `(object path prefix, channel_id)` pairs. Object paths are relative to the role, e.g.
+        /// `"uploads"` routes every event under the role's `uploads` object. The longest matching
+        /// prefix wins.
+        routes: Vec<(String, u32)>,
     },
 }
 
 impl TopicChannels {
+    /// The channel for topics that aren't routed elsewhere.
+    pub fn default_channel_id(&self) -> u32 {
+        match self {
+            TopicChannels::SingleChannel { channel_id } => *channel_id,
+            TopicChannels::MultiChannel {
+                default_channel_id, ..
+            } => *default_channel_id,
+        }
+    }
+
+    /// Every channel topics may be sent on, starting with the default channel.
+    pub fn channel_ids(&self) -> Vec<u32> {
+        let mut channel_ids = vec![self.default_channel_id()];
+        if let TopicChannels::MultiChannel { routes, .. } = self {
+            for &(_, channel_id) in routes {
+                if !channel_ids.contains(&channel_id) {
+                    channel_ids.push(channel_id);
+                }
+            }
+        }
+        channel_ids
+    }
+
     fn create_packet_sender(
         &self,
         worker_cx: &WorkerContext,
         transport: &TransportHandle,
+        plane_id: u32,
         endpoint_addr: EndpointAddr,
     ) -> PacketSender {
+        #[cfg(not(feature = "encryption"))]
+        let _ = plane_id;
+        #[cfg(feature = "encryption")]
+        let encryption = transport
+            .plane_keys
+            .as_ref()
+            .and_then(|plane_keys| plane_keys.sealer(plane_id))
+            .map(|sealer| crate::packet_sender::PacketEncryption::new(sealer, transport));
+
         match self {
             TopicChannels::SingleChannel { channel_id } => {
                 let (writer, flush_batcher) = transport.new_writer(worker_cx, *channel_id);
@@ -523,11 +1107,51 @@ impl TopicChannels {
                     tx_channel: writer,
                     pp: worker_cx.pp.clone(),
                     flush_batcher,
+                    #[cfg(feature = "encryption")]
+                    encryption,
                 }
                 .into()
             }
-            TopicChannels::MultiChannel {} => {
-                todo!();
+            TopicChannels::MultiChannel {
+                default_channel_id,
+                routes,
+            } => {
+                // One writer per distinct channel ID.
+                let mut channel_ids = vec![*default_channel_id];
+                let routes = routes
+                    .iter()
+                    .map(|(prefix, channel_id)| {
+                        let channel_index = channel_ids
+                            .iter()
+                            .position(|id| id == channel_id)
+                            .unwrap_or_else(|| {
+                                channel_ids.push(*channel_id);
+                                channel_ids.len() - 1
+                            });
+                        (prefix.clone(), channel_index)
+                    })
+                    .collect();
+                let channels = channel_ids
+                    .into_iter()
+                    .map(|channel_id| {
+                        let (writer, flush_batcher) = transport.new_writer(worker_cx, channel_id);
+                        TxChannel {
+                            writer,
+                            flush_batcher,
+                        }
+                    })
+                    .collect();
+
+                MultiChannelSender {
+                    endpoint_addr,
+                    routes,
+                    channels,
+                    default_channel: 0,
+                    pp: worker_cx.pp.clone(),
+                    #[cfg(feature = "encryption")]
+                    encryption,
+                }
+                .into()
             }
         }
     }
@@ -551,6 +1175,7 @@ pub struct StartRoleHandle<Role: InterfaceRole> {
     pub role_shutdown_signal: bab::SignalTree,
 
     role_id: u64,
+    queue_config: RoleQueueConfig,
     has_spawned_globals_shutdown: bool,
 }
 
@@ -559,6 +1184,13 @@ where
     Role::Config: Clone + Send,
     Role::Init: Clone + Send,
 {
+    /// Size the role's handler and load balancer queues with `queue_config` instead of the
+    /// runtime's default `RoleQueueConfig` on the workers the role is started on afterwards.
+    pub fn queue_config(mut self, queue_config: RoleQueueConfig) -> Self {
+        self.queue_config = queue_config;
+        self
+    }
+
     pub async fn on_worker_group(
         mut self,
         worker_group: WorkerGroup,
@@ -566,15 +1198,17 @@ where
     ) -> Self {
         let start_worker = worker_group.first_worker_index;
         let end_worker = start_worker + worker_group.worker_count;
+        let mut subscriptions_installed = Vec::new();
         for worker_index in start_worker..end_worker {
             let spawn_globals_shutdown = !self.has_spawned_globals_shutdown;
             self.has_spawned_globals_shutdown = true;
 
             let rt = self.rt.clone();
-            let worker = &self.rt.inner.worker_handles[worker_index as usize];
+            let worker = self.rt.get_worker(WorkerId(worker_index));
             let role_start_fn = role_start_fn.clone();
             let role_shutdown_signal = self.role_shutdown_signal.clone();
             let role_id = self.role_id;
+            let queue_config = self.queue_config;
 
             let plane_id = self.config.plane_id;
             let endpoint_addr = self.config.endpoint_addr;
@@ -583,10 +1217,14 @@ where
             let config: Role::Config = self.config.config.clone();
             let init: Role::Init = self.config.init.clone();
 
-            worker
+            let installed = worker
                 .run_once(move |worker_cx| {
-                    let packet_sender =
-                        topic_channels.create_packet_sender(&worker_cx, &transport, endpoint_addr);
+                    let packet_sender = topic_channels.create_packet_sender(
+                        &worker_cx,
+                        &transport,
+                        plane_id,
+                        endpoint_addr,
+                    );
 
                     let _role_handle = run_role_worker::<Role>(
                         &rt,
@@ -598,6 +1236,7 @@ where
                         role_shutdown_signal.clone(),
                         endpoint_addr,
                         packet_sender,
+                        queue_config,
                         &config,
                         &init,
                         role_start_fn,
@@ -607,25 +1246,53 @@ where
                     // globals the role creates.
                     if spawn_globals_shutdown {
                         let globals = worker_cx.globals.clone();
-                        worker_cx
-                            .spawner
-                            .spawn(async move {
-                                role_shutdown_signal.wait().await;
-                                globals
-                                    .lock()
-                                    .expect("modrpc globals map poisoned")
-                                    .shutdown_tag(ModrpcContextTag::Role(role_id));
-                            })
-                            .expect("modrpc spawn role globals shutdown");
+                        worker_cx.spawn(async move {
+                            role_shutdown_signal.wait().await;
+                            globals
+                                .lock()
+                                .expect("modrpc globals map poisoned")
+                                .shutdown_tag(ModrpcContextTag::Role(role_id));
+                        });
                     }
+
+                    worker_cx.subscriptions_installed()
                 })
                 .await;
+            subscriptions_installed.push(installed);
+        }
+
+        // Wait for the other workers to redirect packets to the role's new subscriptions.
+        for installed in subscriptions_installed {
+            installed.await;
         }
 
         self
     }
 
-    pub fn local(mut self, role_start_fn: impl RoleStartFn<Role>) -> Role::Hooks {
+    /// Start the role on the current worker. The other workers start redirecting packets to the
+    /// role's subscriptions shortly after this returns - use `local_async` to wait until they do.
+    #[deprecated(
+        note = "packets sent from other workers can be dropped until they redirect them \
+        to the role - use `local_async` instead"
+    )]
+    pub fn local(self, role_start_fn: impl RoleStartFn<Role>) -> Role::Hooks {
+        self.start_local(role_start_fn)
+    }
+
+    /// Start the role on the current worker, resolving once the other workers redirect packets
+    /// to the role's subscriptions.
+    pub async fn local_async(self, role_start_fn: impl RoleStartFn<Role>) -> Role::Hooks {
+        let rt = self.rt.clone();
+        let hooks = self.start_local(role_start_fn);
+        let installed = rt
+            .local_worker_context()
+            .expect("modrpc::StartRoleHandle::local_async must be called from a modrpc worker")
+            .subscriptions_installed();
+        installed.await;
+        hooks
+    }
+
+    fn start_local(mut self, role_start_fn: impl RoleStartFn<Role>) -> Role::Hooks {
         let Some(worker_cx) = &self.rt.local_worker_context() else {
             panic!("Can't start a role locally in a thread that isn't a modrpc worker");
         };
@@ -640,7 +1307,7 @@ where
         } = self.config;
 
         let packet_sender =
-            topic_channels.create_packet_sender(&worker_cx, &transport, endpoint_addr);
+            topic_channels.create_packet_sender(&worker_cx, &transport, plane_id, endpoint_addr);
         let hooks = run_role_worker::<Role>(
             &self.rt,
             plane_id,
@@ -651,6 +1318,7 @@ where
             self.role_shutdown_signal.clone(),
             endpoint_addr,
             packet_sender,
+            self.queue_config,
             &config,
             &init,
             role_start_fn,
@@ -661,16 +1329,13 @@ where
         if !self.has_spawned_globals_shutdown {
             let role_shutdown_signal = self.role_shutdown_signal;
             let globals = worker_cx.globals.clone();
-            worker_cx
-                .spawner
-                .spawn(async move {
-                    role_shutdown_signal.wait().await;
-                    globals
-                        .lock()
-                        .expect("modrpc globals map poisoned")
-                        .shutdown_tag(ModrpcContextTag::Role(self.role_id));
-                })
-                .expect("modrpc spawn role globals shutdown");
+            worker_cx.spawn(async move {
+                role_shutdown_signal.wait().await;
+                globals
+                    .lock()
+                    .expect("modrpc globals map poisoned")
+                    .shutdown_tag(ModrpcContextTag::Role(self.role_id));
+            });
 
             self.has_spawned_globals_shutdown = true;
         }
@@ -678,3 +1343,336 @@ where
         hooks
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TransmitPacket, packet_processor::PACKET_PROCESSOR_SOURCE_ANY};
+    use modrpc_executor::{ModrpcExecutor, TokioExecutor};
+    use std::{cell::Cell, time::Duration};
+
+    fn new_test_packet(buffer_pool: &bab::HeapBufferPool, plane_id: u32, topic: u32) -> Packet {
+        let header_len = <TransmitPacket as mproto::BaseLen>::BASE_LEN;
+        let buffer = buffer_pool.try_acquire().unwrap();
+        unsafe {
+            buffer.initialize_rc(1, 0, 0);
+            mproto::encode_value(
+                TransmitPacket {
+                    payload_length: 0,
+                    infra_id: 0,
+                    plane_id,
+                    topic,
+                    source: EndpointAddr { endpoint: 0 },
+                },
+                buffer.slice_mut(0..header_len),
+            );
+            Packet::new(buffer, 0, header_len)
+        }
+    }
+
+    #[test]
+    fn test_subscriptions_installed() {
+        let mut ex = TokioExecutor::new();
+        let (rt, rt_shutdown) = RuntimeHandle::single_threaded(&mut ex);
+        let buffer_pool = bab::HeapBufferPool::new(64, 4, 4);
+        let received = Rc::new(Cell::new(0));
+
+        ex.run_until(async {
+            let worker_group = rt.add_worker_group(2).await;
+
+            // Subscribe to lots of planes at once without waiting on the other workers.
+            let worker_cx = rt.local_worker_context().unwrap();
+            for plane_id in 0..100 {
+                worker_cx.add_handler("test-topic", PACKET_PROCESSOR_SOURCE_ANY, plane_id, 2, {
+                    let received = received.clone();
+                    move |_, _| received.set(received.get() + 1)
+                });
+                crate::add_topic_subscription(worker_cx, "test-topic", plane_id, 2);
+            }
+            worker_cx.subscriptions_installed().await;
+
+            // Every subscription is installed on the other workers by now.
+            for worker_id in worker_group.worker_ids() {
+                let buffer_pool = buffer_pool.clone();
+                rt.get_worker(worker_id)
+                    .run_once(move |worker_cx| {
+                        let handle_packet = worker_cx.get_packet_processor();
+                        worker_cx.spawn(async move {
+                            handle_packet(&new_test_packet(&buffer_pool, 99, 2)).await;
+                        });
+                    })
+                    .await;
+            }
+            for _ in 0..1000 {
+                if received.get() == 2 {
+                    break;
+                }
+                worker_cx.sleep(Duration::from_millis(1)).await;
+            }
+            assert_eq!(received.get(), 2);
+
+            // Nothing is pending any more.
+            worker_cx.subscriptions_installed().await;
+
+            rt_shutdown.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_runtime_config() {
+        let mut ex = TokioExecutor::new();
+        let config = RuntimeConfig {
+            command_queue_capacity: 1,
+            inter_worker_queue_capacity: 1,
+            max_inter_worker_batch_size: 1,
+            local_packet_queue_capacity: 1,
+            ..RuntimeConfig::default()
+        };
+        let (rt, rt_shutdown) = RuntimeBuilder::new_with_local(ex.spawner())
+            .with_config(config.clone())
+            .start::<TokioExecutor>();
+        assert_eq!(rt.config(), &config);
+        let buffer_pool = bab::HeapBufferPool::new(64, 4, 4);
+        let received = Rc::new(Cell::new(0));
+
+        ex.run_until(async {
+            let worker_group = rt.add_worker_group(2).await;
+
+            let worker_cx = rt.local_worker_context().unwrap();
+            worker_cx.add_handler("test-topic", PACKET_PROCESSOR_SOURCE_ANY, 1, 2, {
+                let received = received.clone();
+                move |_, _| received.set(received.get() + 1)
+            });
+            crate::add_topic_subscription(worker_cx, "test-topic", 1, 2);
+            worker_cx.subscriptions_installed().await;
+
+            // Packets still make it through queues with room for one at a time.
+            for worker_id in worker_group.worker_ids() {
+                let buffer_pool = buffer_pool.clone();
+                rt.get_worker(worker_id)
+                    .run_once(move |worker_cx| {
+                        let handle_packet = worker_cx.get_packet_processor();
+                        worker_cx.spawn(async move {
+                            for _ in 0..10 {
+                                handle_packet(&new_test_packet(&buffer_pool, 1, 2)).await;
+                            }
+                        });
+                    })
+                    .await;
+            }
+            for _ in 0..1000 {
+                if received.get() == 20 {
+                    break;
+                }
+                worker_cx.sleep(Duration::from_millis(1)).await;
+            }
+            assert_eq!(received.get(), 20);
+
+            rt_shutdown.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut ex = TokioExecutor::new();
+        let metrics = MetricsRegistry::new();
+        let (rt, rt_shutdown) = RuntimeBuilder::new_with_local(ex.spawner())
+            .with_metrics(metrics.clone())
+            .start::<TokioExecutor>();
+        // Enough buffers for every packet sent, since redirected packets may not be released yet.
+        let buffer_pool = bab::HeapBufferPool::new(64, 8, 4);
+        let received = Rc::new(Cell::new(0));
+
+        ex.run_until(async {
+            let worker_group = rt.add_worker_group(2).await;
+
+            let worker_cx = rt.local_worker_context().unwrap();
+            worker_cx.add_handler("test-topic", PACKET_PROCESSOR_SOURCE_ANY, 1, 2, {
+                let received = received.clone();
+                move |_, _| received.set(received.get() + 1)
+            });
+            crate::add_topic_subscription(worker_cx, "test-topic", 1, 2);
+            worker_cx.subscriptions_installed().await;
+
+            // Each packet is redirected from the worker group to the local worker.
+            for worker_id in worker_group.worker_ids() {
+                let buffer_pool = buffer_pool.clone();
+                rt.get_worker(worker_id)
+                    .run_once(move |worker_cx| {
+                        let handle_packet = worker_cx.get_packet_processor();
+                        worker_cx.spawn(async move {
+                            for _ in 0..10 {
+                                handle_packet(&new_test_packet(&buffer_pool, 1, 2)).await;
+                            }
+                        });
+                    })
+                    .await;
+            }
+            for _ in 0..1000 {
+                if received.get() == 20 {
+                    break;
+                }
+                worker_cx.sleep(Duration::from_millis(1)).await;
+            }
+            assert_eq!(received.get(), 20);
+
+            // Nobody handles this topic.
+            worker_cx.get_packet_processor()(&new_test_packet(&buffer_pool, 1, 3)).await;
+
+            rt_shutdown.shutdown().await;
+        });
+
+        let header_len = <TransmitPacket as mproto::BaseLen>::BASE_LEN;
+        let encoded = rt.metrics().encode_openmetrics();
+        assert!(encoded.contains("modrpc_packets_total{plane=\"1\",topic=\"2\"} 20\n"));
+        assert!(encoded.contains(&format!(
+            "modrpc_packet_bytes_total{{plane=\"1\",topic=\"2\"}} {}\n",
+            20 * header_len,
+        )));
+        assert!(encoded.contains("modrpc_packets_dropped_total{reason=\"no_handlers\"} 1\n"));
+        assert!(encoded.contains("modrpc_inter_worker_queue_depth{worker=\"0\"} 0\n"));
+        assert!(encoded.ends_with("# EOF\n"));
+    }
+
+    #[test]
+    fn test_add_remove_worker_group() {
+        let mut ex = TokioExecutor::new();
+        let (rt, rt_shutdown) = RuntimeHandle::single_threaded(&mut ex);
+        let buffer_pool = bab::HeapBufferPool::new(64, 4, 4);
+        let received = Rc::new(Cell::new(0));
+
+        ex.run_until(async {
+            // Subscribe the local worker to a topic before the new workers exist.
+            let worker_cx = rt.local_worker_context().unwrap();
+            worker_cx.add_handler("test-topic", PACKET_PROCESSOR_SOURCE_ANY, 1, 2, {
+                let received = received.clone();
+                move |_, _| received.set(received.get() + 1)
+            });
+            crate::add_topic_subscription(worker_cx, "test-topic", 1, 2);
+
+            // The second round runs on the first round's worker IDs and threads.
+            for round in 1..=2 {
+                let worker_group = rt.add_worker_group(2).await;
+                assert_eq!(rt.worker_count(), 3);
+
+                // The new workers redirect the topic's packets to the local worker.
+                for worker_id in worker_group.worker_ids() {
+                    let buffer_pool = buffer_pool.clone();
+                    rt.get_worker(worker_id)
+                        .run_once(move |worker_cx| {
+                            let handle_packet = worker_cx.get_packet_processor();
+                            worker_cx.spawn(async move {
+                                handle_packet(&new_test_packet(&buffer_pool, 1, 2)).await;
+                            });
+                        })
+                        .await;
+                }
+                for _ in 0..1000 {
+                    if received.get() == 2 * round {
+                        break;
+                    }
+                    worker_cx.sleep(Duration::from_millis(1)).await;
+                }
+                assert_eq!(received.get(), 2 * round);
+
+                rt.remove_worker_group(worker_group).await;
+                assert_eq!(rt.worker_count(), 1);
+                assert!(
+                    rt.topic_subscriptions()
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .all(|(_, subscription)| subscription.workers.iter().eq([WorkerId(0)]))
+                );
+            }
+
+            rt_shutdown.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_worker_ids_reused() {
+        let mut ex = TokioExecutor::new();
+        let (rt, rt_shutdown) = RuntimeHandle::single_threaded(&mut ex);
+        let worker_table_len = || rt.inner.worker_handles.lock().unwrap().len();
+        let idle_thread_count = || rt.inner.idle_worker_threads.lock().unwrap().len();
+
+        ex.run_until(async {
+            // Adding and removing groups over and over doesn't grow the worker table or start new
+            // threads.
+            for _ in 0..20 {
+                let worker_group = rt.add_worker_group(2).await;
+                assert_eq!(
+                    worker_group.worker_ids().collect::<Vec<_>>(),
+                    [WorkerId(1), WorkerId(2)]
+                );
+                rt.remove_worker_group(worker_group).await;
+                assert_eq!(worker_table_len(), 1);
+                assert_eq!(idle_thread_count(), 2);
+            }
+
+            // Freed IDs are reused by groups that fit in them, and larger groups go after the
+            // workers still running.
+            let a = rt.add_worker_group(2).await;
+            let b = rt.add_worker_group(1).await;
+            rt.remove_worker_group(a).await;
+            assert_eq!(worker_table_len(), 4);
+            let c = rt.add_worker_group(3).await;
+            assert_eq!(c.worker_ids().next(), Some(WorkerId(4)));
+            let d = rt.add_worker_group(2).await;
+            assert_eq!(d.worker_ids().next(), Some(WorkerId(1)));
+            assert_eq!(rt.worker_count(), 7);
+
+            for worker_group in [b, c, d] {
+                rt.remove_worker_group(worker_group).await;
+            }
+            assert_eq!(worker_table_len(), 1);
+            assert!(rt.inner.free_worker_ids.lock().unwrap().is_empty());
+            assert_eq!(idle_thread_count(), 6);
+
+            rt_shutdown.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_shutdown_waits_for_tasks() {
+        let mut ex = TokioExecutor::new();
+        let mut builder = RuntimeBuilder::new_with_local(ex.spawner());
+        builder.new_worker_group(2);
+        let (rt, rt_shutdown) = builder.start::<TokioExecutor>();
+        let leak_check = Arc::new(());
+
+        let transport = ex.run_until(async {
+            let transport = rt
+                .add_transport(crate::LocalTransport {
+                    buffer_size: 256,
+                    buffer_pool_batches: 8,
+                    buffer_pool_batch_size: 8,
+                })
+                .await;
+
+            let task_leak_check = leak_check.clone();
+            rt.run_on_all_workers(move |worker_cx| {
+                let leak_check = task_leak_check.clone();
+                worker_cx.spawn(async move {
+                    let _leak_check = leak_check;
+                    core::future::pending::<()>().await;
+                });
+                let leak_check = task_leak_check.clone();
+                worker_cx.spawner().spawn(async move {
+                    let _leak_check = leak_check;
+                    core::future::pending::<()>().await;
+                });
+            })
+            .await;
+            assert_eq!(Arc::strong_count(&leak_check), 1 + 2 * 3);
+
+            rt_shutdown.shutdown().await;
+            transport
+        });
+
+        assert!(transport.shutdown_signal.is_notified());
+        assert_eq!(Arc::strong_count(&leak_check), 1);
+        assert_eq!(rt.local_worker_context().unwrap().tasks.running(), 0);
+    }
+}
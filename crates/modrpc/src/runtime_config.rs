@@ -0,0 +1,78 @@
+use core::time::Duration;
+
+/// Queue capacities and batching parameters for a runtime. Larger queues, batches and flush
+/// delays trade latency for throughput.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuntimeConfig {
+    /// Commands each worker can have queued by `WorkerHandle::run_once` before senders wait.
+    pub command_queue_capacity: usize,
+    /// Packets each worker can have queued by other workers before they wait.
+    pub inter_worker_queue_capacity: usize,
+    /// Most packets a worker hands to another worker at once.
+    pub max_inter_worker_batch_size: usize,
+    /// Packets each worker can have queued for its own handlers.
+    pub local_packet_queue_capacity: usize,
+    /// Used by transports that don't override it with `RuntimeHandle::add_transport_with_config`.
+    pub transport: TransportConfig,
+    /// Used by roles that don't override it with `StartRoleHandle::queue_config`.
+    pub role: RoleQueueConfig,
+    /// How long `RuntimeShutdownHandle::shutdown` lets each transport write out what it has been
+    /// handed before closing it anyway.
+    pub shutdown_drain_timeout: Duration,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            command_queue_capacity: 8,
+            inter_worker_queue_capacity: 256,
+            max_inter_worker_batch_size: 32,
+            local_packet_queue_capacity: 64,
+            transport: TransportConfig::default(),
+            role: RoleQueueConfig::default(),
+            shutdown_drain_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How eagerly buffers written to a transport are flushed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TransportConfig {
+    /// How long a worker may hold buffers it has written before handing them to the transport.
+    pub max_flush_delay: Duration,
+    /// How long a channel's writer may hold packets before completing its buffer.
+    pub max_writer_flush_delay: Duration,
+    /// Flush a channel's writer immediately once this many bytes are waiting in it.
+    pub max_unflushed_bytes: usize,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            max_flush_delay: Duration::ZERO,
+            max_writer_flush_delay: Duration::ZERO,
+            max_unflushed_bytes: 32_000,
+        }
+    }
+}
+
+/// Sizes of the queues a role sets up for its event handlers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RoleQueueConfig {
+    /// Packets each queued event handler can have waiting.
+    pub handler_queue_capacity: usize,
+    /// Packets a load balanced topic can have waiting for any worker to pick them up.
+    pub load_balancer_capacity: usize,
+    /// Most packets a worker moves to or from a load balancer's shared queue at once.
+    pub load_balancer_burst_size: usize,
+}
+
+impl Default for RoleQueueConfig {
+    fn default() -> Self {
+        Self {
+            handler_queue_capacity: 64,
+            load_balancer_capacity: 64,
+            load_balancer_burst_size: 32,
+        }
+    }
+}
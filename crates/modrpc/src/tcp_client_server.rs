@@ -3,22 +3,38 @@ use std::cell::Cell;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::{
-    EndpointAddr, HeapBufferPool, InterfaceRole, RoleConfig, RoleStartFn, RuntimeHandle,
-    TcpTransport, TopicChannels, TransportHandle, WorkerId, endpoint_proto::PlaneHandshake,
-    rt::WorkerGroup,
+    CompressionNegotiation, EndpointAddr, HeapBufferPool, InterfaceRole, RoleConfig, RoleStartFn,
+    RuntimeHandle, TcpTransport, TransportHandle, TransportOptions, WorkerId,
+    endpoint_proto::PlaneHandshake, rt::WorkerGroup,
 };
 
 pub struct TcpServer {
     next_plane_id: Cell<u32>,
+    options: TransportOptions,
 }
 
 impl TcpServer {
     pub fn new() -> Self {
         Self {
             next_plane_id: Cell::new(0),
+            options: TransportOptions::default(),
         }
     }
 
+    /// Options for the transport of every accepted connection.
+    pub fn with_transport_options(mut self, options: TransportOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Encrypt every accepted plane, exchanging keys with the client over the plane itself. The
+    /// client must connect with `TransportOptions::encryption` set.
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, config: crate::PlaneEncryptionConfig) -> Self {
+        self.options.encryption = Some(config);
+        self
+    }
+
     pub async fn accept<Role: InterfaceRole>(
         &self,
         rt: &RuntimeHandle,
@@ -39,6 +55,7 @@ impl TcpServer {
         self.next_plane_id.set(self.next_plane_id.get() + 1);
 
         Self::handshake(&mut stream, plane_id, EndpointAddr { endpoint: 1 }, &init).await?;
+        let topic_channels = self.options.topic_channels(plane_id);
 
         let transport = rt
             .add_transport(TcpTransport {
@@ -46,6 +63,16 @@ impl TcpServer {
                 stream,
                 in_buffer_pool,
                 out_buffer_pool,
+                keepalive: self.options.keepalive,
+                compression: self.options.compression,
+                compression_negotiation: CompressionNegotiation::Offer,
+                egress: self.options.egress.clone(),
+                #[cfg(feature = "encryption")]
+                plane_keys: self.options.plane_keys(
+                    plane_id,
+                    topic_channels.default_channel_id(),
+                    EndpointAddr { endpoint: 0 },
+                ),
             })
             .await;
 
@@ -53,9 +80,7 @@ impl TcpServer {
             plane_id,
             endpoint_addr: EndpointAddr { endpoint: 0 },
             transport,
-            topic_channels: TopicChannels::SingleChannel {
-                channel_id: plane_id,
-            },
+            topic_channels,
             config: config.clone(),
             init,
         });
@@ -66,7 +91,7 @@ impl TcpServer {
                 .await;
         }
 
-        let role_handle = plane_builder.local(start_fn);
+        let role_handle = plane_builder.local_async(start_fn).await;
 
         Ok(role_handle)
     }
@@ -89,6 +114,7 @@ impl TcpServer {
         self.next_plane_id.set(self.next_plane_id.get() + 1);
 
         Self::handshake(&mut stream, plane_id, EndpointAddr { endpoint: 1 }, &init).await?;
+        let topic_channels = self.options.topic_channels(plane_id);
 
         let transport = rt
             .add_transport(TcpTransport {
@@ -96,6 +122,16 @@ impl TcpServer {
                 stream,
                 in_buffer_pool,
                 out_buffer_pool,
+                keepalive: self.options.keepalive,
+                compression: self.options.compression,
+                compression_negotiation: CompressionNegotiation::Offer,
+                egress: self.options.egress.clone(),
+                #[cfg(feature = "encryption")]
+                plane_keys: self.options.plane_keys(
+                    plane_id,
+                    topic_channels.default_channel_id(),
+                    EndpointAddr { endpoint: 0 },
+                ),
             })
             .await;
 
@@ -103,14 +139,12 @@ impl TcpServer {
             plane_id,
             endpoint_addr: EndpointAddr { endpoint: 0 },
             transport,
-            topic_channels: TopicChannels::SingleChannel {
-                channel_id: plane_id,
-            },
+            topic_channels,
             config,
             init,
         });
 
-        let role_handle = plane_builder.local(start_fn);
+        let role_handle = plane_builder.local_async(start_fn).await;
 
         Ok(role_handle)
     }
@@ -150,6 +184,7 @@ pub async fn tcp_connect<Role: InterfaceRole>(
     out_buffer_pool: HeapBufferPool,
     worker_id: WorkerId,
     config: Role::Config,
+    options: TransportOptions,
     mut stream: tokio::net::TcpStream,
 ) -> std::io::Result<TcpConnection<Role>>
 where
@@ -163,6 +198,7 @@ where
     stream.read_exact(&mut payload_bytes).await?;
     let plane_handshake: PlaneHandshake<Role::Init> = mproto::decode_value(&payload_bytes)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let topic_channels = options.topic_channels(plane_handshake.plane_id);
 
     let transport = rt
         .add_transport(TcpTransport {
@@ -170,21 +206,33 @@ where
             stream,
             in_buffer_pool,
             out_buffer_pool,
+            keepalive: options.keepalive,
+            compression: options.compression,
+            compression_negotiation: CompressionNegotiation::Answer,
+            egress: options.egress.clone(),
+            #[cfg(feature = "encryption")]
+            plane_keys: options.plane_keys(
+                plane_handshake.plane_id,
+                topic_channels.default_channel_id(),
+                plane_handshake.endpoint_addr,
+            ),
         })
         .await;
+    options
+        .subscribe_topics::<Role::Interface>(rt, &transport)
+        .await;
 
     let role_handle = rt
         .start_role::<Role>(RoleConfig {
             plane_id: plane_handshake.plane_id,
             endpoint_addr: plane_handshake.endpoint_addr,
             transport: transport.clone(),
-            topic_channels: TopicChannels::SingleChannel {
-                channel_id: plane_handshake.plane_id,
-            },
+            topic_channels,
             config,
             init: plane_handshake.init.clone(),
         })
-        .local(|_cx| {});
+        .local_async(|_cx| {})
+        .await;
 
     Ok(TcpConnection {
         endpoint: plane_handshake.endpoint_addr,
@@ -194,12 +242,14 @@ where
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn tcp_connect_builder<Role: InterfaceRole, R>(
     rt: &RuntimeHandle,
     in_buffer_pool: HeapBufferPool,
     out_buffer_pool: HeapBufferPool,
     worker_id: WorkerId,
     config: Role::Config,
+    options: TransportOptions,
     mut stream: tokio::net::TcpStream,
     build_fn: impl AsyncFnOnce(crate::StartRoleHandle<Role>) -> R,
 ) -> std::io::Result<(EndpointAddr, TransportHandle, R)>
@@ -214,6 +264,7 @@ where
     stream.read_exact(&mut payload_bytes).await?;
     let plane_handshake: PlaneHandshake<Role::Init> = mproto::decode_value(&payload_bytes)
         .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    let topic_channels = options.topic_channels(plane_handshake.plane_id);
 
     let transport = rt
         .add_transport(TcpTransport {
@@ -221,16 +272,27 @@ where
             stream,
             in_buffer_pool,
             out_buffer_pool,
+            keepalive: options.keepalive,
+            compression: options.compression,
+            compression_negotiation: CompressionNegotiation::Answer,
+            egress: options.egress.clone(),
+            #[cfg(feature = "encryption")]
+            plane_keys: options.plane_keys(
+                plane_handshake.plane_id,
+                topic_channels.default_channel_id(),
+                plane_handshake.endpoint_addr,
+            ),
         })
         .await;
+    options
+        .subscribe_topics::<Role::Interface>(rt, &transport)
+        .await;
 
     let start_role_handle = rt.start_role::<Role>(RoleConfig {
         plane_id: plane_handshake.plane_id,
         endpoint_addr: plane_handshake.endpoint_addr,
         transport: transport.clone(),
-        topic_channels: TopicChannels::SingleChannel {
-            channel_id: plane_handshake.plane_id,
-        },
+        topic_channels,
         config,
         init: plane_handshake.init,
     });
@@ -1,19 +1,28 @@
 use mproto::BaseLen;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-use crate::{Packet, PacketBundle};
+use crate::{Packet, PacketBundle, TransportError, decompress_bundle, is_compressed_bundle};
 
-pub struct TcpIngress {
-    stream: tokio::net::tcp::OwnedReadHalf,
+pub struct TcpIngress<R = tokio::net::tcp::OwnedReadHalf> {
+    stream: R,
     framer: bab::Framer,
     // Max packet size including bundle header.
     max_packet_size: usize,
     cursor: usize,
+    // Bytes read past the end of a compressed bundle - these are consumed before reading from the
+    // socket again.
+    carry: Vec<u8>,
+    carry_pos: usize,
+    // Holds compressed bundles while they are decompressed into the framer.
+    scratch: Vec<u8>,
+    decompression: bool,
+    #[cfg(feature = "encryption")]
+    plane_keys: Option<crate::PlaneKeyring>,
 }
 
-impl TcpIngress {
+impl<R: AsyncRead + Unpin> TcpIngress<R> {
     pub fn new(
-        stream: tokio::net::tcp::OwnedReadHalf,
+        stream: R,
         buffer_pool: bab::HeapBufferPool,
         // Max packet size including bundle header.
         max_packet_size: usize,
@@ -25,10 +34,50 @@ impl TcpIngress {
             framer: bab::Framer::new(buffer_pool),
             max_packet_size,
             cursor: 0,
+            carry: Vec::new(),
+            carry_pos: 0,
+            scratch: Vec::new(),
+            decompression: false,
+            #[cfg(feature = "encryption")]
+            plane_keys: None,
         }
     }
 
-    pub async fn receive(&mut self) -> std::io::Result<Packet> {
+    /// Decompress bundles sent with `COMPRESSED_CHANNEL_BIT` set. Only enable this once we've
+    /// told the peer we can decompress - otherwise the bit is just part of the channel ID.
+    pub fn set_decompression(&mut self, enabled: bool) {
+        self.decompression = enabled;
+    }
+
+    /// Decrypt packets on encrypted planes as they're received.
+    #[cfg(feature = "encryption")]
+    pub fn set_plane_keys(&mut self, plane_keys: crate::PlaneKeyring) {
+        self.plane_keys = Some(plane_keys);
+    }
+
+    async fn read(
+        stream: &mut R,
+        carry: &mut Vec<u8>,
+        carry_pos: &mut usize,
+        buf: &mut [u8],
+    ) -> std::io::Result<usize> {
+        if *carry_pos < carry.len() {
+            let n = std::cmp::min(buf.len(), carry.len() - *carry_pos);
+            buf[..n].copy_from_slice(&carry[*carry_pos..*carry_pos + n]);
+            *carry_pos += n;
+            if *carry_pos == carry.len() {
+                carry.clear();
+                *carry_pos = 0;
+            }
+            return Ok(n);
+        }
+
+        stream.read(buf).await
+    }
+
+    /// Receive the next buffer of bundles. Fails if the connection closes or the peer sends a bundle
+    /// that can't be framed, after which the ingress must not be used again.
+    pub async fn receive(&mut self) -> Result<Packet, TransportError> {
         loop {
             let write = self.framer.write().await;
             if self.cursor < PacketBundle::BASE_LEN {
@@ -41,13 +90,16 @@ impl TcpIngress {
                 );
 
                 if max_read > self.cursor {
-                    let n = self.stream.read(&mut write[self.cursor..max_read]).await?;
+                    let n = Self::read(
+                        &mut self.stream,
+                        &mut self.carry,
+                        &mut self.carry_pos,
+                        &mut write[self.cursor..max_read],
+                    )
+                    .await?;
                     if n == 0 {
                         // Socket was shutdown
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::ConnectionReset,
-                            "tcp transport shutdown",
-                        ));
+                        return Err(connection_reset().into());
                     }
                     self.cursor += n;
                 } else {
@@ -59,9 +111,15 @@ impl TcpIngress {
                 }
             }
 
-            let packet_header: PacketBundle =
-                mproto::decode_value(&write).expect("decode rx bundle header");
+            let packet_header: PacketBundle = mproto::decode_value(&write)
+                .map_err(|_| TransportError::Malformed("invalid bundle header"))?;
             let packet_len = packet_header.length as usize;
+            if PacketBundle::BASE_LEN + packet_len > self.max_packet_size {
+                return Err(TransportError::BundleTooLarge {
+                    len: PacketBundle::BASE_LEN + packet_len,
+                    max_len: self.max_packet_size,
+                });
+            }
 
             while packet_len > self.cursor - PacketBundle::BASE_LEN {
                 // Read till whichever is higher - the max safe read length or the end of the current
@@ -71,19 +129,59 @@ impl TcpIngress {
                     write.len() - self.max_packet_size,
                     PacketBundle::BASE_LEN + packet_len,
                 );
-                let n = self.stream.read(&mut write[self.cursor..max_read]).await?;
+                let n = Self::read(
+                    &mut self.stream,
+                    &mut self.carry,
+                    &mut self.carry_pos,
+                    &mut write[self.cursor..max_read],
+                )
+                .await?;
                 if n == 0 {
                     // Socket was shutdown
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::ConnectionReset,
-                        "tcp transport shutdown",
-                    ));
+                    return Err(connection_reset().into());
                 }
                 self.cursor += n;
             }
 
-            self.framer.commit(PacketBundle::BASE_LEN + packet_len);
-            self.cursor -= PacketBundle::BASE_LEN + packet_len;
+            let bundle_len = PacketBundle::BASE_LEN + packet_len;
+            if self.decompression && is_compressed_bundle(&packet_header) {
+                // Stash anything read past this bundle so that it can be overwritten by the
+                // decompressed payload. Those bytes precede anything still left in the carry.
+                self.carry.drain(..self.carry_pos);
+                self.carry_pos = 0;
+                self.carry
+                    .splice(0..0, write[bundle_len..self.cursor].iter().copied());
+                self.scratch.clear();
+                self.scratch.extend_from_slice(&write[..bundle_len]);
+
+                let out_len = std::cmp::min(write.len(), self.max_packet_size);
+                let decompressed_len = decompress_bundle(&self.scratch, &mut write[..out_len])
+                    .map_err(TransportError::Decompress)?;
+                #[cfg(feature = "encryption")]
+                let decompressed_len = match &self.plane_keys {
+                    Some(plane_keys) => {
+                        plane_keys.decrypt_bundle_in_place(&mut write[..decompressed_len])
+                    }
+                    None => decompressed_len,
+                };
+                self.framer.commit(decompressed_len);
+                self.cursor = 0;
+            } else {
+                #[cfg(feature = "encryption")]
+                let bundle_len = match &self.plane_keys {
+                    Some(plane_keys) => {
+                        // Decryption shrinks the bundle - shift anything read past it down to match.
+                        let decrypted_len =
+                            plane_keys.decrypt_bundle_in_place(&mut write[..bundle_len]);
+                        write.copy_within(bundle_len..self.cursor, decrypted_len);
+                        self.cursor -= bundle_len - decrypted_len;
+                        decrypted_len
+                    }
+                    None => bundle_len,
+                };
+                self.framer.commit(bundle_len);
+                self.cursor -= bundle_len;
+            }
 
             let finished_packet = if self.framer.remaining_on_buffer() < self.max_packet_size {
                 debug_assert_eq!(self.cursor, 0);
@@ -98,3 +196,113 @@ impl TcpIngress {
         }
     }
 }
+
+fn connection_reset() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::ConnectionReset, "tcp transport shutdown")
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod test {
+    use std::{
+        collections::VecDeque,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+    use crate::{BundleCompressor, CompressionConfig, compressed_channel_id};
+
+    /// Returns at most one chunk per read.
+    struct ChunkedStream(VecDeque<Vec<u8>>);
+
+    impl AsyncRead for ChunkedStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if let Some(mut chunk) = self.0.pop_front() {
+                let n = std::cmp::min(chunk.len(), buf.remaining());
+                buf.put_slice(&chunk[..n]);
+                if n < chunk.len() {
+                    self.0.push_front(chunk.split_off(n));
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn payload(seed: u8) -> Vec<u8> {
+        (0..300).map(|i| seed.wrapping_add((i % 5) as u8)).collect()
+    }
+
+    fn plain_bundle(channel_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bundle = mproto::encode_value_vec(PacketBundle {
+            channel_id,
+            length: payload.len() as u16,
+        });
+        bundle.extend_from_slice(payload);
+        bundle
+    }
+
+    fn compressed_bundle(channel_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut compressor = BundleCompressor::new(CompressionConfig::new(64));
+        let compressed = compressor.compress(payload).unwrap();
+        plain_bundle(compressed_channel_id(channel_id).unwrap(), compressed)
+    }
+
+    /// Receive packets until `count` bundles have been read, and split them back into bundles.
+    fn receive_bundles(stream: ChunkedStream, count: usize) -> Vec<(u32, Vec<u8>)> {
+        let buffer_pool = bab::HeapBufferPool::new(2048, 1, 8);
+        let mut ingress = TcpIngress::new(stream, buffer_pool, 512);
+        ingress.set_decompression(true);
+
+        let mut bundles = Vec::new();
+        while bundles.len() < count {
+            let packet = pollster::block_on(ingress.receive()).unwrap();
+            let mut bytes = &packet[..];
+            while !bytes.is_empty() {
+                let header: PacketBundle = mproto::decode_value(bytes).unwrap();
+                let end = PacketBundle::BASE_LEN + header.length as usize;
+                bundles.push((header.channel_id, bytes[PacketBundle::BASE_LEN..end].to_vec()));
+                bytes = &bytes[end..];
+            }
+        }
+        bundles
+    }
+
+    #[test]
+    fn test_compressed_bundles_split_across_reads() {
+        let mut bytes = compressed_bundle(1, &payload(0));
+        bytes.extend(plain_bundle(2, b"plain"));
+        bytes.extend(compressed_bundle(3, &payload(1)));
+
+        // Split every bundle, header included, across several reads.
+        let stream = ChunkedStream(bytes.chunks(5).map(<[u8]>::to_vec).collect());
+        assert_eq!(
+            receive_bundles(stream, 3),
+            vec![(1, payload(0)), (2, b"plain".to_vec()), (3, payload(1))],
+        );
+    }
+
+    #[test]
+    fn test_compressed_bundles_in_one_read() {
+        let mut bytes = compressed_bundle(1, &payload(0));
+        bytes.extend(compressed_bundle(2, &payload(1)));
+        bytes.extend(plain_bundle(3, b"plain"));
+        bytes.extend(compressed_bundle(4, &payload(2)));
+
+        let stream = ChunkedStream(VecDeque::from([bytes]));
+        assert_eq!(
+            receive_bundles(stream, 4),
+            vec![
+                (1, payload(0)),
+                (2, payload(1)),
+                (3, b"plain".to_vec()),
+                (4, payload(2)),
+            ],
+        );
+    }
+}
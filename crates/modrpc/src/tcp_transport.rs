@@ -1,10 +1,17 @@
+use std::rc::Rc;
+
 use futures_lite::future;
+use futures_util::FutureExt;
 use mproto::BaseLen;
 use tokio::io::AsyncWriteExt;
 
 use crate::{
-    HeapBufferPool, Packet, PacketBundle, TcpIngress, TransportBuilder, TransportContext,
-    TransportHandle, WorkerId, transport::WriterConfig,
+    BundleCompressor, CaptureDirection, CompressionConfig, CompressionHandshake,
+    CompressionNegotiation, EgressConfig, EgressScheduler, HeapBufferPool, IdleTimer,
+    KeepaliveConfig, Packet, PacketBundle, TcpIngress, TransportBuilder, TransportContext,
+    TransportHandle, WorkerId, compressed_channel_id, decode_compression_hello,
+    encode_keepalive_bundle, is_control_bundle, is_goodbye_bundle, next_flushes,
+    transport::{WriterConfig, report_transport_error},
 };
 
 pub struct TcpTransport {
@@ -12,19 +19,35 @@ pub struct TcpTransport {
     pub out_buffer_pool: HeapBufferPool,
     pub worker_id: WorkerId,
     pub stream: tokio::net::TcpStream,
+    pub keepalive: Option<KeepaliveConfig>,
+    pub compression: Option<CompressionConfig>,
+    /// Whether this end offers compression or waits for the peer's offer. The accepting end of a
+    /// connection offers and the connecting end answers.
+    pub compression_negotiation: CompressionNegotiation,
+    /// Channel priorities for egress. If unset, all channels are given equal weight.
+    pub egress: Option<EgressConfig>,
+    /// Keys for the encrypted planes carried by this transport, see `PlaneKeyring::join_plane`.
+    #[cfg(feature = "encryption")]
+    pub plane_keys: Option<crate::PlaneKeyring>,
 }
 
 impl TransportBuilder for TcpTransport {
     async fn start_transport(self, cx: TransportContext<'_>) -> TransportHandle {
         let shutdown_signal = bab::SignalTree::new();
+        let drain_signal = bab::SignalTree::new();
 
         let (tcp_read, mut tcp_write) = self.stream.into_split();
+        let capture = cx.rt.capture().map(|capture| capture.add_transport("tcp"));
+
+        #[cfg(feature = "encryption")]
+        let ingress_plane_keys = self.plane_keys.clone();
 
         let writer_flush_sender = cx
             .rt
             .get_worker(self.worker_id)
             .run_once({
                 let shutdown_signal = shutdown_signal.clone();
+                let drain_signal = drain_signal.clone();
                 let in_buffer_pool = self.in_buffer_pool;
                 let out_buffer_pool = self.out_buffer_pool.clone();
                 move |worker_cx| {
@@ -33,57 +56,171 @@ impl TransportBuilder for TcpTransport {
                     let (writer_flush_sender, mut writer_flush_receiver) =
                         bab::new_writer_flusher();
 
+                    let compression_handshake = Rc::new(CompressionHandshake::new(
+                        self.compression.is_some(),
+                        self.compression_negotiation,
+                    ));
+
                     // Spawn task to flush egress packets
                     worker_cx.spawn_traced("tcp-tx", core::time::Duration::from_millis(1000), {
                         let shutdown_notifier = shutdown_signal.clone();
                         let shutdown_waiter = shutdown_signal.clone();
+                        let worker_cx = worker_cx.clone();
+                        let keepalive_interval = self.keepalive.map(|k| k.interval);
+                        let mut compressor = self.compression.map(BundleCompressor::new);
+                        let compression_handshake = compression_handshake.clone();
+                        let mut scheduler = EgressScheduler::new(self.egress.unwrap_or_default());
+                        let capture = capture.clone();
                         async move |tracer| {
                             future::or(
                                 async move {
                                     let bundle_header_buf = bundle_header_buf.as_mut_slice();
 
+                                    if let Some(hello) = compression_handshake.offer() {
+                                        if let Some(capture) = &capture {
+                                            capture.record(CaptureDirection::Outbound, &hello);
+                                        }
+                                        if tcp_write.write_all(&hello).await.is_err() {
+                                            shutdown_notifier.notify();
+                                            return;
+                                        }
+                                    }
+
                                     'flush_loop: loop {
-                                        for flush in writer_flush_receiver.flush().await {
-                                            let start = std::time::Instant::now();
+                                        if let Some(answer) = compression_handshake.take_answer() {
+                                            if let Some(capture) = &capture {
+                                                capture.record(CaptureDirection::Outbound, &answer);
+                                            }
+                                            if tcp_write.write_all(&answer).await.is_err() {
+                                                break 'flush_loop;
+                                            }
+                                        }
 
-                                            if flush.len() > 0 {
-                                                if let Err(_) = tracer
-                                                    .trace_future(async {
-                                                        probius::trace_label("receive-buffer");
-                                                        probius::trace_metric(
-                                                            "buffer_size",
-                                                            flush.len() as i64,
+                                        if !scheduler.is_empty() {
+                                            // Pick up anything flushed since the last write
+                                            // without waiting, so that it can be scheduled ahead
+                                            // of bundles that are already queued.
+                                            if let Some(flushes) =
+                                                writer_flush_receiver.flush().now_or_never()
+                                            {
+                                                scheduler.push_flushes(flushes);
+                                            }
+                                        } else {
+                                            let wake = future::or(
+                                                async {
+                                                    match next_flushes(
+                                                        &mut writer_flush_receiver,
+                                                        &drain_signal,
+                                                    )
+                                                    .await
+                                                    {
+                                                        Some(flushes) => TxWake::Flushes(flushes),
+                                                        None => TxWake::Drained,
+                                                    }
+                                                },
+                                                async {
+                                                    compression_handshake.answer_due().await;
+                                                    TxWake::CompressionAnswer
+                                                },
+                                            );
+                                            let wake = match keepalive_interval {
+                                                Some(interval) => {
+                                                    future::or(wake, async {
+                                                        worker_cx.sleep(interval).await;
+                                                        TxWake::Keepalive
+                                                    })
+                                                    .await
+                                                }
+                                                None => wake.await,
+                                            };
+                                            match wake {
+                                                TxWake::Flushes(flushes) => {
+                                                    scheduler.push_flushes(flushes);
+                                                }
+                                                TxWake::Keepalive => {
+                                                    // Nothing has been sent for a while - let the
+                                                    // peer know we're still here.
+                                                    let keepalive = encode_keepalive_bundle();
+                                                    if let Some(capture) = &capture {
+                                                        capture.record(
+                                                            CaptureDirection::Outbound,
+                                                            &keepalive,
                                                         );
+                                                    }
+                                                    if tcp_write.write_all(&keepalive).await.is_err()
+                                                    {
+                                                        break 'flush_loop;
+                                                    }
+                                                    continue;
+                                                }
+                                                // Written at the top of the loop.
+                                                TxWake::CompressionAnswer => continue,
+                                                // Everything flushed before shutdown has been written.
+                                                TxWake::Drained => break 'flush_loop,
+                                            }
+                                        }
 
-                                                        // Fill bundle header
-                                                        mproto::encode_value(
-                                                            PacketBundle {
-                                                                channel_id: flush.writer_id()
-                                                                    as u32,
-                                                                length: flush.len() as u16,
-                                                            },
-                                                            &mut bundle_header_buf[..],
-                                                        );
+                                        // Write one bundle at a time.
+                                        if let Some((_, flush)) = scheduler.pop() {
+                                            let start = std::time::Instant::now();
+
+                                            if let Err(_) = tracer
+                                                .trace_future(async {
+                                                    probius::trace_label("receive-buffer");
+                                                    probius::trace_metric(
+                                                        "buffer_size",
+                                                        flush.len() as i64,
+                                                    );
 
-                                                        // Write to socket
-                                                        tcp_write
-                                                            .write_all(bundle_header_buf)
-                                                            .await?;
-                                                        tcp_write.write_all(&flush).await?;
-
-                                                        probius::trace_metric(
-                                                            "duration_us",
-                                                            (std::time::Instant::now() - start)
-                                                                .as_micros()
-                                                                as i64,
+                                                    if let Some(capture) = &capture {
+                                                        capture.record_bundle(
+                                                            CaptureDirection::Outbound,
+                                                            flush.writer_id() as u32,
+                                                            &flush[..],
                                                         );
+                                                    }
 
-                                                        Ok::<_, std::io::Error>(())
-                                                    })
-                                                    .await
-                                                {
-                                                    break 'flush_loop;
-                                                }
+                                                    let channel_id = flush.writer_id() as u32;
+                                                    let compressed = compressor
+                                                        .as_mut()
+                                                        .filter(|_| {
+                                                            compression_handshake.peer_decompresses()
+                                                        })
+                                                        .zip(compressed_channel_id(channel_id))
+                                                        .and_then(|(c, compressed_channel_id)| {
+                                                            Some((
+                                                                compressed_channel_id,
+                                                                c.compress(&flush)?,
+                                                            ))
+                                                        });
+                                                    let (channel_id, payload) =
+                                                        compressed.unwrap_or((channel_id, &flush[..]));
+
+                                                    // Fill bundle header
+                                                    mproto::encode_value(
+                                                        PacketBundle {
+                                                            channel_id,
+                                                            length: payload.len() as u16,
+                                                        },
+                                                        &mut bundle_header_buf[..],
+                                                    );
+
+                                                    // Write to socket
+                                                    tcp_write.write_all(bundle_header_buf).await?;
+                                                    tcp_write.write_all(payload).await?;
+
+                                                    probius::trace_metric(
+                                                        "duration_us",
+                                                        (std::time::Instant::now() - start)
+                                                            .as_micros()
+                                                            as i64,
+                                                    );
+
+                                                    Ok::<_, std::io::Error>(())
+                                                })
+                                                .await
+                                            {
+                                                break 'flush_loop;
                                             }
                                         }
                                     }
@@ -101,11 +238,18 @@ impl TransportBuilder for TcpTransport {
                     // Spawn task to receive ingress packets
                     let mut tcp_ingress =
                         TcpIngress::new(tcp_read, in_buffer_pool.clone(), in_buffer_pool.buffer_size());
+                    tcp_ingress.set_decompression(self.compression.is_some());
+                    #[cfg(feature = "encryption")]
+                    if let Some(plane_keys) = ingress_plane_keys {
+                        tcp_ingress.set_plane_keys(plane_keys);
+                    }
                     worker_cx.spawn_traced("tcp-rx", core::time::Duration::from_millis(1000), {
                         let shutdown_notifier = shutdown_signal.clone();
                         let shutdown_waiter = shutdown_signal.clone();
                         let process_packet_fn = worker_cx.get_packet_processor();
-                        let max_buffer_size = in_buffer_pool.buffer_size();
+                        let metrics = worker_cx.rt.metrics().clone();
+                        let worker_cx = worker_cx.clone();
+                        let idle_timer = self.keepalive.map(|k| IdleTimer::new(k.idle_timeout));
                         async move |tracer| {
                             future::or(
                                 async move {
@@ -117,47 +261,90 @@ impl TransportBuilder for TcpTransport {
 
                                     let mut last_rx_end = std::time::Instant::now();
 
-                                    while let Ok(packet_bundle) = tcp_ingress.receive().await {
-                                        let header = crate::shatter_packet_bundle(
-                                            packet_bundle,
-                                            &mut shatter_offsets,
-                                            &mut shatter_out_packets,
-                                        );
-                                        assert!(header.length as usize <= max_buffer_size);
-
-                                        tracer.trace(|| {
-                                            probius::trace_label("tcp-rx");
-                                            probius::trace_branch(|| {
-                                                probius::trace_label("receive-bundle");
-                                                probius::trace_metric(
-                                                    "bytes",
-                                                    header.length as i64,
-                                                );
-                                                probius::trace_metric(
-                                                    "gap_us",
-                                                    (std::time::Instant::now() - last_rx_end)
-                                                        .as_micros()
-                                                        as i64,
+                                    let rx_loop = async {
+                                        loop {
+                                            let packet_bundle = match tcp_ingress.receive().await {
+                                                Ok(packet_bundle) => packet_bundle,
+                                                Err(e) => {
+                                                    report_transport_error(&metrics, "tcp", &e);
+                                                    break;
+                                                }
+                                            };
+                                            if let Some(capture) = &capture {
+                                                capture.record(
+                                                    CaptureDirection::Inbound,
+                                                    &packet_bundle[..],
                                                 );
+                                            }
+                                            if let Some(idle_timer) = &idle_timer {
+                                                idle_timer.reset();
+                                            }
+                                            if is_control_bundle(&packet_bundle[..]) {
+                                                if is_goodbye_bundle(&packet_bundle[..]) {
+                                                    // The peer is closing the transport on purpose.
+                                                    break;
+                                                }
+                                                if let Some(algorithms) =
+                                                    decode_compression_hello(&packet_bundle[..])
+                                                {
+                                                    compression_handshake.receive_hello(algorithms);
+                                                }
+                                                continue;
+                                            }
+
+                                            let header = match crate::shatter_packet_bundle(
+                                                packet_bundle,
+                                                &mut shatter_offsets,
+                                                &mut shatter_out_packets,
+                                            ) {
+                                                Ok(header) => header,
+                                                Err(e) => {
+                                                    report_transport_error(&metrics, "tcp", &e);
+                                                    break;
+                                                }
+                                            };
+
+                                            tracer.trace(|| {
+                                                probius::trace_label("tcp-rx");
+                                                probius::trace_branch(|| {
+                                                    probius::trace_label("receive-bundle");
+                                                    probius::trace_metric(
+                                                        "bytes",
+                                                        header.length as i64,
+                                                    );
+                                                    probius::trace_metric(
+                                                        "gap_us",
+                                                        (std::time::Instant::now() - last_rx_end)
+                                                            .as_micros()
+                                                            as i64,
+                                                    );
+                                                });
                                             });
-                                        });
 
-                                        for packet in shatter_out_packets.drain(..) {
-                                            let packet = unsafe { packet.assume_init() };
-                                            tracer
-                                                .trace_future(async {
-                                                    probius::trace_label("tcp-rx");
-                                                    probius::trace_branch_start();
-                                                    probius::trace_label("receive-packet");
-                                                    probius::trace_branch_start();
-                                                    process_packet_fn(&packet).await;
-                                                    probius::trace_branch_end();
-                                                    probius::trace_branch_end();
-                                                })
-                                                .await;
+                                            for packet in shatter_out_packets.drain(..) {
+                                                let packet = unsafe { packet.assume_init() };
+                                                tracer
+                                                    .trace_future(async {
+                                                        probius::trace_label("tcp-rx");
+                                                        probius::trace_branch_start();
+                                                        probius::trace_label("receive-packet");
+                                                        probius::trace_branch_start();
+                                                        process_packet_fn(&packet).await;
+                                                        probius::trace_branch_end();
+                                                        probius::trace_branch_end();
+                                                    })
+                                                    .await;
+                                            }
+
+                                            last_rx_end = std::time::Instant::now();
                                         }
+                                    };
 
-                                        last_rx_end = std::time::Instant::now();
+                                    if let Some(idle_timer) = &idle_timer {
+                                        // Treat a peer that has gone quiet for too long as dead.
+                                        future::or(rx_loop, idle_timer.expired(&worker_cx)).await;
+                                    } else {
+                                        rx_loop.await;
                                     }
 
                                     shutdown_notifier.notify();
@@ -176,12 +363,25 @@ impl TransportBuilder for TcpTransport {
             })
             .await;
 
-        TransportHandle {
-            shutdown_signal,
-            buffer_pool: self.out_buffer_pool,
-            writer_config: WriterConfig::LocalFlush {
+        let mut transport = TransportHandle::new(
+            self.out_buffer_pool,
+            WriterConfig::LocalFlush {
                 writer_flush_sender,
             },
+            shutdown_signal,
+        );
+        transport.drain_signal = Some(drain_signal);
+        #[cfg(feature = "encryption")]
+        {
+            transport.plane_keys = self.plane_keys;
         }
+        transport
     }
 }
+
+enum TxWake<F> {
+    Flushes(F),
+    Keepalive,
+    CompressionAnswer,
+    Drained,
+}
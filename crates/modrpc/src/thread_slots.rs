@@ -0,0 +1,92 @@
+use core::cell::UnsafeCell;
+use std::sync::OnceLock;
+
+// Thread IDs are `u32`s, and bucket `i` holds `2^i` slots.
+const BUCKET_COUNT: usize = u32::BITS as usize;
+
+type Bucket<T> = OnceLock<Box<[UnsafeCell<Option<T>>]>>;
+
+/// A value per thread, indexed by `thid::ThreadId`. Thread IDs are handed out process-wide and
+/// never reused, so slots are allocated lazily in buckets of doubling size. Allocated slots
+/// never move.
+pub(crate) struct ThreadSlots<T> {
+    buckets: [Bucket<T>; BUCKET_COUNT],
+}
+
+// safety: a slot is only ever accessed from the thread it belongs to.
+unsafe impl<T> Send for ThreadSlots<T> {}
+unsafe impl<T> Sync for ThreadSlots<T> {}
+
+impl<T> ThreadSlots<T> {
+    pub fn new() -> Self {
+        Self {
+            buckets: core::array::from_fn(|_| OnceLock::new()),
+        }
+    }
+
+    /// The current thread's value.
+    pub fn get(&self) -> Option<&T> {
+        let (bucket, index) = bucket_index(thid::ThreadId::current().as_usize());
+        let slot = self.buckets[bucket].get()?.get(index)?;
+        unsafe { (*slot.get()).as_ref() }
+    }
+
+    /// Replace the current thread's value, returning the previous one.
+    ///
+    /// # Safety
+    ///
+    /// No reference returned by `get` on the current thread may still be in use.
+    pub unsafe fn set(&self, value: Option<T>) -> Option<T> {
+        let (bucket, index) = bucket_index(thid::ThreadId::current().as_usize());
+        let slots = self.buckets[bucket]
+            .get_or_init(|| (0..1usize << bucket).map(|_| UnsafeCell::new(None)).collect());
+        core::mem::replace(unsafe { &mut *slots[index].get() }, value)
+    }
+}
+
+fn bucket_index(thread_index: usize) -> (usize, usize) {
+    let n = thread_index + 1;
+    let bucket = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    (bucket, n - (1 << bucket))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bucket_index() {
+        assert_eq!(bucket_index(0), (0, 0));
+        assert_eq!(bucket_index(1), (1, 0));
+        assert_eq!(bucket_index(2), (1, 1));
+        assert_eq!(bucket_index(3), (2, 0));
+        assert_eq!(bucket_index(64), (6, 1));
+        assert_eq!(bucket_index(u32::MAX as usize - 1), (31, (1 << 31) - 1));
+    }
+
+    #[test]
+    fn test_thread_slots() {
+        let slots = std::sync::Arc::new(ThreadSlots::new());
+        assert_eq!(slots.get(), None);
+        unsafe { slots.set(Some(1)) };
+
+        // Threads beyond the first 64 get slots of their own too.
+        let threads: Vec<_> = (0..100)
+            .map(|i| {
+                let slots = slots.clone();
+                std::thread::spawn(move || {
+                    assert_eq!(slots.get(), None);
+                    unsafe { slots.set(Some(i + 2)) };
+                    assert_eq!(slots.get(), Some(&(i + 2)));
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(slots.get(), Some(&1));
+        assert_eq!(unsafe { slots.set(None) }, Some(1));
+        assert_eq!(slots.get(), None);
+    }
+}
@@ -0,0 +1,131 @@
+use mproto::{BaseLen, Decode, DecodeCursor, DecodeResult, Encode, EncodeCursor};
+
+use crate::{EndpointAddr, PacketBundle, TransmitPacket};
+
+/// Channel ID reserved for the bundle an endpoint sends a relay (such as a modrpc_hub) to declare
+/// which topics it wants to receive. Each subscriptions bundle replaces the previous one.
+pub const TOPIC_SUBSCRIPTIONS_CHANNEL_ID: u32 = u32::MAX - 2;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TopicSubscription {
+    pub topic: u32,
+    /// Byte offset into the event payload of the `EndpointAddr` the event is addressed to. If set,
+    /// only events addressed to the subscriber are delivered to it. Relays can't read the payloads
+    /// of encrypted planes, so leave this unset for topics on them.
+    pub addressed_at: Option<u16>,
+}
+
+impl TopicSubscription {
+    pub fn all(topic: u32) -> Self {
+        Self { topic, addressed_at: None }
+    }
+
+    pub fn addressed(topic: u32, addressed_at: u16) -> Self {
+        Self { topic, addressed_at: Some(addressed_at) }
+    }
+
+    /// Whether a packet on this subscription's topic should be delivered to `subscriber`.
+    pub fn matches(&self, packet: &[u8], subscriber: EndpointAddr) -> bool {
+        let Some(addressed_at) = self.addressed_at else {
+            return true;
+        };
+        let offset = TransmitPacket::BASE_LEN + addressed_at as usize;
+        packet.get(offset..offset + EndpointAddr::BASE_LEN)
+            .and_then(|addr| mproto::decode_value::<EndpointAddr>(addr).ok())
+            .is_some_and(|addr| addr == subscriber)
+    }
+}
+
+impl BaseLen for TopicSubscription {
+    const BASE_LEN: usize = u32::BASE_LEN + Option::<u16>::BASE_LEN;
+}
+
+impl Encode for TopicSubscription {
+    fn scratch_len(&self) -> usize {
+        0
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.topic.encode(cursor);
+        self.addressed_at.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for TopicSubscription {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let topic = Decode::decode(cursor)?;
+        let addressed_at = Decode::decode(cursor)?;
+
+        Ok(TopicSubscription { topic, addressed_at })
+    }
+}
+
+/// Encode a subscriptions bundle, ready to be written to a transport's stream.
+pub fn encode_topic_subscriptions(subscriptions: &[TopicSubscription]) -> Vec<u8> {
+    let payload_len = subscriptions.len() * TopicSubscription::BASE_LEN;
+    let mut buf = vec![0u8; PacketBundle::BASE_LEN + payload_len];
+    mproto::encode_value(
+        PacketBundle {
+            channel_id: TOPIC_SUBSCRIPTIONS_CHANNEL_ID,
+            length: payload_len as u16,
+        },
+        &mut buf[..PacketBundle::BASE_LEN],
+    );
+    encode_topic_subscriptions_payload(subscriptions, &mut buf[PacketBundle::BASE_LEN..]);
+    buf
+}
+
+/// Encode the payload of a subscriptions bundle into `out`, which must be exactly
+/// `subscriptions.len() * TopicSubscription::BASE_LEN` bytes long.
+pub(crate) fn encode_topic_subscriptions_payload(
+    subscriptions: &[TopicSubscription],
+    out: &mut [u8],
+) {
+    for (subscription, out) in subscriptions.iter()
+        .zip(out.chunks_exact_mut(TopicSubscription::BASE_LEN))
+    {
+        mproto::encode_value(subscription, out);
+    }
+}
+
+/// If `packet_bundle` is a subscriptions bundle, returns the subscriptions it declares.
+pub fn decode_topic_subscriptions(packet_bundle: &[u8]) -> Option<Vec<TopicSubscription>> {
+    let header = mproto::decode_value::<PacketBundle>(packet_bundle).ok()?;
+    if header.channel_id != TOPIC_SUBSCRIPTIONS_CHANNEL_ID {
+        return None;
+    }
+    let payload =
+        packet_bundle.get(PacketBundle::BASE_LEN..PacketBundle::BASE_LEN + header.length as usize)?;
+    if payload.len() % TopicSubscription::BASE_LEN != 0 {
+        return None;
+    }
+    payload.chunks_exact(TopicSubscription::BASE_LEN)
+        .map(|subscription| mproto::decode_value(subscription).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_topic_subscriptions_roundtrip() {
+        let subscriptions = [TopicSubscription::all(1), TopicSubscription::addressed(2, 4)];
+        let bundle = encode_topic_subscriptions(&subscriptions);
+        assert!(crate::is_control_bundle(&bundle));
+        assert_eq!(decode_topic_subscriptions(&bundle), Some(subscriptions.to_vec()));
+        assert_eq!(decode_topic_subscriptions(&bundle[..bundle.len() - 1]), None);
+        assert_eq!(decode_topic_subscriptions(&crate::encode_keepalive_bundle()), None);
+    }
+
+    #[test]
+    fn test_addressed_match() {
+        let subscription = TopicSubscription::addressed(2, 4);
+        let mut packet = vec![0u8; TransmitPacket::BASE_LEN + 4];
+        packet.extend(7u64.to_le_bytes());
+
+        assert!(subscription.matches(&packet, EndpointAddr { endpoint: 7 }));
+        assert!(!subscription.matches(&packet, EndpointAddr { endpoint: 8 }));
+        assert!(!subscription.matches(&packet[..packet.len() - 1], EndpointAddr { endpoint: 7 }));
+    }
+}
@@ -5,13 +5,214 @@ use std::sync::Arc;
 use bab::Packet;
 
 use crate::{
-    PacketBundle, RuntimeHandle, TransmitPacket, WorkerContext, flush_batcher::FlushBatcher,
+    CompressionConfig, EgressConfig, KeepaliveConfig, PacketBundle, RuntimeHandle,
+    TOPIC_SUBSCRIPTIONS_CHANNEL_ID, TopicChannels, TopicSubscription, TransmitPacket,
+    TransportConfig, WorkerContext, flush_batcher::FlushBatcher,
+    topic_subscription::encode_topic_subscriptions_payload,
 };
 
+/// Channel IDs at or above this are reserved for transport-level control bundles (keepalives,
+/// compression negotiation, goodbyes) which are consumed by the transport and never shattered
+/// into packets.
+pub const FIRST_CONTROL_CHANNEL_ID: u32 = 0xFFFF_FF00;
+
+/// `TransmitPacket::infra_id` of the packets endpoints exchange plane encryption keys with. These
+/// are consumed by the transport's `PlaneKeyring`, and ignored by endpoints that don't encrypt.
+pub const KEY_EXCHANGE_INFRA_ID: u16 = u16::MAX;
+
+/// The next buffers flushed to a transport, for its tx task to write. Once `drain_signal` has
+/// been notified, returns `None` as soon as everything flushed so far has been handed out - see
+/// `TransportHandle::drain_signal`.
+pub async fn next_flushes(
+    writer_flush_receiver: &mut bab::WriterFlushReceiver,
+    drain_signal: &bab::SignalTree,
+) -> Option<impl Iterator<Item = bab::Flush>> {
+    use futures_util::FutureExt;
+
+    if !drain_signal.is_notified() {
+        let flushes = futures_lite::future::or(
+            async { Some(writer_flush_receiver.flush().await) },
+            async {
+                drain_signal.wait().await;
+                None
+            },
+        )
+        .await;
+        if flushes.is_some() {
+            return flushes;
+        }
+    }
+    writer_flush_receiver.flush().now_or_never()
+}
+
+pub fn is_control_bundle(packet_bundle: &[u8]) -> bool {
+    use mproto::BaseLen;
+
+    packet_bundle.len() >= PacketBundle::BASE_LEN
+        && mproto::decode_value::<PacketBundle>(packet_bundle)
+            .is_ok_and(|header| header.channel_id >= FIRST_CONTROL_CHANNEL_ID)
+}
+
+/// Why a transport stopped receiving from its peer.
+#[derive(Debug)]
+pub enum TransportError {
+    /// The connection failed or was closed.
+    Io(std::io::Error),
+    /// The peer sent a bundle whose headers don't describe its contents.
+    Malformed(&'static str),
+    /// The peer sent a bundle larger than the transport's buffers.
+    BundleTooLarge { len: usize, max_len: usize },
+    /// The peer sent a compressed bundle that failed to decompress.
+    Decompress(std::io::Error),
+}
+
+impl TransportError {
+    /// Whether the peer sent data that no well-behaved peer would.
+    pub fn is_protocol_violation(&self) -> bool {
+        !matches!(self, TransportError::Io(_))
+    }
+}
+
+impl core::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TransportError::Io(e) => write!(f, "transport I/O error: {e}"),
+            TransportError::Malformed(reason) => write!(f, "malformed bundle: {reason}"),
+            TransportError::BundleTooLarge { len, max_len } => {
+                write!(f, "bundle of {len} bytes exceeds the maximum of {max_len} bytes")
+            }
+            TransportError::Decompress(e) => write!(f, "failed to decompress bundle: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransportError::Io(e) | TransportError::Decompress(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TransportError {
+    fn from(e: std::io::Error) -> Self {
+        TransportError::Io(e)
+    }
+}
+
+/// Count and log a bad bundle received from a peer before closing its transport. Connection
+/// errors aren't reported.
+pub fn report_transport_error(
+    metrics: &crate::MetricsRegistry,
+    transport: &str,
+    error: &TransportError,
+) {
+    if !error.is_protocol_violation() {
+        return;
+    }
+    metrics
+        .counter(
+            "modrpc_transport_malformed_bundles",
+            "Bundles received from peers that failed validation. The transport is closed.",
+            &[("transport", transport)],
+        )
+        .inc();
+    log::warn!("Closing {transport} transport: {error}");
+}
+
 pub struct TransportContext<'a> {
     pub rt: &'a RuntimeHandle,
 }
 
+/// Optional behavior of the network transports started by the TCP and WebSocket connect and
+/// accept helpers.
+#[derive(Clone, Debug, Default)]
+pub struct TransportOptions {
+    /// Send keepalives while idle and close the transport if the peer goes silent. Required to
+    /// stay connected to a hub built with `AppHubBuilder::keepalive`.
+    pub keepalive: Option<KeepaliveConfig>,
+    /// Compress bundles for peers that can decompress them. Whichever end accepted the connection
+    /// offers compression and the connecting end answers, so both ends must enable it.
+    pub compression: Option<CompressionConfig>,
+    /// Channel priorities for egress. If unset, all channels are given equal weight.
+    pub egress: Option<EgressConfig>,
+    /// Encrypt the plane with per-endpoint sender keys exchanged with every other endpoint on it.
+    /// Packets from a peer are dropped until keys have been exchanged with it - see
+    /// `PlaneKeyring::wait_for_peer` on the `plane_keys` of the returned `TransportHandle`.
+    #[cfg(feature = "encryption")]
+    pub encryption: Option<crate::PlaneEncryptionConfig>,
+    /// Channels the role's topics are sent on. Defaults to a single channel with the same ID as
+    /// the plane. Clients of a hub must send on the channels the hub relays the plane over - see
+    /// `AppHubBuilder::plane_channels`.
+    pub topic_channels: Option<TopicChannels>,
+    /// Ask the relay on the other end (such as a modrpc_hub) to only send the topics of the
+    /// role's interface, and to only send addressed events to the endpoint they're addressed to -
+    /// see `InterfaceBuilder::addressed_event_from`. Relays can't read the payloads of encrypted
+    /// planes, so every event is sent to every endpoint on them.
+    pub subscribe_topics: bool,
+}
+
+impl TransportOptions {
+    #[cfg(any(feature = "tcp-transport", feature = "web-ws-transport"))]
+    pub(crate) fn topic_channels(&self, plane_id: u32) -> TopicChannels {
+        self.topic_channels
+            .clone()
+            .unwrap_or(TopicChannels::SingleChannel {
+                channel_id: plane_id,
+            })
+    }
+
+    /// Send the relay on the other end of `transport` the topic subscriptions of `Interface`, if
+    /// `subscribe_topics` is set.
+    #[cfg(any(feature = "tcp-transport", feature = "web-ws-transport"))]
+    pub(crate) async fn subscribe_topics<Interface: crate::InterfaceSchema>(
+        &self,
+        rt: &RuntimeHandle,
+        transport: &TransportHandle,
+    ) {
+        if !self.subscribe_topics {
+            return;
+        }
+
+        let mut ib = crate::InterfaceBuilder::new();
+        Interface::new(&mut ib);
+        let subscriptions = ib.topic_subscriptions();
+        #[cfg(feature = "encryption")]
+        let subscriptions = match self.encryption {
+            Some(_) => subscriptions
+                .into_iter()
+                .map(|subscription| TopicSubscription::all(subscription.topic))
+                .collect(),
+            None => subscriptions,
+        };
+        let worker_cx = rt
+            .local_worker_context()
+            .expect("modrpc topic subscriptions must be sent from a modrpc worker");
+        transport
+            .send_topic_subscriptions(worker_cx, &subscriptions)
+            .await;
+    }
+
+    /// Create the keyring for a transport that carries `plane_id` on `channel_id`, if encryption
+    /// is enabled.
+    #[cfg(all(
+        feature = "encryption",
+        any(feature = "tcp-transport", feature = "web-ws-transport")
+    ))]
+    pub(crate) fn plane_keys(
+        &self,
+        plane_id: u32,
+        channel_id: u32,
+        endpoint_addr: crate::EndpointAddr,
+    ) -> Option<crate::PlaneKeyring> {
+        let config = self.encryption.clone()?;
+        let plane_keys = crate::PlaneKeyring::new();
+        plane_keys.join_plane(plane_id, channel_id, endpoint_addr, config);
+        Some(plane_keys)
+    }
+}
+
 pub trait TransportBuilder {
     #[allow(async_fn_in_trait)]
     async fn start_transport(self, cx: TransportContext<'_>) -> TransportHandle;
@@ -28,8 +229,23 @@ pub struct TransportHandle {
     pub buffer_pool: bab::HeapBufferPool,
     pub writer_config: WriterConfig,
     pub shutdown_signal: bab::SignalTree,
+    /// Overrides the runtime's default `TransportConfig`.
+    pub config: Option<TransportConfig>,
+    /// Keys for the encrypted planes carried by this transport.
+    #[cfg(feature = "encryption")]
+    pub plane_keys: Option<crate::PlaneKeyring>,
+    /// Notified by `RuntimeShutdownHandle::shutdown` once every writer has been flushed. The
+    /// transport then writes out everything flushed to it (see `next_flushes`) and notifies
+    /// `shutdown_signal`, or is shut down anyway after `RuntimeConfig::shutdown_drain_timeout`.
+    /// Transports without one are shut down straight away. Its tx task must be the only thing
+    /// waiting on it.
+    pub drain_signal: Option<bab::SignalTree>,
 }
 
+/// The local-flush writers a worker has created for a transport, keyed by the transport's
+/// `WriterFlushSender`.
+struct LocalWriters(Vec<bab::DynWriter>);
+
 #[derive(Clone)]
 pub enum WriterConfig {
     Shared {
@@ -51,15 +267,63 @@ impl TransportBuilder for LocalTransport {
             self.buffer_pool_batch_size,
         );
 
-        TransportHandle {
+        TransportHandle::new(buffer_pool, WriterConfig::LocalNoFlush, shutdown_signal)
+    }
+}
+
+impl TransportHandle {
+    pub fn new(
+        buffer_pool: bab::HeapBufferPool,
+        writer_config: WriterConfig,
+        shutdown_signal: bab::SignalTree,
+    ) -> Self {
+        Self {
             buffer_pool,
-            writer_config: WriterConfig::LocalNoFlush,
+            writer_config,
             shutdown_signal,
+            config: None,
+            #[cfg(feature = "encryption")]
+            plane_keys: None,
+            drain_signal: None,
         }
     }
-}
 
-impl TransportHandle {
+    /// Hand the transport everything written to it on this worker so far.
+    pub(crate) fn flush_writers(&self, worker_cx: &WorkerContext) {
+        let Some(writer_flush_sender) = self.writer_flush_sender() else {
+            return;
+        };
+        if let WriterConfig::LocalFlush { .. } = &self.writer_config {
+            worker_cx.with_local_fn(
+                writer_flush_sender.id(),
+                || LocalWriters(Vec::new()),
+                |writers| {
+                    for writer in &writers.0 {
+                        writer.flush_local();
+                    }
+                },
+            );
+        }
+        // Shared writers only buffer in the flush sender.
+        writer_flush_sender.flush();
+    }
+
+    /// Ask the relay on the other end of the transport (such as a modrpc_hub) to only send the
+    /// given topics from now on, replacing any earlier subscriptions.
+    pub async fn send_topic_subscriptions(
+        &self,
+        worker_cx: &WorkerContext,
+        subscriptions: &[TopicSubscription],
+    ) {
+        let (writer, _) = self.new_writer(worker_cx, TOPIC_SUBSCRIPTIONS_CHANNEL_ID);
+        let mut payload = writer
+            .reserve(subscriptions.len() * <TopicSubscription as mproto::BaseLen>::BASE_LEN)
+            .await;
+        encode_topic_subscriptions_payload(subscriptions, &mut payload);
+        let _: Packet = payload.into();
+        writer.flush();
+    }
+
     pub(crate) fn writer_flush_sender(&self) -> Option<bab::WriterFlushSender> {
         match &self.writer_config {
             WriterConfig::Shared {
@@ -73,16 +337,25 @@ impl TransportHandle {
         }
     }
 
+    fn effective_config(&self, worker_cx: &WorkerContext) -> TransportConfig {
+        self.config.unwrap_or(worker_cx.rt().config().transport)
+    }
+
     pub(crate) fn get_local_flush_batcher(
+        &self,
         worker_cx: &WorkerContext,
         writer_flush_sender: &bab::WriterFlushSender,
     ) -> FlushBatcher {
+        let config = self.effective_config(worker_cx);
         // TODO how do we clean these up when the transport shuts down?
         worker_cx.with_local_fn(
             writer_flush_sender.id(),
             || {
-                // TODO configurable
-                FlushBatcher::new(std::time::Duration::from_micros(0))
+                FlushBatcher::new(
+                    config.max_flush_delay,
+                    config.max_unflushed_bytes,
+                    worker_cx.rt().metrics(),
+                )
             },
             |flush_batcher| flush_batcher.clone(),
         )
@@ -116,27 +389,30 @@ impl TransportHandle {
                 // bab::SharedWriter::flush_local is a no-op, so the transport-level flusher can be
                 // used directly.
                 let transport_flush_batcher =
-                    Self::get_local_flush_batcher(worker_cx, &writer_flush_sender);
+                    self.get_local_flush_batcher(worker_cx, writer_flush_sender);
                 (writer.clone().to_dyn(), Some(transport_flush_batcher))
             }
             WriterConfig::LocalFlush {
                 writer_flush_sender,
             } => {
                 let transport_flush_batcher =
-                    Self::get_local_flush_batcher(worker_cx, &writer_flush_sender);
+                    self.get_local_flush_batcher(worker_cx, writer_flush_sender);
 
                 // TODO how do we clean these up when the transport shuts down?
                 // Convert this to per-Transport ThreadLocal<HashMap<channel_id, bab::DynWriter>>?
+                // Keyed by transport too, so that transports sharing a worker don't share writers.
+                let created = core::cell::Cell::new(false);
                 let (writer, flush_batcher) = worker_cx.with_local_fn(
-                    channel_id,
+                    (writer_flush_sender.id(), channel_id),
                     || {
+                        created.set(true);
                         let writer = bab::Writer::new_local_flush(
                             self.buffer_pool.clone(),
                             buffer_tailroom,
                             writer_flush_sender.clone(),
                             channel_id as usize,
                         );
-                        let flush_batcher = Self::spawn_writer_flush_batcher(
+                        let flush_batcher = self.spawn_writer_flush_batcher(
                             worker_cx,
                             writer.clone().to_dyn(),
                             transport_flush_batcher,
@@ -146,6 +422,14 @@ impl TransportHandle {
                     },
                     |w| w.clone(),
                 );
+                if created.get() {
+                    // Remember the writer so that it can be flushed when the transport drains.
+                    worker_cx.with_local_fn(
+                        writer_flush_sender.id(),
+                        || LocalWriters(Vec::new()),
+                        |writers| writers.0.push(writer.clone().to_dyn()),
+                    );
+                }
                 (writer.to_dyn(), flush_batcher)
             }
             WriterConfig::LocalNoFlush => {
@@ -161,14 +445,19 @@ impl TransportHandle {
     }
 
     fn spawn_writer_flush_batcher(
+        &self,
         worker_cx: &WorkerContext,
         writer: bab::DynWriter,
         transport_flush_batcher: FlushBatcher,
     ) -> FlushBatcher {
         use crate::flush_batcher::FlushBatcherStatus;
 
-        // TODO configurable
-        let flush_batcher = FlushBatcher::new(std::time::Duration::from_micros(0));
+        let config = self.effective_config(worker_cx);
+        let flush_batcher = FlushBatcher::new(
+            config.max_writer_flush_delay,
+            config.max_unflushed_bytes,
+            worker_cx.rt().metrics(),
+        );
         let mut sleeper = worker_cx.new_sleeper();
         worker_cx.spawn({
             let flush_batcher = flush_batcher.clone();
@@ -184,6 +473,10 @@ impl TransportHandle {
                                 core::future::poll_fn(|cx| sleeper.as_mut().poll_sleep(cx)).await;
                             }
                             FlushBatcherStatus::FlushNow => {
+                                let unflushed_bytes = writer.unflushed_bytes();
+                                if unflushed_bytes > 0 {
+                                    flush_batcher.record_flush(unflushed_bytes);
+                                }
                                 writer.flush_local();
                                 transport_flush_batcher.schedule_flush();
                                 break;
@@ -201,33 +494,76 @@ impl TransportHandle {
     }
 }
 
+/// Split a bundle received from a peer into its packets. No packets are produced if the bundle's
+/// headers don't exactly describe its contents.
 pub fn shatter_packet_bundle(
     packet: Packet,
     offsets: &mut Vec<usize>,
     out_packets: &mut Vec<MaybeUninit<Packet>>,
-) -> PacketBundle {
-    use mproto::BaseLen;
-
+) -> Result<PacketBundle, TransportError> {
     offsets.clear();
     out_packets.clear();
 
-    let bundle_header: PacketBundle = mproto::decode_value(&packet[..])
-        // TODO error handling
-        .expect("decode rx bundle header");
+    let bundle_header = match packet_offsets(&packet[..], |offset| offsets.push(offset)) {
+        Ok(bundle_header) => bundle_header,
+        Err(e) => {
+            offsets.clear();
+            return Err(e);
+        }
+    };
 
-    let mut cursor = PacketBundle::BASE_LEN;
-    while cursor < PacketBundle::BASE_LEN + bundle_header.length as usize {
-        let packet_header: TransmitPacket = mproto::decode_value(&packet[cursor..])
-            // TODO error handling
-            .expect("decode rx bundle header");
-        offsets.push(cursor);
-        cursor += TransmitPacket::BASE_LEN + packet_header.payload_length as usize;
+    if offsets.is_empty() {
+        return Ok(bundle_header);
     }
-
     out_packets.resize_with(offsets.len(), || MaybeUninit::uninit());
     packet.shatter_into(offsets, out_packets);
 
-    bundle_header
+    Ok(bundle_header)
+}
+
+/// Check that a bundle received from a peer, header included, is made up of whole packets -
+/// without splitting it, for relays that pass bundles on as they are.
+pub fn validate_packet_bundle(packet_bundle: &[u8]) -> Result<PacketBundle, TransportError> {
+    packet_offsets(packet_bundle, |_| {})
+}
+
+/// Walk the packets of a bundle, passing the offset of each to `on_packet`.
+fn packet_offsets(
+    packet_bundle: &[u8],
+    mut on_packet: impl FnMut(usize),
+) -> Result<PacketBundle, TransportError> {
+    use mproto::BaseLen;
+
+    if packet_bundle.len() < PacketBundle::BASE_LEN {
+        return Err(TransportError::Malformed("truncated bundle header"));
+    }
+    let bundle_header: PacketBundle = mproto::decode_value(packet_bundle)
+        .map_err(|_| TransportError::Malformed("invalid bundle header"))?;
+    let bundle_end = PacketBundle::BASE_LEN + bundle_header.length as usize;
+    if bundle_end > packet_bundle.len() {
+        return Err(TransportError::Malformed("bundle length exceeds received bytes"));
+    }
+
+    let mut cursor = PacketBundle::BASE_LEN;
+    while cursor < bundle_end {
+        let packet_header = if cursor + TransmitPacket::BASE_LEN <= bundle_end {
+            mproto::decode_value::<TransmitPacket>(&packet_bundle[cursor..]).ok()
+        } else {
+            None
+        };
+        let Some(packet_header) = packet_header else {
+            return Err(TransportError::Malformed("truncated packet header"));
+        };
+        let packet_end = cursor + TransmitPacket::BASE_LEN + packet_header.payload_length as usize;
+        if packet_end > bundle_end {
+            return Err(TransportError::Malformed("packet length exceeds bundle"));
+        }
+
+        on_packet(cursor);
+        cursor = packet_end;
+    }
+
+    Ok(bundle_header)
 }
 
 pub struct ShatterPacketBundle<'a> {
@@ -253,9 +589,16 @@ impl Iterator for ShatterPacketBundle<'_> {
             else {
                 return None;
             };
+            let packet_end =
+                self.cursor + TransmitPacket::BASE_LEN + packet_header.payload_length as usize;
+            if packet_end > self.packet.len() {
+                // Truncated - drop the rest of the bundle.
+                self.cursor = self.packet.len();
+                return None;
+            }
             let packet = self.packet.clone();
             packet.advance(self.cursor);
-            self.cursor += TransmitPacket::BASE_LEN + packet_header.payload_length as usize;
+            self.cursor = packet_end;
 
             Some(packet)
         } else {
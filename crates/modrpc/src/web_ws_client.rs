@@ -1,8 +1,8 @@
 use gloo_net::websocket::{Message, futures::WebSocket};
 
 use crate::{
-    EndpointAddr, HeapBufferPool, InterfaceRole, RoleConfig, RuntimeHandle, TopicChannels,
-    TransportHandle, WebSocketTransport, endpoint_proto::PlaneHandshake,
+    CompressionNegotiation, EndpointAddr, HeapBufferPool, InterfaceRole, RoleConfig, RuntimeHandle,
+    TransportHandle, TransportOptions, WebSocketTransport, endpoint_proto::PlaneHandshake,
 };
 
 pub struct WebSocketConnection<Role: InterfaceRole> {
@@ -17,14 +17,46 @@ pub async fn web_ws_connect<Role: InterfaceRole>(
     buffer_pool: HeapBufferPool,
     addr: &str,
     config: Role::Config,
+    options: TransportOptions,
 ) -> Result<WebSocketConnection<Role>, ()>
 where
     Role::Config: Clone + Send,
     Role::Init: Clone + std::fmt::Debug + Send + Sync,
 {
-    use futures_util::StreamExt;
+    let websocket = WebSocket::open(addr).map_err(|_| ())?;
+    web_ws_start_plane(rt, buffer_pool, websocket, config, options).await
+}
 
+/// Like `web_ws_connect`, but first authenticates with `hello` - see `web_ws_client_hello`.
+pub async fn web_ws_connect_with_hello<Role: InterfaceRole>(
+    rt: &RuntimeHandle,
+    buffer_pool: HeapBufferPool,
+    addr: &str,
+    hello: impl mproto::Encode,
+    config: Role::Config,
+    options: TransportOptions,
+) -> Result<WebSocketConnection<Role>, ()>
+where
+    Role::Config: Clone + Send,
+    Role::Init: Clone + std::fmt::Debug + Send + Sync,
+{
     let mut websocket = WebSocket::open(addr).map_err(|_| ())?;
+    crate::web_ws_client_hello(&mut websocket, hello).await.map_err(|_| ())?;
+    web_ws_start_plane(rt, buffer_pool, websocket, config, options).await
+}
+
+async fn web_ws_start_plane<Role: InterfaceRole>(
+    rt: &RuntimeHandle,
+    buffer_pool: HeapBufferPool,
+    mut websocket: WebSocket,
+    config: Role::Config,
+    options: TransportOptions,
+) -> Result<WebSocketConnection<Role>, ()>
+where
+    Role::Config: Clone + Send,
+    Role::Init: Clone + std::fmt::Debug + Send + Sync,
+{
+    use futures_util::StreamExt;
 
     let payload = websocket
         .next()
@@ -38,13 +70,27 @@ where
 
     let plane_handshake: PlaneHandshake<Role::Init> =
         mproto::decode_value(&payload_bytes).map_err(|_| ())?;
+    let topic_channels = options.topic_channels(plane_handshake.plane_id);
 
     let transport = rt
         .add_transport(WebSocketTransport {
             websocket,
             buffer_pool,
+            keepalive: options.keepalive,
+            compression: options.compression,
+            compression_negotiation: CompressionNegotiation::Answer,
+            egress: options.egress.clone(),
+            #[cfg(feature = "encryption")]
+            plane_keys: options.plane_keys(
+                plane_handshake.plane_id,
+                topic_channels.default_channel_id(),
+                plane_handshake.endpoint_addr,
+            ),
         })
         .await;
+    options
+        .subscribe_topics::<Role::Interface>(rt, &transport)
+        .await;
 
     let transport_shutdown_signal = transport.shutdown_signal.clone();
 
@@ -53,13 +99,11 @@ where
             plane_id: plane_handshake.plane_id,
             endpoint_addr: plane_handshake.endpoint_addr,
             transport: transport.clone(),
-            topic_channels: TopicChannels::SingleChannel {
-                channel_id: plane_handshake.plane_id,
-            },
+            topic_channels,
             config,
             init: plane_handshake.init.clone(),
         })
-        .local(|cx| {
+        .local_async(|cx| {
             // Shutdown the role when the transport is shutdown.
             let role_shutdown_signal = cx.role_shutdown_signal().clone();
             cx.raw_spawner().spawn(async move {
@@ -67,7 +111,8 @@ where
                 role_shutdown_signal.notify();
             })
             .expect("spawn modrpc web-ws transport shutdown waiter");
-        });
+        })
+        .await;
 
     Ok(WebSocketConnection {
         endpoint: plane_handshake.endpoint_addr,
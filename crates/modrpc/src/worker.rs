@@ -1,5 +1,8 @@
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
 
 use core::{cell::RefCell, future::Future, hash::Hash, pin::Pin, time::Duration};
 
@@ -8,15 +11,74 @@ use futures_lite::future;
 use crate::{
     EndpointAddr, LocalSpawner, Packet, RoleSetup, RuntimeHandle, SendPacket,
     context_map::{ContextClass, ContextMap, LocalContextMap, ModrpcContextTag},
+    metrics::{Gauge, MetricsRegistry},
     packet_processor::{
         PACKET_PROCESSOR_SOURCE_INTER_WORKER, PACKET_PROCESSOR_SOURCE_NEW, PacketProcessor,
         PacketProcessorSource,
     },
+    role_setup::PendingRedirects,
 };
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct WorkerId(pub u16);
 
+/// Counts the tasks running on a worker so that shutdown can wait for all of them to exit.
+#[derive(Clone)]
+pub(crate) struct TaskTracker {
+    inner: Arc<TaskTrackerInner>,
+}
+
+struct TaskTrackerInner {
+    running: AtomicUsize,
+    idle: waitq::WaiterQueue<()>,
+}
+
+impl TaskTracker {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(TaskTrackerInner {
+                running: AtomicUsize::new(0),
+                idle: waitq::WaiterQueue::new(),
+            }),
+        }
+    }
+
+    pub fn track<F: Future>(&self, future: F) -> impl Future<Output = F::Output> + use<F> {
+        self.inner.running.fetch_add(1, Ordering::Relaxed);
+        let guard = TaskGuard {
+            tracker: self.clone(),
+        };
+        async move {
+            let _guard = guard;
+            future.await
+        }
+    }
+
+    pub fn running(&self) -> usize {
+        self.inner.running.load(Ordering::Acquire)
+    }
+
+    /// Resolves once every tracked task has exited.
+    pub async fn idle(&self) {
+        self.inner
+            .idle
+            .wait_for(|| (self.running() == 0).then_some(()))
+            .await
+    }
+}
+
+struct TaskGuard {
+    tracker: TaskTracker,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        if self.tracker.inner.running.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.inner.idle.notify_all();
+        }
+    }
+}
+
 impl WorkerId {
     pub fn local() -> Self {
         WorkerId(0)
@@ -28,6 +90,7 @@ pub struct WorkerSpawner {
     pub(crate) interval_fn:
         for<'a> fn(Duration, &'a mut dyn FnMut()) -> Pin<Box<dyn Future<Output = ()> + 'a>>,
     pub(crate) shutdown_signal: bab::SignalTree,
+    pub(crate) tasks: TaskTracker,
 }
 
 impl WorkerSpawner {
@@ -38,7 +101,10 @@ impl WorkerSpawner {
     pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
         let shutdown_signal = self.shutdown_signal.clone();
         self.spawner
-            .spawn(future::or(future, shutdown_signal.wait_owned()))
+            .spawn(
+                self.tasks
+                    .track(future::or(future, shutdown_signal.wait_owned())),
+            )
             .unwrap();
     }
 
@@ -59,8 +125,8 @@ pub struct WorkerContext {
     pub(crate) rt: RuntimeHandle,
     pub(crate) spawner: LocalSpawner,
     pub(crate) shutdown_signal: bab::SignalTree,
+    pub(crate) tasks: TaskTracker,
     pub(crate) worker_id: WorkerId,
-    pub(crate) worker_count: u16,
     pub(crate) pp: Rc<PacketProcessor>,
     pub(crate) local_packet_tx: localq::mpsc::Sender<Packet>,
     pub(crate) locals: Rc<RefCell<LocalContextMap>>,
@@ -69,15 +135,16 @@ pub struct WorkerContext {
         for<'a> fn(Duration, &'a mut dyn FnMut()) -> Pin<Box<dyn Future<Output = ()> + 'a>>,
     pub(crate) sleep_fn: fn(Duration) -> Pin<Box<dyn Future<Output = ()>>>,
     pub(crate) new_sleeper_fn: fn() -> Pin<Box<dyn modrpc_executor::Sleeper>>,
+    pub(crate) pending_redirects: Rc<RefCell<PendingRedirects>>,
 }
 
 impl WorkerContext {
-    pub fn new<E: modrpc_executor::ModrpcExecutor>(
+    pub(crate) fn new<E: modrpc_executor::ModrpcExecutor>(
         rt: RuntimeHandle,
         spawner: LocalSpawner,
         shutdown_signal: bab::SignalTree,
+        tasks: TaskTracker,
         worker_id: WorkerId,
-        worker_count: u16,
         pp: Rc<PacketProcessor>,
         local_packet_tx: localq::mpsc::Sender<Packet>,
         locals: Rc<RefCell<LocalContextMap>>,
@@ -87,8 +154,8 @@ impl WorkerContext {
             rt,
             spawner,
             shutdown_signal,
+            tasks,
             worker_id,
-            worker_count,
             pp,
             local_packet_tx,
             locals,
@@ -96,6 +163,7 @@ impl WorkerContext {
             interval_fn: Self::interval_loop::<E>,
             sleep_fn: Self::sleep_dyn::<E>,
             new_sleeper_fn: Self::new_sleeper_dyn::<E>,
+            pending_redirects: Rc::new(RefCell::new(PendingRedirects::default())),
         }
     }
 
@@ -108,6 +176,7 @@ impl WorkerContext {
             spawner: self.spawner.clone(),
             interval_fn: self.interval_fn,
             shutdown_signal: self.shutdown_signal.clone(),
+            tasks: self.tasks.clone(),
         }
     }
 
@@ -115,8 +184,15 @@ impl WorkerContext {
         self.worker_id
     }
 
+    /// The number of workers currently in the runtime.
     pub fn worker_count(&self) -> u16 {
-        self.worker_count
+        self.rt.worker_count()
+    }
+
+    /// Resolves once every topic subscription made on this worker so far has been installed on
+    /// the other workers. The future can be awaited from any thread.
+    pub fn subscriptions_installed(&self) -> impl Future<Output = ()> + Send + use<> {
+        self.pending_redirects.borrow_mut().installed()
     }
 
     pub fn local_packet_tx(&self) -> &localq::mpsc::Sender<Packet> {
@@ -130,7 +206,10 @@ impl WorkerContext {
     pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
         let shutdown_signal = self.shutdown_signal.clone();
         self.spawner
-            .spawn(future::or(future, shutdown_signal.wait_owned()))
+            .spawn(
+                self.tasks
+                    .track(future::or(future, shutdown_signal.wait_owned())),
+            )
             .unwrap();
     }
 
@@ -274,6 +353,20 @@ impl WorkerContext {
             .add_route_to_local_queue(topic_name, source_filter, plane_id, topic, handler);
     }
 
+    pub(crate) fn add_inter_worker_batcher(
+        &self,
+        worker_id: WorkerId,
+        inter_worker_tx: &burstq::Sender<SendPacket>,
+    ) {
+        let batcher_tx = build_inter_worker_batcher(
+            &self.spawner(),
+            self.rt.config().max_inter_worker_batch_size,
+            inter_worker_tx,
+            inter_worker_queue_depth(self.rt.metrics(), worker_id),
+        );
+        self.pp.set_inter_worker_sender(worker_id, Some(batcher_tx));
+    }
+
     pub fn add_redirect_to_worker(
         &self,
         topic_name: &str,
@@ -361,6 +454,7 @@ pub(crate) fn spawn_worker<E: modrpc_executor::ModrpcExecutor>(
     // Spawn task to process incoming inter-worker packets
     worker_cx.spawn_traced("inter-worker-rx", Duration::from_millis(1000), {
         let worker_cx = worker_cx.clone();
+        let queue_depth = inter_worker_queue_depth(worker_cx.rt.metrics(), worker_cx.worker_id);
         async move |tracer| {
             let batch_size = 32;
             let mut batch: Vec<MaybeUninit<_>> =
@@ -377,6 +471,7 @@ pub(crate) fn spawn_worker<E: modrpc_executor::ModrpcExecutor>(
                 tracer.trace(|| {
                     probius::trace_metric("batch_size", n as i64);
                 });
+                queue_depth.sub(n as i64);
 
                 for i in 0..n {
                     let packet = unsafe { batch[i].assume_init_read() };
@@ -442,7 +537,7 @@ pub fn get_global_queue_sender<K: Eq + Hash + Send + 'static>(
 
     let (local_tx, local_rx) = localq::mpsc::channel::<Packet>(tx_burst_size);
     cx.role_spawner()
-        .spawn(run_burstq_batcher(global_tx, local_rx));
+        .spawn(run_burstq_batcher(global_tx, local_rx, None));
 
     local_tx
 }
@@ -459,13 +554,16 @@ pub fn get_global_queue_receiver<K: Eq + Hash + Send + 'static>(
     )
 }
 
+/// Forward packets from `local_rx` to `shared_tx` in batches. `queue_depth` is increased by the
+/// number of packets sent - whoever receives them is responsible for decreasing it.
 pub async fn run_burstq_batcher(
     shared_tx: burstq::Sender<SendPacket>,
     mut local_rx: localq::mpsc::Receiver<Packet>,
+    queue_depth: Option<Gauge>,
 ) {
     while let Ok(first_packet) = local_rx.recv().await {
         let batch_size = local_rx.len() + 1;
-        let _ = shared_tx
+        let result = shared_tx
             .send(batch_size, |mut w| unsafe {
                 w.write_at(0, first_packet.send());
                 for i in 1..w.len() {
@@ -473,27 +571,57 @@ pub async fn run_burstq_batcher(
                 }
             })
             .await;
+        if let (Ok(_), Some(queue_depth)) = (result, &queue_depth) {
+            queue_depth.add(batch_size as i64);
+        }
     }
 }
 
+/// The number of packets waiting in a worker's inter-worker queue.
+pub(crate) fn inter_worker_queue_depth(metrics: &MetricsRegistry, worker_id: WorkerId) -> Gauge {
+    metrics.gauge(
+        "modrpc_inter_worker_queue_depth",
+        "Packets waiting in a worker's queue of packets from other workers.",
+        &[("worker", &worker_id.0.to_string())],
+    )
+}
+
 pub fn build_inter_worker_batchers(
     spawner: &WorkerSpawner,
     local_worker_id: WorkerId,
     max_batch_size: usize,
-    global_senders: &[burstq::Sender<SendPacket>],
+    global_senders: &[Option<burstq::Sender<SendPacket>>],
+    metrics: &MetricsRegistry,
 ) -> Vec<Option<localq::mpsc::Sender<Packet>>> {
     let mut senders = vec![];
 
     for (worker_index, shared_tx) in global_senders.iter().enumerate() {
+        let Some(shared_tx) = shared_tx else {
+            senders.push(None);
+            continue;
+        };
         if worker_index == local_worker_id.0 as usize {
             senders.push(None);
             continue;
         }
-        let (local_tx, local_rx) = localq::mpsc::channel::<Packet>(max_batch_size);
-        senders.push(Some(local_tx));
-
-        spawner.spawn(run_burstq_batcher(shared_tx.clone(), local_rx));
+        senders.push(Some(build_inter_worker_batcher(
+            spawner,
+            max_batch_size,
+            shared_tx,
+            inter_worker_queue_depth(metrics, WorkerId(worker_index as u16)),
+        )));
     }
 
     senders
 }
+
+pub fn build_inter_worker_batcher(
+    spawner: &WorkerSpawner,
+    max_batch_size: usize,
+    shared_tx: &burstq::Sender<SendPacket>,
+    queue_depth: Gauge,
+) -> localq::mpsc::Sender<Packet> {
+    let (local_tx, local_rx) = localq::mpsc::channel::<Packet>(max_batch_size);
+    spawner.spawn(run_burstq_batcher(shared_tx.clone(), local_rx, Some(queue_depth)));
+    local_tx
+}
@@ -0,0 +1,101 @@
+use crate::WorkerId;
+
+/// A set of worker IDs. IDs below 64 are stored inline so that sets of the first 64 workers
+/// never allocate.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct WorkerSet {
+    low: u64,
+    // Bit `j` of `high[i]` is worker `64 * (i + 1) + j`.
+    high: Vec<u64>,
+}
+
+impl WorkerSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the worker was newly inserted.
+    pub fn insert(&mut self, worker_id: WorkerId) -> bool {
+        let (word_index, mask) = split(worker_id);
+        if self.high.len() < word_index {
+            self.high.resize(word_index, 0);
+        }
+        let word = self.word_mut(word_index).expect("WorkerSet::insert word");
+        let inserted = *word & mask == 0;
+        *word |= mask;
+        inserted
+    }
+
+    /// Returns whether the worker was in the set.
+    pub fn remove(&mut self, worker_id: WorkerId) -> bool {
+        let (word_index, mask) = split(worker_id);
+        let Some(word) = self.word_mut(word_index) else {
+            return false;
+        };
+        let removed = *word & mask != 0;
+        *word &= !mask;
+        while self.high.last() == Some(&0) {
+            self.high.pop();
+        }
+        removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.low == 0 && self.high.iter().all(|word| *word == 0)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = WorkerId> + '_ {
+        core::iter::once(self.low)
+            .chain(self.high.iter().copied())
+            .enumerate()
+            .flat_map(|(word_index, word)| {
+                (0..u64::BITS)
+                    .filter(move |bit| word & (1 << bit) != 0)
+                    .map(move |bit| WorkerId((word_index * u64::BITS as usize) as u16 + bit as u16))
+            })
+    }
+
+    fn word_mut(&mut self, word_index: usize) -> Option<&mut u64> {
+        match word_index {
+            0 => Some(&mut self.low),
+            i => self.high.get_mut(i - 1),
+        }
+    }
+}
+
+fn split(worker_id: WorkerId) -> (usize, u64) {
+    let worker_index = worker_id.0 as usize;
+    (
+        worker_index / u64::BITS as usize,
+        1 << (worker_index % u64::BITS as usize),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_worker_set() {
+        let mut set = WorkerSet::new();
+        assert!(set.is_empty());
+
+        for worker_id in [0, 63, 64, 200, u16::MAX] {
+            assert!(set.insert(WorkerId(worker_id)));
+            assert!(!set.insert(WorkerId(worker_id)));
+        }
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            [0, 63, 64, 200, u16::MAX].map(WorkerId),
+        );
+
+        assert!(set.remove(WorkerId(200)));
+        assert!(!set.remove(WorkerId(200)));
+        assert!(!set.remove(WorkerId(1000)));
+        for worker_id in [0, 63, 64, u16::MAX] {
+            set.remove(WorkerId(worker_id));
+        }
+        assert!(set.is_empty());
+        assert!(set.high.is_empty());
+    }
+}
@@ -2,13 +2,18 @@ use futures_util::{Stream, StreamExt};
 use mproto::BaseLen;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
-use crate::{Packet, endpoint_proto::PacketBundle};
+use crate::{
+    Packet, TransportError, decompress_bundle, endpoint_proto::PacketBundle, is_compressed_bundle,
+};
 
 pub struct WebSocketIngress<Ws> {
     ws: Ws,
     framer: bab::Framer,
     // Max packet size including bundle header.
     max_packet_size: usize,
+    decompression: bool,
+    #[cfg(feature = "encryption")]
+    plane_keys: Option<crate::PlaneKeyring>,
 }
 
 impl<Ws> WebSocketIngress<Ws>
@@ -27,23 +32,46 @@ where
             ws,
             framer: bab::Framer::new(buffer_pool),
             max_packet_size,
+            decompression: false,
+            #[cfg(feature = "encryption")]
+            plane_keys: None,
         }
     }
 
-    pub async fn receive(&mut self) -> std::io::Result<Packet> {
+    /// Decompress bundles sent with `COMPRESSED_CHANNEL_BIT` set. Only enable this once we've
+    /// told the peer we can decompress - otherwise the bit is just part of the channel ID.
+    pub fn set_decompression(&mut self, enabled: bool) {
+        self.decompression = enabled;
+    }
+
+    /// Decrypt packets on encrypted planes as they're received.
+    #[cfg(feature = "encryption")]
+    pub fn set_plane_keys(&mut self, plane_keys: crate::PlaneKeyring) {
+        self.plane_keys = Some(plane_keys);
+    }
+
+    /// Receive the next buffer of bundles. Fails if the connection closes or the peer sends a message
+    /// that isn't exactly one bundle, after which the ingress must not be used again.
+    pub async fn receive(&mut self) -> Result<Packet, TransportError> {
         loop {
-            let (_bundle_header, bundle_bytes) = match self.ws.next().await {
+            let (bundle_header, bundle_bytes) = match self.ws.next().await {
                 Some(Ok(Message::Binary(payload))) => {
-                    let Ok(bundle_header) = mproto::decode_value::<PacketBundle>(&payload[..])
-                    else {
-                        continue;
-                    };
-
-                    assert_eq!(
-                        PacketBundle::BASE_LEN + bundle_header.length as usize,
-                        payload.len()
-                    );
-                    assert!(payload.len() < self.max_packet_size);
+                    if payload.len() < PacketBundle::BASE_LEN {
+                        return Err(TransportError::Malformed("truncated bundle header"));
+                    }
+                    let bundle_header = mproto::decode_value::<PacketBundle>(&payload[..])
+                        .map_err(|_| TransportError::Malformed("invalid bundle header"))?;
+                    if PacketBundle::BASE_LEN + bundle_header.length as usize != payload.len() {
+                        return Err(TransportError::Malformed(
+                            "bundle length doesn't match message length",
+                        ));
+                    }
+                    if payload.len() > self.max_packet_size {
+                        return Err(TransportError::BundleTooLarge {
+                            len: payload.len(),
+                            max_len: self.max_packet_size,
+                        });
+                    }
 
                     (bundle_header, payload)
                 }
@@ -52,19 +80,32 @@ where
                     continue;
                 }
                 Some(Err(e)) => {
-                    return Err(std::io::Error::new(std::io::ErrorKind::Other, e));
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, e).into());
                 }
                 None => {
                     return Err(std::io::Error::new(
                         std::io::ErrorKind::ConnectionReset,
                         "WebSocket connection closed",
-                    ));
+                    )
+                    .into());
                 }
             };
 
             let write = self.framer.write().await;
-            write[..bundle_bytes.len()].copy_from_slice(&bundle_bytes);
-            self.framer.commit(bundle_bytes.len());
+            let bundle_len = if self.decompression && is_compressed_bundle(&bundle_header) {
+                let out_len = std::cmp::min(write.len(), self.max_packet_size);
+                decompress_bundle(&bundle_bytes, &mut write[..out_len])
+                    .map_err(TransportError::Decompress)?
+            } else {
+                write[..bundle_bytes.len()].copy_from_slice(&bundle_bytes);
+                bundle_bytes.len()
+            };
+            #[cfg(feature = "encryption")]
+            let bundle_len = match &self.plane_keys {
+                Some(plane_keys) => plane_keys.decrypt_bundle_in_place(&mut write[..bundle_len]),
+                None => bundle_len,
+            };
+            self.framer.commit(bundle_len);
 
             let finished_packet = if self.framer.remaining_on_buffer() < self.max_packet_size {
                 self.framer.next_buffer()
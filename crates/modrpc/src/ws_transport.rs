@@ -1,59 +1,181 @@
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{FutureExt, SinkExt, StreamExt};
 use tokio::net::TcpStream;
 use tokio_tungstenite::{WebSocketStream, tungstenite::protocol::Message};
 
+use std::rc::Rc;
+
 use futures_lite::future;
 use mproto::BaseLen;
 
 use crate::{
-    HeapBufferPool, Packet, PacketBundle, TransportBuilder, TransportContext, TransportHandle,
-    transport::WriterConfig, ws_ingress::WebSocketIngress,
+    BundleCompressor, CaptureDirection, CompressionConfig, CompressionHandshake,
+    CompressionNegotiation, EgressConfig, EgressScheduler, HeapBufferPool, IdleTimer,
+    KeepaliveConfig, Packet, PacketBundle, TransportBuilder, TransportContext, TransportHandle,
+    compressed_channel_id, decode_compression_hello, encode_keepalive_bundle, is_control_bundle,
+    is_goodbye_bundle, next_flushes, transport::{WriterConfig, report_transport_error},
+    ws_ingress::WebSocketIngress,
 };
 
 pub struct WebSocketTransport {
     pub buffer_pool: HeapBufferPool,
     pub websocket: WebSocketStream<TcpStream>,
+    pub keepalive: Option<KeepaliveConfig>,
+    pub compression: Option<CompressionConfig>,
+    /// Whether this end offers compression or waits for the peer's offer. The accepting end of a
+    /// connection offers and the connecting end answers.
+    pub compression_negotiation: CompressionNegotiation,
+    /// Channel priorities for egress. If unset, all channels are given equal weight.
+    pub egress: Option<EgressConfig>,
+    /// Keys for the encrypted planes carried by this transport, see `PlaneKeyring::join_plane`.
+    #[cfg(feature = "encryption")]
+    pub plane_keys: Option<crate::PlaneKeyring>,
 }
 
 impl TransportBuilder for WebSocketTransport {
     async fn start_transport(self, cx: TransportContext<'_>) -> TransportHandle {
         let shutdown_signal = bab::SignalTree::new();
+        let drain_signal = bab::SignalTree::new();
 
         let Some(worker_cx) = cx.rt.local_worker_context() else {
             panic!("tokio_tungstenite WebSocket modrpc transport requires a local worker.");
         };
 
         let (mut ws_tx, ws_rx) = self.websocket.split();
+        let capture = cx.rt.capture().map(|capture| capture.add_transport("websocket"));
 
         let (writer_flush_sender, mut writer_flush_receiver) = bab::new_writer_flusher();
 
+        let compression_handshake = Rc::new(CompressionHandshake::new(
+            self.compression.is_some(),
+            self.compression_negotiation,
+        ));
+
         // Spawn task to flush egress packets
         worker_cx.spawn({
             let buffer_pool = self.buffer_pool.clone();
             let shutdown_signal = shutdown_signal.clone();
             let shutdown_notifier = shutdown_signal.clone();
+            let drain_signal = drain_signal.clone();
+            let worker_cx = worker_cx.clone();
+            let keepalive_interval = self.keepalive.map(|k| k.interval);
+            let mut compressor = self.compression.map(BundleCompressor::new);
+            let compression_handshake = compression_handshake.clone();
+            let mut scheduler = EgressScheduler::new(self.egress.unwrap_or_default());
+            let capture = capture.clone();
             future::or(
                 async move {
+                    if let Some(hello) = compression_handshake.offer() {
+                        let hello = hello.to_vec();
+                        if let Some(capture) = &capture {
+                            capture.record(CaptureDirection::Outbound, &hello);
+                        }
+                        if ws_tx.send(Message::Binary(hello.into())).await.is_err() {
+                            shutdown_notifier.notify();
+                            return;
+                        }
+                    }
+
                     'flush_loop: loop {
-                        for flush in writer_flush_receiver.flush().await {
-                            if flush.len() > 0 {
-                                let mut buf = vec![0u8; PacketBundle::BASE_LEN + flush.len()];
-
-                                // Fill bundle header
-                                mproto::encode_value(
-                                    PacketBundle {
-                                        channel_id: flush.writer_id() as u32,
-                                        length: flush.len() as u16,
-                                    },
-                                    &mut buf[..PacketBundle::BASE_LEN],
-                                );
-                                // Copy payload
-                                buf[PacketBundle::BASE_LEN..].copy_from_slice(&flush);
+                        if let Some(answer) = compression_handshake.take_answer() {
+                            let answer = answer.to_vec();
+                            if let Some(capture) = &capture {
+                                capture.record(CaptureDirection::Outbound, &answer);
+                            }
+                            if ws_tx.send(Message::Binary(answer.into())).await.is_err() {
+                                break 'flush_loop;
+                            }
+                        }
 
-                                // Write to socket
-                                if let Err(_) = ws_tx.send(Message::Binary(buf.into())).await {
-                                    break 'flush_loop;
+                        if !scheduler.is_empty() {
+                            // Pick up anything flushed since the last write without waiting, so
+                            // that it can be scheduled ahead of bundles that are already queued.
+                            if let Some(flushes) = writer_flush_receiver.flush().now_or_never() {
+                                scheduler.push_flushes(flushes);
+                            }
+                        } else {
+                            let wake = future::or(
+                                async {
+                                    match next_flushes(&mut writer_flush_receiver, &drain_signal)
+                                        .await
+                                    {
+                                        Some(flushes) => TxWake::Flushes(flushes),
+                                        None => TxWake::Drained,
+                                    }
+                                },
+                                async {
+                                    compression_handshake.answer_due().await;
+                                    TxWake::CompressionAnswer
+                                },
+                            );
+                            let wake = match keepalive_interval {
+                                Some(interval) => {
+                                    future::or(wake, async {
+                                        worker_cx.sleep(interval).await;
+                                        TxWake::Keepalive
+                                    })
+                                    .await
+                                }
+                                None => wake.await,
+                            };
+                            match wake {
+                                TxWake::Flushes(flushes) => scheduler.push_flushes(flushes),
+                                TxWake::Keepalive => {
+                                    // Nothing has been sent for a while - let the peer know we're
+                                    // still here.
+                                    let keepalive = encode_keepalive_bundle().to_vec();
+                                    if let Some(capture) = &capture {
+                                        capture.record(CaptureDirection::Outbound, &keepalive);
+                                    }
+                                    if ws_tx.send(Message::Binary(keepalive.into())).await.is_err()
+                                    {
+                                        break 'flush_loop;
+                                    }
+                                    continue;
                                 }
+                                // Written at the top of the loop.
+                                TxWake::CompressionAnswer => continue,
+                                // Everything flushed before shutdown has been written.
+                                TxWake::Drained => break 'flush_loop,
+                            }
+                        }
+
+                        // Write one bundle at a time.
+                        if let Some((_, flush)) = scheduler.pop() {
+                            if let Some(capture) = &capture {
+                                capture.record_bundle(
+                                    CaptureDirection::Outbound,
+                                    flush.writer_id() as u32,
+                                    &flush[..],
+                                );
+                            }
+
+                            let channel_id = flush.writer_id() as u32;
+                            let compressed = compressor
+                                .as_mut()
+                                .filter(|_| compression_handshake.peer_decompresses())
+                                .zip(compressed_channel_id(channel_id))
+                                .and_then(|(c, compressed_channel_id)| {
+                                    Some((compressed_channel_id, c.compress(&flush)?))
+                                });
+                            let (channel_id, payload) =
+                                compressed.unwrap_or((channel_id, &flush[..]));
+
+                            let mut buf = vec![0u8; PacketBundle::BASE_LEN + payload.len()];
+
+                            // Fill bundle header
+                            mproto::encode_value(
+                                PacketBundle {
+                                    channel_id,
+                                    length: payload.len() as u16,
+                                },
+                                &mut buf[..PacketBundle::BASE_LEN],
+                            );
+                            // Copy payload
+                            buf[PacketBundle::BASE_LEN..].copy_from_slice(payload);
+
+                            // Write to socket
+                            if let Err(_) = ws_tx.send(Message::Binary(buf.into())).await {
+                                break 'flush_loop;
                             }
                         }
                     }
@@ -73,10 +195,18 @@ impl TransportBuilder for WebSocketTransport {
             self.buffer_pool.clone(),
             self.buffer_pool.buffer_size(),
         );
+        ws_ingress.set_decompression(self.compression.is_some());
+        #[cfg(feature = "encryption")]
+        if let Some(plane_keys) = &self.plane_keys {
+            ws_ingress.set_plane_keys(plane_keys.clone());
+        }
         worker_cx.spawn({
             let shutdown_notifier = shutdown_signal.clone();
             let shutdown_waiter = shutdown_signal.clone();
             let process_packet_fn = worker_cx.get_packet_processor();
+            let metrics = worker_cx.rt.metrics().clone();
+            let worker_cx = worker_cx.clone();
+            let idle_timer = self.keepalive.map(|k| IdleTimer::new(k.idle_timeout));
             future::or(
                 async move {
                     use core::mem::MaybeUninit;
@@ -84,17 +214,55 @@ impl TransportBuilder for WebSocketTransport {
                     let mut shatter_offsets: Vec<usize> = Vec::new();
                     let mut shatter_out_packets: Vec<MaybeUninit<Packet>> = Vec::new();
 
-                    while let Ok(packet_bundle) = ws_ingress.receive().await {
-                        let _header = crate::shatter_packet_bundle(
-                            packet_bundle,
-                            &mut shatter_offsets,
-                            &mut shatter_out_packets,
-                        );
+                    let rx_loop = async {
+                        loop {
+                            let packet_bundle = match ws_ingress.receive().await {
+                                Ok(packet_bundle) => packet_bundle,
+                                Err(e) => {
+                                    report_transport_error(&metrics, "websocket", &e);
+                                    break;
+                                }
+                            };
+                            if let Some(capture) = &capture {
+                                capture.record(CaptureDirection::Inbound, &packet_bundle[..]);
+                            }
+                            if let Some(idle_timer) = &idle_timer {
+                                idle_timer.reset();
+                            }
+                            if is_control_bundle(&packet_bundle[..]) {
+                                if is_goodbye_bundle(&packet_bundle[..]) {
+                                    // The peer is closing the transport on purpose.
+                                    break;
+                                }
+                                if let Some(algorithms) =
+                                    decode_compression_hello(&packet_bundle[..])
+                                {
+                                    compression_handshake.receive_hello(algorithms);
+                                }
+                                continue;
+                            }
+
+                            if let Err(e) = crate::shatter_packet_bundle(
+                                packet_bundle,
+                                &mut shatter_offsets,
+                                &mut shatter_out_packets,
+                            ) {
+                                report_transport_error(&metrics, "websocket", &e);
+                                break;
+                            }
 
-                        for packet in shatter_out_packets.drain(..) {
-                            let packet = unsafe { packet.assume_init() };
-                            process_packet_fn(&packet).await;
+                            for packet in shatter_out_packets.drain(..) {
+                                let packet = unsafe { packet.assume_init() };
+                                process_packet_fn(&packet).await;
+                            }
                         }
+                    };
+
+                    if let Some(idle_timer) = &idle_timer {
+                        // Treat a peer that has gone quiet for too long as dead.
+                        future::or(rx_loop, idle_timer.expired(&worker_cx)).await;
+                    } else {
+                        rx_loop.await;
                     }
 
                     shutdown_notifier.notify();
@@ -103,12 +271,25 @@ impl TransportBuilder for WebSocketTransport {
             )
         });
 
-        TransportHandle {
-            shutdown_signal,
-            buffer_pool: self.buffer_pool,
-            writer_config: WriterConfig::LocalFlush {
+        let mut transport = TransportHandle::new(
+            self.buffer_pool,
+            WriterConfig::LocalFlush {
                 writer_flush_sender,
             },
+            shutdown_signal,
+        );
+        transport.drain_signal = Some(drain_signal);
+        #[cfg(feature = "encryption")]
+        {
+            transport.plane_keys = self.plane_keys;
         }
+        transport
     }
 }
+
+enum TxWake<F> {
+    Flushes(F),
+    Keepalive,
+    CompressionAnswer,
+    Drained,
+}
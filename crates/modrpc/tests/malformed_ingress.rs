@@ -0,0 +1,123 @@
+use mproto::BaseLen;
+
+use modrpc::{EndpointAddr, Packet, PacketBundle, TransmitPacket, TransportError};
+
+fn new_packet(buffer_pool: &bab::HeapBufferPool, bytes: &[u8]) -> Packet {
+    let buffer = buffer_pool.try_acquire().unwrap();
+    unsafe {
+        buffer.initialize_rc(1, 0, 0);
+        buffer.slice_mut(0..bytes.len()).copy_from_slice(bytes);
+        Packet::new(buffer, 0, bytes.len())
+    }
+}
+
+fn bundle(length: u16, packets: &[(u16, &[u8])]) -> Vec<u8> {
+    let mut bundle = mproto::encode_value_vec(PacketBundle { channel_id: 0, length });
+    for (payload_length, payload) in packets {
+        bundle.extend(mproto::encode_value_vec(TransmitPacket {
+            payload_length: *payload_length,
+            infra_id: 0,
+            plane_id: 0,
+            topic: 0,
+            source: EndpointAddr { endpoint: 0 },
+        }));
+        bundle.extend_from_slice(payload);
+    }
+    bundle
+}
+
+fn shatter(buffer_pool: &bab::HeapBufferPool, bytes: &[u8]) -> Result<Vec<Packet>, TransportError> {
+    let mut offsets = Vec::new();
+    let mut out_packets = Vec::new();
+    modrpc::shatter_packet_bundle(new_packet(buffer_pool, bytes), &mut offsets, &mut out_packets)?;
+    Ok(out_packets.into_iter().map(|packet| unsafe { packet.assume_init() }).collect())
+}
+
+#[test]
+fn test_shatter_packet_bundle() {
+    let buffer_pool = bab::HeapBufferPool::new(256, 1, 8);
+    let packet_len = TransmitPacket::BASE_LEN as u16;
+
+    let packets = shatter(&buffer_pool, &bundle(2 * packet_len + 5, &[(2, b"hi"), (3, b"bye")]))
+        .unwrap();
+    assert_eq!(packets.len(), 2);
+    assert_eq!(&packets[0][TransmitPacket::BASE_LEN..], b"hi");
+    assert_eq!(&packets[1][TransmitPacket::BASE_LEN..], b"bye");
+
+    assert_eq!(shatter(&buffer_pool, &bundle(0, &[])).unwrap().len(), 0);
+
+    // Truncated bundle header
+    let result = shatter(&buffer_pool, &bundle(0, &[])[..PacketBundle::BASE_LEN - 1]);
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+    // Bundle longer than what was received
+    let result = shatter(&buffer_pool, &bundle(packet_len + 3, &[(2, b"hi")]));
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+    // Packet longer than its bundle
+    let result = shatter(&buffer_pool, &bundle(packet_len + 2, &[(3, b"hi")]));
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+    // Truncated packet header
+    let mut short_header = bundle(3, &[]);
+    short_header.extend_from_slice(&[0; 3]);
+    let result = shatter(&buffer_pool, &short_header);
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+}
+
+#[test]
+fn test_validate_packet_bundle() {
+    let packet_len = TransmitPacket::BASE_LEN as u16;
+
+    let header = modrpc::validate_packet_bundle(&bundle(2 * packet_len + 5, &[(2, b"hi"), (3, b"bye")]))
+        .unwrap();
+    assert_eq!(header.length, 2 * packet_len + 5);
+
+    // Packet longer than its bundle
+    let result = modrpc::validate_packet_bundle(&bundle(packet_len + 2, &[(3, b"hi")]));
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+    // Trailing bytes that aren't a whole packet
+    let mut short_header = bundle(3, &[]);
+    short_header.extend_from_slice(&[0; 3]);
+    let result = modrpc::validate_packet_bundle(&short_header);
+    assert!(matches!(result, Err(TransportError::Malformed(_))));
+}
+
+#[cfg(feature = "tcp-transport")]
+#[test]
+fn test_tcp_ingress() {
+    let buffer_pool = bab::HeapBufferPool::new(64, 1, 4);
+    let max_packet_size = 32;
+
+    let mut stream = bundle(3, &[]);
+    stream.extend_from_slice(b"abc");
+    let mut ingress = modrpc::TcpIngress::new(&stream[..], buffer_pool.clone(), max_packet_size);
+    let packet = pollster::block_on(ingress.receive()).unwrap();
+    assert_eq!(&packet[..], &stream[..]);
+    let result = pollster::block_on(ingress.receive());
+    assert!(matches!(result, Err(TransportError::Io(_))));
+
+    let mut stream = bundle(max_packet_size as u16, &[]);
+    stream.extend_from_slice(&[0; 64]);
+    let mut ingress = modrpc::TcpIngress::new(&stream[..], buffer_pool, max_packet_size);
+    let result = pollster::block_on(ingress.receive());
+    assert!(matches!(result, Err(TransportError::BundleTooLarge { .. })));
+}
+
+#[cfg(feature = "tcp-transport")]
+#[test]
+fn test_tcp_ingress_high_channel_without_compression() {
+    let buffer_pool = bab::HeapBufferPool::new(64, 1, 4);
+    let mut stream = mproto::encode_value_vec(PacketBundle {
+        channel_id: modrpc::COMPRESSED_CHANNEL_BIT | 7,
+        length: 3,
+    });
+    stream.extend_from_slice(b"abc");
+
+    // Without compression negotiated the high bit is just part of the channel ID.
+    let mut ingress = modrpc::TcpIngress::new(&stream[..], buffer_pool.clone(), 32);
+    let packet = pollster::block_on(ingress.receive()).unwrap();
+    assert_eq!(&packet[..], &stream[..]);
+
+    let mut ingress = modrpc::TcpIngress::new(&stream[..], buffer_pool, 32);
+    ingress.set_decompression(true);
+    let result = pollster::block_on(ingress.receive());
+    assert!(matches!(result, Err(TransportError::Decompress(_))));
+}
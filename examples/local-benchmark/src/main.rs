@@ -5,13 +5,74 @@ use std::{
 
 use modrpc_executor::ModrpcExecutor;
 
+/// Runtime settings to benchmark, selected by name on the command line, e.g.
+/// `cargo run --release -- default throughput`. Runs every preset if none are given.
+const PRESETS: &[(&str, fn() -> modrpc::RuntimeConfig)] = &[
+    ("default", modrpc::RuntimeConfig::default),
+    ("low-latency", low_latency_config),
+    ("throughput", throughput_config),
+];
+
+fn low_latency_config() -> modrpc::RuntimeConfig {
+    modrpc::RuntimeConfig {
+        inter_worker_queue_capacity: 64,
+        max_inter_worker_batch_size: 8,
+        local_packet_queue_capacity: 16,
+        transport: modrpc::TransportConfig {
+            max_flush_delay: Duration::ZERO,
+            max_writer_flush_delay: Duration::ZERO,
+            max_unflushed_bytes: 8_000,
+        },
+        role: modrpc::RoleQueueConfig {
+            handler_queue_capacity: 16,
+            load_balancer_capacity: 16,
+            load_balancer_burst_size: 8,
+        },
+        ..modrpc::RuntimeConfig::default()
+    }
+}
+
+fn throughput_config() -> modrpc::RuntimeConfig {
+    modrpc::RuntimeConfig {
+        inter_worker_queue_capacity: 1024,
+        max_inter_worker_batch_size: 128,
+        local_packet_queue_capacity: 256,
+        transport: modrpc::TransportConfig {
+            max_flush_delay: Duration::from_micros(20),
+            max_writer_flush_delay: Duration::from_micros(10),
+            max_unflushed_bytes: 60_000,
+        },
+        role: modrpc::RoleQueueConfig {
+            handler_queue_capacity: 256,
+            load_balancer_capacity: 256,
+            load_balancer_burst_size: 64,
+        },
+        ..modrpc::RuntimeConfig::default()
+    }
+}
+
 fn main() {
     //let probius_flusher = probius::init_tcp_sink("modrpc-local-benchmark", "127.0.0.1:9876");
 
+    let mut names: Vec<String> = std::env::args().skip(1).collect();
+    if names.is_empty() {
+        names = PRESETS.iter().map(|(name, _)| name.to_string()).collect();
+    }
+    for name in names {
+        let Some((_, config)) = PRESETS.iter().find(|(preset, _)| *preset == name) else {
+            eprintln!("Unknown preset {name:?}");
+            std::process::exit(1);
+        };
+        println!("Running preset {name}");
+        run_benchmark(config());
+    }
+}
+
+fn run_benchmark(config: modrpc::RuntimeConfig) {
     let mut ex = modrpc_executor::TokioExecutor::new();
     let _guard = ex.tokio_runtime().enter();
 
-    let mut rt = modrpc::RuntimeBuilder::new_no_local();
+    let mut rt = modrpc::RuntimeBuilder::new_no_local().with_config(config);
     let client_group = rt.new_worker_group(4);
     let main_group = rt.new_worker_group(2);
     let (rt, rt_shutdown) = rt
@@ -154,7 +215,6 @@ fn main() {
 
         foo_shutdown_signal.notify();
 
-        // TODO This needs to also wait for local worker's tasks to complete.
         rt_shutdown.shutdown().await;
     });
 }
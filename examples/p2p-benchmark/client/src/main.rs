@@ -41,6 +41,7 @@ fn main() {
                 out_buffer_pool.clone(),
                 modrpc::WorkerId::local(),
                 p2p_benchmark_modrpc::P2pBenchmarkClientConfig { },
+                modrpc::TransportOptions::default(),
                 stream,
                 {
                     let start_tasks = start_tasks.clone();
@@ -60,7 +61,7 @@ fn main() {
                             // Note we run the transport on the local worker, so the client role
                             // needs to be setup on it for the responses arriving on the transport
                             // to be relayed to the requesting threads.
-                            .local(|_cx| { })
+                            .local_async(|_cx| { }).await
                     }
                 }
             )
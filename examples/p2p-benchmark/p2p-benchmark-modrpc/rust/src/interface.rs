@@ -8,7 +8,11 @@ pub struct P2pBenchmarkInterface {
 impl InterfaceSchema for P2pBenchmarkInterface {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            test_request: RequestInterface::new(ib),
+            test_request: ib.object(
+                &["Client", "Server"],
+                &["Client", "Server"],
+                |ib| RequestInterface::new(ib),
+            ),
         }
     }
 }
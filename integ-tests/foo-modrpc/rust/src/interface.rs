@@ -10,9 +10,21 @@ pub struct FooInterface {
 impl InterfaceSchema for FooInterface {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            foo_the_bar: RequestInterface::new(ib),
-            bar_the_foo: RequestInterface::new(ib),
-            fooness: PropertyInterface::new(ib),
+            foo_the_bar: ib.object(
+                &["Client", "Server"],
+                &["Server", "Client"],
+                |ib| RequestInterface::new(ib),
+            ),
+            bar_the_foo: ib.object(
+                &["Client", "Server"],
+                &["Server", "Client"],
+                |ib| RequestInterface::new(ib),
+            ),
+            fooness: ib.object(
+                &["Observer", "Owner"],
+                &["Client", "Server"],
+                |ib| PropertyInterface::new(ib),
+            ),
         }
     }
 }
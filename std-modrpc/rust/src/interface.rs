@@ -1,4 +1,4 @@
-use crate::proto::{MultiStreamItem, PropertyUpdate, Request, Response, StreamItem};
+use crate::proto::{MembershipSnapshot, MembershipUpdate, MultiStreamItem, PropertyUpdate, Request, Response, StreamItem};
 use modrpc::{InterfaceBuilder, InterfaceEvent, InterfaceSchema};
 
 pub struct PropertyInterface<T> {
@@ -8,11 +8,32 @@ pub struct PropertyInterface<T> {
 impl<T> InterfaceSchema for PropertyInterface<T> {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            update: ib.event("update"),
+            update: ib.event_from("update", &["Owner"]),
         }
     }
 }
 
+pub struct MembershipInterface<M> {
+    pub sync: InterfaceEvent<()>,
+    pub joined: InterfaceEvent<MembershipUpdate<M>>,
+    pub left: InterfaceEvent<MembershipUpdate<M>>,
+    pub snapshot: InterfaceEvent<MembershipSnapshot<M>>,
+}
+
+impl<M> InterfaceSchema for MembershipInterface<M> {
+    fn new(ib: &mut InterfaceBuilder) -> Self {
+        Self {
+            sync: ib.event_from("sync", &["Observer"]),
+            joined: ib.event_from("joined", &["Host"]),
+            left: ib.event_from("left", &["Host"]),
+            snapshot: ib.event_from("snapshot", &["Host"]),
+        }
+    }
+}
+
+/// Offset of `requester` in an encoded `Response` - it follows the `u32` request ID.
+const RESPONSE_REQUESTER_OFFSET: u16 = <u32 as mproto::BaseLen>::BASE_LEN as u16;
+
 pub struct RequestInterface<Req, Resp> {
     pub request: InterfaceEvent<Request<Req>>,
     pub response: InterfaceEvent<Response<Resp>>,
@@ -21,8 +42,8 @@ pub struct RequestInterface<Req, Resp> {
 impl<Req, Resp> InterfaceSchema for RequestInterface<Req, Resp> {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            request: ib.event("request"),
-            response: ib.event("response"),
+            request: ib.event_from("request", &["Client"]),
+            response: ib.addressed_event_from("response", &["Server"], RESPONSE_REQUESTER_OFFSET),
         }
     }
 }
@@ -34,7 +55,7 @@ pub struct StreamInterface<T> {
 impl<T> InterfaceSchema for StreamInterface<T> {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            item: ib.event("item"),
+            item: ib.event_from("item", &["Sender"]),
         }
     }
 }
@@ -46,7 +67,7 @@ pub struct MultiStreamInterface<T> {
 impl<T> InterfaceSchema for MultiStreamInterface<T> {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            item: ib.event("item"),
+            item: ib.event_from("item", &["Sender"]),
         }
     }
 }
@@ -58,7 +79,7 @@ pub struct ByteStreamInterface {
 impl InterfaceSchema for ByteStreamInterface {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            blob: ib.event("blob"),
+            blob: ib.event_from("blob", &["Sender"]),
         }
     }
 }
@@ -70,7 +91,7 @@ pub struct MultiByteStreamInterface {
 impl InterfaceSchema for MultiByteStreamInterface {
     fn new(ib: &mut InterfaceBuilder) -> Self {
         Self {
-            blob: ib.event("blob"),
+            blob: ib.event_from("blob", &["Sender"]),
         }
     }
 }
@@ -2,6 +2,7 @@ pub use interface::*;
 pub use proto::*;
 pub use roles::*;
 pub use role_impls::*;
+pub use trace_context::TraceContextScope;
 
 mod interface;
 mod proto;
@@ -9,3 +10,4 @@ mod receive_stream;
 mod request_tracker;
 mod roles;
 mod role_impls;
+mod trace_context;
@@ -156,1059 +156,2167 @@ impl<'a, T: Owned> PartialEq for PropertyUpdateLazy<'a, T> {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct Request<T> {
-    pub request_id: u32,
-    pub worker: u16,
-    pub payload: T,
+pub struct Member<M> {
+    pub endpoint: u64,
+    pub metadata: M,
 }
 
-pub struct RequestLazy<'a, T> {
+pub struct MemberLazy<'a, M> {
     buffer: &'a [u8],
     offset: usize,
-    _t: core::marker::PhantomData<T>,
+    _m: core::marker::PhantomData<M>,
 }
 
-pub struct RequestGen<
-    Payload: Encode,
+pub struct MemberGen<
+    Metadata: Encode,
 > {
-    pub request_id: u32,
-    pub worker: u16,
-    pub payload: Payload,
+    pub endpoint: u64,
+    pub metadata: Metadata,
 }
 
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<Request<T>> for RequestGen<Payload> { }
+    M: Owned,
+    Metadata: Encode + Compatible<M>
+> Compatible<Member<M>> for MemberGen<Metadata> { }
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<RequestGen<Payload>> for Request<T> { }
+    M: Owned,
+    Metadata: Encode + Compatible<M>
+> Compatible<MemberGen<Metadata>> for Member<M> { }
 
 impl<
-    Payload: Encode,
-> BaseLen for RequestGen<Payload> {
-    const BASE_LEN: usize = 6 + Payload::BASE_LEN;
+    Metadata: Encode,
+> BaseLen for MemberGen<Metadata> {
+    const BASE_LEN: usize = 8 + Metadata::BASE_LEN;
 }
 
 impl<
-    Payload: Encode,
-> Encode for RequestGen<Payload> {
+    Metadata: Encode,
+> Encode for MemberGen<Metadata> {
     fn scratch_len(&self) -> usize {
-        self.request_id.scratch_len() + self.worker.scratch_len() + self.payload.scratch_len()
+        self.endpoint.scratch_len() + self.metadata.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.request_id.encode(cursor);
-        self.worker.encode(cursor);
-        self.payload.encode(cursor);
+        self.endpoint.encode(cursor);
+        self.metadata.encode(cursor);
     }
 }
 
-impl<T: Owned> Owned for Request<T> {
-    type Lazy<'a> = RequestLazy<'a, T>;
+impl<M: Owned> Owned for Member<M> {
+    type Lazy<'a> = MemberLazy<'a, M>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a, T: Owned> Lazy<'a> for RequestLazy<'a, T> {
-    type Owned = Request<T>;
+impl<'a, M: Owned> Lazy<'a> for MemberLazy<'a, M> {
+    type Owned = Member<M>;
 }
 
-impl<'a, T: Owned> Compatible<RequestLazy<'a, T>> for RequestLazy<'a, T> { }
-impl<'a, T: Owned> Compatible<RequestLazy<'a, T>> for Request<T> { }
-impl<T: Owned> Compatible<Request<T>> for Request<T> { }
-impl<'a, T: Owned> Compatible<Request<T>> for RequestLazy<'a, T> { }
+impl<'a, M: Owned> Compatible<MemberLazy<'a, M>> for MemberLazy<'a, M> { }
+impl<'a, M: Owned> Compatible<MemberLazy<'a, M>> for Member<M> { }
+impl<M: Owned> Compatible<Member<M>> for Member<M> { }
+impl<'a, M: Owned> Compatible<Member<M>> for MemberLazy<'a, M> { }
 
-impl<'a, T: Owned> RequestLazy<'a, T> {
+impl<'a, M: Owned> MemberLazy<'a, M> {
 
-    pub fn request_id(&self) -> DecodeResult<u32> {
+    pub fn endpoint(&self) -> DecodeResult<u64> {
         Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
     }
 
-    pub fn worker(&self) -> DecodeResult<u16> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4))
-    }
-
-    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6))
+    pub fn metadata(&self) -> DecodeResult<M::Lazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
     }
 }
 
-impl<T: BaseLen> BaseLen for Request<T> {
-    const BASE_LEN: usize = 6 + T::BASE_LEN;
+impl<M: BaseLen> BaseLen for Member<M> {
+    const BASE_LEN: usize = 8 + M::BASE_LEN;
 }
 
-impl<T: Encode> Encode for Request<T> {
+impl<M: Encode> Encode for Member<M> {
     fn scratch_len(&self) -> usize {
-        self.request_id.scratch_len() + self.worker.scratch_len() + self.payload.scratch_len()
+        self.endpoint.scratch_len() + self.metadata.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.request_id.encode(cursor);
-        self.worker.encode(cursor);
-        self.payload.encode(cursor);
+        self.endpoint.encode(cursor);
+        self.metadata.encode(cursor);
     }
 }
 
-impl<'a, T: Decode<'a>> Decode<'a> for Request<T> {
+impl<'a, M: Decode<'a>> Decode<'a> for Member<M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let request_id = Decode::decode(cursor)?;
-        let worker = Decode::decode(cursor)?;
-        let payload = Decode::decode(cursor)?;
+        let endpoint = Decode::decode(cursor)?;
+        let metadata = Decode::decode(cursor)?;
 
-        Ok(Request {
-            request_id,
-            worker,
-            payload,
+        Ok(Member {
+            endpoint,
+            metadata,
         })
     }
 }
 
-impl<'a, T: Owned> BaseLen for RequestLazy<'a, T> {
-    const BASE_LEN: usize = 6 + T::BASE_LEN;
+impl<'a, M: Owned> BaseLen for MemberLazy<'a, M> {
+    const BASE_LEN: usize = 8 + M::BASE_LEN;
 }
 
-impl<'a, T: Owned> Encode for RequestLazy<'a, T> {
+impl<'a, M: Owned> Encode for MemberLazy<'a, M> {
     fn scratch_len(&self) -> usize {
-        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6)).unwrap();
-        request_id.scratch_len() + worker.scratch_len() + payload.scratch_len()
+        let endpoint: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let metadata: M::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        endpoint.scratch_len() + metadata.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6)).unwrap();
-        request_id.encode(cursor);
-        worker.encode(cursor);
-        payload.encode(cursor);
+        let endpoint: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let metadata: M::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        endpoint.encode(cursor);
+        metadata.encode(cursor);
     }
 }
 
-impl<'a, T: Owned> Decode<'a> for RequestLazy<'a, T> {
+impl<'a, M: Owned> Decode<'a> for MemberLazy<'a, M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(RequestLazy {
+        Ok(MemberLazy {
             buffer: cursor.buffer(),
             offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a, T: Owned> TryFrom<RequestLazy<'a, T>> for Request<T> {
+impl<'a, M: Owned> TryFrom<MemberLazy<'a, M>> for Member<M> {
     type Error = DecodeError;
 
-    fn try_from(other: RequestLazy<'a, T>) -> Result<Self, Self::Error> {
+    fn try_from(other: MemberLazy<'a, M>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a, T> Copy for RequestLazy<'a, T> { }
+impl<'a, M> Copy for MemberLazy<'a, M> { }
 
-impl<'a, T> Clone for RequestLazy<'a, T> {
+impl<'a, M> Clone for MemberLazy<'a, M> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a, T> core::fmt::Debug for RequestLazy<'a, T> {
+impl<'a, M> core::fmt::Debug for MemberLazy<'a, M> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("RequestLazy")
+        f.debug_struct("MemberLazy")
             .finish()
     }
 }
 
-impl<'a, T: Owned> PartialEq for RequestLazy<'a, T> {
+impl<'a, M: Owned> PartialEq for MemberLazy<'a, M> {
     fn eq(&self, other: &Self) -> bool {
-        self.request_id().unwrap() == other.request_id().unwrap()
-            && self.worker().unwrap() == other.worker().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
+        self.endpoint().unwrap() == other.endpoint().unwrap()
+            && self.metadata().unwrap() == other.metadata().unwrap()
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct Response<T> {
-    pub request_id: u32,
-    pub requester: u64,
-    pub requester_worker: u16,
-    pub payload: T,
+#[cfg(any(feature = "std", feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct MembershipUpdate<M> {
+    pub endpoint: u64,
+    pub members: Vec<Member<M>>,
 }
 
-pub struct ResponseLazy<'a, T> {
+pub struct MembershipUpdateLazy<'a, M> {
     buffer: &'a [u8],
     offset: usize,
-    _t: core::marker::PhantomData<T>,
+    _m: core::marker::PhantomData<M>,
 }
 
-pub struct ResponseGen<
-    Payload: Encode,
+pub struct MembershipUpdateGen<
+    Members: Encode,
 > {
-    pub request_id: u32,
-    pub requester: u64,
-    pub requester_worker: u16,
-    pub payload: Payload,
+    pub endpoint: u64,
+    pub members: Members,
 }
 
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<Response<T>> for ResponseGen<Payload> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipUpdate<M>> for MembershipUpdateGen<Members> { }
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<ResponseGen<Payload>> for Response<T> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipUpdateGen<Members>> for MembershipUpdate<M> { }
 
 impl<
-    Payload: Encode,
-> BaseLen for ResponseGen<Payload> {
-    const BASE_LEN: usize = 14 + Payload::BASE_LEN;
+    Members: Encode,
+> BaseLen for MembershipUpdateGen<Members> {
+    const BASE_LEN: usize = 8 + Members::BASE_LEN;
 }
 
 impl<
-    Payload: Encode,
-> Encode for ResponseGen<Payload> {
+    Members: Encode,
+> Encode for MembershipUpdateGen<Members> {
     fn scratch_len(&self) -> usize {
-        self.request_id.scratch_len() + self.requester.scratch_len() + self.requester_worker.scratch_len() + self.payload.scratch_len()
+        self.endpoint.scratch_len() + self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.request_id.encode(cursor);
-        self.requester.encode(cursor);
-        self.requester_worker.encode(cursor);
-        self.payload.encode(cursor);
+        self.endpoint.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<T: Owned> Owned for Response<T> {
-    type Lazy<'a> = ResponseLazy<'a, T>;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Owned for MembershipUpdate<M> {
+    type Lazy<'a> = MembershipUpdateLazy<'a, M>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a, T: Owned> Lazy<'a> for ResponseLazy<'a, T> {
-    type Owned = Response<T>;
+impl<'a, M: Owned> Lazy<'a> for MembershipUpdateLazy<'a, M> {
+    type Owned = MembershipUpdate<M>;
 }
 
-impl<'a, T: Owned> Compatible<ResponseLazy<'a, T>> for ResponseLazy<'a, T> { }
-impl<'a, T: Owned> Compatible<ResponseLazy<'a, T>> for Response<T> { }
-impl<T: Owned> Compatible<Response<T>> for Response<T> { }
-impl<'a, T: Owned> Compatible<Response<T>> for ResponseLazy<'a, T> { }
+impl<'a, M: Owned> Compatible<MembershipUpdateLazy<'a, M>> for MembershipUpdateLazy<'a, M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipUpdateLazy<'a, M>> for MembershipUpdate<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Compatible<MembershipUpdate<M>> for MembershipUpdate<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipUpdate<M>> for MembershipUpdateLazy<'a, M> { }
 
-impl<'a, T: Owned> ResponseLazy<'a, T> {
+impl<'a, M: Owned> MembershipUpdateLazy<'a, M> {
 
-    pub fn request_id(&self) -> DecodeResult<u32> {
+    pub fn endpoint(&self) -> DecodeResult<u64> {
         Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
     }
 
-    pub fn requester(&self) -> DecodeResult<u64> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4))
-    }
-
-    pub fn requester_worker(&self) -> DecodeResult<u16> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12))
-    }
-
-    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14))
+    pub fn members(&self) -> DecodeResult<mproto::ListLazy<'a, Member<M>>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
     }
 }
 
-impl<T: BaseLen> BaseLen for Response<T> {
-    const BASE_LEN: usize = 14 + T::BASE_LEN;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: BaseLen> BaseLen for MembershipUpdate<M> {
+    const BASE_LEN: usize = 16;
 }
 
-impl<T: Encode> Encode for Response<T> {
+impl<M: Encode> Encode for MembershipUpdate<M> {
     fn scratch_len(&self) -> usize {
-        self.request_id.scratch_len() + self.requester.scratch_len() + self.requester_worker.scratch_len() + self.payload.scratch_len()
+        self.endpoint.scratch_len() + self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.request_id.encode(cursor);
-        self.requester.encode(cursor);
-        self.requester_worker.encode(cursor);
-        self.payload.encode(cursor);
+        self.endpoint.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<'a, T: Decode<'a>> Decode<'a> for Response<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Decode<'a>> Decode<'a> for MembershipUpdate<M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let request_id = Decode::decode(cursor)?;
-        let requester = Decode::decode(cursor)?;
-        let requester_worker = Decode::decode(cursor)?;
-        let payload = Decode::decode(cursor)?;
+        let endpoint = Decode::decode(cursor)?;
+        let members = Decode::decode(cursor)?;
 
-        Ok(Response {
-            request_id,
-            requester,
-            requester_worker,
-            payload,
+        Ok(MembershipUpdate {
+            endpoint,
+            members,
         })
     }
 }
 
-impl<'a, T: Owned> BaseLen for ResponseLazy<'a, T> {
-    const BASE_LEN: usize = 14 + T::BASE_LEN;
+impl<'a, M: Owned> BaseLen for MembershipUpdateLazy<'a, M> {
+    const BASE_LEN: usize = 16;
 }
 
-impl<'a, T: Owned> Encode for ResponseLazy<'a, T> {
+impl<'a, M: Owned> Encode for MembershipUpdateLazy<'a, M> {
     fn scratch_len(&self) -> usize {
-        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let requester: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
-        let requester_worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14)).unwrap();
-        request_id.scratch_len() + requester.scratch_len() + requester_worker.scratch_len() + payload.scratch_len()
+        let endpoint: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        endpoint.scratch_len() + members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let requester: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
-        let requester_worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14)).unwrap();
-        request_id.encode(cursor);
-        requester.encode(cursor);
-        requester_worker.encode(cursor);
-        payload.encode(cursor);
+        let endpoint: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        endpoint.encode(cursor);
+        members.encode(cursor);
     }
 }
 
-impl<'a, T: Owned> Decode<'a> for ResponseLazy<'a, T> {
+impl<'a, M: Owned> Decode<'a> for MembershipUpdateLazy<'a, M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(ResponseLazy {
+        Ok(MembershipUpdateLazy {
             buffer: cursor.buffer(),
             offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a, T: Owned> TryFrom<ResponseLazy<'a, T>> for Response<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> TryFrom<MembershipUpdateLazy<'a, M>> for MembershipUpdate<M> {
     type Error = DecodeError;
 
-    fn try_from(other: ResponseLazy<'a, T>) -> Result<Self, Self::Error> {
+    fn try_from(other: MembershipUpdateLazy<'a, M>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a, T> Copy for ResponseLazy<'a, T> { }
+impl<'a, M> Copy for MembershipUpdateLazy<'a, M> { }
 
-impl<'a, T> Clone for ResponseLazy<'a, T> {
+impl<'a, M> Clone for MembershipUpdateLazy<'a, M> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a, T> core::fmt::Debug for ResponseLazy<'a, T> {
+impl<'a, M> core::fmt::Debug for MembershipUpdateLazy<'a, M> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("ResponseLazy")
+        f.debug_struct("MembershipUpdateLazy")
             .finish()
     }
 }
 
-impl<'a, T: Owned> PartialEq for ResponseLazy<'a, T> {
+impl<'a, M: Owned> PartialEq for MembershipUpdateLazy<'a, M> {
     fn eq(&self, other: &Self) -> bool {
-        self.request_id().unwrap() == other.request_id().unwrap()
-            && self.requester().unwrap() == other.requester().unwrap()&& self.requester_worker().unwrap() == other.requester_worker().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
+        self.endpoint().unwrap() == other.endpoint().unwrap()
+            && self.members().unwrap() == other.members().unwrap()
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct StreamItem<T> {
-    pub seq: u64,
-    pub payload: T,
+#[cfg(any(feature = "std", feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct MembershipSnapshot<M> {
+    pub members: Vec<Member<M>>,
 }
 
-pub struct StreamItemLazy<'a, T> {
+pub struct MembershipSnapshotLazy<'a, M> {
     buffer: &'a [u8],
     offset: usize,
-    _t: core::marker::PhantomData<T>,
+    _m: core::marker::PhantomData<M>,
 }
 
-pub struct StreamItemGen<
-    Payload: Encode,
+pub struct MembershipSnapshotGen<
+    Members: Encode,
 > {
-    pub seq: u64,
-    pub payload: Payload,
+    pub members: Members,
 }
 
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<StreamItem<T>> for StreamItemGen<Payload> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipSnapshot<M>> for MembershipSnapshotGen<Members> { }
 impl<
-    T: Owned,
-    Payload: Encode + Compatible<T>
-> Compatible<StreamItemGen<Payload>> for StreamItem<T> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipSnapshotGen<Members>> for MembershipSnapshot<M> { }
 
 impl<
-    Payload: Encode,
-> BaseLen for StreamItemGen<Payload> {
-    const BASE_LEN: usize = 8 + Payload::BASE_LEN;
+    Members: Encode,
+> BaseLen for MembershipSnapshotGen<Members> {
+    const BASE_LEN: usize = Members::BASE_LEN;
 }
 
 impl<
-    Payload: Encode,
-> Encode for StreamItemGen<Payload> {
+    Members: Encode,
+> Encode for MembershipSnapshotGen<Members> {
     fn scratch_len(&self) -> usize {
-        self.seq.scratch_len() + self.payload.scratch_len()
+        self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.seq.encode(cursor);
-        self.payload.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<T: Owned> Owned for StreamItem<T> {
-    type Lazy<'a> = StreamItemLazy<'a, T>;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Owned for MembershipSnapshot<M> {
+    type Lazy<'a> = MembershipSnapshotLazy<'a, M>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a, T: Owned> Lazy<'a> for StreamItemLazy<'a, T> {
-    type Owned = StreamItem<T>;
+impl<'a, M: Owned> Lazy<'a> for MembershipSnapshotLazy<'a, M> {
+    type Owned = MembershipSnapshot<M>;
 }
 
-impl<'a, T: Owned> Compatible<StreamItemLazy<'a, T>> for StreamItemLazy<'a, T> { }
-impl<'a, T: Owned> Compatible<StreamItemLazy<'a, T>> for StreamItem<T> { }
-impl<T: Owned> Compatible<StreamItem<T>> for StreamItem<T> { }
-impl<'a, T: Owned> Compatible<StreamItem<T>> for StreamItemLazy<'a, T> { }
+impl<'a, M: Owned> Compatible<MembershipSnapshotLazy<'a, M>> for MembershipSnapshotLazy<'a, M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipSnapshotLazy<'a, M>> for MembershipSnapshot<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Compatible<MembershipSnapshot<M>> for MembershipSnapshot<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipSnapshot<M>> for MembershipSnapshotLazy<'a, M> { }
 
-impl<'a, T: Owned> StreamItemLazy<'a, T> {
+impl<'a, M: Owned> MembershipSnapshotLazy<'a, M> {
 
-    pub fn seq(&self) -> DecodeResult<u64> {
+    pub fn members(&self) -> DecodeResult<mproto::ListLazy<'a, Member<M>>> {
         Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
     }
-
-    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
-    }
 }
 
-impl<T: BaseLen> BaseLen for StreamItem<T> {
-    const BASE_LEN: usize = 8 + T::BASE_LEN;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: BaseLen> BaseLen for MembershipSnapshot<M> {
+    const BASE_LEN: usize = 8;
 }
 
-impl<T: Encode> Encode for StreamItem<T> {
+impl<M: Encode> Encode for MembershipSnapshot<M> {
     fn scratch_len(&self) -> usize {
-        self.seq.scratch_len() + self.payload.scratch_len()
+        self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.seq.encode(cursor);
-        self.payload.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<'a, T: Decode<'a>> Decode<'a> for StreamItem<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Decode<'a>> Decode<'a> for MembershipSnapshot<M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let seq = Decode::decode(cursor)?;
-        let payload = Decode::decode(cursor)?;
+        let members = Decode::decode(cursor)?;
 
-        Ok(StreamItem {
-            seq,
-            payload,
+        Ok(MembershipSnapshot {
+            members,
         })
     }
 }
 
-impl<'a, T: Owned> BaseLen for StreamItemLazy<'a, T> {
-    const BASE_LEN: usize = 8 + T::BASE_LEN;
+impl<'a, M: Owned> BaseLen for MembershipSnapshotLazy<'a, M> {
+    const BASE_LEN: usize = 8;
 }
 
-impl<'a, T: Owned> Encode for StreamItemLazy<'a, T> {
+impl<'a, M: Owned> Encode for MembershipSnapshotLazy<'a, M> {
     fn scratch_len(&self) -> usize {
-        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
-        seq.scratch_len() + payload.scratch_len()
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
-        seq.encode(cursor);
-        payload.encode(cursor);
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        members.encode(cursor);
     }
 }
 
-impl<'a, T: Owned> Decode<'a> for StreamItemLazy<'a, T> {
+impl<'a, M: Owned> Decode<'a> for MembershipSnapshotLazy<'a, M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(StreamItemLazy {
+        Ok(MembershipSnapshotLazy {
             buffer: cursor.buffer(),
             offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a, T: Owned> TryFrom<StreamItemLazy<'a, T>> for StreamItem<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> TryFrom<MembershipSnapshotLazy<'a, M>> for MembershipSnapshot<M> {
     type Error = DecodeError;
 
-    fn try_from(other: StreamItemLazy<'a, T>) -> Result<Self, Self::Error> {
+    fn try_from(other: MembershipSnapshotLazy<'a, M>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a, T> Copy for StreamItemLazy<'a, T> { }
+impl<'a, M> Copy for MembershipSnapshotLazy<'a, M> { }
 
-impl<'a, T> Clone for StreamItemLazy<'a, T> {
+impl<'a, M> Clone for MembershipSnapshotLazy<'a, M> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a, T> core::fmt::Debug for StreamItemLazy<'a, T> {
+impl<'a, M> core::fmt::Debug for MembershipSnapshotLazy<'a, M> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("StreamItemLazy")
+        f.debug_struct("MembershipSnapshotLazy")
             .finish()
     }
 }
 
-impl<'a, T: Owned> PartialEq for StreamItemLazy<'a, T> {
+impl<'a, M: Owned> PartialEq for MembershipSnapshotLazy<'a, M> {
     fn eq(&self, other: &Self) -> bool {
-        self.seq().unwrap() == other.seq().unwrap()
-            && self.payload().unwrap() == other.payload().unwrap()
+        self.members().unwrap() == other.members().unwrap()
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct MultiStreamId {
-    pub owner: u64,
-    pub id: u32,
+pub struct Request<T> {
+    pub request_id: u32,
+    pub worker: u16,
+    pub trace_context: Option<TraceContext>,
+    pub payload: T,
 }
 
-pub struct MultiStreamIdLazy<'a> {
+pub struct RequestLazy<'a, T> {
     buffer: &'a [u8],
     offset: usize,
+    _t: core::marker::PhantomData<T>,
 }
 
-pub struct MultiStreamIdGen<> {
-    pub owner: u64,
-    pub id: u32,
+pub struct RequestGen<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode,
+> {
+    pub request_id: u32,
+    pub worker: u16,
+    pub trace_context: TTraceContext,
+    pub payload: Payload,
 }
 
-impl<> Compatible<MultiStreamId> for MultiStreamIdGen<> { }
-impl<> Compatible<MultiStreamIdGen<>> for MultiStreamId { }
+impl<
+    T: Owned,
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode + Compatible<T>
+> Compatible<Request<T>> for RequestGen<TTraceContext, Payload> { }
+impl<
+    T: Owned,
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode + Compatible<T>
+> Compatible<RequestGen<TTraceContext, Payload>> for Request<T> { }
 
-impl<> BaseLen for MultiStreamIdGen<> {
-    const BASE_LEN: usize = 12;
+impl<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode,
+> BaseLen for RequestGen<TTraceContext, Payload> {
+    const BASE_LEN: usize = 6 + TTraceContext::BASE_LEN + Payload::BASE_LEN;
 }
 
-impl<> Encode for MultiStreamIdGen<> {
+impl<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode,
+> Encode for RequestGen<TTraceContext, Payload> {
     fn scratch_len(&self) -> usize {
-        self.owner.scratch_len() + self.id.scratch_len()
+        self.request_id.scratch_len() + self.worker.scratch_len() + self.trace_context.scratch_len() + self.payload.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.owner.encode(cursor);
-        self.id.encode(cursor);
+        self.request_id.encode(cursor);
+        self.worker.encode(cursor);
+        self.trace_context.encode(cursor);
+        self.payload.encode(cursor);
     }
 }
 
-impl Owned for MultiStreamId {
-    type Lazy<'a> = MultiStreamIdLazy<'a>;
+impl<T: Owned> Owned for Request<T> {
+    type Lazy<'a> = RequestLazy<'a, T>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a> Lazy<'a> for MultiStreamIdLazy<'a> {
-    type Owned = MultiStreamId;
+impl<'a, T: Owned> Lazy<'a> for RequestLazy<'a, T> {
+    type Owned = Request<T>;
 }
 
-impl<'a> Compatible<MultiStreamIdLazy<'a>> for MultiStreamIdLazy<'a> { }
-impl<'a> Compatible<MultiStreamIdLazy<'a>> for MultiStreamId { }
-impl Compatible<MultiStreamId> for MultiStreamId { }
-impl<'a> Compatible<MultiStreamId> for MultiStreamIdLazy<'a> { }
+impl<'a, T: Owned> Compatible<RequestLazy<'a, T>> for RequestLazy<'a, T> { }
+impl<'a, T: Owned> Compatible<RequestLazy<'a, T>> for Request<T> { }
+impl<T: Owned> Compatible<Request<T>> for Request<T> { }
+impl<'a, T: Owned> Compatible<Request<T>> for RequestLazy<'a, T> { }
 
-impl<'a> MultiStreamIdLazy<'a> {
+impl<'a, T: Owned> RequestLazy<'a, T> {
 
-    pub fn owner(&self) -> DecodeResult<u64> {
+    pub fn request_id(&self) -> DecodeResult<u32> {
         Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
     }
 
-    pub fn id(&self) -> DecodeResult<u32> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
+    pub fn worker(&self) -> DecodeResult<u16> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4))
+    }
+
+    pub fn trace_context(&self) -> DecodeResult<Option<TraceContextLazy<'a>>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6))
+    }
+
+    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 32))
     }
 }
 
-impl BaseLen for MultiStreamId {
-    const BASE_LEN: usize = 12;
+impl<T: BaseLen> BaseLen for Request<T> {
+    const BASE_LEN: usize = 32 + T::BASE_LEN;
 }
 
-impl Encode for MultiStreamId {
+impl<T: Encode> Encode for Request<T> {
     fn scratch_len(&self) -> usize {
-        self.owner.scratch_len() + self.id.scratch_len()
+        self.request_id.scratch_len() + self.worker.scratch_len() + self.trace_context.scratch_len() + self.payload.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.owner.encode(cursor);
-        self.id.encode(cursor);
+        self.request_id.encode(cursor);
+        self.worker.encode(cursor);
+        self.trace_context.encode(cursor);
+        self.payload.encode(cursor);
     }
 }
 
-impl<'a> Decode<'a> for MultiStreamId {
+impl<'a, T: Decode<'a>> Decode<'a> for Request<T> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let owner = Decode::decode(cursor)?;
-        let id = Decode::decode(cursor)?;
+        let request_id = Decode::decode(cursor)?;
+        let worker = Decode::decode(cursor)?;
+        let trace_context = Decode::decode(cursor)?;
+        let payload = Decode::decode(cursor)?;
 
-        Ok(MultiStreamId {
-            owner,
-            id,
+        Ok(Request {
+            request_id,
+            worker,
+            trace_context,
+            payload,
         })
     }
 }
 
-impl<'a> BaseLen for MultiStreamIdLazy<'a> {
-    const BASE_LEN: usize = 12;
+impl<'a, T: Owned> BaseLen for RequestLazy<'a, T> {
+    const BASE_LEN: usize = 32 + T::BASE_LEN;
 }
 
-impl<'a> Encode for MultiStreamIdLazy<'a> {
+impl<'a, T: Owned> Encode for RequestLazy<'a, T> {
     fn scratch_len(&self) -> usize {
-        let owner: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
-        owner.scratch_len() + id.scratch_len()
+        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        let trace_context: Option<TraceContextLazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 32)).unwrap();
+        request_id.scratch_len() + worker.scratch_len() + trace_context.scratch_len() + payload.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        let owner: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
-        owner.encode(cursor);
-        id.encode(cursor);
+        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        let trace_context: Option<TraceContextLazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 6)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 32)).unwrap();
+        request_id.encode(cursor);
+        worker.encode(cursor);
+        trace_context.encode(cursor);
+        payload.encode(cursor);
     }
 }
 
-impl<'a> Decode<'a> for MultiStreamIdLazy<'a> {
+impl<'a, T: Owned> Decode<'a> for RequestLazy<'a, T> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(MultiStreamIdLazy {
+        Ok(RequestLazy {
             buffer: cursor.buffer(),
             offset,
+            _t: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a> TryFrom<MultiStreamIdLazy<'a>> for MultiStreamId {
+impl<'a, T: Owned> TryFrom<RequestLazy<'a, T>> for Request<T> {
     type Error = DecodeError;
 
-    fn try_from(other: MultiStreamIdLazy<'a>) -> Result<Self, Self::Error> {
+    fn try_from(other: RequestLazy<'a, T>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a> Copy for MultiStreamIdLazy<'a> { }
+impl<'a, T> Copy for RequestLazy<'a, T> { }
 
-impl<'a> Clone for MultiStreamIdLazy<'a> {
+impl<'a, T> Clone for RequestLazy<'a, T> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
+            _t: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a> core::fmt::Debug for MultiStreamIdLazy<'a> {
+impl<'a, T> core::fmt::Debug for RequestLazy<'a, T> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("MultiStreamIdLazy")
+        f.debug_struct("RequestLazy")
             .finish()
     }
 }
 
-impl<'a> PartialEq for MultiStreamIdLazy<'a> {
+impl<'a, T: Owned> PartialEq for RequestLazy<'a, T> {
     fn eq(&self, other: &Self) -> bool {
-        self.owner().unwrap() == other.owner().unwrap()
-            && self.id().unwrap() == other.id().unwrap()
+        self.request_id().unwrap() == other.request_id().unwrap()
+            && self.worker().unwrap() == other.worker().unwrap()&& self.trace_context().unwrap() == other.trace_context().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct MultiStreamItem<T> {
-    pub stream_id: MultiStreamId,
-    pub seq: u64,
-    pub payload: Option<T>,
+pub struct Response<T> {
+    pub request_id: u32,
+    pub requester: u64,
+    pub requester_worker: u16,
+    pub trace_context: Option<TraceContext>,
+    pub payload: T,
 }
 
-pub struct MultiStreamItemLazy<'a, T> {
+pub struct ResponseLazy<'a, T> {
     buffer: &'a [u8],
     offset: usize,
     _t: core::marker::PhantomData<T>,
 }
 
-pub struct MultiStreamItemGen<
-    StreamId: Encode + Compatible<MultiStreamId>,
+pub struct ResponseGen<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
     Payload: Encode,
 > {
-    pub stream_id: StreamId,
-    pub seq: u64,
+    pub request_id: u32,
+    pub requester: u64,
+    pub requester_worker: u16,
+    pub trace_context: TTraceContext,
     pub payload: Payload,
 }
 
-impl<
-    T: Owned,
-    StreamId: Encode + Compatible<MultiStreamId>,
-    Payload: Encode + Compatible<Option<T>>
-> Compatible<MultiStreamItem<T>> for MultiStreamItemGen<StreamId, Payload> { }
-impl<
-    T: Owned,
-    StreamId: Encode + Compatible<MultiStreamId>,
-    Payload: Encode + Compatible<Option<T>>
-> Compatible<MultiStreamItemGen<StreamId, Payload>> for MultiStreamItem<T> { }
+impl<
+    T: Owned,
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode + Compatible<T>
+> Compatible<Response<T>> for ResponseGen<TTraceContext, Payload> { }
+impl<
+    T: Owned,
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode + Compatible<T>
+> Compatible<ResponseGen<TTraceContext, Payload>> for Response<T> { }
+
+impl<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode,
+> BaseLen for ResponseGen<TTraceContext, Payload> {
+    const BASE_LEN: usize = 14 + TTraceContext::BASE_LEN + Payload::BASE_LEN;
+}
+
+impl<
+    TTraceContext: Encode + Compatible<Option<TraceContext>>,
+    Payload: Encode,
+> Encode for ResponseGen<TTraceContext, Payload> {
+    fn scratch_len(&self) -> usize {
+        self.request_id.scratch_len() + self.requester.scratch_len() + self.requester_worker.scratch_len() + self.trace_context.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.request_id.encode(cursor);
+        self.requester.encode(cursor);
+        self.requester_worker.encode(cursor);
+        self.trace_context.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<T: Owned> Owned for Response<T> {
+    type Lazy<'a> = ResponseLazy<'a, T>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a, T: Owned> Lazy<'a> for ResponseLazy<'a, T> {
+    type Owned = Response<T>;
+}
+
+impl<'a, T: Owned> Compatible<ResponseLazy<'a, T>> for ResponseLazy<'a, T> { }
+impl<'a, T: Owned> Compatible<ResponseLazy<'a, T>> for Response<T> { }
+impl<T: Owned> Compatible<Response<T>> for Response<T> { }
+impl<'a, T: Owned> Compatible<Response<T>> for ResponseLazy<'a, T> { }
+
+impl<'a, T: Owned> ResponseLazy<'a, T> {
+
+    pub fn request_id(&self) -> DecodeResult<u32> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn requester(&self) -> DecodeResult<u64> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4))
+    }
+
+    pub fn requester_worker(&self) -> DecodeResult<u16> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12))
+    }
+
+    pub fn trace_context(&self) -> DecodeResult<Option<TraceContextLazy<'a>>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14))
+    }
+
+    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 40))
+    }
+}
+
+impl<T: BaseLen> BaseLen for Response<T> {
+    const BASE_LEN: usize = 40 + T::BASE_LEN;
+}
+
+impl<T: Encode> Encode for Response<T> {
+    fn scratch_len(&self) -> usize {
+        self.request_id.scratch_len() + self.requester.scratch_len() + self.requester_worker.scratch_len() + self.trace_context.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.request_id.encode(cursor);
+        self.requester.encode(cursor);
+        self.requester_worker.encode(cursor);
+        self.trace_context.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Decode<'a>> Decode<'a> for Response<T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let request_id = Decode::decode(cursor)?;
+        let requester = Decode::decode(cursor)?;
+        let requester_worker = Decode::decode(cursor)?;
+        let trace_context = Decode::decode(cursor)?;
+        let payload = Decode::decode(cursor)?;
+
+        Ok(Response {
+            request_id,
+            requester,
+            requester_worker,
+            trace_context,
+            payload,
+        })
+    }
+}
+
+impl<'a, T: Owned> BaseLen for ResponseLazy<'a, T> {
+    const BASE_LEN: usize = 40 + T::BASE_LEN;
+}
+
+impl<'a, T: Owned> Encode for ResponseLazy<'a, T> {
+    fn scratch_len(&self) -> usize {
+        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let requester: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        let requester_worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
+        let trace_context: Option<TraceContextLazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 40)).unwrap();
+        request_id.scratch_len() + requester.scratch_len() + requester_worker.scratch_len() + trace_context.scratch_len() + payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let request_id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let requester: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 4)).unwrap();
+        let requester_worker: u16 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
+        let trace_context: Option<TraceContextLazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 14)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 40)).unwrap();
+        request_id.encode(cursor);
+        requester.encode(cursor);
+        requester_worker.encode(cursor);
+        trace_context.encode(cursor);
+        payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Owned> Decode<'a> for ResponseLazy<'a, T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(ResponseLazy {
+            buffer: cursor.buffer(),
+            offset,
+            _t: core::marker::PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Owned> TryFrom<ResponseLazy<'a, T>> for Response<T> {
+    type Error = DecodeError;
+
+    fn try_from(other: ResponseLazy<'a, T>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a, T> Copy for ResponseLazy<'a, T> { }
+
+impl<'a, T> Clone for ResponseLazy<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+            _t: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T> core::fmt::Debug for ResponseLazy<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ResponseLazy")
+            .finish()
+    }
+}
+
+impl<'a, T: Owned> PartialEq for ResponseLazy<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.request_id().unwrap() == other.request_id().unwrap()
+            && self.requester().unwrap() == other.requester().unwrap()&& self.requester_worker().unwrap() == other.requester_worker().unwrap()&& self.trace_context().unwrap() == other.trace_context().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub flags: u8,
+}
+
+pub struct TraceContextLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct TraceContextGen<> {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub flags: u8,
+}
+
+impl<> Compatible<TraceContext> for TraceContextGen<> { }
+impl<> Compatible<TraceContextGen<>> for TraceContext { }
+
+impl<> BaseLen for TraceContextGen<> {
+    const BASE_LEN: usize = 25;
+}
+
+impl<> Encode for TraceContextGen<> {
+    fn scratch_len(&self) -> usize {
+        self.trace_id.scratch_len() + self.span_id.scratch_len() + self.flags.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.trace_id.encode(cursor);
+        self.span_id.encode(cursor);
+        self.flags.encode(cursor);
+    }
+}
+
+impl Owned for TraceContext {
+    type Lazy<'a> = TraceContextLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for TraceContextLazy<'a> {
+    type Owned = TraceContext;
+}
+
+impl<'a> Compatible<TraceContextLazy<'a>> for TraceContextLazy<'a> { }
+impl<'a> Compatible<TraceContextLazy<'a>> for TraceContext { }
+impl Compatible<TraceContext> for TraceContext { }
+impl<'a> Compatible<TraceContext> for TraceContextLazy<'a> { }
+
+impl<'a> TraceContextLazy<'a> {
+
+    pub fn trace_id(&self) -> DecodeResult<u128> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn span_id(&self) -> DecodeResult<u64> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 16))
+    }
+
+    pub fn flags(&self) -> DecodeResult<u8> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 24))
+    }
+}
+
+impl BaseLen for TraceContext {
+    const BASE_LEN: usize = 25;
+}
+
+impl Encode for TraceContext {
+    fn scratch_len(&self) -> usize {
+        self.trace_id.scratch_len() + self.span_id.scratch_len() + self.flags.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.trace_id.encode(cursor);
+        self.span_id.encode(cursor);
+        self.flags.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for TraceContext {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let trace_id = Decode::decode(cursor)?;
+        let span_id = Decode::decode(cursor)?;
+        let flags = Decode::decode(cursor)?;
+
+        Ok(TraceContext {
+            trace_id,
+            span_id,
+            flags,
+        })
+    }
+}
+
+impl<'a> BaseLen for TraceContextLazy<'a> {
+    const BASE_LEN: usize = 25;
+}
+
+impl<'a> Encode for TraceContextLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let trace_id: u128 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let span_id: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 16)).unwrap();
+        let flags: u8 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 24)).unwrap();
+        trace_id.scratch_len() + span_id.scratch_len() + flags.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let trace_id: u128 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let span_id: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 16)).unwrap();
+        let flags: u8 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 24)).unwrap();
+        trace_id.encode(cursor);
+        span_id.encode(cursor);
+        flags.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for TraceContextLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(TraceContextLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<TraceContextLazy<'a>> for TraceContext {
+    type Error = DecodeError;
+
+    fn try_from(other: TraceContextLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for TraceContextLazy<'a> { }
+
+impl<'a> Clone for TraceContextLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for TraceContextLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TraceContextLazy")
+            .finish()
+    }
+}
+
+impl<'a> PartialEq for TraceContextLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.trace_id().unwrap() == other.trace_id().unwrap()
+            && self.span_id().unwrap() == other.span_id().unwrap()&& self.flags().unwrap() == other.flags().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct StreamItem<T> {
+    pub seq: u64,
+    pub payload: T,
+}
+
+pub struct StreamItemLazy<'a, T> {
+    buffer: &'a [u8],
+    offset: usize,
+    _t: core::marker::PhantomData<T>,
+}
+
+pub struct StreamItemGen<
+    Payload: Encode,
+> {
+    pub seq: u64,
+    pub payload: Payload,
+}
+
+impl<
+    T: Owned,
+    Payload: Encode + Compatible<T>
+> Compatible<StreamItem<T>> for StreamItemGen<Payload> { }
+impl<
+    T: Owned,
+    Payload: Encode + Compatible<T>
+> Compatible<StreamItemGen<Payload>> for StreamItem<T> { }
+
+impl<
+    Payload: Encode,
+> BaseLen for StreamItemGen<Payload> {
+    const BASE_LEN: usize = 8 + Payload::BASE_LEN;
+}
+
+impl<
+    Payload: Encode,
+> Encode for StreamItemGen<Payload> {
+    fn scratch_len(&self) -> usize {
+        self.seq.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.seq.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<T: Owned> Owned for StreamItem<T> {
+    type Lazy<'a> = StreamItemLazy<'a, T>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a, T: Owned> Lazy<'a> for StreamItemLazy<'a, T> {
+    type Owned = StreamItem<T>;
+}
+
+impl<'a, T: Owned> Compatible<StreamItemLazy<'a, T>> for StreamItemLazy<'a, T> { }
+impl<'a, T: Owned> Compatible<StreamItemLazy<'a, T>> for StreamItem<T> { }
+impl<T: Owned> Compatible<StreamItem<T>> for StreamItem<T> { }
+impl<'a, T: Owned> Compatible<StreamItem<T>> for StreamItemLazy<'a, T> { }
+
+impl<'a, T: Owned> StreamItemLazy<'a, T> {
+
+    pub fn seq(&self) -> DecodeResult<u64> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn payload(&self) -> DecodeResult<T::Lazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
+    }
+}
+
+impl<T: BaseLen> BaseLen for StreamItem<T> {
+    const BASE_LEN: usize = 8 + T::BASE_LEN;
+}
+
+impl<T: Encode> Encode for StreamItem<T> {
+    fn scratch_len(&self) -> usize {
+        self.seq.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.seq.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Decode<'a>> Decode<'a> for StreamItem<T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let seq = Decode::decode(cursor)?;
+        let payload = Decode::decode(cursor)?;
+
+        Ok(StreamItem {
+            seq,
+            payload,
+        })
+    }
+}
+
+impl<'a, T: Owned> BaseLen for StreamItemLazy<'a, T> {
+    const BASE_LEN: usize = 8 + T::BASE_LEN;
+}
+
+impl<'a, T: Owned> Encode for StreamItemLazy<'a, T> {
+    fn scratch_len(&self) -> usize {
+        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        seq.scratch_len() + payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let payload: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        seq.encode(cursor);
+        payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Owned> Decode<'a> for StreamItemLazy<'a, T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(StreamItemLazy {
+            buffer: cursor.buffer(),
+            offset,
+            _t: core::marker::PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Owned> TryFrom<StreamItemLazy<'a, T>> for StreamItem<T> {
+    type Error = DecodeError;
+
+    fn try_from(other: StreamItemLazy<'a, T>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a, T> Copy for StreamItemLazy<'a, T> { }
+
+impl<'a, T> Clone for StreamItemLazy<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+            _t: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T> core::fmt::Debug for StreamItemLazy<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("StreamItemLazy")
+            .finish()
+    }
+}
+
+impl<'a, T: Owned> PartialEq for StreamItemLazy<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq().unwrap() == other.seq().unwrap()
+            && self.payload().unwrap() == other.payload().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct MultiStreamId {
+    pub owner: u64,
+    pub id: u32,
+}
+
+pub struct MultiStreamIdLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct MultiStreamIdGen<> {
+    pub owner: u64,
+    pub id: u32,
+}
+
+impl<> Compatible<MultiStreamId> for MultiStreamIdGen<> { }
+impl<> Compatible<MultiStreamIdGen<>> for MultiStreamId { }
+
+impl<> BaseLen for MultiStreamIdGen<> {
+    const BASE_LEN: usize = 12;
+}
+
+impl<> Encode for MultiStreamIdGen<> {
+    fn scratch_len(&self) -> usize {
+        self.owner.scratch_len() + self.id.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.owner.encode(cursor);
+        self.id.encode(cursor);
+    }
+}
+
+impl Owned for MultiStreamId {
+    type Lazy<'a> = MultiStreamIdLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for MultiStreamIdLazy<'a> {
+    type Owned = MultiStreamId;
+}
+
+impl<'a> Compatible<MultiStreamIdLazy<'a>> for MultiStreamIdLazy<'a> { }
+impl<'a> Compatible<MultiStreamIdLazy<'a>> for MultiStreamId { }
+impl Compatible<MultiStreamId> for MultiStreamId { }
+impl<'a> Compatible<MultiStreamId> for MultiStreamIdLazy<'a> { }
+
+impl<'a> MultiStreamIdLazy<'a> {
+
+    pub fn owner(&self) -> DecodeResult<u64> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn id(&self) -> DecodeResult<u32> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8))
+    }
+}
+
+impl BaseLen for MultiStreamId {
+    const BASE_LEN: usize = 12;
+}
+
+impl Encode for MultiStreamId {
+    fn scratch_len(&self) -> usize {
+        self.owner.scratch_len() + self.id.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.owner.encode(cursor);
+        self.id.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for MultiStreamId {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let owner = Decode::decode(cursor)?;
+        let id = Decode::decode(cursor)?;
+
+        Ok(MultiStreamId {
+            owner,
+            id,
+        })
+    }
+}
+
+impl<'a> BaseLen for MultiStreamIdLazy<'a> {
+    const BASE_LEN: usize = 12;
+}
+
+impl<'a> Encode for MultiStreamIdLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        let owner: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        owner.scratch_len() + id.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let owner: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let id: u32 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 8)).unwrap();
+        owner.encode(cursor);
+        id.encode(cursor);
+    }
+}
+
+impl<'a> Decode<'a> for MultiStreamIdLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(MultiStreamIdLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<MultiStreamIdLazy<'a>> for MultiStreamId {
+    type Error = DecodeError;
+
+    fn try_from(other: MultiStreamIdLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for MultiStreamIdLazy<'a> { }
+
+impl<'a> Clone for MultiStreamIdLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for MultiStreamIdLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MultiStreamIdLazy")
+            .finish()
+    }
+}
+
+impl<'a> PartialEq for MultiStreamIdLazy<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.owner().unwrap() == other.owner().unwrap()
+            && self.id().unwrap() == other.id().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct MultiStreamItem<T> {
+    pub stream_id: MultiStreamId,
+    pub seq: u64,
+    pub payload: Option<T>,
+}
+
+pub struct MultiStreamItemLazy<'a, T> {
+    buffer: &'a [u8],
+    offset: usize,
+    _t: core::marker::PhantomData<T>,
+}
+
+pub struct MultiStreamItemGen<
+    StreamId: Encode + Compatible<MultiStreamId>,
+    Payload: Encode,
+> {
+    pub stream_id: StreamId,
+    pub seq: u64,
+    pub payload: Payload,
+}
+
+impl<
+    T: Owned,
+    StreamId: Encode + Compatible<MultiStreamId>,
+    Payload: Encode + Compatible<Option<T>>
+> Compatible<MultiStreamItem<T>> for MultiStreamItemGen<StreamId, Payload> { }
+impl<
+    T: Owned,
+    StreamId: Encode + Compatible<MultiStreamId>,
+    Payload: Encode + Compatible<Option<T>>
+> Compatible<MultiStreamItemGen<StreamId, Payload>> for MultiStreamItem<T> { }
+
+impl<
+    StreamId: Encode + Compatible<MultiStreamId>,
+    Payload: Encode,
+> BaseLen for MultiStreamItemGen<StreamId, Payload> {
+    const BASE_LEN: usize = 8 + StreamId::BASE_LEN + Payload::BASE_LEN;
+}
+
+impl<
+    StreamId: Encode + Compatible<MultiStreamId>,
+    Payload: Encode,
+> Encode for MultiStreamItemGen<StreamId, Payload> {
+    fn scratch_len(&self) -> usize {
+        self.stream_id.scratch_len() + self.seq.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.stream_id.encode(cursor);
+        self.seq.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<T: Owned> Owned for MultiStreamItem<T> {
+    type Lazy<'a> = MultiStreamItemLazy<'a, T>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a, T: Owned> Lazy<'a> for MultiStreamItemLazy<'a, T> {
+    type Owned = MultiStreamItem<T>;
+}
+
+impl<'a, T: Owned> Compatible<MultiStreamItemLazy<'a, T>> for MultiStreamItemLazy<'a, T> { }
+impl<'a, T: Owned> Compatible<MultiStreamItemLazy<'a, T>> for MultiStreamItem<T> { }
+impl<T: Owned> Compatible<MultiStreamItem<T>> for MultiStreamItem<T> { }
+impl<'a, T: Owned> Compatible<MultiStreamItem<T>> for MultiStreamItemLazy<'a, T> { }
+
+impl<'a, T: Owned> MultiStreamItemLazy<'a, T> {
+
+    pub fn stream_id(&self) -> DecodeResult<MultiStreamIdLazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+
+    pub fn seq(&self) -> DecodeResult<u64> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12))
+    }
+
+    pub fn payload(&self) -> DecodeResult<Option<T::Lazy<'a>>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20))
+    }
+}
+
+impl<T: BaseLen> BaseLen for MultiStreamItem<T> {
+    const BASE_LEN: usize = 21 + T::BASE_LEN;
+}
+
+impl<T: Encode> Encode for MultiStreamItem<T> {
+    fn scratch_len(&self) -> usize {
+        self.stream_id.scratch_len() + self.seq.scratch_len() + self.payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.stream_id.encode(cursor);
+        self.seq.encode(cursor);
+        self.payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Decode<'a>> Decode<'a> for MultiStreamItem<T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let stream_id = Decode::decode(cursor)?;
+        let seq = Decode::decode(cursor)?;
+        let payload = Decode::decode(cursor)?;
+
+        Ok(MultiStreamItem {
+            stream_id,
+            seq,
+            payload,
+        })
+    }
+}
+
+impl<'a, T: Owned> BaseLen for MultiStreamItemLazy<'a, T> {
+    const BASE_LEN: usize = 21 + T::BASE_LEN;
+}
+
+impl<'a, T: Owned> Encode for MultiStreamItemLazy<'a, T> {
+    fn scratch_len(&self) -> usize {
+        let stream_id: MultiStreamIdLazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
+        let payload: Option<T::Lazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20)).unwrap();
+        stream_id.scratch_len() + seq.scratch_len() + payload.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let stream_id: MultiStreamIdLazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
+        let payload: Option<T::Lazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20)).unwrap();
+        stream_id.encode(cursor);
+        seq.encode(cursor);
+        payload.encode(cursor);
+    }
+}
+
+impl<'a, T: Owned> Decode<'a> for MultiStreamItemLazy<'a, T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(MultiStreamItemLazy {
+            buffer: cursor.buffer(),
+            offset,
+            _t: core::marker::PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Owned> TryFrom<MultiStreamItemLazy<'a, T>> for MultiStreamItem<T> {
+    type Error = DecodeError;
+
+    fn try_from(other: MultiStreamItemLazy<'a, T>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a, T> Copy for MultiStreamItemLazy<'a, T> { }
+
+impl<'a, T> Clone for MultiStreamItemLazy<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+            _t: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T> core::fmt::Debug for MultiStreamItemLazy<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MultiStreamItemLazy")
+            .finish()
+    }
+}
+
+impl<'a, T: Owned> PartialEq for MultiStreamItemLazy<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.stream_id().unwrap() == other.stream_id().unwrap()
+            && self.seq().unwrap() == other.seq().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct PropertyInitState<T> {
+    pub value: T,
+}
+
+pub struct PropertyInitStateLazy<'a, T> {
+    buffer: &'a [u8],
+    offset: usize,
+    _t: core::marker::PhantomData<T>,
+}
+
+pub struct PropertyInitStateGen<
+    Value: Encode,
+> {
+    pub value: Value,
+}
+
+impl<
+    T: Owned,
+    Value: Encode + Compatible<T>
+> Compatible<PropertyInitState<T>> for PropertyInitStateGen<Value> { }
+impl<
+    T: Owned,
+    Value: Encode + Compatible<T>
+> Compatible<PropertyInitStateGen<Value>> for PropertyInitState<T> { }
+
+impl<
+    Value: Encode,
+> BaseLen for PropertyInitStateGen<Value> {
+    const BASE_LEN: usize = Value::BASE_LEN;
+}
+
+impl<
+    Value: Encode,
+> Encode for PropertyInitStateGen<Value> {
+    fn scratch_len(&self) -> usize {
+        self.value.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.value.encode(cursor);
+    }
+}
+
+impl<T: Owned> Owned for PropertyInitState<T> {
+    type Lazy<'a> = PropertyInitStateLazy<'a, T>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a, T: Owned> Lazy<'a> for PropertyInitStateLazy<'a, T> {
+    type Owned = PropertyInitState<T>;
+}
+
+impl<'a, T: Owned> Compatible<PropertyInitStateLazy<'a, T>> for PropertyInitStateLazy<'a, T> { }
+impl<'a, T: Owned> Compatible<PropertyInitStateLazy<'a, T>> for PropertyInitState<T> { }
+impl<T: Owned> Compatible<PropertyInitState<T>> for PropertyInitState<T> { }
+impl<'a, T: Owned> Compatible<PropertyInitState<T>> for PropertyInitStateLazy<'a, T> { }
+
+impl<'a, T: Owned> PropertyInitStateLazy<'a, T> {
+
+    pub fn value(&self) -> DecodeResult<T::Lazy<'a>> {
+        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
+    }
+}
+
+impl<T: BaseLen> BaseLen for PropertyInitState<T> {
+    const BASE_LEN: usize = T::BASE_LEN;
+}
+
+impl<T: Encode> Encode for PropertyInitState<T> {
+    fn scratch_len(&self) -> usize {
+        self.value.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        self.value.encode(cursor);
+    }
+}
+
+impl<'a, T: Decode<'a>> Decode<'a> for PropertyInitState<T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let value = Decode::decode(cursor)?;
+
+        Ok(PropertyInitState {
+            value,
+        })
+    }
+}
+
+impl<'a, T: Owned> BaseLen for PropertyInitStateLazy<'a, T> {
+    const BASE_LEN: usize = T::BASE_LEN;
+}
+
+impl<'a, T: Owned> Encode for PropertyInitStateLazy<'a, T> {
+    fn scratch_len(&self) -> usize {
+        let value: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        value.scratch_len()
+    }
+
+    fn encode(&self, cursor: &mut EncodeCursor) {
+        let value: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        value.encode(cursor);
+    }
+}
+
+impl<'a, T: Owned> Decode<'a> for PropertyInitStateLazy<'a, T> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(PropertyInitStateLazy {
+            buffer: cursor.buffer(),
+            offset,
+            _t: core::marker::PhantomData,
+        })
+    }
+}
+
+impl<'a, T: Owned> TryFrom<PropertyInitStateLazy<'a, T>> for PropertyInitState<T> {
+    type Error = DecodeError;
+
+    fn try_from(other: PropertyInitStateLazy<'a, T>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a, T> Copy for PropertyInitStateLazy<'a, T> { }
+
+impl<'a, T> Clone for PropertyInitStateLazy<'a, T> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+            _t: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<'a, T> core::fmt::Debug for PropertyInitStateLazy<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PropertyInitStateLazy")
+            .finish()
+    }
+}
+
+impl<'a, T: Owned> PartialEq for PropertyInitStateLazy<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value().unwrap() == other.value().unwrap()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
+pub struct PropertyObserverConfig {}
+
+pub struct PropertyObserverConfigLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct PropertyObserverConfigGen<> {}
+
+impl<> Compatible<PropertyObserverConfig> for PropertyObserverConfigGen<> { }
+impl<> Compatible<PropertyObserverConfigGen<>> for PropertyObserverConfig { }
+
+impl<> BaseLen for PropertyObserverConfigGen<> {
+    const BASE_LEN: usize = 0;
+}
+
+impl<> Encode for PropertyObserverConfigGen<> {
+    fn scratch_len(&self) -> usize {
+        0
+    }
+
+    fn encode(&self, _: &mut EncodeCursor) {}
+}
+
+impl Owned for PropertyObserverConfig {
+    type Lazy<'a> = PropertyObserverConfigLazy<'a>;
+
+    fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
+        TryFrom::try_from(lazy)
+    }
+}
+
+impl<'a> Lazy<'a> for PropertyObserverConfigLazy<'a> {
+    type Owned = PropertyObserverConfig;
+}
+
+impl<'a> Compatible<PropertyObserverConfigLazy<'a>> for PropertyObserverConfigLazy<'a> { }
+impl<'a> Compatible<PropertyObserverConfigLazy<'a>> for PropertyObserverConfig { }
+impl Compatible<PropertyObserverConfig> for PropertyObserverConfig { }
+impl<'a> Compatible<PropertyObserverConfig> for PropertyObserverConfigLazy<'a> { }
+
+impl<'a> PropertyObserverConfigLazy<'a> {}
+
+impl BaseLen for PropertyObserverConfig {
+    const BASE_LEN: usize = 0;
+}
+
+impl Encode for PropertyObserverConfig {
+    fn scratch_len(&self) -> usize {
+        0
+    }
+
+    fn encode(&self, _: &mut EncodeCursor) {}
+}
+
+impl<'a> Decode<'a> for PropertyObserverConfig {
+    fn decode(_: &DecodeCursor<'a>) -> DecodeResult<Self> {
+
+        Ok(PropertyObserverConfig {})
+    }
+}
+
+impl<'a> BaseLen for PropertyObserverConfigLazy<'a> {
+    const BASE_LEN: usize = 0;
+}
+
+impl<'a> Encode for PropertyObserverConfigLazy<'a> {
+    fn scratch_len(&self) -> usize {
+        0
+    }
+
+    fn encode(&self, _: &mut EncodeCursor) {}
+}
+
+impl<'a> Decode<'a> for PropertyObserverConfigLazy<'a> {
+    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
+        let offset = cursor.offset();
+        cursor.advance(Self::BASE_LEN);
+        Ok(PropertyObserverConfigLazy {
+            buffer: cursor.buffer(),
+            offset,
+        })
+    }
+}
+
+impl<'a> TryFrom<PropertyObserverConfigLazy<'a>> for PropertyObserverConfig {
+    type Error = DecodeError;
+
+    fn try_from(other: PropertyObserverConfigLazy<'a>) -> Result<Self, Self::Error> {
+        let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
+        Decode::decode(&cursor)
+    }
+}
+
+impl<'a> Copy for PropertyObserverConfigLazy<'a> { }
+
+impl<'a> Clone for PropertyObserverConfigLazy<'a> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer,
+            offset: self.offset,
+        }
+    }
+}
+
+impl<'a> core::fmt::Debug for PropertyObserverConfigLazy<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PropertyObserverConfigLazy")
+            .finish()
+    }
+}
+
+impl<'a> PartialEq for PropertyObserverConfigLazy<'a> {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
+pub struct PropertyOwnerConfig {}
+
+pub struct PropertyOwnerConfigLazy<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+pub struct PropertyOwnerConfigGen<> {}
+
+impl<> Compatible<PropertyOwnerConfig> for PropertyOwnerConfigGen<> { }
+impl<> Compatible<PropertyOwnerConfigGen<>> for PropertyOwnerConfig { }
 
-impl<
-    StreamId: Encode + Compatible<MultiStreamId>,
-    Payload: Encode,
-> BaseLen for MultiStreamItemGen<StreamId, Payload> {
-    const BASE_LEN: usize = 8 + StreamId::BASE_LEN + Payload::BASE_LEN;
+impl<> BaseLen for PropertyOwnerConfigGen<> {
+    const BASE_LEN: usize = 0;
 }
 
-impl<
-    StreamId: Encode + Compatible<MultiStreamId>,
-    Payload: Encode,
-> Encode for MultiStreamItemGen<StreamId, Payload> {
+impl<> Encode for PropertyOwnerConfigGen<> {
     fn scratch_len(&self) -> usize {
-        self.stream_id.scratch_len() + self.seq.scratch_len() + self.payload.scratch_len()
+        0
     }
 
-    fn encode(&self, cursor: &mut EncodeCursor) {
-        self.stream_id.encode(cursor);
-        self.seq.encode(cursor);
-        self.payload.encode(cursor);
-    }
+    fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<T: Owned> Owned for MultiStreamItem<T> {
-    type Lazy<'a> = MultiStreamItemLazy<'a, T>;
+impl Owned for PropertyOwnerConfig {
+    type Lazy<'a> = PropertyOwnerConfigLazy<'a>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a, T: Owned> Lazy<'a> for MultiStreamItemLazy<'a, T> {
-    type Owned = MultiStreamItem<T>;
+impl<'a> Lazy<'a> for PropertyOwnerConfigLazy<'a> {
+    type Owned = PropertyOwnerConfig;
 }
 
-impl<'a, T: Owned> Compatible<MultiStreamItemLazy<'a, T>> for MultiStreamItemLazy<'a, T> { }
-impl<'a, T: Owned> Compatible<MultiStreamItemLazy<'a, T>> for MultiStreamItem<T> { }
-impl<T: Owned> Compatible<MultiStreamItem<T>> for MultiStreamItem<T> { }
-impl<'a, T: Owned> Compatible<MultiStreamItem<T>> for MultiStreamItemLazy<'a, T> { }
-
-impl<'a, T: Owned> MultiStreamItemLazy<'a, T> {
-
-    pub fn stream_id(&self) -> DecodeResult<MultiStreamIdLazy<'a>> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
-    }
-
-    pub fn seq(&self) -> DecodeResult<u64> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12))
-    }
+impl<'a> Compatible<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfigLazy<'a> { }
+impl<'a> Compatible<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfig { }
+impl Compatible<PropertyOwnerConfig> for PropertyOwnerConfig { }
+impl<'a> Compatible<PropertyOwnerConfig> for PropertyOwnerConfigLazy<'a> { }
 
-    pub fn payload(&self) -> DecodeResult<Option<T::Lazy<'a>>> {
-        Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20))
-    }
-}
+impl<'a> PropertyOwnerConfigLazy<'a> {}
 
-impl<T: BaseLen> BaseLen for MultiStreamItem<T> {
-    const BASE_LEN: usize = 21 + T::BASE_LEN;
+impl BaseLen for PropertyOwnerConfig {
+    const BASE_LEN: usize = 0;
 }
 
-impl<T: Encode> Encode for MultiStreamItem<T> {
+impl Encode for PropertyOwnerConfig {
     fn scratch_len(&self) -> usize {
-        self.stream_id.scratch_len() + self.seq.scratch_len() + self.payload.scratch_len()
+        0
     }
 
-    fn encode(&self, cursor: &mut EncodeCursor) {
-        self.stream_id.encode(cursor);
-        self.seq.encode(cursor);
-        self.payload.encode(cursor);
-    }
+    fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a, T: Decode<'a>> Decode<'a> for MultiStreamItem<T> {
-    fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let stream_id = Decode::decode(cursor)?;
-        let seq = Decode::decode(cursor)?;
-        let payload = Decode::decode(cursor)?;
+impl<'a> Decode<'a> for PropertyOwnerConfig {
+    fn decode(_: &DecodeCursor<'a>) -> DecodeResult<Self> {
 
-        Ok(MultiStreamItem {
-            stream_id,
-            seq,
-            payload,
-        })
+        Ok(PropertyOwnerConfig {})
     }
 }
 
-impl<'a, T: Owned> BaseLen for MultiStreamItemLazy<'a, T> {
-    const BASE_LEN: usize = 21 + T::BASE_LEN;
+impl<'a> BaseLen for PropertyOwnerConfigLazy<'a> {
+    const BASE_LEN: usize = 0;
 }
 
-impl<'a, T: Owned> Encode for MultiStreamItemLazy<'a, T> {
+impl<'a> Encode for PropertyOwnerConfigLazy<'a> {
     fn scratch_len(&self) -> usize {
-        let stream_id: MultiStreamIdLazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
-        let payload: Option<T::Lazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20)).unwrap();
-        stream_id.scratch_len() + seq.scratch_len() + payload.scratch_len()
+        0
     }
 
-    fn encode(&self, cursor: &mut EncodeCursor) {
-        let stream_id: MultiStreamIdLazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        let seq: u64 = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 12)).unwrap();
-        let payload: Option<T::Lazy<'a>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 20)).unwrap();
-        stream_id.encode(cursor);
-        seq.encode(cursor);
-        payload.encode(cursor);
-    }
+    fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a, T: Owned> Decode<'a> for MultiStreamItemLazy<'a, T> {
+impl<'a> Decode<'a> for PropertyOwnerConfigLazy<'a> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(MultiStreamItemLazy {
+        Ok(PropertyOwnerConfigLazy {
             buffer: cursor.buffer(),
             offset,
-            _t: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a, T: Owned> TryFrom<MultiStreamItemLazy<'a, T>> for MultiStreamItem<T> {
+impl<'a> TryFrom<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfig {
     type Error = DecodeError;
 
-    fn try_from(other: MultiStreamItemLazy<'a, T>) -> Result<Self, Self::Error> {
+    fn try_from(other: PropertyOwnerConfigLazy<'a>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a, T> Copy for MultiStreamItemLazy<'a, T> { }
+impl<'a> Copy for PropertyOwnerConfigLazy<'a> { }
 
-impl<'a, T> Clone for MultiStreamItemLazy<'a, T> {
+impl<'a> Clone for PropertyOwnerConfigLazy<'a> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
-            _t: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a, T> core::fmt::Debug for MultiStreamItemLazy<'a, T> {
+impl<'a> core::fmt::Debug for PropertyOwnerConfigLazy<'a> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("MultiStreamItemLazy")
+        f.debug_struct("PropertyOwnerConfigLazy")
             .finish()
     }
 }
 
-impl<'a, T: Owned> PartialEq for MultiStreamItemLazy<'a, T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.stream_id().unwrap() == other.stream_id().unwrap()
-            && self.seq().unwrap() == other.seq().unwrap()&& self.payload().unwrap() == other.payload().unwrap()
+impl<'a> PartialEq for PropertyOwnerConfigLazy<'a> {
+    fn eq(&self, _: &Self) -> bool {
+        true
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
-pub struct PropertyInitState<T> {
-    pub value: T,
+#[cfg(any(feature = "std", feature = "alloc"))]
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct MembershipInitState<M> {
+    pub members: Vec<Member<M>>,
 }
 
-pub struct PropertyInitStateLazy<'a, T> {
+pub struct MembershipInitStateLazy<'a, M> {
     buffer: &'a [u8],
     offset: usize,
-    _t: core::marker::PhantomData<T>,
+    _m: core::marker::PhantomData<M>,
 }
 
-pub struct PropertyInitStateGen<
-    Value: Encode,
+pub struct MembershipInitStateGen<
+    Members: Encode,
 > {
-    pub value: Value,
+    pub members: Members,
 }
 
 impl<
-    T: Owned,
-    Value: Encode + Compatible<T>
-> Compatible<PropertyInitState<T>> for PropertyInitStateGen<Value> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipInitState<M>> for MembershipInitStateGen<Members> { }
 impl<
-    T: Owned,
-    Value: Encode + Compatible<T>
-> Compatible<PropertyInitStateGen<Value>> for PropertyInitState<T> { }
+    M: Owned,
+    Members: Encode + Compatible<Vec<Member<M>>>
+> Compatible<MembershipInitStateGen<Members>> for MembershipInitState<M> { }
 
 impl<
-    Value: Encode,
-> BaseLen for PropertyInitStateGen<Value> {
-    const BASE_LEN: usize = Value::BASE_LEN;
+    Members: Encode,
+> BaseLen for MembershipInitStateGen<Members> {
+    const BASE_LEN: usize = Members::BASE_LEN;
 }
 
 impl<
-    Value: Encode,
-> Encode for PropertyInitStateGen<Value> {
+    Members: Encode,
+> Encode for MembershipInitStateGen<Members> {
     fn scratch_len(&self) -> usize {
-        self.value.scratch_len()
+        self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.value.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<T: Owned> Owned for PropertyInitState<T> {
-    type Lazy<'a> = PropertyInitStateLazy<'a, T>;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Owned for MembershipInitState<M> {
+    type Lazy<'a> = MembershipInitStateLazy<'a, M>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a, T: Owned> Lazy<'a> for PropertyInitStateLazy<'a, T> {
-    type Owned = PropertyInitState<T>;
+impl<'a, M: Owned> Lazy<'a> for MembershipInitStateLazy<'a, M> {
+    type Owned = MembershipInitState<M>;
 }
 
-impl<'a, T: Owned> Compatible<PropertyInitStateLazy<'a, T>> for PropertyInitStateLazy<'a, T> { }
-impl<'a, T: Owned> Compatible<PropertyInitStateLazy<'a, T>> for PropertyInitState<T> { }
-impl<T: Owned> Compatible<PropertyInitState<T>> for PropertyInitState<T> { }
-impl<'a, T: Owned> Compatible<PropertyInitState<T>> for PropertyInitStateLazy<'a, T> { }
+impl<'a, M: Owned> Compatible<MembershipInitStateLazy<'a, M>> for MembershipInitStateLazy<'a, M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipInitStateLazy<'a, M>> for MembershipInitState<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: Owned> Compatible<MembershipInitState<M>> for MembershipInitState<M> { }
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> Compatible<MembershipInitState<M>> for MembershipInitStateLazy<'a, M> { }
 
-impl<'a, T: Owned> PropertyInitStateLazy<'a, T> {
+impl<'a, M: Owned> MembershipInitStateLazy<'a, M> {
 
-    pub fn value(&self) -> DecodeResult<T::Lazy<'a>> {
+    pub fn members(&self) -> DecodeResult<mproto::ListLazy<'a, Member<M>>> {
         Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0))
     }
 }
 
-impl<T: BaseLen> BaseLen for PropertyInitState<T> {
-    const BASE_LEN: usize = T::BASE_LEN;
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<M: BaseLen> BaseLen for MembershipInitState<M> {
+    const BASE_LEN: usize = 8;
 }
 
-impl<T: Encode> Encode for PropertyInitState<T> {
+impl<M: Encode> Encode for MembershipInitState<M> {
     fn scratch_len(&self) -> usize {
-        self.value.scratch_len()
+        self.members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        self.value.encode(cursor);
+        self.members.encode(cursor);
     }
 }
 
-impl<'a, T: Decode<'a>> Decode<'a> for PropertyInitState<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Decode<'a>> Decode<'a> for MembershipInitState<M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
-        let value = Decode::decode(cursor)?;
+        let members = Decode::decode(cursor)?;
 
-        Ok(PropertyInitState {
-            value,
+        Ok(MembershipInitState {
+            members,
         })
     }
 }
 
-impl<'a, T: Owned> BaseLen for PropertyInitStateLazy<'a, T> {
-    const BASE_LEN: usize = T::BASE_LEN;
+impl<'a, M: Owned> BaseLen for MembershipInitStateLazy<'a, M> {
+    const BASE_LEN: usize = 8;
 }
 
-impl<'a, T: Owned> Encode for PropertyInitStateLazy<'a, T> {
+impl<'a, M: Owned> Encode for MembershipInitStateLazy<'a, M> {
     fn scratch_len(&self) -> usize {
-        let value: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        value.scratch_len()
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        members.scratch_len()
     }
 
     fn encode(&self, cursor: &mut EncodeCursor) {
-        let value: T::Lazy<'a> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
-        value.encode(cursor);
+        let members: mproto::ListLazy<'a, Member<M>> = Decode::decode(&DecodeCursor::at_offset(self.buffer, self.offset + 0)).unwrap();
+        members.encode(cursor);
     }
 }
 
-impl<'a, T: Owned> Decode<'a> for PropertyInitStateLazy<'a, T> {
+impl<'a, M: Owned> Decode<'a> for MembershipInitStateLazy<'a, M> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(PropertyInitStateLazy {
+        Ok(MembershipInitStateLazy {
             buffer: cursor.buffer(),
             offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         })
     }
 }
 
-impl<'a, T: Owned> TryFrom<PropertyInitStateLazy<'a, T>> for PropertyInitState<T> {
+#[cfg(any(feature = "std", feature = "alloc"))]
+impl<'a, M: Owned> TryFrom<MembershipInitStateLazy<'a, M>> for MembershipInitState<M> {
     type Error = DecodeError;
 
-    fn try_from(other: PropertyInitStateLazy<'a, T>) -> Result<Self, Self::Error> {
+    fn try_from(other: MembershipInitStateLazy<'a, M>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a, T> Copy for PropertyInitStateLazy<'a, T> { }
+impl<'a, M> Copy for MembershipInitStateLazy<'a, M> { }
 
-impl<'a, T> Clone for PropertyInitStateLazy<'a, T> {
+impl<'a, M> Clone for MembershipInitStateLazy<'a, M> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
             offset: self.offset,
-            _t: core::marker::PhantomData,
+            _m: core::marker::PhantomData,
         }
     }
 }
 
-impl<'a, T> core::fmt::Debug for PropertyInitStateLazy<'a, T> {
+impl<'a, M> core::fmt::Debug for MembershipInitStateLazy<'a, M> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("PropertyInitStateLazy")
+        f.debug_struct("MembershipInitStateLazy")
             .finish()
     }
 }
 
-impl<'a, T: Owned> PartialEq for PropertyInitStateLazy<'a, T> {
+impl<'a, M: Owned> PartialEq for MembershipInitStateLazy<'a, M> {
     fn eq(&self, other: &Self) -> bool {
-        self.value().unwrap() == other.value().unwrap()
+        self.members().unwrap() == other.members().unwrap()
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
-pub struct PropertyObserverConfig {}
+pub struct MembershipObserverConfig {}
 
-pub struct PropertyObserverConfigLazy<'a> {
+pub struct MembershipObserverConfigLazy<'a> {
     buffer: &'a [u8],
     offset: usize,
 }
 
-pub struct PropertyObserverConfigGen<> {}
+pub struct MembershipObserverConfigGen<> {}
 
-impl<> Compatible<PropertyObserverConfig> for PropertyObserverConfigGen<> { }
-impl<> Compatible<PropertyObserverConfigGen<>> for PropertyObserverConfig { }
+impl<> Compatible<MembershipObserverConfig> for MembershipObserverConfigGen<> { }
+impl<> Compatible<MembershipObserverConfigGen<>> for MembershipObserverConfig { }
 
-impl<> BaseLen for PropertyObserverConfigGen<> {
+impl<> BaseLen for MembershipObserverConfigGen<> {
     const BASE_LEN: usize = 0;
 }
 
-impl<> Encode for PropertyObserverConfigGen<> {
+impl<> Encode for MembershipObserverConfigGen<> {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1216,30 +2324,30 @@ impl<> Encode for PropertyObserverConfigGen<> {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl Owned for PropertyObserverConfig {
-    type Lazy<'a> = PropertyObserverConfigLazy<'a>;
+impl Owned for MembershipObserverConfig {
+    type Lazy<'a> = MembershipObserverConfigLazy<'a>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a> Lazy<'a> for PropertyObserverConfigLazy<'a> {
-    type Owned = PropertyObserverConfig;
+impl<'a> Lazy<'a> for MembershipObserverConfigLazy<'a> {
+    type Owned = MembershipObserverConfig;
 }
 
-impl<'a> Compatible<PropertyObserverConfigLazy<'a>> for PropertyObserverConfigLazy<'a> { }
-impl<'a> Compatible<PropertyObserverConfigLazy<'a>> for PropertyObserverConfig { }
-impl Compatible<PropertyObserverConfig> for PropertyObserverConfig { }
-impl<'a> Compatible<PropertyObserverConfig> for PropertyObserverConfigLazy<'a> { }
+impl<'a> Compatible<MembershipObserverConfigLazy<'a>> for MembershipObserverConfigLazy<'a> { }
+impl<'a> Compatible<MembershipObserverConfigLazy<'a>> for MembershipObserverConfig { }
+impl Compatible<MembershipObserverConfig> for MembershipObserverConfig { }
+impl<'a> Compatible<MembershipObserverConfig> for MembershipObserverConfigLazy<'a> { }
 
-impl<'a> PropertyObserverConfigLazy<'a> {}
+impl<'a> MembershipObserverConfigLazy<'a> {}
 
-impl BaseLen for PropertyObserverConfig {
+impl BaseLen for MembershipObserverConfig {
     const BASE_LEN: usize = 0;
 }
 
-impl Encode for PropertyObserverConfig {
+impl Encode for MembershipObserverConfig {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1247,18 +2355,18 @@ impl Encode for PropertyObserverConfig {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a> Decode<'a> for PropertyObserverConfig {
+impl<'a> Decode<'a> for MembershipObserverConfig {
     fn decode(_: &DecodeCursor<'a>) -> DecodeResult<Self> {
 
-        Ok(PropertyObserverConfig {})
+        Ok(MembershipObserverConfig {})
     }
 }
 
-impl<'a> BaseLen for PropertyObserverConfigLazy<'a> {
+impl<'a> BaseLen for MembershipObserverConfigLazy<'a> {
     const BASE_LEN: usize = 0;
 }
 
-impl<'a> Encode for PropertyObserverConfigLazy<'a> {
+impl<'a> Encode for MembershipObserverConfigLazy<'a> {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1266,29 +2374,29 @@ impl<'a> Encode for PropertyObserverConfigLazy<'a> {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a> Decode<'a> for PropertyObserverConfigLazy<'a> {
+impl<'a> Decode<'a> for MembershipObserverConfigLazy<'a> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(PropertyObserverConfigLazy {
+        Ok(MembershipObserverConfigLazy {
             buffer: cursor.buffer(),
             offset,
         })
     }
 }
 
-impl<'a> TryFrom<PropertyObserverConfigLazy<'a>> for PropertyObserverConfig {
+impl<'a> TryFrom<MembershipObserverConfigLazy<'a>> for MembershipObserverConfig {
     type Error = DecodeError;
 
-    fn try_from(other: PropertyObserverConfigLazy<'a>) -> Result<Self, Self::Error> {
+    fn try_from(other: MembershipObserverConfigLazy<'a>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a> Copy for PropertyObserverConfigLazy<'a> { }
+impl<'a> Copy for MembershipObserverConfigLazy<'a> { }
 
-impl<'a> Clone for PropertyObserverConfigLazy<'a> {
+impl<'a> Clone for MembershipObserverConfigLazy<'a> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
@@ -1297,37 +2405,37 @@ impl<'a> Clone for PropertyObserverConfigLazy<'a> {
     }
 }
 
-impl<'a> core::fmt::Debug for PropertyObserverConfigLazy<'a> {
+impl<'a> core::fmt::Debug for MembershipObserverConfigLazy<'a> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("PropertyObserverConfigLazy")
+        f.debug_struct("MembershipObserverConfigLazy")
             .finish()
     }
 }
 
-impl<'a> PartialEq for PropertyObserverConfigLazy<'a> {
+impl<'a> PartialEq for MembershipObserverConfigLazy<'a> {
     fn eq(&self, _: &Self) -> bool {
         true
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Eq, Ord, Hash, Default)]
-pub struct PropertyOwnerConfig {}
+pub struct MembershipHostConfig {}
 
-pub struct PropertyOwnerConfigLazy<'a> {
+pub struct MembershipHostConfigLazy<'a> {
     buffer: &'a [u8],
     offset: usize,
 }
 
-pub struct PropertyOwnerConfigGen<> {}
+pub struct MembershipHostConfigGen<> {}
 
-impl<> Compatible<PropertyOwnerConfig> for PropertyOwnerConfigGen<> { }
-impl<> Compatible<PropertyOwnerConfigGen<>> for PropertyOwnerConfig { }
+impl<> Compatible<MembershipHostConfig> for MembershipHostConfigGen<> { }
+impl<> Compatible<MembershipHostConfigGen<>> for MembershipHostConfig { }
 
-impl<> BaseLen for PropertyOwnerConfigGen<> {
+impl<> BaseLen for MembershipHostConfigGen<> {
     const BASE_LEN: usize = 0;
 }
 
-impl<> Encode for PropertyOwnerConfigGen<> {
+impl<> Encode for MembershipHostConfigGen<> {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1335,30 +2443,30 @@ impl<> Encode for PropertyOwnerConfigGen<> {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl Owned for PropertyOwnerConfig {
-    type Lazy<'a> = PropertyOwnerConfigLazy<'a>;
+impl Owned for MembershipHostConfig {
+    type Lazy<'a> = MembershipHostConfigLazy<'a>;
 
     fn lazy_to_owned(lazy: Self::Lazy<'_>) -> DecodeResult<Self> {
         TryFrom::try_from(lazy)
     }
 }
 
-impl<'a> Lazy<'a> for PropertyOwnerConfigLazy<'a> {
-    type Owned = PropertyOwnerConfig;
+impl<'a> Lazy<'a> for MembershipHostConfigLazy<'a> {
+    type Owned = MembershipHostConfig;
 }
 
-impl<'a> Compatible<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfigLazy<'a> { }
-impl<'a> Compatible<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfig { }
-impl Compatible<PropertyOwnerConfig> for PropertyOwnerConfig { }
-impl<'a> Compatible<PropertyOwnerConfig> for PropertyOwnerConfigLazy<'a> { }
+impl<'a> Compatible<MembershipHostConfigLazy<'a>> for MembershipHostConfigLazy<'a> { }
+impl<'a> Compatible<MembershipHostConfigLazy<'a>> for MembershipHostConfig { }
+impl Compatible<MembershipHostConfig> for MembershipHostConfig { }
+impl<'a> Compatible<MembershipHostConfig> for MembershipHostConfigLazy<'a> { }
 
-impl<'a> PropertyOwnerConfigLazy<'a> {}
+impl<'a> MembershipHostConfigLazy<'a> {}
 
-impl BaseLen for PropertyOwnerConfig {
+impl BaseLen for MembershipHostConfig {
     const BASE_LEN: usize = 0;
 }
 
-impl Encode for PropertyOwnerConfig {
+impl Encode for MembershipHostConfig {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1366,18 +2474,18 @@ impl Encode for PropertyOwnerConfig {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a> Decode<'a> for PropertyOwnerConfig {
+impl<'a> Decode<'a> for MembershipHostConfig {
     fn decode(_: &DecodeCursor<'a>) -> DecodeResult<Self> {
 
-        Ok(PropertyOwnerConfig {})
+        Ok(MembershipHostConfig {})
     }
 }
 
-impl<'a> BaseLen for PropertyOwnerConfigLazy<'a> {
+impl<'a> BaseLen for MembershipHostConfigLazy<'a> {
     const BASE_LEN: usize = 0;
 }
 
-impl<'a> Encode for PropertyOwnerConfigLazy<'a> {
+impl<'a> Encode for MembershipHostConfigLazy<'a> {
     fn scratch_len(&self) -> usize {
         0
     }
@@ -1385,29 +2493,29 @@ impl<'a> Encode for PropertyOwnerConfigLazy<'a> {
     fn encode(&self, _: &mut EncodeCursor) {}
 }
 
-impl<'a> Decode<'a> for PropertyOwnerConfigLazy<'a> {
+impl<'a> Decode<'a> for MembershipHostConfigLazy<'a> {
     fn decode(cursor: &DecodeCursor<'a>) -> DecodeResult<Self> {
         let offset = cursor.offset();
         cursor.advance(Self::BASE_LEN);
-        Ok(PropertyOwnerConfigLazy {
+        Ok(MembershipHostConfigLazy {
             buffer: cursor.buffer(),
             offset,
         })
     }
 }
 
-impl<'a> TryFrom<PropertyOwnerConfigLazy<'a>> for PropertyOwnerConfig {
+impl<'a> TryFrom<MembershipHostConfigLazy<'a>> for MembershipHostConfig {
     type Error = DecodeError;
 
-    fn try_from(other: PropertyOwnerConfigLazy<'a>) -> Result<Self, Self::Error> {
+    fn try_from(other: MembershipHostConfigLazy<'a>) -> Result<Self, Self::Error> {
         let cursor = DecodeCursor::at_offset(other.buffer, other.offset);
         Decode::decode(&cursor)
     }
 }
 
-impl<'a> Copy for PropertyOwnerConfigLazy<'a> { }
+impl<'a> Copy for MembershipHostConfigLazy<'a> { }
 
-impl<'a> Clone for PropertyOwnerConfigLazy<'a> {
+impl<'a> Clone for MembershipHostConfigLazy<'a> {
     fn clone(&self) -> Self {
         Self {
             buffer: self.buffer,
@@ -1416,14 +2524,14 @@ impl<'a> Clone for PropertyOwnerConfigLazy<'a> {
     }
 }
 
-impl<'a> core::fmt::Debug for PropertyOwnerConfigLazy<'a> {
+impl<'a> core::fmt::Debug for MembershipHostConfigLazy<'a> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("PropertyOwnerConfigLazy")
+        f.debug_struct("MembershipHostConfigLazy")
             .finish()
     }
 }
 
-impl<'a> PartialEq for PropertyOwnerConfigLazy<'a> {
+impl<'a> PartialEq for MembershipHostConfigLazy<'a> {
     fn eq(&self, _: &Self) -> bool {
         true
     }
@@ -18,9 +18,20 @@ pub struct RequestTracker {
 }
 
 impl RequestTracker {
-    pub fn new() -> Self {
+    pub fn new(metrics: &modrpc::MetricsRegistry) -> Self {
         Self {
             inner: Rc::new(RequestTrackerInner {
+                request_duration: metrics.histogram(
+                    "modrpc_request_duration_seconds",
+                    "Time from sending a request to receiving its response.",
+                    &[],
+                    &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+                ),
+                unknown_responses: metrics.counter(
+                    "modrpc_request_unknown_responses",
+                    "Responses received for requests that were no longer pending.",
+                    &[],
+                ),
                 pending_requests: RefCell::new(slab::Slab::new()),
                 request_subscriptions: RefCell::new(HashMap::new()),
                 // TODO configurable pool size
@@ -79,6 +90,8 @@ impl RequestTracker {
         let request_id = self.inner.pending_requests.borrow_mut().insert(PendingRequestState {
             response: Cell::new(None),
             waker: Cell::new(None),
+            #[cfg(not(target_arch = "wasm32"))]
+            started_at: std::time::Instant::now(),
         }) as u32;
 
         PendingRequest { state: self.inner.clone(), request_id }
@@ -87,9 +100,11 @@ impl RequestTracker {
     fn client_finish_request(&self, request_id: u32, response_packet: Packet) {
         let local_pending_requests = self.inner.pending_requests.borrow();
         let Some(pending_request) = local_pending_requests.get(request_id as usize) else {
-            // TODO metric - RequestTracker client received response with unknown request_id
+            self.inner.unknown_responses.inc();
             return;
         };
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.request_duration.observe(pending_request.started_at.elapsed().as_secs_f64());
         pending_request.response.set(Some(response_packet));
         if let Some(waker) = pending_request.waker.take() {
             waker.wake();
@@ -146,6 +161,8 @@ impl RequestTracker {
 
 struct RequestTrackerInner {
     pending_requests: RefCell<slab::Slab<PendingRequestState>>,
+    request_duration: modrpc::Histogram,
+    unknown_responses: modrpc::Counter,
 
     // Map request (plane ID, topic ID) to their response callbacks
     request_subscriptions: RefCell<HashMap<(u32, u32), Vec<RequestSubscriptionCallback>>>,
@@ -158,6 +175,8 @@ struct RequestTrackerInner {
 struct PendingRequestState {
     response: Cell<Option<Packet>>,
     waker: Cell<Option<Waker>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    started_at: std::time::Instant,
 }
 
 pub struct PendingRequest {
@@ -235,5 +254,6 @@ impl Drop for PendingRequestSubscription {
 }
 
 pub fn get_request_tracker(setup: &modrpc::RoleSetup) -> RequestTracker {
-    setup.worker_context().with_local_fn((), || RequestTracker::new(), |x| x.clone())
+    let worker_cx = setup.worker_context();
+    worker_cx.with_local_fn((), || RequestTracker::new(worker_cx.rt().metrics()), |x| x.clone())
 }
@@ -73,7 +73,13 @@ impl ByteStreamSender {
 
     /// SAFETY: You must have exclusive ownership of the buffer and there must be enough headroom
     /// for modrpc::TransmitPacket::BASE_LEN + 8 bytes
-    pub async unsafe fn send_buffer(&self, buffer: modrpc::BufferPtr) -> u64 {
+    ///
+    /// On an encrypted plane the buffer also needs `modrpc::PLANE_ENCRYPTION_OVERHEAD` bytes of
+    /// tailroom. If it doesn't have them the buffer is dropped, and the stream can't be continued.
+    pub async unsafe fn send_buffer(
+        &self,
+        buffer: modrpc::BufferPtr,
+    ) -> Result<u64, modrpc::SendBufferError> {
         let headroom = <modrpc::TransmitPacket as mproto::BaseLen>::BASE_LEN;
         let payload_len =
             modrpc::WriterFlushSender::get_complete_buffer_len(buffer) as usize - headroom - 8;
@@ -86,9 +92,9 @@ impl ByteStreamSender {
         let start_index_buf = unsafe { buffer.slice_mut(headroom..headroom + 8) };
         start_index_buf.copy_from_slice(&start_index.to_le_bytes());
 
-        unsafe { self.state.hooks.blob.send_buffer(buffer).await; }
+        unsafe { self.state.hooks.blob.send_buffer(buffer).await?; }
 
-        start_index
+        Ok(start_index)
     }
 
     pub async fn wait_consumed(&self, _cursor: u64) {
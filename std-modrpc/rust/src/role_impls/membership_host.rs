@@ -0,0 +1,103 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::proto::{
+    Member, MembershipHostConfig, MembershipInitState, MembershipSnapshotGen, MembershipUpdateGen,
+};
+use modrpc::RoleSetup;
+
+struct State<M> {
+    hooks: crate::MembershipHostHooks<M>,
+    members: RefCell<Vec<Member<M>>>,
+}
+
+#[derive(Clone)]
+pub struct MembershipHost<M> {
+    state: Rc<State<M>>,
+}
+
+impl<
+    M: mproto::Owned + Clone,
+> MembershipHost<M> {
+    /// Add a member, replacing its metadata if it's already a member.
+    pub async fn join(&self, endpoint: u64, metadata: M) {
+        let members = {
+            let mut members = self.state.members.borrow_mut();
+            members.retain(|member| member.endpoint != endpoint);
+            members.push(Member { endpoint, metadata });
+            members.clone()
+        };
+        self.state.hooks.joined.send(MembershipUpdateGen { endpoint, members: &members }).await;
+    }
+
+    pub async fn leave(&self, endpoint: u64) {
+        let members = {
+            let mut members = self.state.members.borrow_mut();
+            let member_count = members.len();
+            members.retain(|member| member.endpoint != endpoint);
+            if members.len() == member_count {
+                return;
+            }
+            members.clone()
+        };
+        self.state.hooks.left.send(MembershipUpdateGen { endpoint, members: &members }).await;
+    }
+
+    pub fn members(&self) -> Vec<Member<M>> {
+        self.state.members.borrow().clone()
+    }
+
+    /// The init state for an observer joining now.
+    pub fn init_state(&self) -> MembershipInitState<M> {
+        MembershipInitState { members: self.members() }
+    }
+}
+
+pub struct MembershipHostBuilder<M> {
+    stubs: crate::MembershipHostStubs<M>,
+    state: Rc<State<M>>,
+}
+
+impl<
+    M: mproto::Owned + Clone + 'static,
+> MembershipHostBuilder<M> {
+    pub fn new(
+        _name: &'static str,
+        hooks: crate::MembershipHostHooks<M>,
+        stubs: crate::MembershipHostStubs<M>,
+        _config: &MembershipHostConfig,
+        init: MembershipInitState<M>,
+    ) -> Self {
+        let state = Rc::new(State {
+            hooks,
+            members: RefCell::new(init.members),
+        });
+        Self { stubs, state }
+    }
+
+    pub fn create_handle(
+        &self,
+        _setup: &RoleSetup,
+    ) -> crate::MembershipHost<M> {
+        crate::MembershipHost { state: self.state.clone() }
+    }
+
+    pub fn build(
+        self,
+        setup: &RoleSetup,
+    ) {
+        // Observers miss updates sent before their role starts, so each asks for a snapshot once
+        // it's listening.
+        let state = self.state;
+        let spawner = setup.role_spawner().clone();
+        self.stubs.sync
+            .inline(setup, move |_source, ()| {
+                let state = state.clone();
+                spawner.spawn(async move {
+                    let members = state.members.borrow().clone();
+                    state.hooks.snapshot.send(MembershipSnapshotGen { members: &members }).await;
+                });
+            })
+            .subscribe();
+    }
+}
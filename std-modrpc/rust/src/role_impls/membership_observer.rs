@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::proto::{Member, MembershipInitState, MembershipObserverConfig};
+use modrpc::RoleSetup;
+
+type OnJoined<M> = Box<dyn Fn(&Member<M>)>;
+type OnLeft = Box<dyn Fn(u64)>;
+
+struct State<M> {
+    members: RefCell<Vec<Member<M>>>,
+    on_joined: RefCell<Vec<OnJoined<M>>>,
+    on_left: RefCell<Vec<OnLeft>>,
+}
+
+impl<M> State<M> {
+    fn is_member(&self, endpoint: u64) -> bool {
+        self.members.borrow().iter().any(|member| member.endpoint == endpoint)
+    }
+}
+
+#[derive(Clone)]
+pub struct MembershipObserver<M> {
+    state: Rc<State<M>>,
+}
+
+impl<M: Clone> MembershipObserver<M> {
+    pub fn members(&self) -> Vec<Member<M>> {
+        self.state.members.borrow().clone()
+    }
+
+    pub fn with_members<R>(&self, f: impl FnOnce(&[Member<M>]) -> R) -> R {
+        f(&self.state.members.borrow())
+    }
+
+    pub fn is_member(&self, endpoint: u64) -> bool {
+        self.state.is_member(endpoint)
+    }
+
+    /// Call `f` with each member that joins from now on. Members already known from a snapshot
+    /// aren't reported.
+    pub fn on_joined(&self, f: impl Fn(&Member<M>) + 'static) {
+        self.state.on_joined.borrow_mut().push(Box::new(f));
+    }
+
+    /// Call `f` with the endpoint of each member that leaves from now on.
+    pub fn on_left(&self, f: impl Fn(u64) + 'static) {
+        self.state.on_left.borrow_mut().push(Box::new(f));
+    }
+}
+
+pub struct MembershipObserverBuilder<M> {
+    hooks: crate::MembershipObserverHooks<M>,
+    stubs: crate::MembershipObserverStubs<M>,
+    state: Rc<State<M>>,
+}
+
+impl<
+    M: mproto::Owned + Clone + 'static,
+> MembershipObserverBuilder<M> {
+    pub fn new(
+        _name: &'static str,
+        hooks: crate::MembershipObserverHooks<M>,
+        stubs: crate::MembershipObserverStubs<M>,
+        _config: &MembershipObserverConfig,
+        init: MembershipInitState<M>,
+    ) -> Self {
+        let state = Rc::new(State {
+            members: RefCell::new(init.members),
+            on_joined: RefCell::new(Vec::new()),
+            on_left: RefCell::new(Vec::new()),
+        });
+        Self { hooks, stubs, state }
+    }
+
+    pub fn create_handle(
+        &self,
+        _setup: &RoleSetup,
+    ) -> crate::MembershipObserver<M> {
+        crate::MembershipObserver { state: self.state.clone() }
+    }
+
+    pub fn build(
+        self,
+        setup: &RoleSetup,
+    ) {
+        // Every update carries the whole member list, and the host answers `sync` with a snapshot,
+        // so updates missed while joining don't leave the list stale.
+        let state = self.state.clone();
+        self.stubs.joined
+            .inline(setup, move |_source, update| {
+                let was_member = state.is_member(update.endpoint);
+                *state.members.borrow_mut() = update.members;
+                if was_member {
+                    return;
+                }
+                let members = state.members.borrow();
+                if let Some(member) = members.iter().find(|member| member.endpoint == update.endpoint) {
+                    for on_joined in state.on_joined.borrow().iter() {
+                        on_joined(member);
+                    }
+                }
+            })
+            .subscribe();
+
+        let state = self.state.clone();
+        self.stubs.left
+            .inline(setup, move |_source, update| {
+                let was_member = state.is_member(update.endpoint);
+                *state.members.borrow_mut() = update.members;
+                if !was_member {
+                    return;
+                }
+                for on_left in state.on_left.borrow().iter() {
+                    on_left(update.endpoint);
+                }
+            })
+            .subscribe();
+
+        let state = self.state;
+        self.stubs.snapshot
+            .inline(setup, move |_source, snapshot| {
+                *state.members.borrow_mut() = snapshot.members;
+            })
+            .subscribe();
+
+        let sync = self.hooks.sync;
+        setup.role_spawner().spawn(async move {
+            sync.send(()).await;
+        });
+    }
+}
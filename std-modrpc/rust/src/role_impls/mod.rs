@@ -18,6 +18,10 @@ mod request_server;
 pub use request_server::*;
 mod request_client;
 pub use request_client::*;
+mod membership_host;
+pub use membership_host::*;
+mod membership_observer;
+pub use membership_observer::*;
 mod property_owner;
 pub use property_owner::*;
 mod property_observer;
@@ -321,13 +321,13 @@ mod test {
                     config: MultiByteStreamSenderConfig { },
                     init: MultiByteStreamInitState { },
                 })
-                .local(|cx| {
+                .local_async(|cx| {
                     let builder = MultiByteStreamSenderBuilder::new(
                         "mb_sender", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone(),
                     );
                     mb_sender = Some(builder.create_handle(cx.setup));
                     builder.build(cx.setup);
-                });
+                }).await;
 
             let mut mb_receiver = None;
             let _ =
@@ -339,13 +339,13 @@ mod test {
                     config: MultiByteStreamReceiverConfig { },
                     init: MultiByteStreamInitState { },
                 })
-                .local(|cx| {
+                .local_async(|cx| {
                     let builder = MultiByteStreamReceiverBuilder::new(
                         "mb_receiver", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone(),
                     );
                     mb_receiver = Some(builder.create_handle(cx.setup));
                     builder.build(cx.setup);
-                });
+                }).await;
 
             let mb_sender = mb_sender.unwrap();
             let mb_receiver = mb_receiver.unwrap();
@@ -109,7 +109,13 @@ impl SendMultiByteStream {
 
     /// SAFETY: You must have exclusive ownership of the buffer and there must be enough headroom
     /// for `modrpc::TransmitPacket::BASE_LEN + MULTI_BYTE_STREAM_HEADER_LEN` bytes.
-    pub async unsafe fn send_buffer(&self, buffer: modrpc::BufferPtr) -> u64 {
+    ///
+    /// On an encrypted plane the buffer also needs `modrpc::PLANE_ENCRYPTION_OVERHEAD` bytes of
+    /// tailroom. If it doesn't have them the buffer is dropped, and the stream can't be continued.
+    pub async unsafe fn send_buffer(
+        &self,
+        buffer: modrpc::BufferPtr,
+    ) -> Result<u64, modrpc::SendBufferError> {
         let packet_header = <modrpc::TransmitPacket as mproto::BaseLen>::BASE_LEN;
         let payload_len = modrpc::WriterFlushSender::get_complete_buffer_len(buffer) as usize
             - packet_header
@@ -126,9 +132,9 @@ impl SendMultiByteStream {
         header_buf[8..12].copy_from_slice(&self.stream_id.id.to_le_bytes());
         header_buf[12..20].copy_from_slice(&start_index.to_le_bytes());
 
-        unsafe { self.blob_tx.send_buffer(buffer).await; }
+        unsafe { self.blob_tx.send_buffer(buffer).await?; }
 
-        start_index
+        Ok(start_index)
     }
 
     /// Signal the end of the stream to the receiver.
@@ -1,4 +1,5 @@
 use modrpc::RoleSetup;
+use tracing::Instrument;
 
 use crate::{
     proto::{
@@ -8,8 +9,10 @@ use crate::{
         RequestInitState,
         Response,
         ResponseLazy,
+        TraceContext,
     },
     request_tracker::{RequestTracker, get_request_tracker},
+    trace_context::{request_span, start_request_span},
 };
 
 pub use sealed::ResponseWaiter;
@@ -84,20 +87,28 @@ impl<
     pub async fn call<LikeReq>(&self, payload: LikeReq) -> Resp
         where LikeReq: mproto::Compatible<Req>
     {
-        let pending_request = self.tracker.client_start_request();
-        self.hooks.request.send(RequestGen::<LikeReq> {
-            worker: self.worker_id,
-            request_id: pending_request.request_id(),
-            payload,
-        })
-        .await;
-
-        let response_buf = pending_request.await;
-        let header_len = <modrpc::TransmitPacket as mproto::BaseLen>::BASE_LEN;
-        let response: Response<Resp>
-            = mproto::decode_value(&response_buf.as_ref()[header_len..]).unwrap();
-
-        response.payload
+        let span = request_span("client", self.name);
+        let trace_context = start_request_span(&span, TraceContext::current());
+
+        async move {
+            let pending_request = self.tracker.client_start_request();
+            self.hooks.request.send(RequestGen::<_, LikeReq> {
+                worker: self.worker_id,
+                request_id: pending_request.request_id(),
+                trace_context,
+                payload,
+            })
+            .await;
+
+            let response_buf = pending_request.await;
+            let header_len = <modrpc::TransmitPacket as mproto::BaseLen>::BASE_LEN;
+            let response: Response<Resp>
+                = mproto::decode_value(&response_buf.as_ref()[header_len..]).unwrap();
+
+            response.payload
+        }
+        .instrument(span)
+        .await
     }
 
     pub fn subscribe(
@@ -4,10 +4,15 @@ use crate::proto::{
     RequestServerConfig,
     Response,
     ResponseGen,
+    TraceContext,
 };
 use modrpc::RoleSetup;
+use tracing::Instrument;
 
-use crate::request_tracker::{RequestTracker, get_request_tracker};
+use crate::{
+    request_tracker::{RequestTracker, get_request_tracker},
+    trace_context::{TraceContextScope, decode_trace_context, request_span, start_request_span},
+};
 
 pub struct RequestServer<Req, Resp> {
     name: &'static str,
@@ -64,6 +69,7 @@ impl<
         setup: &RoleSetup,
         mut handler: impl AsyncFnMut(RequestContext<Resp>, Req::Lazy<'_>) + 'static,
     ) {
+        let name = self.name;
         let mut response_tx: modrpc::EventTx<Response<Resp>> = self.hooks.response;
         self.stubs.request
             .queued(setup, async move |source: modrpc::EndpointAddr, request: RequestLazy<Req>| {
@@ -71,7 +77,11 @@ impl<
                 let Ok(requester_worker) = request.worker() else { return; };
                 let Ok(payload) = request.payload() else { return; };
 
-                handler(
+                let span = request_span("server", name);
+                let trace_context =
+                    start_request_span(&span, decode_trace_context(request.trace_context()));
+
+                let handler_future = handler(
                     RequestContext {
                         source,
                         reply: ResponseSender {
@@ -79,11 +89,14 @@ impl<
                             request_id,
                             source: source,
                             requester_worker,
+                            trace_context,
                         },
                     },
                     payload,
-                )
-                .await;
+                );
+                TraceContextScope::new(trace_context, handler_future)
+                    .instrument(span)
+                    .await;
             })
             .load_balance();
     }
@@ -93,6 +106,7 @@ impl<
         setup: &RoleSetup,
         mut handler: impl AsyncFnMut(modrpc::EndpointAddr, Req::Lazy<'_>) -> Resp + 'static,
     ) {
+        let name = self.name;
         let response_tx: modrpc::EventTx<Response<Resp>> = self.hooks.response;
         self.stubs.request.queued(
             setup,
@@ -101,11 +115,18 @@ impl<
                 let Ok(requester_worker) = request.worker() else { return; };
                 let Ok(request_payload) = request.payload() else { return; };
 
-                let response = handler(source, request_payload).await;
+                let span = request_span("server", name);
+                let trace_context =
+                    start_request_span(&span, decode_trace_context(request.trace_context()));
+
+                let response = TraceContextScope::new(trace_context, handler(source, request_payload))
+                    .instrument(span)
+                    .await;
                 response_tx.send(Response {
                     request_id,
                     requester: source.endpoint,
                     requester_worker,
+                    trace_context,
                     payload: response,
                 })
                 .await;
@@ -141,6 +162,8 @@ pub struct ResponseSender<'a, T> {
     pub request_id: u32,
     pub source: modrpc::EndpointAddr,
     pub requester_worker: u16,
+    /// The server span's context, sent back to the client.
+    pub trace_context: Option<TraceContext>,
 }
 
 impl<T: mproto::Owned> ResponseSender<'_, T> {
@@ -150,6 +173,7 @@ impl<T: mproto::Owned> ResponseSender<'_, T> {
             request_id: self.request_id,
             requester: self.source.endpoint,
             requester_worker: self.requester_worker,
+            trace_context: self.trace_context,
             payload: response,
         }).await;
     }
@@ -164,6 +188,7 @@ impl<O: mproto::Owned, E: mproto::Owned> ResponseSender<'_, Result<O, E>> {
             request_id: self.request_id,
             requester: self.source.endpoint,
             requester_worker: self.requester_worker,
+            trace_context: self.trace_context,
             payload: Ok::<_, E>(response),
         }).await;
     }
@@ -174,6 +199,7 @@ impl<O: mproto::Owned, E: mproto::Owned> ResponseSender<'_, Result<O, E>> {
             request_id: self.request_id,
             requester: self.source.endpoint,
             requester_worker: self.requester_worker,
+            trace_context: self.trace_context,
             payload: Err::<O, _>(response),
         }).await;
     }
@@ -198,11 +198,11 @@ mod test {
                     config: StreamSenderConfig { },
                     init: StreamInitState { },
                 })
-                .local(|cx| {
+                .local_async(|cx| {
                     let builder = StreamSenderBuilder::new("stream_sender", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone());
                     stream_sender = Some(builder.create_handle(cx.setup));
                     builder.build(cx.setup);
-                });
+                }).await;
 
             let mut stream_receiver = None;
             let _ =
@@ -214,11 +214,11 @@ mod test {
                     config: StreamReceiverConfig { },
                     init: StreamInitState { },
                 })
-                .local(|cx| {
+                .local_async(|cx| {
                     let builder = StreamReceiverBuilder::new("stream_receiver", cx.hooks.clone(), cx.stubs, cx.config, cx.init.clone());
                     stream_receiver = Some(builder.create_handle(cx.setup));
                     builder.build(cx.setup);
-                });
+                }).await;
 
             let stream_sender = stream_sender.unwrap();
             let stream_receiver = stream_receiver.unwrap();
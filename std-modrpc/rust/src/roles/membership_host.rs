@@ -0,0 +1,61 @@
+#![allow(unused_variables)]
+
+use crate::interface::MembershipInterface;
+use crate::proto::{MembershipHostConfig, MembershipInitState, MembershipSnapshot, MembershipUpdate};
+use modrpc::{EventRxBuilder, EventTx, InterfaceRole, RoleSetup};
+
+pub struct MembershipHostHooks<M> {
+    pub joined: EventTx<MembershipUpdate<M>>,
+    pub left: EventTx<MembershipUpdate<M>>,
+    pub snapshot: EventTx<MembershipSnapshot<M>>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+pub struct MembershipHostStubs<M> {
+    pub sync: EventRxBuilder<()>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+pub struct MembershipHostRole<M> {
+    _phantom: std::marker::PhantomData<M>,
+}
+
+impl<M: mproto::Owned> InterfaceRole for MembershipHostRole<M> {
+    type Interface = MembershipInterface<M>;
+    type Config = MembershipHostConfig;
+    type Init = MembershipInitState<M>;
+    type Stubs = MembershipHostStubs<M>;
+    type Hooks = MembershipHostHooks<M>;
+
+    fn setup_worker(
+        i: &Self::Interface,
+        setup: &mut RoleSetup,
+        config: &Self::Config,
+        init: &Self::Init,
+    ) -> (Self::Stubs, Self::Hooks) {
+
+        (
+            Self::Stubs {
+                sync: setup.event_rx(i.sync),
+                _phantom: std::marker::PhantomData,
+            },
+            Self::Hooks {
+                joined: setup.event_tx(i.joined),
+                left: setup.event_tx(i.left),
+                snapshot: setup.event_tx(i.snapshot),
+                _phantom: std::marker::PhantomData,
+            },
+        )
+    }
+}
+
+impl<M> Clone for MembershipHostHooks<M> {
+    fn clone(&self) -> Self {
+        Self {
+            joined: self.joined.clone(),
+            left: self.left.clone(),
+            snapshot: self.snapshot.clone(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
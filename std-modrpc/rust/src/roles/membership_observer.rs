@@ -0,0 +1,59 @@
+#![allow(unused_variables)]
+
+use crate::interface::MembershipInterface;
+use crate::proto::{MembershipInitState, MembershipObserverConfig, MembershipSnapshot, MembershipUpdate};
+use modrpc::{EventRxBuilder, EventTx, InterfaceRole, RoleSetup};
+
+pub struct MembershipObserverHooks<M> {
+    pub sync: EventTx<()>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+pub struct MembershipObserverStubs<M> {
+    pub joined: EventRxBuilder<MembershipUpdate<M>>,
+    pub left: EventRxBuilder<MembershipUpdate<M>>,
+    pub snapshot: EventRxBuilder<MembershipSnapshot<M>>,
+    _phantom: std::marker::PhantomData<M>,
+}
+
+pub struct MembershipObserverRole<M> {
+    _phantom: std::marker::PhantomData<M>,
+}
+
+impl<M: mproto::Owned> InterfaceRole for MembershipObserverRole<M> {
+    type Interface = MembershipInterface<M>;
+    type Config = MembershipObserverConfig;
+    type Init = MembershipInitState<M>;
+    type Stubs = MembershipObserverStubs<M>;
+    type Hooks = MembershipObserverHooks<M>;
+
+    fn setup_worker(
+        i: &Self::Interface,
+        setup: &mut RoleSetup,
+        config: &Self::Config,
+        init: &Self::Init,
+    ) -> (Self::Stubs, Self::Hooks) {
+
+        (
+            Self::Stubs {
+                joined: setup.event_rx(i.joined),
+                left: setup.event_rx(i.left),
+                snapshot: setup.event_rx(i.snapshot),
+                _phantom: std::marker::PhantomData,
+            },
+            Self::Hooks {
+                sync: setup.event_tx(i.sync),
+                _phantom: std::marker::PhantomData,
+            },
+        )
+    }
+}
+
+impl<M> Clone for MembershipObserverHooks<M> {
+    fn clone(&self) -> Self {
+        Self {
+            sync: self.sync.clone(),
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
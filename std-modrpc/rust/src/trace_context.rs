@@ -0,0 +1,221 @@
+use core::{
+    cell::Cell,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::hash::{BuildHasher, RandomState};
+
+use crate::proto::{TraceContext, TraceContextLazy};
+
+const FLAG_SAMPLED: u8 = 0x01;
+
+std::thread_local! {
+    static CURRENT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+    static RNG: Cell<u64> = Cell::new(RandomState::new().hash_one(0u64));
+}
+
+impl TraceContext {
+    /// Start a new trace with random ids.
+    pub fn new_root(sampled: bool) -> Self {
+        let trace_id = loop {
+            let trace_id = ((next_random() as u128) << 64) | next_random() as u128;
+            if trace_id != 0 {
+                break trace_id;
+            }
+        };
+        Self {
+            trace_id,
+            span_id: new_span_id(),
+            flags: if sampled { FLAG_SAMPLED } else { 0 },
+        }
+    }
+
+    /// A new span in the same trace, with this span as its parent.
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: new_span_id(),
+            flags: self.flags,
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & FLAG_SAMPLED != 0
+    }
+
+    /// The context of the request being handled by the running task, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(Cell::get)
+    }
+
+    /// Run `future` with `self` as the current context. Requests sent by the future continue this
+    /// context's trace.
+    pub fn scope<F: Future>(self, future: F) -> TraceContextScope<F> {
+        TraceContextScope::new(Some(self), future)
+    }
+
+    /// Format as a W3C `traceparent` header value.
+    pub fn to_traceparent(&self) -> String {
+        let mut traceparent = String::with_capacity(55);
+        let _ = write!(
+            traceparent,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags,
+        );
+        traceparent
+    }
+
+    /// Parse a W3C `traceparent` header value.
+    pub fn from_traceparent(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.split('-');
+        let version = parts.next().and_then(|version| parse_hex::<u8>(version, 2))?;
+        let trace_id = parts.next().and_then(|trace_id| parse_hex::<u128>(trace_id, 32))?;
+        let span_id = parts.next().and_then(|span_id| parse_hex::<u64>(span_id, 16))?;
+        let flags = parts.next().and_then(|flags| parse_hex::<u8>(flags, 2))?;
+        // Versions after 00 may append fields.
+        if version == 0xff || (version == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == 0 || span_id == 0 {
+            return None;
+        }
+
+        Some(Self { trace_id, span_id, flags })
+    }
+}
+
+/// The span of one side of a request. Its ids are recorded by `start_request_span`.
+pub(crate) fn request_span(kind: &'static str, name: &'static str) -> tracing::Span {
+    tracing::info_span!(
+        "modrpc.request",
+        otel.kind = kind,
+        rpc.method = name,
+        trace_id = tracing::field::Empty,
+        span_id = tracing::field::Empty,
+        parent_span_id = tracing::field::Empty,
+    )
+}
+
+/// Pick the context of `span`, a child of `parent` if there is one. Without a parent a new trace is
+/// only started if `span` is being recorded.
+pub(crate) fn start_request_span(
+    span: &tracing::Span,
+    parent: Option<TraceContext>,
+) -> Option<TraceContext> {
+    let trace_context = match parent {
+        Some(parent) => parent.child(),
+        None if !span.is_disabled() => TraceContext::new_root(true),
+        None => return None,
+    };
+
+    span.record("trace_id", tracing::field::display(format_args!("{:032x}", trace_context.trace_id)));
+    span.record("span_id", tracing::field::display(format_args!("{:016x}", trace_context.span_id)));
+    if let Some(parent) = parent {
+        span.record("parent_span_id", tracing::field::display(format_args!("{:016x}", parent.span_id)));
+    }
+
+    Some(trace_context)
+}
+
+pub(crate) fn decode_trace_context(
+    trace_context: mproto::DecodeResult<Option<TraceContextLazy<'_>>>,
+) -> Option<TraceContext> {
+    TraceContext::try_from(trace_context.ok()??).ok()
+}
+
+fn parse_hex<T: TryFrom<u128>>(s: &str, len: usize) -> Option<T> {
+    if s.len() != len || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    u128::from_str_radix(s, 16).ok()?.try_into().ok()
+}
+
+fn new_span_id() -> u64 {
+    loop {
+        let span_id = next_random();
+        if span_id != 0 {
+            return span_id;
+        }
+    }
+}
+
+fn next_random() -> u64 {
+    RNG.with(|rng| {
+        // SplitMix64
+        let state = rng.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        rng.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+/// Future returned by `TraceContext::scope`.
+pub struct TraceContextScope<F> {
+    trace_context: Option<TraceContext>,
+    future: F,
+}
+
+impl<F> TraceContextScope<F> {
+    pub(crate) fn new(trace_context: Option<TraceContext>, future: F) -> Self {
+        Self { trace_context, future }
+    }
+}
+
+impl<F: Future> Future for TraceContextScope<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let trace_context = self.trace_context;
+        let future = unsafe { self.map_unchecked_mut(|s| &mut s.future) };
+
+        let prev_trace_context = CURRENT.with(|current| current.replace(trace_context));
+        let result = future.poll(cx);
+        CURRENT.with(|current| current.set(prev_trace_context));
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let trace_context = TraceContext::from_traceparent(traceparent).unwrap();
+        assert_eq!(trace_context.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(trace_context.span_id, 0x00f067aa0ba902b7);
+        assert!(trace_context.is_sampled());
+        assert_eq!(trace_context.to_traceparent(), traceparent);
+
+        let child = trace_context.child();
+        assert_eq!(child.trace_id, trace_context.trace_id);
+        assert_ne!(child.span_id, trace_context.span_id);
+
+        let root = TraceContext::new_root(false);
+        assert!(!root.is_sampled());
+        assert_eq!(TraceContext::from_traceparent(&root.to_traceparent()), Some(root));
+
+        assert!(TraceContext::from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::from_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none());
+        assert!(TraceContext::from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00").is_none());
+        assert!(TraceContext::from_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00").is_some());
+    }
+
+    #[test]
+    fn test_scope() {
+        let trace_context = TraceContext::new_root(true);
+        assert_eq!(TraceContext::current(), None);
+        futures_lite::future::block_on(trace_context.scope(async {
+            assert_eq!(TraceContext::current(), Some(trace_context));
+            futures_lite::future::yield_now().await;
+            assert_eq!(TraceContext::current(), Some(trace_context));
+        }));
+        assert_eq!(TraceContext::current(), None);
+    }
+}
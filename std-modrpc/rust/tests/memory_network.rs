@@ -0,0 +1,319 @@
+use core::time::Duration;
+use std::{
+    cell::{Cell, RefCell},
+    io::Write,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use modrpc_executor::{ModrpcExecutor, SimExecutor};
+use mproto::BaseLen;
+use std_modrpc::{
+    RequestClient,
+    RequestClientBuilder,
+    RequestClientConfig,
+    RequestClientRole,
+    RequestInitState,
+    RequestServerBuilder,
+    RequestServerConfig,
+    RequestServerRole,
+    StreamInitState,
+    StreamItem,
+    StreamReceiver,
+    StreamReceiverBuilder,
+    StreamReceiverConfig,
+    StreamReceiverRole,
+    StreamSender,
+    StreamSenderBuilder,
+    StreamSenderConfig,
+    StreamSenderRole,
+    TraceContext,
+};
+
+fn memory_transport(link: modrpc::MemoryLink) -> modrpc::MemoryTransport {
+    modrpc::MemoryTransport {
+        in_buffer_pool: modrpc::HeapBufferPool::new(256, 16, 16),
+        out_buffer_pool: modrpc::HeapBufferPool::new(256, 16, 16),
+        worker_id: modrpc::WorkerId::local(),
+        link,
+    }
+}
+
+fn role_config<Role: modrpc::InterfaceRole>(
+    transport: modrpc::TransportHandle,
+    endpoint: u64,
+    config: Role::Config,
+    init: Role::Init,
+) -> modrpc::RoleConfig<Role> {
+    modrpc::RoleConfig {
+        plane_id: 0,
+        endpoint_addr: modrpc::EndpointAddr { endpoint },
+        transport,
+        topic_channels: modrpc::TopicChannels::SingleChannel { channel_id: 0 },
+        config,
+        init,
+    }
+}
+
+async fn start_server(
+    rt: &modrpc::RuntimeHandle,
+    link: modrpc::MemoryLink,
+    handler: impl AsyncFnMut(modrpc::EndpointAddr, u32) -> u32 + 'static,
+) {
+    let transport = rt.add_transport(memory_transport(link)).await;
+    let _ =
+        rt.start_role::<RequestServerRole<u32, u32>>(
+            role_config(transport, 1, RequestServerConfig { }, RequestInitState { }),
+        )
+        .local_async(|cx| {
+            RequestServerBuilder::new("request_server", cx.hooks.clone(), cx.stubs, cx.config, *cx.init)
+                .build(cx.setup, handler);
+        }).await;
+}
+
+async fn start_client(
+    rt: &modrpc::RuntimeHandle,
+    link: modrpc::MemoryLink,
+) -> RequestClient<u32, u32> {
+    let transport = rt.add_transport(memory_transport(link)).await;
+    let mut request_client = None;
+    let _ =
+        rt.start_role::<RequestClientRole<u32, u32>>(
+            role_config(transport, 2, RequestClientConfig { }, RequestInitState { }),
+        )
+        .local_async(|cx| {
+            let builder = RequestClientBuilder::new("request_client", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+            request_client = Some(builder.create_handle(cx.setup));
+            builder.build(cx.setup);
+        }).await;
+    request_client.unwrap()
+}
+
+#[test]
+fn test_request_over_memory_network() {
+    let mut ex = SimExecutor::with_seed(7);
+    let (client_rt, _client_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (server_rt, _server_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let network = modrpc::MemoryNetwork::new(modrpc::MemoryNetworkConfig {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(5),
+        ..modrpc::MemoryNetworkConfig::default()
+    });
+    let (client_link, server_link) = network.connect("client", "server");
+
+    ex.run_until(async move {
+        start_server(&server_rt, server_link, async |_source, request: u32| request * 2).await;
+        let request_client = Rc::new(start_client(&client_rt, client_link).await);
+
+        // The request and its response each take 10-15ms of virtual time.
+        let start = SimExecutor::now();
+        assert_eq!(request_client.call(21u32).await, 42);
+        let round_trip = SimExecutor::now() - start;
+        assert!(round_trip >= Duration::from_millis(20));
+        assert!(round_trip <= Duration::from_millis(30));
+
+        // Requests sent during a partition are lost.
+        network.partition("client", "server");
+        let lost_response = Rc::new(Cell::new(None));
+        client_rt.local_worker_context().unwrap().spawn({
+            let request_client = request_client.clone();
+            let lost_response = lost_response.clone();
+            async move {
+                lost_response.set(Some(request_client.call(1u32).await));
+            }
+        });
+        SimExecutor::sleep(Duration::from_secs(10)).await;
+        assert_eq!(lost_response.get(), None);
+
+        network.heal("client", "server");
+        assert_eq!(request_client.call(2u32).await, 4);
+        assert_eq!(lost_response.get(), None);
+
+        let metrics = client_rt.metrics().encode_openmetrics();
+        assert!(metrics.contains("modrpc_request_duration_seconds_count 2\n"));
+    });
+}
+
+#[test]
+fn test_trace_context_propagation() {
+    let mut ex = SimExecutor::with_seed(7);
+    let (client_rt, _client_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (server_rt, _server_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let network = modrpc::MemoryNetwork::new(modrpc::MemoryNetworkConfig::default());
+    let (client_link, server_link) = network.connect("client", "server");
+
+    ex.run_until(async move {
+        let server_trace_context = Rc::new(Cell::new(None));
+        start_server(&server_rt, server_link, {
+            let server_trace_context = server_trace_context.clone();
+            async move |_source, request: u32| {
+                server_trace_context.set(TraceContext::current());
+                request * 2
+            }
+        })
+        .await;
+        let request_client = start_client(&client_rt, client_link).await;
+
+        // Nothing is recording spans, so no trace is started.
+        assert_eq!(request_client.call(1u32).await, 2);
+        assert_eq!(server_trace_context.get(), None);
+
+        let parent = TraceContext::new_root(true);
+        assert_eq!(parent.scope(request_client.call(2u32)).await, 4);
+        let server_trace_context = server_trace_context.get().unwrap();
+        assert_eq!(server_trace_context.trace_id, parent.trace_id);
+        assert_ne!(server_trace_context.span_id, parent.span_id);
+        assert!(server_trace_context.is_sampled());
+        assert_eq!(TraceContext::current(), None);
+    });
+}
+
+/// Collects a capture in memory.
+#[derive(Clone, Default)]
+struct CaptureBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for CaptureBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// `seq` of every stream item received in a capture, in the order they arrived.
+fn received_seqs(capture: &[u8]) -> Vec<u64> {
+    let mut reader = modrpc::CaptureReader::new(capture).unwrap();
+    let mut seqs = Vec::new();
+    while let Some(record) = reader.next_record().unwrap() {
+        if record.direction != modrpc::CaptureDirection::Inbound {
+            continue;
+        }
+        let payload = &record.bundle[modrpc::PacketBundle::BASE_LEN + modrpc::TransmitPacket::BASE_LEN..];
+        let item: StreamItem<u32> = mproto::decode_value(payload).unwrap();
+        seqs.push(item.seq);
+    }
+    seqs
+}
+
+#[test]
+fn test_stream_over_memory_network() {
+    let mut ex = SimExecutor::with_seed(7);
+    let (sender_rt, _sender_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let capture_buf = CaptureBuf::default();
+    let (receiver_rt, _receiver_rt_shutdown) =
+        modrpc::RuntimeBuilder::new_with_local(ex.spawner())
+        .with_capture(modrpc::PacketCapture::new(capture_buf.clone()).unwrap())
+        .start::<SimExecutor>();
+    let network = modrpc::MemoryNetwork::new(modrpc::MemoryNetworkConfig {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(10),
+        ..modrpc::MemoryNetworkConfig::default()
+    });
+    let (sender_link, receiver_link) = network.connect("sender", "receiver");
+
+    ex.run_until(async {
+        let transport = receiver_rt.add_transport(memory_transport(receiver_link)).await;
+        let mut stream_receiver: Option<StreamReceiver<u32>> = None;
+        let _ =
+            receiver_rt.start_role::<StreamReceiverRole<u32>>(
+                role_config(transport, 1, StreamReceiverConfig { }, StreamInitState { }),
+            )
+            .local_async(|cx| {
+                let builder = StreamReceiverBuilder::new("stream_receiver", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+                stream_receiver = Some(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }).await;
+        let mut subscription = stream_receiver.unwrap().subscribe(Some(0));
+
+        let transport = sender_rt.add_transport(memory_transport(sender_link)).await;
+        let mut stream_sender: Option<StreamSender<u32>> = None;
+        let _ =
+            sender_rt.start_role::<StreamSenderRole<u32>>(
+                role_config(transport, 2, StreamSenderConfig { }, StreamInitState { }),
+            )
+            .local_async(|cx| {
+                let builder = StreamSenderBuilder::new("stream_sender", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+                stream_sender = Some(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }).await;
+        let stream_sender = stream_sender.unwrap();
+
+        for i in 0..20u32 {
+            stream_sender.send(i).await;
+            // Each item goes out in its own bundle, so the jitter can reorder them.
+            SimExecutor::sleep(Duration::from_millis(1)).await;
+        }
+
+        // The subscription puts the items back in order.
+        for i in 0..20u32 {
+            assert_eq!(subscription.next().await.unwrap(), i);
+        }
+    });
+
+    receiver_rt.capture().unwrap().flush().unwrap();
+    let seqs = received_seqs(&capture_buf.0.lock().unwrap());
+    assert_eq!(seqs.len(), 20);
+    assert!(!seqs.is_sorted());
+}
+
+#[test]
+fn test_shutdown_delivers_pending_sends() {
+    let mut ex = SimExecutor::with_seed(7);
+    let (sender_rt, sender_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let (receiver_rt, _receiver_rt_shutdown) = modrpc::RuntimeHandle::single_threaded(&mut ex);
+    let network = modrpc::MemoryNetwork::new(modrpc::MemoryNetworkConfig {
+        latency: Duration::from_millis(10),
+        ..modrpc::MemoryNetworkConfig::default()
+    });
+    let (sender_link, receiver_link) = network.connect("sender", "receiver");
+
+    ex.run_until(async {
+        let transport = receiver_rt.add_transport(memory_transport(receiver_link)).await;
+        let mut stream_receiver: Option<StreamReceiver<u32>> = None;
+        let _ =
+            receiver_rt.start_role::<StreamReceiverRole<u32>>(
+                role_config(transport, 1, StreamReceiverConfig { }, StreamInitState { }),
+            )
+            .local_async(|cx| {
+                let builder = StreamReceiverBuilder::new("stream_receiver", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+                stream_receiver = Some(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }).await;
+        let mut subscription = stream_receiver.unwrap().subscribe(Some(0));
+        let received = Rc::new(RefCell::new(Vec::new()));
+        receiver_rt.local_worker_context().unwrap().spawn({
+            let received = received.clone();
+            async move {
+                while let Ok(item) = subscription.next().await {
+                    received.borrow_mut().push(item);
+                }
+            }
+        });
+
+        let transport = sender_rt.add_transport(memory_transport(sender_link)).await;
+        let mut stream_sender: Option<StreamSender<u32>> = None;
+        let _ =
+            sender_rt.start_role::<StreamSenderRole<u32>>(
+                role_config(transport, 2, StreamSenderConfig { }, StreamInitState { }),
+            )
+            .local_async(|cx| {
+                let builder = StreamSenderBuilder::new("stream_sender", cx.hooks.clone(), cx.stubs, cx.config, *cx.init);
+                stream_sender = Some(builder.create_handle(cx.setup));
+                builder.build(cx.setup);
+            }).await;
+        let stream_sender = stream_sender.unwrap();
+
+        // Shut down straight after sending, while the items are still in the sender's buffers.
+        for i in 0..3u32 {
+            stream_sender.send(i).await;
+        }
+        drop(stream_sender);
+        sender_rt_shutdown.shutdown().await;
+
+        SimExecutor::sleep(Duration::from_secs(1)).await;
+        assert_eq!(*received.borrow(), vec![0, 1, 2]);
+    });
+}